@@ -1,13 +1,73 @@
 //! Utility code shared across check.rs and generate.rs
 
 #[cfg(not(test))]
-use crate::config::Service;
+use crate::config::{Run, Service};
 #[cfg(test)]
-use connate::config::Service;
+use connate::config::{Run, Service};
+
+#[cfg(not(test))]
+use crate::internal_api::duration_millis;
+#[cfg(test)]
+use connate::internal_api::duration_millis;
 
 use std::collections::HashMap;
 #[cfg(feature = "host-checks")]
 use std::fs::read_to_string;
+use std::time::Duration;
+
+/// Convert one of a service's durations to the milliseconds it is lowered to
+///
+/// For a convenient interface, we're using Rust's Duration type which can represent a very large
+/// number of milliseconds.  However, this is being fed into poll(2) which takes an i32, so refuse
+/// to build rather than silently wrap.
+pub fn service_duration_millis(service_name: &str, duration: Duration, duration_name: &str) -> i32 {
+    duration_millis(duration).unwrap_or_else(|| {
+        panic!(
+            "Service '{}' has {} duration which is larger than maximum allowed {} milliseconds, or roughly {} days",
+            service_name,
+            duration_name,
+            i32::MAX,
+            i32::MAX / 1000 / 60 / 60 / 24
+        )
+    })
+}
+
+/// The arguments a `Run::Exec` or `Run::Script` is executed with, starting with the pathname
+///
+/// A script's code is passed as the final argument to its interpreter.
+pub fn exec_args(run: &Run) -> Vec<&'static str> {
+    match *run {
+        Run::Exec(args) => args.to_vec(),
+        Run::Script { interpreter, code } => interpreter.iter().copied().chain([code]).collect(),
+        Run::None | Run::Shell(_) | Run::Fn(_) => Vec::new(),
+    }
+}
+
+/// The path a service's `Log::File` path lowers to
+///
+/// `%i` is replaced with the service's instance, which is whatever follows the first `@` in its
+/// name.  For example, `agetty@tty1` logging to `/var/log/agetty-%i.log` logs to
+/// `/var/log/agetty-tty1.log`.
+pub fn log_file_path(svc: &Service, path: &str) -> String {
+    if !path.contains("%i") {
+        return path.to_string();
+    }
+
+    let instance = match svc.name.split_once('@') {
+        Some((_, instance)) if !instance.is_empty() => instance,
+        _ => panic!(
+            "Service '{}' has log path '{}' containing %i, but its name lacks an instance after '@', e.g. 'agetty@tty1'",
+            svc.name, path
+        ),
+    };
+    if instance.contains('/') {
+        panic!(
+            "Service '{}' has log path '{}' containing %i, but its instance '{}' contains a '/'",
+            svc.name, path, instance
+        );
+    }
+    path.replace("%i", instance)
+}
 
 pub fn get_uid_map() -> HashMap<String, u32> {
     #[cfg(feature = "host-checks")]
@@ -59,6 +119,54 @@ pub fn get_gid_map() -> HashMap<String, u32> {
     }
 }
 
+/// Map each user to the groups a login would give them
+///
+/// The user's primary group from /etc/passwd comes first, followed by every other group whose
+/// /etc/group entry lists the user as a member.
+pub fn get_user_groups_map() -> HashMap<String, Vec<u32>> {
+    #[cfg(feature = "host-checks")]
+    {
+        let mut groups_map: HashMap<String, Vec<u32>> = HashMap::new();
+        let passwd = read_to_string("/etc/passwd").expect("Failed to read /etc/passwd");
+        for line in passwd.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split(':').collect();
+            if parts.len() < 4 {
+                continue;
+            }
+            let name = parts[0];
+            let gid = parts[3].parse().expect("Failed to parse gid");
+            groups_map.insert(name.to_string(), vec![gid]);
+        }
+
+        let group = read_to_string("/etc/group").expect("Failed to read /etc/group");
+        for line in group.lines() {
+            if line.starts_with('#') {
+                continue;
+            }
+            let parts: Vec<&str> = line.split(':').collect();
+            if parts.len() < 4 {
+                continue;
+            }
+            let gid = parts[2].parse().expect("Failed to parse gid");
+            for member in parts[3].split(',') {
+                if let Some(gids) = groups_map.get_mut(member)
+                    && !gids.contains(&gid)
+                {
+                    gids.push(gid);
+                }
+            }
+        }
+        groups_map
+    }
+    #[cfg(not(feature = "host-checks"))]
+    {
+        HashMap::new()
+    }
+}
+
 pub fn get_svc_map(svcs: &[Service]) -> HashMap<&'static str, &Service> {
     let mut svc_map = HashMap::new();
     for svc in svcs {
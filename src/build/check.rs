@@ -2,7 +2,7 @@ use crate::build_util::*;
 use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 use std::os::unix::fs::PermissionsExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
 
@@ -16,6 +16,20 @@ use crate::constants::*;
 #[cfg(test)]
 use connate::constants::*;
 
+#[cfg(not(test))]
+use crate::types::Signal;
+#[cfg(test)]
+use connate::types::Signal;
+
+/// Maximum number of `Log::Service` hops from a service to its final logger
+const MAX_LOG_CHAIN_DEPTH: usize = 4;
+
+/// Lowest number a `FIXED_FDS` entry may use
+///
+/// Keeps them clear of stdio and of the low numbers the kernel hands out to the FDs connate opens
+/// before moving them into place, which a fixed FD would otherwise silently replace.
+const MIN_FIXED_FD: i32 = 10;
+
 /// Trait providing compile-time configuration checking methods.
 pub trait ConfigCheck: Config {
     fn check_config() {
@@ -25,34 +39,54 @@ pub trait ConfigCheck: Config {
 
         // Checks for things that aren't specific to one services
         Self::check_lock_file();
+        Self::check_shell();
+        Self::check_system_service(&svc_map);
+        Self::check_targets(&svc_map);
+        Self::check_emergency_services(&svc_map);
+        Self::check_max_concurrent_starts();
+        Self::check_blocked_time();
+        Self::check_fixed_fds();
         Self::check_name_uniqueness();
         Self::check_name_default();
+        Self::check_stop_last();
         Self::check_log_uniqueness();
+        Self::check_cgroup_uniqueness();
 
         if Self::SERVICES.is_empty() {
             panic!("No services configured.");
         }
+        Self::check_fd_count();
 
         // Per-service checks
         for svc in Self::SERVICES {
             svc.check_name();
             // svc.check_init_target(); // type system check is comprehensive
+            svc.check_start_if_cmdline();
             svc.check_needs(&svc_map);
             svc.check_wants(&svc_map);
             svc.check_conflicts(&svc_map);
+            svc.check_serialize_with(&svc_map);
             svc.check_groups(&svc_map);
+            svc.check_stop_last_dependencies();
             svc.check_setup();
             svc.check_run();
             svc.check_ready();
             svc.check_cleanup();
             // svc.check_stop_all_children(); // type system check is comprehensive
+            svc.check_children_stop();
             svc.check_max_setup_time();
             svc.check_max_ready_time();
             svc.check_max_stop_time();
             svc.check_max_cleanup_time();
+            svc.check_max_wait_time();
+            svc.check_up_stable_time();
             svc.check_retry();
+            svc.check_exit_codes();
+            svc.check_stdin();
             svc.check_log(&svc_map);
+            svc.check_log_groups(Self::SERVICES);
             svc.check_env();
+            svc.check_inherit_env();
             svc.check_user(
                 #[cfg(feature = "host-checks")]
                 &uid_map,
@@ -61,18 +95,144 @@ pub trait ConfigCheck: Config {
                 #[cfg(feature = "host-checks")]
                 &gid_map,
             );
+            svc.check_use_user_groups();
             svc.check_chdir();
-            // svc.check_no_new_privs(); // type system check is comprehensive
+            svc.check_cgroup();
+            svc.check_kill_mode();
+            svc.check_no_new_privs();
+            // svc.check_new_session(); // type system check is comprehensive
+            svc.check_controlling_tty(&svc_map);
         }
 
         // Graph traversals for cycle detection
         // This must follow prior checks ensuring dependencies exist at all
         for svc in Self::SERVICES {
+            // Before the general cycle check for a more specific message on logger cycles
+            svc.check_log_chain(&svc_map);
             svc.check_start_stop_cycle(&svc_map);
+            // Before the general target stability check for a more specific message on services
+            // which can never start
+            svc.check_dependency_conflicts(&svc_map);
             svc.check_target_up_stable(&svc_map);
         }
     }
 
+    fn check_max_concurrent_starts() {
+        if Self::MAX_CONCURRENT_STARTS == Some(0) {
+            panic!(
+                "MAX_CONCURRENT_STARTS is Some(0), which would prevent any service from starting"
+            );
+        }
+    }
+
+    // Service indices are usize throughout, including over IPC, and session state holds one
+    // fixed-size record per service, so the service count itself is not otherwise limited.
+    fn check_fd_count() {
+        let mut loggers: HashSet<&str> = HashSet::new();
+        let mut console_pipes = 0;
+        for svc in Self::SERVICES {
+            if let Log::Service(logger) = svc.log {
+                loggers.insert(logger);
+            }
+            if matches!(svc.log, Log::Inherit | Log::Service(_)) && svc.controlling_tty.is_none() {
+                console_pipes += 1;
+            }
+        }
+
+        // stdio, the FDs connate reserves for itself, and both ends of each logger's stdin pipe
+        let mut held = 3 + Self::fixed_fds().len() + 2 * loggers.len();
+        if Self::LINE_BUFFER_CONSOLE {
+            // The non-blocking reopen of stdout, and both ends of each console pipe
+            held += 1 + 2 * console_pipes;
+        }
+        if held > DEFAULT_NOFILE_LIMIT {
+            panic!(
+                "connate would hold {} FDs open for {} loggers{}, but the kernel starts init with a limit of {} open FDs",
+                held,
+                loggers.len(),
+                if Self::LINE_BUFFER_CONSOLE {
+                    format!(" and {console_pipes} LINE_BUFFER_CONSOLE pipes")
+                } else {
+                    String::new()
+                },
+                DEFAULT_NOFILE_LIMIT,
+            );
+        }
+    }
+
+    fn check_blocked_time() {
+        if Self::BLOCKED_TIME.is_zero() {
+            panic!("BLOCKED_TIME is zero, which would give up on waiting services at once");
+        }
+        if i32::try_from(Self::BLOCKED_TIME.as_millis()).is_err() {
+            panic!(
+                "BLOCKED_TIME is larger than maximum allowed {} milliseconds",
+                i32::MAX
+            );
+        }
+    }
+
+    fn check_targets(svc_map: &HashMap<&'static str, &'static Service>) {
+        let mut names = HashSet::new();
+
+        for (name, members) in Self::TARGETS {
+            if name.is_empty() {
+                panic!("TARGETS has a target with an empty name");
+            }
+            if name.len() > MSG_SVC_NAME_SIZE {
+                panic!("Target '{name}' name is longer than the maximum {MSG_SVC_NAME_SIZE} bytes");
+            }
+            if !names.insert(name) {
+                panic!("TARGETS has duplicate target name '{name}'");
+            }
+
+            for member in *members {
+                let Some(svc) = svc_map.get(member) else {
+                    panic!("Target '{name}' lists service '{member}' which does not exist");
+                };
+                if members.iter().filter(|&m| m == member).count() > 1 {
+                    panic!("Target '{name}' lists service '{member}' more than once");
+                }
+                // Isolating to the target would thrash between the two
+                if let Some(conflict) = svc.conflicts.iter().find(|c| members.contains(c)) {
+                    panic!(
+                        "Target '{name}' lists both '{member}' and '{conflict}', which conflict"
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_emergency_services(svc_map: &HashMap<&'static str, &'static Service>) {
+        let members = Self::EMERGENCY_SERVICES;
+        for member in members {
+            let Some(svc) = svc_map.get(member) else {
+                panic!("EMERGENCY_SERVICES lists service '{member}' which does not exist");
+            };
+            if members.iter().filter(|&m| m == member).count() > 1 {
+                panic!("EMERGENCY_SERVICES lists service '{member}' more than once");
+            }
+            // Isolating to emergency mode would thrash between the two
+            if let Some(conflict) = svc.conflicts.iter().find(|c| members.contains(c)) {
+                panic!("EMERGENCY_SERVICES lists both '{member}' and '{conflict}', which conflict");
+            }
+        }
+    }
+
+    fn check_system_service(svc_map: &HashMap<&'static str, &'static Service>) {
+        if let Some(name) = Self::SYSTEM_SERVICE
+            && !svc_map.contains_key(name)
+        {
+            panic!("The configured SYSTEM_SERVICE '{name}' does not exist");
+        }
+
+        if matches!(Self::CTRL_ALT_DEL, CtrlAltDel::Reboot) && Self::SYSTEM_SERVICE.is_none() {
+            panic!(
+                "CTRL_ALT_DEL is CtrlAltDel::Reboot, which restarts SYSTEM_SERVICE, but SYSTEM_SERVICE is None"
+            );
+        }
+    }
+
     fn check_lock_file() {
         let Some(path) = Self::LOCK_FILE else {
             return;
@@ -111,6 +271,60 @@ pub trait ConfigCheck: Config {
         }
     }
 
+    fn check_shell() {
+        let shell = Self::SHELL.to_str().unwrap_or_else(|_| {
+            panic!("The configured SHELL {:?} is not valid UTF-8", Self::SHELL)
+        });
+
+        if !Path::new(shell).is_absolute() {
+            panic!(
+                "The configured SHELL '{shell}' is not absolute. Only absolute paths are allowed."
+            );
+        }
+
+        #[cfg(feature = "host-checks")]
+        {
+            let uses_shell = Self::SERVICES.iter().any(|svc| {
+                [&svc.setup, &svc.run, &svc.cleanup]
+                    .iter()
+                    .any(|run| matches!(run, Run::Shell(_)))
+            });
+            if uses_shell && !Path::new(shell).exists() {
+                panic!(
+                    "The configured SHELL '{shell}' does not exist.
+                        If you are building for a remote system, build with --no-default-features.
+                        Otherwise, either install it or change the path."
+                );
+            }
+        }
+    }
+
+    fn fixed_fds() -> [(&'static str, i32); 7] {
+        [
+            ("session_state", Self::FIXED_FDS.session_state),
+            ("signal", Self::FIXED_FDS.signal),
+            ("lock_file", Self::FIXED_FDS.lock_file),
+            ("req_read", Self::FIXED_FDS.req_read),
+            ("req_write", Self::FIXED_FDS.req_write),
+            ("resp_read", Self::FIXED_FDS.resp_read),
+            ("resp_write", Self::FIXED_FDS.resp_write),
+        ]
+    }
+
+    fn check_fixed_fds() {
+        let mut seen: HashMap<i32, &str> = HashMap::new();
+        for (name, fd) in Self::fixed_fds() {
+            if fd < MIN_FIXED_FD {
+                panic!(
+                    "FIXED_FDS.{name} is {fd}, which may collide with stdio or FDs connate opens during setup.  Fixed FDs must be {MIN_FIXED_FD} or greater."
+                );
+            }
+            if let Some(other) = seen.insert(fd, name) {
+                panic!("FIXED_FDS.{name} and FIXED_FDS.{other} are both set to {fd}");
+            }
+        }
+    }
+
     fn check_name_uniqueness() {
         let mut names = HashSet::new();
 
@@ -121,6 +335,19 @@ pub trait ConfigCheck: Config {
         }
     }
 
+    fn check_stop_last() {
+        let stop_last: Vec<_> = Self::SERVICES
+            .iter()
+            .filter(|svc| svc.stop_last)
+            .map(|svc| svc.name)
+            .collect();
+        if let [first, second, ..] = stop_last[..] {
+            panic!(
+                "Services '{first}' and '{second}' both set stop_last, but only one service can stop last"
+            );
+        }
+    }
+
     fn check_name_default() {
         for svc in Self::SERVICES {
             if svc.name == Self::DEFAULT_SERVICE.name {
@@ -133,17 +360,39 @@ pub trait ConfigCheck: Config {
     }
 
     fn check_log_uniqueness() {
-        let mut log_files: HashSet<&str> = HashSet::new();
+        let mut log_files: HashSet<String> = HashSet::new();
 
         for svc in Self::SERVICES {
-            if let Log::File { path, .. } = &svc.log
-                && !log_files.insert(*path)
-            {
+            let Log::File { path, .. } = &svc.log else {
+                continue;
+            };
+            // Instances sharing a path containing `%i` log to different files
+            let path = log_file_path(svc, path);
+            if log_files.contains(&path) {
                 panic!(
                     "Multiple services are configured to log to the same file: '{}'",
                     path,
                 );
             }
+            log_files.insert(path);
+        }
+    }
+
+    fn check_cgroup_uniqueness() {
+        let mut cgroups: HashMap<PathBuf, &str> = HashMap::new();
+
+        for svc in Self::SERVICES {
+            let Some(path) = svc.cgroup else {
+                continue;
+            };
+            // Compare by components such that e.g. a trailing slash does not hide a duplicate
+            let normalized: PathBuf = Path::new(path).components().collect();
+            if let Some(other) = cgroups.insert(normalized, svc.name) {
+                panic!(
+                    "Services '{}' and '{}' are both configured to use cgroup '{}'.  Each service needs its own cgroup, as connate signals and freezes it as a whole.",
+                    other, svc.name, path,
+                );
+            }
         }
     }
 }
@@ -177,6 +426,37 @@ impl Service {
         }
     }
 
+    fn check_start_if_cmdline(&self) {
+        let Some(param) = self.start_if_cmdline else {
+            return;
+        };
+
+        if param.is_empty() {
+            panic!(
+                "Service '{}' has an empty start_if_cmdline, which can never be on the kernel command line",
+                self.name
+            );
+        }
+        if param.chars().any(|c| c.is_ascii_whitespace() || c == '\0') {
+            panic!(
+                "Service '{}' has start_if_cmdline '{}' which contains whitespace or a null byte. Kernel command line parameters are matched whole.",
+                self.name, param
+            );
+        }
+        if param.len() > MSG_SVC_NAME_SIZE {
+            panic!(
+                "Service '{}' has start_if_cmdline '{}' with more bytes than max allowed of {}",
+                self.name, param, MSG_SVC_NAME_SIZE
+            );
+        }
+        if matches!(self.init_target, Target::Down) {
+            panic!(
+                "Service '{}' has start_if_cmdline set but init_target Target::Down, so it never starts either way. Set init_target to the target to use when the parameter is present.",
+                self.name
+            );
+        }
+    }
+
     fn check_needs(&self, svc_map: &HashMap<&'static str, &'static Service>) {
         self.check_dependency(self.needs, "needs", svc_map);
     }
@@ -198,15 +478,43 @@ impl Service {
         self.check_dependency(self.conflicts, "conflicts", svc_map);
     }
 
+    fn check_serialize_with(&self, svc_map: &HashMap<&'static str, &'static Service>) {
+        self.check_dependency(self.serialize_with, "serialize_with", svc_map);
+    }
+
     fn check_groups(&self, svc_map: &HashMap<&'static str, &'static Service>) {
         self.check_dependency(self.groups, "groups", svc_map);
     }
 
+    fn check_stop_last_dependencies(&self) {
+        if !self.stop_last {
+            return;
+        }
+        // Anything it depends on would wait for it to stop first, while it waits for everything
+        // else to stop first
+        let dependency = self
+            .needs
+            .first()
+            .map(|dep| ("needs", *dep))
+            .or_else(|| self.wants.first().map(|dep| ("wants", *dep)))
+            .or(match self.log {
+                Log::Service(log_service) => Some(("logs to", log_service)),
+                _ => None,
+            });
+        if let Some((dep_type, dep)) = dependency {
+            panic!(
+                "Service '{}' sets stop_last but {dep_type} '{dep}', which would have to stop after it",
+                self.name
+            );
+        }
+    }
+
     fn check_setup(&self) {
         match self.setup {
             Run::None => {}
             Run::Exec(args) => self.check_exec_args(args, "setup"),
             Run::Shell(cmd) => self.check_shell_command(cmd, "setup"),
+            Run::Script { interpreter, code } => self.check_script(interpreter, code, "setup"),
             Run::Fn(_) => {}
         }
     }
@@ -216,6 +524,7 @@ impl Service {
             Run::None => {}
             Run::Exec(args) => self.check_exec_args(args, "run"),
             Run::Shell(cmd) => self.check_shell_command(cmd, "run"),
+            Run::Script { interpreter, code } => self.check_script(interpreter, code, "run"),
             Run::Fn(_) => {}
         }
     }
@@ -225,6 +534,7 @@ impl Service {
             Run::None => {}
             Run::Exec(args) => self.check_exec_args(args, "cleanup"),
             Run::Shell(cmd) => self.check_shell_command(cmd, "cleanup"),
+            Run::Script { interpreter, code } => self.check_script(interpreter, code, "cleanup"),
             Run::Fn(_) => {}
         }
     }
@@ -261,21 +571,138 @@ impl Service {
         self.check_duration(self.max_cleanup_time, "max_cleanup_time");
     }
 
+    fn check_max_wait_time(&self) {
+        self.check_duration(self.max_wait_time, "max_wait_time");
+    }
+
+    fn check_up_stable_time(&self) {
+        self.check_duration(self.up_stable_time, "up_stable_time");
+    }
+
     fn check_retry(&self) {
         match self.retry {
-            Retry::Never => {}
-            Retry::AfterFixed { after, .. } => self.check_duration(Some(after), "retry after"),
-            Retry::AfterDoublingDelay { initial_delay, .. } => {
-                self.check_duration(Some(initial_delay), "retry initial_delay")
+            Retry::Never | Retry::Immediate { .. } => {}
+            Retry::AfterFixed { after, jitter, .. } => {
+                self.check_duration(Some(after), "retry after");
+                self.check_duration(jitter, "retry jitter");
+            }
+            Retry::AfterDoublingDelay {
+                initial_delay,
+                max_delay,
+                jitter,
+                ..
+            } => {
+                self.check_duration(Some(initial_delay), "retry initial_delay");
+                self.check_duration(max_delay, "retry max_delay");
+                self.check_duration(jitter, "retry jitter");
+                if let Some(max_delay) = max_delay
+                    && max_delay < initial_delay
+                {
+                    panic!(
+                        "Service '{}' has retry max_delay which is shorter than its initial_delay",
+                        self.name
+                    );
+                }
             }
         };
     }
 
+    fn check_exit_codes(&self) {
+        for (codes, field) in [
+            (self.success_exit_codes, "success_exit_codes"),
+            (self.fatal_exit_codes, "fatal_exit_codes"),
+        ] {
+            for code in codes {
+                if !(0..=255).contains(code) {
+                    panic!(
+                        "Service '{}' has {} entry {} which is not a possible exit code (0-255)",
+                        self.name, field, code
+                    );
+                }
+                // A panic's code cannot be mistaken for a signal's, as there is no signal 127
+                if *code > 128 && *code != PANIC_EXIT_CODE {
+                    panic!(
+                        "Service '{}' has {} entry {} which is indistinguishable from death by \
+                         signal {}",
+                        self.name,
+                        field,
+                        code,
+                        code - 128
+                    );
+                }
+            }
+        }
+
+        if self.fatal_exit_codes.contains(&0) {
+            panic!(
+                "Service '{}' has fatal_exit_codes entry 0, which always indicates success",
+                self.name
+            );
+        }
+
+        for code in self.success_exit_codes {
+            if self.fatal_exit_codes.contains(code) {
+                panic!(
+                    "Service '{}' has exit code {} in both success_exit_codes and fatal_exit_codes",
+                    self.name, code
+                );
+            }
+        }
+    }
+
+    fn check_stdin(&self) {
+        let StdinSource::File(path) = self.stdin else {
+            return;
+        };
+
+        if path.contains('\0') {
+            panic!(
+                "Service '{}' has stdin path '{}' which contains a disallowed null byte",
+                self.name, path
+            );
+        }
+
+        let path_obj = Path::new(path);
+        if !path_obj.is_absolute() {
+            panic!(
+                "Service '{}' has stdin path '{}' which is not absolute. Only absolute paths are allowed.",
+                self.name, path
+            );
+        }
+
+        #[cfg(feature = "host-checks")]
+        {
+            if !path_obj.exists() {
+                panic!(
+                    "Service '{}' has stdin path '{}' which does not exist",
+                    self.name, path
+                );
+            }
+            if path_obj.is_dir() {
+                panic!(
+                    "Service '{}' has stdin path '{}' which is a directory",
+                    self.name, path
+                );
+            }
+        }
+    }
+
     fn check_log(&self, svc_map: &HashMap<&'static str, &'static Service>) {
         match &self.log {
             Log::None => {}
             Log::Inherit => {}
-            Log::File { path, .. } => {
+            Log::File {
+                path, permissions, ..
+            } => {
+                let path = &log_file_path(self, path);
+                if let FilePerm::Mode(mode) = permissions
+                    && *mode > 0o777
+                {
+                    panic!(
+                        "Service '{}' has log file mode {:#o} which is larger than maximum allowed 0o777",
+                        self.name, mode
+                    );
+                }
                 if path.contains('\0') {
                     panic!(
                         "Service '{}' has log path '{}' which contains a disallowed null byte",
@@ -296,6 +723,8 @@ impl Service {
                     );
                 }
 
+                // Writability is not checked against `user`: the file is opened before dropping
+                // privileges, and connate's own runtime user is unknown at build time.
                 #[cfg(feature = "host-checks")]
                 {
                     if path_obj.is_dir() {
@@ -366,10 +795,59 @@ impl Service {
                         self.name, log_svc_name, log_svc_name
                     );
                 }
+
+                // Check that the log service doesn't expect stdin from elsewhere, as the log pipe
+                // replaces it
+                if !matches!(log_svc.stdin, StdinSource::Null) {
+                    panic!(
+                        "Service '{}' logs to service '{}', but '{}' has stdin set to something other than StdinSource::Null. Its stdin is the log pipe.",
+                        self.name, log_svc_name, log_svc_name
+                    );
+                }
             }
         }
     }
 
+    /// Warn about explicit `groups` entries which interact poorly with `Log::Service`
+    ///
+    /// The implicit logger wiring does not use groups: a logger is started before and stopped
+    /// after every service which logs to it via start/stop dependencies.  Explicitly grouping a
+    /// logger which is shared by several services couples all of them together.
+    fn check_log_groups(&self, services: &'static [Service]) {
+        let Log::Service(log_svc_name) = &self.log else {
+            return;
+        };
+        if !self.groups.contains(log_svc_name) {
+            return;
+        }
+
+        let other_producers: Vec<&str> = services
+            .iter()
+            .filter(|svc| svc.name != self.name)
+            .filter(|svc| matches!(&svc.log, Log::Service(name) if name == log_svc_name))
+            .map(|svc| svc.name)
+            .collect();
+        if other_producers.is_empty() {
+            return;
+        }
+
+        println!(
+            "cargo:warning=Service '{}' lists its logger '{}' in groups, but '{}' also logs for {}. Bringing '{}' down or restarting it will do the same to '{}' and thus to every service logging to it. Consider removing '{}' from groups; it is already started before and stopped after '{}'.",
+            self.name,
+            log_svc_name,
+            log_svc_name,
+            other_producers
+                .iter()
+                .map(|name| format!("'{name}'"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.name,
+            log_svc_name,
+            log_svc_name,
+            self.name,
+        );
+    }
+
     fn check_env(&self) {
         let mut vars = HashSet::new();
 
@@ -398,6 +876,14 @@ impl Service {
                 );
             }
 
+            // Must fit in an IPC response for `conctl env`
+            if var_eq_val.len() > MSG_PATH_SIZE {
+                panic!(
+                    "Service '{}' has environment variable '{}' which is longer than the maximum {} bytes",
+                    self.name, var, MSG_PATH_SIZE
+                );
+            }
+
             // Check for duplicate variable names
             if !vars.insert(var) {
                 panic!(
@@ -428,6 +914,48 @@ impl Service {
         }
     }
 
+    fn check_inherit_env(&self) {
+        if !self.inherit_env.is_empty() && !self.clear_env {
+            panic!(
+                "Service '{}' has inherit_env but not clear_env, in which case it already inherits all of connate's environment.  This is probably an unintentional configuration.",
+                self.name
+            );
+        }
+
+        let mut vars = HashSet::new();
+        for var in self.inherit_env {
+            let valid = var
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && var.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                panic!(
+                    "Service '{}' has inherit_env variable '{}' with an invalid name. Names must start with a letter or underscore and may only contain letters, digits, and underscores.",
+                    self.name, var
+                );
+            }
+
+            if !vars.insert(var) {
+                panic!(
+                    "Service '{}' has duplicate inherit_env variable '{}'",
+                    self.name, var
+                );
+            }
+
+            if self
+                .env
+                .iter()
+                .any(|var_eq_val| var_eq_val.split_once('=').map(|(v, _)| v) == Some(var))
+            {
+                panic!(
+                    "Service '{}' both sets and inherits environment variable '{}', in which case the set value always wins.  This is probably an unintentional configuration.",
+                    self.name, var
+                );
+            }
+        }
+    }
+
     fn check_user(&self, #[cfg(feature = "host-checks")] uid_map: &HashMap<String, u32>) {
         let Some(user) = &self.user else {
             return;
@@ -476,6 +1004,15 @@ impl Service {
         }
     }
 
+    fn check_use_user_groups(&self) {
+        if self.use_user_groups && self.user.is_none() {
+            panic!(
+                "Service '{}' has use_user_groups set without a user whose groups to use",
+                self.name
+            );
+        }
+    }
+
     fn check_chdir(&self) {
         let Some(path) = self.chdir else {
             return;
@@ -513,6 +1050,178 @@ impl Service {
         }
     }
 
+    fn check_controlling_tty(&self, svc_map: &HashMap<&'static str, &'static Service>) {
+        let Some(path) = self.controlling_tty else {
+            return;
+        };
+
+        if path.contains('\0') {
+            panic!(
+                "Service '{}' has controlling_tty '{}' which contains a disallowed null byte",
+                self.name, path
+            );
+        }
+
+        let path_obj = Path::new(path);
+        if !path_obj.is_absolute() {
+            panic!(
+                "Service '{}' has controlling_tty '{}' which is not absolute. Only absolute paths are allowed.",
+                self.name, path
+            );
+        }
+
+        // Only a session leader can take a controlling terminal
+        if !self.new_session {
+            panic!(
+                "Service '{}' has controlling_tty set but new_session disabled. A process must lead a new session to acquire a controlling terminal.",
+                self.name
+            );
+        }
+
+        // The terminal replaces stdio, which would silently discard any other configuration
+        if !matches!(self.stdin, StdinSource::Null) {
+            panic!(
+                "Service '{}' has controlling_tty set along with stdin. The terminal is used for stdin; leave stdin as StdinSource::Null.",
+                self.name
+            );
+        }
+        if !matches!(self.log, Log::Inherit) {
+            panic!(
+                "Service '{}' has controlling_tty set along with log. The terminal is used for stdout and stderr; leave log as Log::Inherit.",
+                self.name
+            );
+        }
+        if svc_map
+            .values()
+            .any(|s| matches!(&s.log, Log::Service(logger) if *logger == self.name))
+        {
+            panic!(
+                "Service '{}' has controlling_tty set but other services log to it. A logger must read its stdin from its log pipe.",
+                self.name
+            );
+        }
+
+        // Terminals such as /dev/ttyS0 may only appear once their driver loads, so only check
+        // there is nothing else at the path.
+        #[cfg(feature = "host-checks")]
+        if let Ok(metadata) = path_obj.metadata() {
+            use std::os::unix::fs::FileTypeExt;
+            if !metadata.file_type().is_char_device() {
+                panic!(
+                    "Service '{}' has controlling_tty '{}' which is not a character device",
+                    self.name, path
+                );
+            }
+        }
+    }
+
+    fn check_children_stop(&self) {
+        let ChildrenStop::Signal(signal, time) = self.children_stop else {
+            return;
+        };
+
+        self.check_duration(Some(time), "children_stop");
+
+        if !self.stop_all_children {
+            panic!(
+                "Service '{}' sets children_stop but not stop_all_children. children_stop only applies to the children stop_all_children stops.",
+                self.name
+            );
+        }
+
+        if matches!(signal, Signal::SIGCHLD | Signal::UNRECOGNIZED) {
+            panic!(
+                "Service '{}' has children_stop signal {} which does not stop processes",
+                self.name,
+                signal.name()
+            );
+        }
+    }
+
+    fn check_kill_mode(&self) {
+        if matches!(self.kill_mode, KillMode::Process) {
+            return;
+        }
+
+        if self.cgroup.is_none() {
+            panic!(
+                "Service '{}' has a kill_mode other than KillMode::Process but no cgroup. Signaling every process of the service requires the service to have its own cgroup.",
+                self.name
+            );
+        }
+
+        if self.stop_all_children {
+            panic!(
+                "Service '{}' sets both stop_all_children and a cgroup-based kill_mode. Both stop every process of the service; use only the kill_mode.",
+                self.name
+            );
+        }
+    }
+
+    fn check_cgroup(&self) {
+        let Some(path) = self.cgroup else {
+            return;
+        };
+
+        if path.contains('\0') {
+            panic!(
+                "Service '{}' has cgroup '{}' which contains a disallowed null byte",
+                self.name, path
+            );
+        }
+
+        if !Path::new(path).is_absolute() {
+            panic!(
+                "Service '{}' has cgroup '{}' which is not absolute. Only absolute paths are allowed.",
+                self.name, path
+            );
+        }
+
+        // Leave room for the longest cgroup interface file name connate appends
+        if path.len() + CGROUP_FILE_NAME_MAX_LEN > MSG_PATH_SIZE {
+            panic!(
+                "Service '{}' has cgroup '{}' which is longer than the maximum {} bytes",
+                self.name,
+                path,
+                MSG_PATH_SIZE - CGROUP_FILE_NAME_MAX_LEN,
+            );
+        }
+
+        // The cgroup2 filesystem is commonly only mounted at runtime, so there is nothing to check
+        // on the host.
+    }
+
+    fn check_log_chain(self: &'static Service, svc_map: &HashMap<&'static str, &'static Service>) {
+        // Follow `Log::Service` edges from this service.  check_log() has already validated each
+        // individual hop; here we consider the chain as a whole.
+        let mut chain = vec![self.name];
+        let mut current_svc = self;
+        while let Log::Service(log_svc_name) = &current_svc.log {
+            let log_svc = svc_map[log_svc_name];
+
+            if chain.contains(&log_svc.name) {
+                panic!(
+                    "Logger cycle: {} logs to {}. A logger cannot directly or indirectly log to itself.",
+                    chain.join(" logs to "),
+                    log_svc.name
+                );
+            }
+            chain.push(log_svc.name);
+
+            // Every hop adds a pipe and a start/stop ordering constraint.  Chains this deep are
+            // almost certainly a mistake.
+            if chain.len() - 1 > MAX_LOG_CHAIN_DEPTH {
+                panic!(
+                    "Log chain too long: {}. At most {} loggers may be chained.",
+                    chain.join(" logs to "),
+                    MAX_LOG_CHAIN_DEPTH
+                );
+            }
+
+            current_svc = log_svc;
+        }
+    }
+
     fn check_start_stop_cycle(
         self: &'static Service,
         svc_map: &HashMap<&'static str, &'static Service>,
@@ -521,8 +1230,8 @@ impl Service {
         // Currently searching dependencies of `current_svc`
         // If we see `original_svc` as a dependency, that's a cycle.
         //
-        // The stop requirement tree is exactly the same, just in the inverse direction.  The same
-        // check covers both.
+        // The stop requirement tree is the same, just in the inverse direction and without
+        // `serialize_with`, which only orders starts.  The same check covers both.
         fn dfs<'a>(
             original_svc: &'static Service,
             current_svc: &'static Service,
@@ -538,6 +1247,9 @@ impl Service {
             for &dep in current_svc.wants {
                 deps.push(("wants", svc_map[dep]));
             }
+            for &dep in current_svc.serialize_with {
+                deps.push(("serializes with", svc_map[dep]));
+            }
             if let Log::Service(log_service) = &current_svc.log {
                 deps.push(("logs to", svc_map[log_service]));
             }
@@ -593,6 +1305,86 @@ impl Service {
         dfs(self, self, svc_map, &mut path, &mut visited)
     }
 
+    fn check_dependency_conflicts(
+        self: &'static Service,
+        svc_map: &HashMap<&'static str, &'static Service>,
+    ) {
+        // Breadth-first search over everything starting this service brings up.  Each service
+        // reached is recorded with the service and relation it was reached through, such that the
+        // path to it can be explained.
+        let mut reached: HashMap<&'static str, (&'static str, &'static str)> = HashMap::new();
+        let mut order = vec![self];
+        let mut i = 0;
+        while let Some(&current_svc) = order.get(i) {
+            i += 1;
+
+            let mut deps = Vec::new();
+            for &dep in current_svc.needs {
+                deps.push(("needs", dep));
+            }
+            for &dep in current_svc.wants {
+                deps.push(("wants", dep));
+            }
+            if let Log::Service(log_service) = &current_svc.log {
+                deps.push(("logs to", *log_service));
+            }
+
+            for (dep_type, dep) in deps {
+                if dep == self.name || reached.contains_key(dep) {
+                    continue;
+                }
+                reached.insert(dep, (dep_type, current_svc.name));
+                order.push(svc_map[dep]);
+            }
+        }
+
+        // Explain how starting this service brings up `name`, e.g. "a needs b which wants c"
+        let explain = |name: &'static str| {
+            let mut hops = Vec::new();
+            let mut current = name;
+            while let Some(&(dep_type, from)) = reached.get(current) {
+                hops.push((dep_type, current));
+                current = from;
+            }
+            let mut path = String::from(self.name);
+            for (i, (dep_type, name)) in hops.iter().rev().enumerate() {
+                path.push_str(if i == 0 { " " } else { " which " });
+                path.push_str(dep_type);
+                path.push(' ');
+                path.push_str(name);
+            }
+            path
+        };
+
+        for svc in &order {
+            for &conflict in svc.conflicts {
+                if conflict != self.name && !reached.contains_key(conflict) {
+                    continue;
+                }
+                let conflict_path = if svc.name == self.name {
+                    format!("{} conflicts with {}", self.name, conflict)
+                } else {
+                    format!("{} which conflicts with {}", explain(svc.name), conflict)
+                };
+                if conflict == self.name {
+                    panic!(
+                        "Service '{}' can never start: {}. Starting '{}' would bring it back down.",
+                        self.name, conflict_path, self.name
+                    );
+                }
+                panic!(
+                    "Service '{}' can never start: {}, but {}. Starting '{}' would require '{}' \
+                     to be both up and down.",
+                    self.name,
+                    explain(conflict),
+                    conflict_path,
+                    self.name,
+                    conflict
+                );
+            }
+        }
+    }
+
     fn check_target_up_stable(
         self: &'static Service,
         svc_map: &HashMap<&'static str, &'static Service>,
@@ -776,6 +1568,44 @@ impl Service {
         }
     }
 
+    /// Warn about setuid or setgid binaries which `no_new_privs` keeps from taking effect
+    ///
+    /// The binary still runs, just without the privileges it expects, which tends to surface as
+    /// confusing permission errors at runtime rather than an obvious failure.
+    fn check_no_new_privs(&self) {
+        #[cfg(feature = "host-checks")]
+        {
+            if !self.no_new_privs {
+                return;
+            }
+
+            for (run, context) in [
+                (&self.setup, "setup"),
+                (&self.run, "run"),
+                (&self.cleanup, "cleanup"),
+            ] {
+                // Existence is checked with the rest of the path
+                let Some(path) = exec_args(run).first().copied() else {
+                    continue;
+                };
+                let Ok(metadata) = Path::new(path).metadata() else {
+                    continue;
+                };
+                let mode = metadata.permissions().mode();
+                let bits = match (mode & 0o4000 != 0, mode & 0o2000 != 0) {
+                    (true, true) => "setuid and setgid",
+                    (true, false) => "setuid",
+                    (false, true) => "setgid",
+                    (false, false) => continue,
+                };
+                println!(
+                    "cargo:warning=Service '{}' has no_new_privs: true, but its {} path '{}' is {}. no_new_privs keeps the kernel from honoring the {} bit, so it will run without the privileges it expects. Set no_new_privs: false for this service.",
+                    self.name, context, path, bits, bits,
+                );
+            }
+        }
+    }
+
     /// Helper function to validate arguments for start/run/finish
     fn check_exec_args(&self, args: &[&str], context: &str) {
         let Some(path) = args.first() else {
@@ -856,33 +1686,28 @@ impl Service {
                 self.name, context, cmd
             );
         }
+    }
 
-        #[cfg(feature = "host-checks")]
-        {
-            if !Path::new("/bin/sh").exists() {
-                panic!(
-                    "Service '{}' uses Shell for {} but /bin/sh does not exist",
-                    self.name, context
-                );
-            }
+    fn check_script(&self, interpreter: &[&str], code: &str, context: &str) {
+        // The interpreter's path is validated like any other executable's
+        self.check_exec_args(interpreter, &format!("{context} Script interpreter"));
+        if code.is_empty() {
+            panic!(
+                "Service '{}' has an empty {} Script code",
+                self.name, context
+            );
+        }
+        if CString::from_str(code).is_err() {
+            panic!(
+                "Service '{}' has {} Script code which cannot be converted into a C string: {}",
+                self.name, context, code
+            );
         }
     }
 
     fn check_duration(&self, duration: Option<Duration>, duration_name: &str) {
-        let Some(duration) = duration else {
-            return;
-        };
-
-        // For a convenient interface, we're using Rust's Duration type which can represent a very
-        // large number of milliseconds.  However, this is being fed into poll(2) which takes an i32.
-        if duration.as_millis() > i32::MAX as u128 {
-            panic!(
-                "Service '{}' has {} duration which is larger than maximum allowed {} milliseconds, or roughly {} days",
-                self.name,
-                duration_name,
-                i32::MAX,
-                i32::MAX / 1000 / 60 / 60 / 24
-            );
+        if let Some(duration) = duration {
+            service_duration_millis(self.name, duration, duration_name);
         }
     }
 }
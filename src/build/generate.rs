@@ -9,6 +9,11 @@ use crate::config::*;
 #[cfg(test)]
 use connate::config::*;
 
+#[cfg(not(test))]
+use crate::constants::UP_TIME_MILLIS;
+#[cfg(test)]
+use connate::constants::UP_TIME_MILLIS;
+
 /// Like write!() but with an extra field that indents by 4x that many spaces.
 macro_rules! iwrite {
     ($writer:expr, $level:expr, $($args:tt)*) => {
@@ -35,11 +40,13 @@ pub trait GenerateInternalConfig: Config {
     {
         let uid_map = get_uid_map();
         let gid_map = get_gid_map();
+        let user_groups_map = get_user_groups_map();
         let svc_map = get_svc_map(Self::SERVICES);
         let svc_i_map = get_svc_index_map(Self::SERVICES);
 
         Self::generate_header(&mut f)?;
         Self::generate_lock_file(&mut f)?;
+        Self::generate_shell(&mut f)?;
         Self::generate_service_count(&mut f)?;
         Self::generate_services_array(&mut f)?;
         Self::generate_services_methods(&mut f)?;
@@ -62,12 +69,15 @@ pub trait GenerateInternalConfig: Config {
             svc.generate_name(&mut f)?;
             svc.generate_index(&mut f, i)?;
             svc.generate_init_target(&mut f)?;
+            svc.generate_start_if_cmdline(&mut f)?;
             //
             // Dependency entries
             //
             svc.generate_needs(&mut f, &svc_i_map)?;
             svc.generate_wants(&mut f, &svc_i_map)?;
             svc.generate_conflicts(&mut f, &svc_i_map)?;
+            svc.generate_conflict_action(&mut f)?;
+            svc.generate_serialize_with(&mut f, &svc_i_map)?;
             svc.generate_stop_dependencies(&mut f, &svc_i_map, &svc_map)?;
             svc.generate_groups(&mut f, &svc_i_map)?;
             svc.generate_target_up_propagate_up(&mut f, &svc_i_map, &svc_map)?;
@@ -82,6 +92,8 @@ pub trait GenerateInternalConfig: Config {
             svc.generate_ready(&mut f)?;
             svc.generate_cleanup(&mut f, i)?;
             svc.generate_stop_all_children(&mut f)?;
+            svc.generate_children_stop(&mut f)?;
+            svc.generate_kill_mode(&mut f)?;
             //
             // Retry and timeout entries
             //
@@ -89,18 +101,33 @@ pub trait GenerateInternalConfig: Config {
             svc.generate_max_ready_time_millis(&mut f)?;
             svc.generate_max_stop_time_millis(&mut f)?;
             svc.generate_max_cleanup_time_millis(&mut f)?;
+            svc.generate_max_wait_time_millis(&mut f)?;
+            svc.generate_up_stable_time_millis(&mut f)?;
             svc.generate_retry_wait_period_millis(&mut f)?;
             svc.generate_retry_wait_multiplier(&mut f)?;
+            svc.generate_retry_max_delay_millis(&mut f)?;
+            svc.generate_retry_jitter_millis(&mut f)?;
             svc.generate_max_attempt_count(&mut f)?;
+            svc.generate_reset_attempts_on(&mut f)?;
+            svc.generate_success_exit_codes(&mut f)?;
+            svc.generate_fatal_exit_codes(&mut f)?;
             //
             // Execution attribute entries
             //
+            svc.generate_stdin(&mut f)?;
             svc.generate_log(&mut f, &svc_i_map)?;
+            svc.generate_env(&mut f)?;
+            svc.generate_clear_env(&mut f)?;
+            svc.generate_inherit_env(&mut f)?;
             svc.generate_is_logger(&mut f, &svc_map)?;
             svc.generate_uid(&mut f, &uid_map)?;
-            svc.generate_gid(&mut f, &gid_map)?;
+            svc.generate_gid(&mut f, &gid_map, &user_groups_map)?;
+            svc.generate_supplementary_gids(&mut f, &user_groups_map)?;
             svc.generate_no_new_privs(&mut f)?;
+            svc.generate_new_session(&mut f)?;
+            svc.generate_controlling_tty(&mut f)?;
             svc.generate_chdir(&mut f)?;
+            svc.generate_cgroup(&mut f)?;
             writeln!(f, "}};")?;
         }
 
@@ -126,12 +153,29 @@ use core::ffi::CStr;"#
         }
     }
 
+    fn generate_shell(f: &mut File) -> Result<()> {
+        writeln!(f)?;
+        // Only referenced by services using `Run::Shell`
+        writeln!(f, "#[allow(dead_code)]")?;
+        writeln!(f, "const SHELL: &CStr = c{:?};", Self::SHELL)
+    }
+
     fn generate_service_count(f: &mut File) -> Result<()> {
         writeln!(f)?;
         writeln!(
             f,
             "pub const SERVICE_COUNT: usize = {};",
             Self::SERVICES.len()
+        )?;
+        // Only referenced by conctl, to cache every service's name
+        writeln!(f, "#[allow(dead_code)]")?;
+        writeln!(
+            f,
+            "pub const SERVICE_NAMES_LEN: usize = {};",
+            Self::SERVICES
+                .iter()
+                .map(|svc| svc.name.len())
+                .sum::<usize>()
         )
     }
 
@@ -179,9 +223,13 @@ impl StaticServices {{
     /// Additionally, this method assumes that the inner storage has not been accessed
     /// or modified through any other means, and that calling this function establishes
     /// the initial state of the services array for the lifetime of the program.
-    pub const unsafe fn initialize(
+    ///
+    /// Services whose `start_if_cmdline` parameter is absent from the kernel command line, or
+    /// from `argv` if /proc is not mounted, start with a `Down` target.
+    pub unsafe fn initialize(
         &self,
         now: connate::types::timespec,
+        argv: &connate::os::Argv,
     ) -> &'static mut [Service; SERVICE_COUNT] {{
         unsafe {{
             let ptr = self.inner.0.get();
@@ -202,9 +250,18 @@ impl StaticServices {{
             iwriteln!(f, 4, "pid: None,")?;
             iwriteln!(f, 4, "supervisor_pid: None,")?;
             iwriteln!(f, 4, "stdin_pipe: None,")?;
+            iwriteln!(f, 4, "console_pipe: None,")?;
             iwriteln!(f, 4, "attempt_count: 0,")?;
+            iwriteln!(f, 4, "retry_jitter_millis: 0,")?;
             iwriteln!(f, 4, "exit_code: None,")?;
+            iwriteln!(f, 4, "exit_expected: false,")?;
+            iwriteln!(f, 4, "has_run: false,")?;
+            iwriteln!(f, 4, "fail_reason: None,")?;
+            iwriteln!(f, 4, "progress: None,")?;
+            iwriteln!(f, 4, "blocked_by: None,")?;
             iwriteln!(f, 4, "time: now,")?;
+            iwriteln!(f, 4, "start_time: now,")?;
+            iwriteln!(f, 4, "start_duration_millis: None,")?;
             iwriteln!(f, 4, "ready: false,")?;
             iwriteln!(f, 4, "dirty: true,")?;
             #[cfg(feature = "settle")]
@@ -216,7 +273,9 @@ impl StaticServices {{
         writeln!(
             f,
             r#"
-           services_uninit.assume_init_mut()
+           let services = services_uninit.assume_init_mut();
+           connate::supervisor::apply_start_if_cmdline(services, argv);
+           services
        }}
    }}
 }}"#
@@ -227,22 +286,19 @@ impl StaticServices {{
         writeln!(
             f,
             r#"
-/// Methods to find service leveraging compile-time knowledge of service names
+/// Maps service names to indices leveraging compile-time knowledge of service names
 ///
 /// At the time of writing, compiles down to a decision tree that is faster than O(n) search across
 /// array of services.
-pub trait ServiceArrayFind {{
-    fn find_by_name(&self, name: &[u8]) -> Option<&Service>;
-    fn find_by_name_mut(&mut self, name: &[u8]) -> Option<&mut Service>;
-}}
+pub struct ServiceNames;
 
-impl<const N: usize> ServiceArrayFind for [Service; N] {{
-    fn find_by_name(&self, name: &[u8]) -> Option<&Service> {{
+impl ServiceLookup for ServiceNames {{
+    fn index_of(name: &[u8]) -> Option<usize> {{
         match name {{"#
         )?;
 
         for (i, svc) in Self::SERVICES.iter().enumerate() {
-            iwriteln!(f, 3, "b{:?} => Some(&self[{i}]),", svc.name)?;
+            iwriteln!(f, 3, "b{:?} => Some({i}),", svc.name)?;
         }
 
         writeln!(
@@ -251,29 +307,45 @@ impl<const N: usize> ServiceArrayFind for [Service; N] {{
         }}
     }}
 
-    fn find_by_name_mut(&mut self, name: &[u8]) -> Option<&mut Service> {{
+    // With no TARGETS configured, only the fallback arm remains
+    #[allow(clippy::match_single_binding)]
+    fn boot_target(name: &[u8]) -> Option<&'static [usize]> {{
         match name {{"#
         )?;
 
-        for (i, svc) in Self::SERVICES.iter().enumerate() {
-            iwriteln!(f, 3, "b{:?} => Some(&mut self[{i}]),", svc.name)?;
+        let svc_i_map = get_svc_index_map(Self::SERVICES);
+        for (name, members) in Self::TARGETS {
+            let indices: Vec<String> = members
+                .iter()
+                .map(|member| svc_i_map[member].to_string())
+                .collect();
+            iwriteln!(f, 3, "b{:?} => Some(&[{}]),", name, indices.join(", "))?;
         }
 
+        let emergency: Vec<String> = Self::EMERGENCY_SERVICES
+            .iter()
+            .map(|member| svc_i_map[member].to_string())
+            .collect();
         writeln!(
             f,
             r#"            _ => None,
         }}
     }}
-}}"#
+
+    fn emergency_target() -> &'static [usize] {{
+        &[{}]
+    }}
+}}"#,
+            emergency.join(", ")
         )
     }
 }
 
 impl Service {
     fn generate_env_cstr(&self, f: &mut File, i: usize) -> Result<()> {
-        let needs_env = matches!(self.setup, Run::Exec(_) | Run::Shell(_))
-            || matches!(self.run, Run::Exec(_) | Run::Shell(_))
-            || matches!(self.cleanup, Run::Exec(_) | Run::Shell(_));
+        let needs_env = [&self.setup, &self.run, &self.cleanup]
+            .iter()
+            .any(|run| matches!(run, Run::Exec(_) | Run::Shell(_) | Run::Script { .. }));
 
         if !needs_env {
             return Ok(());
@@ -288,7 +360,9 @@ impl Service {
     fn generate_setup_cstr(&self, f: &mut File, i: usize) -> Result<()> {
         match self.setup {
             Run::None => {}
-            Run::Exec(args) => {
+            Run::Exec(_) | Run::Script { .. } => {
+                let args = exec_args(&self.setup);
+
                 // Generate execve() arguments:
                 // - pathname
                 // - argv
@@ -335,10 +409,10 @@ impl Service {
                 // - envp
 
                 // pathname
-                writeln!(f, "const SERVICE{i}_SETUP_PATHNAME: &CStr = c\"/bin/sh\";",)?;
+                writeln!(f, "const SERVICE{i}_SETUP_PATHNAME: &CStr = SHELL;")?;
 
                 // argv
-                writeln!(f, "const SERVICE{i}_SETUP_ARG0: &CStr = c\"/bin/sh\";")?;
+                writeln!(f, "const SERVICE{i}_SETUP_ARG0: &CStr = SHELL;")?;
                 writeln!(f, "const SERVICE{i}_SETUP_ARG1: &CStr = c\"-c\";")?;
                 writeln!(f, "const SERVICE{i}_SETUP_ARG2: &CStr = c{cmd:?};")?;
                 writeln!(
@@ -371,7 +445,9 @@ impl Service {
     fn generate_run_cstr(&self, f: &mut File, i: usize) -> Result<()> {
         match self.run {
             Run::None => {}
-            Run::Exec(args) => {
+            Run::Exec(_) | Run::Script { .. } => {
+                let args = exec_args(&self.run);
+
                 // Generate execve() arguments:
                 // - pathname
                 // - argv
@@ -414,10 +490,10 @@ impl Service {
                 // - envp
 
                 // pathname
-                writeln!(f, "const SERVICE{i}_RUN_PATHNAME: &CStr = c\"/bin/sh\";",)?;
+                writeln!(f, "const SERVICE{i}_RUN_PATHNAME: &CStr = SHELL;")?;
 
                 // argv
-                writeln!(f, "const SERVICE{i}_RUN_ARG0: &CStr = c\"/bin/sh\";")?;
+                writeln!(f, "const SERVICE{i}_RUN_ARG0: &CStr = SHELL;")?;
                 writeln!(f, "const SERVICE{i}_RUN_ARG1: &CStr = c\"-c\";")?;
                 writeln!(f, "const SERVICE{i}_RUN_ARG2: &CStr = c{cmd:?};")?;
                 writeln!(
@@ -450,7 +526,9 @@ impl Service {
     fn generate_cleanup_cstr(&self, f: &mut File, i: usize) -> Result<()> {
         match self.cleanup {
             Run::None => {}
-            Run::Exec(args) => {
+            Run::Exec(_) | Run::Script { .. } => {
+                let args = exec_args(&self.cleanup);
+
                 // Generate execve() arguments:
                 // - pathname
                 // - argv
@@ -497,13 +575,10 @@ impl Service {
                 // - envp
 
                 // pathname
-                writeln!(
-                    f,
-                    "const SERVICE{i}_CLEANUP_PATHNAME: &CStr = c\"/bin/sh\";",
-                )?;
+                writeln!(f, "const SERVICE{i}_CLEANUP_PATHNAME: &CStr = SHELL;")?;
 
                 // argv
-                writeln!(f, "const SERVICE{i}_CLEANUP_ARG0: &CStr = c\"/bin/sh\";")?;
+                writeln!(f, "const SERVICE{i}_CLEANUP_ARG0: &CStr = SHELL;")?;
                 writeln!(f, "const SERVICE{i}_CLEANUP_ARG1: &CStr = c\"-c\";")?;
                 writeln!(f, "const SERVICE{i}_CLEANUP_ARG2: &CStr = c{cmd:?};")?;
                 writeln!(
@@ -554,6 +629,13 @@ impl Service {
         }
     }
 
+    fn generate_start_if_cmdline(&self, f: &mut File) -> Result<()> {
+        match self.start_if_cmdline {
+            Some(param) => iwriteln!(f, 1, "start_if_cmdline: Some(b{:?}),", param),
+            None => iwriteln!(f, 1, "start_if_cmdline: None,"),
+        }
+    }
+
     //
     // Dependency entries
     //
@@ -601,6 +683,30 @@ impl Service {
         iwriteln!(f, 1, "],")
     }
 
+    fn generate_conflict_action(&self, f: &mut File) -> Result<()> {
+        match self.conflict_action {
+            ConflictAction::Wait => iwriteln!(f, 1, "conflict_action: ConflictAction::Wait,"),
+            ConflictAction::Stop => iwriteln!(f, 1, "conflict_action: ConflictAction::Stop,"),
+        }
+    }
+
+    fn generate_serialize_with(
+        &self,
+        f: &mut File,
+        svc_i_map: &HashMap<&'static str, usize>,
+    ) -> Result<()> {
+        if self.serialize_with.is_empty() {
+            iwriteln!(f, 1, "serialize_with: &[],")?;
+            return Ok(());
+        }
+
+        iwriteln!(f, 1, "serialize_with: &[")?;
+        for dep in self.serialize_with {
+            iwriteln!(f, 2, "{}, // {}", svc_i_map[dep], dep)?;
+        }
+        iwriteln!(f, 1, "],")
+    }
+
     fn generate_stop_dependencies(
         &self,
         f: &mut File,
@@ -614,6 +720,11 @@ impl Service {
             if self.name == svc.name {
                 continue;
             }
+            // Everything else stops before a service which stops last
+            if self.stop_last {
+                deps.push((svc_i_map[svc.name], svc.name));
+                continue;
+            }
             // Services that need this service
             if svc.needs.contains(&self.name) {
                 deps.push((svc_i_map[svc.name], svc.name));
@@ -816,11 +927,16 @@ impl Service {
         deps.extend_from_slice(self.groups);
 
         // For every service in the configuration, if it depends on self via its
-        // needs, wants, or log (Service variant) fields, add it as a reverse dependency.
+        // needs, wants, or log (Service variant) fields, add it as a reverse dependency.  A
+        // service which stops last is treated as though everything depends on it.
         for (_, svc) in svc_map.iter() {
             if svc.name == self.name {
                 continue;
             }
+            if self.stop_last {
+                deps.push(svc.name);
+                continue;
+            }
             if svc.needs.contains(&self.name) {
                 deps.push(svc.name);
             }
@@ -853,6 +969,11 @@ impl Service {
         }
 
         for (_, svc) in svc_map.iter() {
+            // A service which stops last waits on every other service to stop
+            if svc.stop_last && svc.name != self.name {
+                deps.insert(svc.name);
+                continue;
+            }
             if svc.needs.contains(&self.name) {
                 deps.insert(svc.name);
                 continue;
@@ -865,6 +986,10 @@ impl Service {
                 deps.insert(svc.name);
                 continue;
             }
+            if svc.serialize_with.contains(&self.name) {
+                deps.insert(svc.name);
+                continue;
+            }
             if let Log::Service(log_service) = &svc.log
                 && *log_service == self.name
             {
@@ -894,30 +1019,58 @@ impl Service {
     //
 
     fn generate_max_setup_time_millis(&self, f: &mut File) -> Result<()> {
-        let max = self.max_setup_time.map(|dur| dur.as_millis() as i32);
+        let max = self
+            .max_setup_time
+            .map(|dur| service_duration_millis(self.name, dur, "max_setup_time"));
         iwriteln!(f, 1, "max_setup_time_millis: {:?},", max)
     }
 
     fn generate_max_ready_time_millis(&self, f: &mut File) -> Result<()> {
-        let max = self.max_ready_time.map(|dur| dur.as_millis() as i32);
+        let max = self
+            .max_ready_time
+            .map(|dur| service_duration_millis(self.name, dur, "max_ready_time"));
         iwriteln!(f, 1, "max_ready_time_millis: {:?},", max)
     }
 
     fn generate_max_stop_time_millis(&self, f: &mut File) -> Result<()> {
-        let max = self.max_stop_time.map(|dur| dur.as_millis() as i32);
+        let max = self
+            .max_stop_time
+            .map(|dur| service_duration_millis(self.name, dur, "max_stop_time"));
         iwriteln!(f, 1, "max_stop_time_millis: {:?},", max)
     }
 
     fn generate_max_cleanup_time_millis(&self, f: &mut File) -> Result<()> {
-        let max = self.max_cleanup_time.map(|dur| dur.as_millis() as i32);
+        let max = self
+            .max_cleanup_time
+            .map(|dur| service_duration_millis(self.name, dur, "max_cleanup_time"));
         iwriteln!(f, 1, "max_cleanup_time_millis: {:?},", max)
     }
 
+    fn generate_max_wait_time_millis(&self, f: &mut File) -> Result<()> {
+        let max = self
+            .max_wait_time
+            .map(|dur| service_duration_millis(self.name, dur, "max_wait_time"));
+        iwriteln!(f, 1, "max_wait_time_millis: {:?},", max)
+    }
+
+    fn generate_up_stable_time_millis(&self, f: &mut File) -> Result<()> {
+        let millis = match self.up_stable_time {
+            Some(dur) => service_duration_millis(self.name, dur, "up_stable_time"),
+            None => UP_TIME_MILLIS as i32,
+        };
+        iwriteln!(f, 1, "up_stable_time_millis: {},", millis)
+    }
+
     fn generate_retry_wait_period_millis(&self, f: &mut File) -> Result<()> {
         let delay = match self.retry {
             Retry::Never => 0, // Doesn't matter, value is effectively ignored
-            Retry::AfterFixed { after, .. } => after.as_millis() as i32,
-            Retry::AfterDoublingDelay { initial_delay, .. } => initial_delay.as_millis() as i32,
+            Retry::Immediate { .. } => 0,
+            Retry::AfterFixed { after, .. } => {
+                service_duration_millis(self.name, after, "retry after")
+            }
+            Retry::AfterDoublingDelay { initial_delay, .. } => {
+                service_duration_millis(self.name, initial_delay, "retry initial_delay")
+            }
         };
         iwriteln!(f, 1, "retry_wait_period_millis: {},", delay)
     }
@@ -925,15 +1078,38 @@ impl Service {
     fn generate_retry_wait_multiplier(&self, f: &mut File) -> Result<()> {
         let multiplier = match self.retry {
             Retry::Never => 0, // Doesn't matter, value is effectively ignored
-            Retry::AfterFixed { .. } => 1,
+            Retry::Immediate { .. } | Retry::AfterFixed { .. } => 1,
             Retry::AfterDoublingDelay { .. } => 2,
         };
         iwriteln!(f, 1, "retry_wait_multiplier: {},", multiplier)
     }
 
+    fn generate_retry_max_delay_millis(&self, f: &mut File) -> Result<()> {
+        let max = match self.retry {
+            Retry::Never | Retry::Immediate { .. } | Retry::AfterFixed { .. } => None,
+            Retry::AfterDoublingDelay { max_delay, .. } => {
+                max_delay.map(|dur| service_duration_millis(self.name, dur, "retry max_delay"))
+            }
+        };
+        iwriteln!(f, 1, "max_delay_millis: {:?},", max)
+    }
+
+    fn generate_retry_jitter_millis(&self, f: &mut File) -> Result<()> {
+        let jitter = match self.retry {
+            Retry::Never | Retry::Immediate { .. } => None,
+            Retry::AfterFixed { jitter, .. } => jitter,
+            Retry::AfterDoublingDelay { jitter, .. } => jitter,
+        };
+        let jitter = jitter.map_or(0, |dur| {
+            service_duration_millis(self.name, dur, "retry jitter")
+        });
+        iwriteln!(f, 1, "retry_jitter_millis: {},", jitter)
+    }
+
     fn generate_max_attempt_count(&self, f: &mut File) -> Result<()> {
         let max = match self.retry {
             Retry::Never => Some(0),
+            Retry::Immediate { max_attempt_count } => max_attempt_count,
             Retry::AfterFixed {
                 max_attempt_count, ..
             } => max_attempt_count,
@@ -944,6 +1120,26 @@ impl Service {
         iwriteln!(f, 1, "max_attempt_count: {:?},", max)
     }
 
+    fn generate_reset_attempts_on(&self, f: &mut File) -> Result<()> {
+        match self.reset_attempts_on {
+            ResetPolicy::UpStable => {
+                iwriteln!(f, 1, "reset_attempts_on: ResetPolicy::UpStable,")
+            }
+            ResetPolicy::CleanExit => {
+                iwriteln!(f, 1, "reset_attempts_on: ResetPolicy::CleanExit,")
+            }
+            ResetPolicy::Never => iwriteln!(f, 1, "reset_attempts_on: ResetPolicy::Never,"),
+        }
+    }
+
+    fn generate_success_exit_codes(&self, f: &mut File) -> Result<()> {
+        iwriteln!(f, 1, "success_exit_codes: &{:?},", self.success_exit_codes)
+    }
+
+    fn generate_fatal_exit_codes(&self, f: &mut File) -> Result<()> {
+        iwriteln!(f, 1, "fatal_exit_codes: &{:?},", self.fatal_exit_codes)
+    }
+
     //
     // Execution entries
     //
@@ -951,7 +1147,7 @@ impl Service {
     fn generate_setup(&self, f: &mut File, i: usize) -> Result<()> {
         match self.setup {
             Run::None => iwriteln!(f, 1, "setup: Run::None,"),
-            Run::Exec(_) | Run::Shell(_) => {
+            Run::Exec(_) | Run::Shell(_) | Run::Script { .. } => {
                 iwriteln!(f, 1, "setup: Run::Exec {{")?;
                 iwriteln!(f, 2, "pathname: SERVICE{i}_SETUP_PATHNAME,")?;
                 iwriteln!(f, 2, "argv: SERVICE{i}_SETUP_ARGV.as_ptr(),")?;
@@ -990,7 +1186,7 @@ impl Service {
     fn generate_run(&self, f: &mut File, i: usize) -> Result<()> {
         match self.run {
             Run::None => iwriteln!(f, 1, "run: Run::None,"),
-            Run::Exec(_) | Run::Shell(_) => {
+            Run::Exec(_) | Run::Shell(_) | Run::Script { .. } => {
                 iwriteln!(f, 1, "run: Run::Exec {{")?;
                 iwriteln!(f, 2, "pathname: SERVICE{i}_RUN_PATHNAME,")?;
                 iwriteln!(f, 2, "argv: SERVICE{i}_RUN_ARGV.as_ptr(),")?;
@@ -1037,7 +1233,7 @@ impl Service {
     fn generate_cleanup(&self, f: &mut File, i: usize) -> Result<()> {
         match self.cleanup {
             Run::None => iwriteln!(f, 1, "cleanup: Run::None,"),
-            Run::Exec(_) | Run::Shell(_) => {
+            Run::Exec(_) | Run::Shell(_) | Run::Script { .. } => {
                 iwriteln!(f, 1, "cleanup: Run::Exec {{")?;
                 iwriteln!(f, 2, "pathname: SERVICE{i}_CLEANUP_PATHNAME,")?;
                 iwriteln!(f, 2, "argv: SERVICE{i}_CLEANUP_ARGV.as_ptr(),")?;
@@ -1079,10 +1275,42 @@ impl Service {
         iwriteln!(f, 1, "stop_all_children: {},", self.stop_all_children)
     }
 
+    fn generate_children_stop(&self, f: &mut File) -> Result<()> {
+        let (signal, time) = match self.children_stop {
+            ChildrenStop::Kill => ("SIGKILL", 0),
+            ChildrenStop::Signal(signal, time) => (
+                signal.name(),
+                service_duration_millis(self.name, time, "children_stop"),
+            ),
+        };
+        iwriteln!(
+            f,
+            1,
+            "children_stop_signal: connate::types::Signal::{signal},"
+        )?;
+        iwriteln!(f, 1, "children_stop_time_millis: {time},")
+    }
+
+    fn generate_kill_mode(&self, f: &mut File) -> Result<()> {
+        match self.kill_mode {
+            KillMode::Process => iwriteln!(f, 1, "kill_mode: KillMode::Process,"),
+            KillMode::ControlGroup => iwriteln!(f, 1, "kill_mode: KillMode::ControlGroup,"),
+            KillMode::Mixed => iwriteln!(f, 1, "kill_mode: KillMode::Mixed,"),
+        }
+    }
+
     //
     // Execution attribute entries
     //
 
+    fn generate_stdin(&self, f: &mut File) -> Result<()> {
+        match self.stdin {
+            StdinSource::Null => iwriteln!(f, 1, "stdin: StdinSource::Null,"),
+            StdinSource::Inherit => iwriteln!(f, 1, "stdin: StdinSource::Inherit,"),
+            StdinSource::File(path) => iwriteln!(f, 1, "stdin: StdinSource::File(c{path:?}),"),
+        }
+    }
+
     fn generate_log(&self, f: &mut File, svc_i_map: &HashMap<&'static str, usize>) -> Result<()> {
         iwrite!(f, 1, "log: ")?;
         match &self.log {
@@ -1094,7 +1322,7 @@ impl Service {
                 permissions,
             } => {
                 writeln!(f, "Log::File {{")?;
-                iwriteln!(f, 3, "filepath: c{path:?},")?;
+                iwriteln!(f, 3, "filepath: c{:?},", log_file_path(self, path))?;
                 match permissions {
                     FilePerm::Public => {
                         iwriteln!(f, 3, "mode: 0o644,")?;
@@ -1102,6 +1330,9 @@ impl Service {
                     FilePerm::Private => {
                         iwriteln!(f, 3, "mode: 0o600,")?;
                     }
+                    FilePerm::Mode(mode) => {
+                        iwriteln!(f, 3, "mode: 0o{mode:o},")?;
+                    }
                 };
                 iwriteln!(f, 2, "}},")?;
             }
@@ -1133,10 +1364,35 @@ impl Service {
         }
     }
 
-    fn generate_gid(&self, f: &mut File, gid_map: &HashMap<String, u32>) -> Result<()> {
-        match self.group {
-            Some(group) => iwriteln!(f, 1, "gid: Some({}),", gid_map[group]),
-            None => iwriteln!(f, 1, "gid: None,"),
+    fn generate_gid(
+        &self,
+        f: &mut File,
+        gid_map: &HashMap<String, u32>,
+        user_groups_map: &HashMap<String, Vec<u32>>,
+    ) -> Result<()> {
+        match (self.group, self.user) {
+            (Some(group), _) => iwriteln!(f, 1, "gid: Some({}),", gid_map[group]),
+            // Fall back to the user's primary group, which leads its group list
+            (None, Some(user)) if self.use_user_groups => {
+                iwriteln!(f, 1, "gid: Some({}),", user_groups_map[user][0])
+            }
+            (None, _) => iwriteln!(f, 1, "gid: None,"),
+        }
+    }
+
+    fn generate_supplementary_gids(
+        &self,
+        f: &mut File,
+        user_groups_map: &HashMap<String, Vec<u32>>,
+    ) -> Result<()> {
+        match self.user {
+            Some(user) if self.use_user_groups => iwriteln!(
+                f,
+                1,
+                "supplementary_gids: Some(&{:?}),",
+                user_groups_map[user]
+            ),
+            _ => iwriteln!(f, 1, "supplementary_gids: None,"),
         }
     }
 
@@ -1144,10 +1400,52 @@ impl Service {
         iwriteln!(f, 1, "no_new_privs: {},", self.no_new_privs)
     }
 
+    fn generate_new_session(&self, f: &mut File) -> Result<()> {
+        iwriteln!(f, 1, "new_session: {},", self.new_session)
+    }
+
+    fn generate_controlling_tty(&self, f: &mut File) -> Result<()> {
+        match self.controlling_tty {
+            Some(path) => iwriteln!(f, 1, "controlling_tty: Some(c{:?}),", path),
+            None => iwriteln!(f, 1, "controlling_tty: None,"),
+        }
+    }
+
     fn generate_chdir(&self, f: &mut File) -> Result<()> {
         match self.chdir {
             Some(path) => iwriteln!(f, 1, "chdir: Some(c{:?}),", path),
             None => iwriteln!(f, 1, "chdir: None,"),
         }
     }
+
+    fn generate_env(&self, f: &mut File) -> Result<()> {
+        iwrite!(f, 1, "env: &[")?;
+        for var in self.env {
+            write!(f, "c{var:?}, ")?;
+        }
+        writeln!(f, "],")
+    }
+
+    fn generate_clear_env(&self, f: &mut File) -> Result<()> {
+        iwriteln!(f, 1, "clear_env: {},", self.clear_env)
+    }
+
+    fn generate_inherit_env(&self, f: &mut File) -> Result<()> {
+        if self.inherit_env.is_empty() {
+            return iwriteln!(f, 1, "inherit_env: &[],");
+        }
+
+        iwriteln!(f, 1, "inherit_env: &[")?;
+        for var in self.inherit_env {
+            iwriteln!(f, 2, "b{:?},", var)?;
+        }
+        iwriteln!(f, 1, "],")
+    }
+
+    fn generate_cgroup(&self, f: &mut File) -> Result<()> {
+        match self.cgroup {
+            Some(path) => iwriteln!(f, 1, "cgroup: Some(c{:?}),", path),
+            None => iwriteln!(f, 1, "cgroup: None,"),
+        }
+    }
 }
@@ -1,8 +1,6 @@
-use connate::constants::*;
-use connate::err::*;
+use super::ServiceNames;
 use connate::ipc::*;
 use connate::os::*;
-use connate::util::BufWriter;
 
 /// Generic helper for commands that query dependencies (needs, wants, conflicts, groups)
 pub fn query_dependencies<F>(mut ipc_client: IpcClient, mut argv: Argv, request_fn: F) -> !
@@ -16,55 +14,15 @@ where
             // - Query all services
             // - By index, since we don't have the names up-front
             // - Print service name to associate data with service
-            let mut max_name_len: usize = 0;
-            let mut service_count: usize = 0;
+            let names = ServiceNames::fetch(&mut ipc_client);
+            failed |= names.failed;
 
-            // First pass: find max name length
-            for i in 0.. {
-                match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
-                    Response::Name(name) => {
-                        max_name_len = core::cmp::max(max_name_len, name.len());
-                        service_count = i + 1;
-                    }
-                    Response::ServiceNotFound => break,
-                    response => {
-                        failed |= response.cmd_return_failed();
-                        break;
-                    }
-                }
-            }
-
-            // Second pass: print with padding
-            let mut name_buf = [0u8; MSG_SVC_NAME_SIZE];
-            for i in 0..service_count {
-                match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
-                    Response::Name(name) => {
-                        // We can't re-use the provided name, as it's a buffer in `ipc_client`
-                        // that's holding it as mut.
-                        //
-                        // Copy it out.
-                        let mut writer = BufWriter::new(&mut name_buf);
-                        writer
-                            .push(name)
-                            .or_abort("Unable to deserialize response from connate");
-                        let name_len = name.len();
-                        let name = name_buf
-                            .get(..name_len)
-                            .ok_or(Errno::EINVAL)
-                            .or_abort("Unable to deserialize response from connate");
-
-                        print_color(Color::Service, name);
-                        print_color(Color::Glue, ":");
-                        name.print_padding(max_name_len + 1);
-                        failed |= query_deps_for_service(&mut ipc_client, name, &request_fn);
-                    }
-                    Response::ServiceNotFound => break,
-                    response => {
-                        failed |= response.cmd_return_failed();
-                        break;
-                    }
-                }
-            }
+            failed |= names.for_each(&mut ipc_client, |ipc_client, _, name| {
+                print_color(Color::Service, name);
+                print_color(Color::Glue, ":");
+                name.print_padding(names.max_len + 1);
+                failed |= query_deps_for_service(ipc_client, name, &request_fn);
+            });
         }
         Some(name) if argv.is_empty() => {
             // - Query single service
@@ -101,7 +59,7 @@ where
 
 /// Helper to query and print dependencies for a single service
 /// Returns "failed": true if service not found, false if service exists
-fn query_deps_for_service<F>(ipc_client: &mut IpcClient, name: &[u8], request_fn: &F) -> bool
+pub fn query_deps_for_service<F>(ipc_client: &mut IpcClient, name: &[u8], request_fn: &F) -> bool
 where
     F: for<'a> Fn(&'a [u8], usize) -> Request<'a>,
 {
@@ -163,3 +121,17 @@ pub fn cmd_groups(ipc_client: IpcClient, argv: Argv) -> ! {
         Request::QueryGroups(idx, name)
     })
 }
+
+#[inline]
+pub fn cmd_blockers(ipc_client: IpcClient, argv: Argv) -> ! {
+    query_dependencies(ipc_client, argv, |name, idx| {
+        Request::QueryBlockers(idx, name)
+    })
+}
+
+#[inline]
+pub fn cmd_logs_for(ipc_client: IpcClient, argv: Argv) -> ! {
+    query_dependencies(ipc_client, argv, |name, idx| {
+        Request::QueryLogsFor(idx, name)
+    })
+}
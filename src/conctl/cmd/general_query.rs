@@ -1,132 +1,273 @@
+use super::{ServiceNames, for_each_status, query_deps_for_service};
+use crate::internal::SERVICE_COUNT;
+use connate::constants::{MSG_SVC_NAME_SIZE, PROGRESS_MSG_SIZE};
+use connate::err::*;
+use connate::internal_api::ConfigField;
 use connate::ipc::*;
 use connate::os::*;
+use connate::util::{BufWriter, fmt_millis};
+
+/// Progress reported by the services `status` prints, by index
+///
+/// Fetched between `status`'s passes over every service, as the IPC client is busy during them.
+struct ProgressMessages {
+    msgs: [[u8; PROGRESS_MSG_SIZE]; SERVICE_COUNT],
+    lens: [usize; SERVICE_COUNT],
+}
+
+impl ProgressMessages {
+    fn new() -> Self {
+        Self {
+            msgs: [[0; PROGRESS_MSG_SIZE]; SERVICE_COUNT],
+            lens: [0; SERVICE_COUNT],
+        }
+    }
+
+    fn fetch(&mut self, ipc_client: &mut IpcClient, i: usize) {
+        // Copied out, as the progress query reuses the response buffer
+        let mut name_buf = [0u8; MSG_SVC_NAME_SIZE];
+        let len = match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
+            Response::Name(name) => {
+                for (dst, &src) in name_buf.iter_mut().zip(name) {
+                    *dst = src;
+                }
+                name.len()
+            }
+            _ => return,
+        };
+        let name = name_buf.get(..len).unwrap_or(&[]);
+
+        if let Response::Progress(msg) = ipc_client.send_and_receive(Request::QueryProgress(name))
+            && let (Some(dst), Some(dst_len)) = (self.msgs.get_mut(i), self.lens.get_mut(i))
+        {
+            for (dst, &src) in dst.iter_mut().zip(msg) {
+                *dst = src;
+            }
+            *dst_len = core::cmp::min(msg.len(), PROGRESS_MSG_SIZE);
+        }
+    }
+
+    fn get(&self, i: usize) -> &[u8] {
+        match (self.msgs.get(i), self.lens.get(i)) {
+            (Some(msg), Some(&len)) => msg.get(..len).unwrap_or(&[]),
+            _ => &[],
+        }
+    }
+}
+
+/// Follow a status with what the service last reported it is doing, if anything
+fn print_progress(msg: &[u8]) {
+    if !msg.is_empty() {
+        print("  ");
+        print_color(Color::Dim, msg);
+    }
+}
+
+/// Whether a status is of a service which may have reported progress
+fn may_report_progress(response: &Response) -> bool {
+    matches!(response, Response::Status(state, ..) if state.reports_progress())
+}
+
+/// Query what the named service last reported it is doing
+fn query_progress<'a>(ipc_client: &'a mut IpcClient, name: &[u8]) -> &'a [u8] {
+    match ipc_client.send_and_receive(Request::QueryProgress(name)) {
+        Response::Progress(msg) => msg,
+        // Nothing reported, or connate predates progress
+        _ => &[],
+    }
+}
 
 pub fn cmd_status(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
     use Color::*;
     let mut failed = false;
+    let filter = StatusFilter::parse(&mut argv);
+    // Whether any service was printed.  `--failed` doubles as a health check by exiting non-zero
+    // if so.
+    let mut printed = false;
 
     match argv.pop() {
         None => {
             // - Query all services
-            // - By index, since we don't have the names up-front
+            // - In bulk, since we don't have the names up-front
             // - Print service name to associate data with service
             let mut max_name_len: usize = 0;
             let mut status_widths = StatusWidths::default();
-            let mut service_count: usize = 0;
-
-            // First pass: find field widths for padding
-            for i in 0.. {
-                match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
-                    Response::Name(name) => {
-                        max_name_len = core::cmp::max(max_name_len, name.len());
-                        let response = ipc_client.send_and_receive(Request::QueryByIndexStatus(i));
-                        if let Some((s, t, p, r)) = response.status_field_lens() {
-                            status_widths.update(s, t, p, r);
-                        }
-                        service_count += 1;
+            let mut reporting = [false; SERVICE_COUNT];
+
+            // First pass: find field widths for padding over the services which will be printed
+            let mut i: usize = 0;
+            failed |= for_each_status(&mut ipc_client, |name, response| {
+                if filter.matches(&response) {
+                    max_name_len = core::cmp::max(max_name_len, name.len());
+                    if let Some((s, t, p, r)) = response.status_field_lens() {
+                        status_widths.update(s, t, p, r);
                     }
-                    Response::ServiceNotFound => break,
-                    response => {
-                        failed |= response.cmd_return_failed();
-                        break;
+                    if let Some(reporting) = reporting.get_mut(i) {
+                        *reporting = may_report_progress(&response);
                     }
                 }
+                i += 1;
+            });
+
+            let mut progress = ProgressMessages::new();
+            for (i, _) in reporting.iter().enumerate().filter(|(_, r)| **r) {
+                progress.fetch(&mut ipc_client, i);
             }
 
             // Second pass: print with padding
-            for i in 0..service_count {
-                match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
-                    Response::Name(name) => {
-                        print_color(Service, name);
-                        print_color(Glue, ":");
-                        name.print_padding(max_name_len + 1);
-                        let response = ipc_client.send_and_receive(Request::QueryByIndexStatus(i));
-                        failed |= response.cmd_return_failed();
-                        response.print_status_padded(&status_widths);
-                        print("\n");
-                    }
-                    Response::ServiceNotFound => break,
-                    response => {
-                        failed |= response.cmd_return_failed();
-                        break;
-                    }
+            let mut i: usize = 0;
+            failed |= for_each_status(&mut ipc_client, |name, response| {
+                let index = i;
+                i += 1;
+                if !filter.matches(&response) {
+                    return;
                 }
-            }
+                print_color(Service, name);
+                print_color(Glue, ":");
+                name.print_padding(max_name_len + 1);
+                response.print_status_padded(&status_widths);
+                print_progress(progress.get(index));
+                print("\n");
+                printed = true;
+            });
         }
         Some(name) if argv.is_empty() => {
             // - Query single service
             // - By name, since we have service name
             // - Don't print service name, since it's obvious from context and simplifies scripting
             let response = ipc_client.send_and_receive(Request::QueryByNameStatus(name.to_bytes()));
-            failed |= response.cmd_return_failed();
-            println(response);
+            if filter.matches(&response) {
+                failed |= response.cmd_return_failed();
+                let reporting = may_report_progress(&response);
+                print(response);
+                if reporting {
+                    print_progress(query_progress(&mut ipc_client, name.to_bytes()));
+                }
+                print("\n");
+                printed = true;
+            }
         }
         Some(first) => {
             // - Query all services
             // - By name, since we have the service names
             // - Print service name to associate data with service
+            // - Each status carries the name it describes rather than relying on response order
+            let first_and_rest = || core::iter::once(first).chain(argv.iter());
 
-            // Calculate max name length from argv
-            let mut max_name_len = first.to_bytes().len();
-            for name in argv.iter() {
-                max_name_len = core::cmp::max(max_name_len, name.to_bytes().len());
-            }
-
-            // First pass: find field widths for padding
+            // First pass: find field widths for padding over the services which will be printed
+            let mut max_name_len: usize = 0;
             let mut status_widths = StatusWidths::default();
-            let first_response =
-                ipc_client.send_and_receive(Request::QueryByNameStatus(first.to_bytes()));
-            if let Some((s, t, p, r)) = first_response.status_field_lens() {
-                status_widths.update(s, t, p, r);
-            }
-            for name in argv.iter() {
-                let response =
-                    ipc_client.send_and_receive(Request::QueryByNameStatus(name.to_bytes()));
-                if let Some((s, t, p, r)) = response.status_field_lens() {
-                    status_widths.update(s, t, p, r);
+            for name in first_and_rest() {
+                let mut response =
+                    ipc_client.send_and_receive(Request::QueryByNameNamedStatus(name.to_bytes()));
+                // Predates named statuses
+                if matches!(response, Response::InvalidRequest) {
+                    response =
+                        ipc_client.send_and_receive(Request::QueryByNameStatus(name.to_bytes()));
+                }
+                let (echoed, response) = response.split_name();
+                if filter.matches(&response) {
+                    let name = echoed.unwrap_or(name.to_bytes());
+                    max_name_len = core::cmp::max(max_name_len, name.len());
+                    if let Some((s, t, p, r)) = response.status_field_lens() {
+                        status_widths.update(s, t, p, r);
+                    }
                 }
             }
 
             // Second pass: print with padding
-            let first_response =
-                ipc_client.send_and_receive(Request::QueryByNameStatus(first.to_bytes()));
-            failed |= first_response.cmd_return_failed();
-            print_color(Service, first.to_bytes());
-            print_color(Glue, ":");
-            first.to_bytes().print_padding(max_name_len + 1);
-            first_response.print_status_padded(&status_widths);
-            print("\n");
-
-            for name in argv.iter() {
-                let response =
-                    ipc_client.send_and_receive(Request::QueryByNameStatus(name.to_bytes()));
+            for name in first_and_rest() {
+                let mut response =
+                    ipc_client.send_and_receive(Request::QueryByNameNamedStatus(name.to_bytes()));
+                // Predates named statuses
+                if matches!(response, Response::InvalidRequest) {
+                    response =
+                        ipc_client.send_and_receive(Request::QueryByNameStatus(name.to_bytes()));
+                }
+                let (echoed, response) = response.split_name();
+                if !filter.matches(&response) {
+                    continue;
+                }
                 failed |= response.cmd_return_failed();
-                print_color(Service, name.to_bytes());
+                let reporting = may_report_progress(&response);
+                let query_name = name.to_bytes();
+                let name = echoed.unwrap_or(query_name);
+                print_color(Service, name);
                 print_color(Glue, ":");
-                name.to_bytes().print_padding(max_name_len + 1);
+                name.print_padding(max_name_len + 1);
                 response.print_status_padded(&status_widths);
+                if reporting {
+                    print_progress(query_progress(&mut ipc_client, query_name));
+                }
                 print("\n");
+                printed = true;
             }
         }
     }
 
-    exit(if failed { 1 } else { 0 });
+    exit(if failed || (filter.failed && printed) {
+        1
+    } else {
+        0
+    });
 }
 
-pub fn cmd_list(mut ipc_client: IpcClient) -> ! {
+/// List service names, optionally alongside their states and/or as JSON
+pub fn cmd_list(mut ipc_client: IpcClient, argv: Argv) -> ! {
+    use Color::*;
+    let mut with_state = false;
+    let mut json = false;
+    for arg in argv.iter() {
+        match arg.to_bytes() {
+            b"--state" => with_state = true,
+            b"--json" => json = true,
+            _ => abort_with_msg("Invalid list flag, expected --state or --json"),
+        }
+    }
+
     let mut failed = false;
-    for i in 0.. {
-        match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
-            Response::Name(name) => {
-                print_color(Color::Service, name);
-                print("\n");
+    if json {
+        let mut count: usize = 0;
+        failed |= for_each_status(&mut ipc_client, |name, response| {
+            print(if count == 0 { "[\n" } else { ",\n" });
+            count += 1;
+            if !with_state {
+                print("  ");
+                print_json_string(name);
+                return;
             }
-            Response::ServiceNotFound => break,
-            response => {
-                failed |= response.cmd_return_failed();
-                break;
+            print("  {\"name\": ");
+            print_json_string(name);
+            print_json_key("state");
+            match response {
+                Response::Status(state, ..) => print_json_string(state.name().as_bytes()),
+                _ => print("null"),
             }
-        }
+            print("}");
+        });
+        print(if count == 0 { "[]\n" } else { "\n]\n" });
+    } else if with_state {
+        // First pass: find name width for padding
+        let mut max_name_len: usize = 0;
+        failed |= for_each_status(&mut ipc_client, |name, _| {
+            max_name_len = core::cmp::max(max_name_len, name.len());
+        });
+
+        // Second pass: print with padding
+        failed |= for_each_status(&mut ipc_client, |name, response| {
+            print_color(Service, name);
+            print_color(Glue, ":");
+            name.print_padding(max_name_len + 1);
+            match response {
+                Response::Status(state, ..) => println(state),
+                response => println(response),
+            }
+        });
+    } else {
+        failed |= for_each_status(&mut ipc_client, |name, _| {
+            print_color(Service, name);
+            print("\n");
+        });
     }
 
     exit(if failed { 1 } else { 0 });
@@ -151,42 +292,17 @@ where
             // - Query all services
             // - By index, since we don't have the names up-front
             // - Print service name to associate data with service
-            let mut max_name_len: usize = 0;
-            let mut service_count: usize = 0;
-
-            // First pass: find max name length
-            for i in 0.. {
-                match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
-                    Response::Name(name) => {
-                        max_name_len = core::cmp::max(max_name_len, name.len());
-                        service_count = i + 1;
-                    }
-                    Response::ServiceNotFound => break,
-                    response => {
-                        failed |= response.cmd_return_failed();
-                        break;
-                    }
-                }
-            }
+            let names = ServiceNames::fetch(&mut ipc_client);
+            failed |= names.failed;
 
-            // Second pass: print with padding
-            for i in 0..service_count {
-                match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
-                    Response::Name(name) => {
-                        print_color(Service, name);
-                        print_color(Glue, ":");
-                        name.print_padding(max_name_len + 1);
-                        let response = ipc_client.send_and_receive(by_index(i));
-                        failed |= response.cmd_return_failed();
-                        println(response);
-                    }
-                    Response::ServiceNotFound => break,
-                    response => {
-                        failed |= response.cmd_return_failed();
-                        break;
-                    }
-                }
-            }
+            failed |= names.for_each(&mut ipc_client, |ipc_client, i, name| {
+                print_color(Service, name);
+                print_color(Glue, ":");
+                name.print_padding(names.max_len + 1);
+                let response = ipc_client.send_and_receive(by_index(i));
+                failed |= response.cmd_return_failed();
+                println(response);
+            });
         }
         Some(name) if argv.is_empty() => {
             // - Query single service
@@ -299,3 +415,431 @@ pub fn cmd_log(ipc_client: IpcClient, argv: Argv) -> ! {
         Request::QueryByNameLog,
     )
 }
+
+/// Print a single field of a single service, without a label, for scripting
+///
+/// Equivalent to `state`, `target`, `pid`, `code`, `attempt`, `time`, or `log` given one service.
+pub fn cmd_get(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
+    let (Some(name), Some(field), true) = (argv.pop(), argv.pop(), argv.is_empty()) else {
+        abort_with_msg("get takes exactly one service and one field, e.g. `get sshd pid`");
+    };
+    let name = name.to_bytes();
+
+    let request = match field.to_bytes() {
+        b"state" => Request::QueryByNameState(name),
+        b"target" => Request::QueryByNameTarget(name),
+        b"pid" => Request::QueryByNamePid(name),
+        b"code" => Request::QueryByNameExitCode(name),
+        b"attempt" => Request::QueryByNameAttemptCount(name),
+        b"time" => Request::QueryByNameTime(name),
+        b"since" => Request::QueryByNameSince(name),
+        b"log" => Request::QueryByNameLog(name),
+        b"progress" => Request::QueryProgress(name),
+        _ => abort_with_msg(
+            "get fields must be one of state, target, pid, code, attempt, time, since, log, or progress",
+        ),
+    };
+
+    let response = ipc_client.send_and_receive(request);
+    let failed = response.cmd_return_failed();
+    println(response);
+    exit(if failed { 1 } else { 0 });
+}
+
+/// Print why a failed, retrying, or unstoppable service got that way, with a hint on what to check
+pub fn cmd_why(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
+    let (Some(name), true) = (argv.pop(), argv.is_empty()) else {
+        abort_with_msg("why takes exactly one service");
+    };
+
+    match ipc_client.send_and_receive(Request::QueryFailReason(name.to_bytes())) {
+        Response::FailReason(reason) => {
+            print(reason);
+            print_color(Color::Glue, ": ");
+            println(reason.description());
+            exit(0);
+        }
+        response => {
+            let failed = response.cmd_return_failed();
+            println(response);
+            exit(if failed { 1 } else { 0 });
+        }
+    }
+}
+
+/// Print how long each service took to start the last time it came up, slowest first
+///
+/// Measured from beginning `.setup` to being up, excluding time waiting on dependencies.  Services
+/// which have never come up are left out.
+pub fn cmd_analyze(mut ipc_client: IpcClient, argv: Argv) -> ! {
+    use Color::*;
+
+    if !argv.is_empty() {
+        abort_with_msg("analyze takes no arguments");
+    }
+
+    let names = ServiceNames::fetch(&mut ipc_client);
+    let mut failed = names.failed;
+
+    // (milliseconds, index) of the services which have come up, slowest first
+    let mut slowest = [(0i64, 0usize); SERVICE_COUNT];
+    let mut count: usize = 0;
+    failed |= names.for_each(&mut ipc_client, |ipc_client, i, _| {
+        let millis = match ipc_client.send_and_receive(Request::QueryStartDuration(i)) {
+            Response::Number(millis) => millis,
+            // Never came up
+            Response::FieldIsNone => return,
+            response => {
+                failed |= response.cmd_return_failed();
+                return;
+            }
+        };
+
+        // Insert in order, bubbling the new entry up past faster ones
+        let Some(entry) = slowest.get_mut(count) else {
+            return;
+        };
+        *entry = (millis, i);
+        let mut pos = count;
+        count += 1;
+        while let Some(prev) = pos.checked_sub(1) {
+            match (slowest.get(prev), slowest.get(pos)) {
+                (Some(&faster), Some(&entry)) if faster.0 < entry.0 => {
+                    slowest.swap(prev, pos);
+                    pos = prev;
+                }
+                _ => break,
+            }
+        }
+    });
+
+    for &(millis, i) in slowest.iter().take(count) {
+        match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
+            Response::Name(name) => {
+                print_color(Service, name);
+                print_color(Glue, ":");
+                name.print_padding(names.max_len + 1);
+            }
+            response => {
+                failed |= response.cmd_return_failed();
+                continue;
+            }
+        }
+        let mut buf = [0u8; 32];
+        let mut writer = BufWriter::new(&mut buf);
+        if fmt_millis(&mut writer, millis).is_ok() {
+            println(writer.as_slice());
+        }
+    }
+
+    exit(if failed { 1 } else { 0 });
+}
+
+/// Print the memory and CPU usage of the service(s)' cgroups
+///
+/// Services without a configured cgroup report `N/A`.
+pub fn cmd_usage(mut ipc_client: IpcClient, argv: Argv) -> ! {
+    let mut failed = false;
+
+    if argv.is_empty() {
+        abort_with_msg("No service specified");
+    }
+
+    let mut max_name_len: usize = 0;
+    for name in argv.iter() {
+        max_name_len = core::cmp::max(max_name_len, name.to_bytes().len());
+    }
+
+    for name in argv.iter() {
+        let name = name.to_bytes();
+        let response = ipc_client.send_and_receive(Request::QueryByNameUsage(name));
+
+        print_color(Color::Service, name);
+        print_color(Color::Glue, ":");
+        name.print_padding(max_name_len + 1);
+
+        failed |= response.cmd_return_failed();
+        println(response);
+    }
+
+    exit(if failed { 1 } else { 0 });
+}
+
+/// Print the environment the service's processes are executed with, one `VAR=VALUE` per line
+pub fn cmd_env(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
+    let name = argv.pop().or_abort("No service specified");
+    if !argv.is_empty() {
+        abort_with_msg("env takes exactly one service");
+    }
+    let name = name.to_bytes();
+
+    for i in 0.. {
+        match ipc_client.send_and_receive(Request::QueryEnv(i, name)) {
+            Response::EnvVar(var) => println(Response::EnvVar(var)),
+            // No more variables
+            Response::FieldIsNone => break,
+            response => {
+                println(response);
+                exit(1);
+            }
+        }
+    }
+
+    exit(0);
+}
+
+/// Print the effective configuration of the service(s) as `key=value` lines
+pub fn cmd_cat(mut ipc_client: IpcClient, argv: Argv) -> ! {
+    let mut failed = false;
+
+    if argv.is_empty() {
+        abort_with_msg("No service specified");
+    }
+
+    for (i, name) in argv.iter().enumerate() {
+        // Separate services with a blank line
+        if i > 0 {
+            print("\n");
+        }
+        failed |= cat_service(&mut ipc_client, name.to_bytes());
+    }
+
+    exit(if failed { 1 } else { 0 });
+}
+
+/// Helper to print every configuration field of a single service
+/// Returns "failed": true if service not found or a query failed
+fn cat_service(ipc_client: &mut IpcClient, name: &[u8]) -> bool {
+    type DepRequest = for<'a> fn(&'a [u8], usize) -> Request<'a>;
+    const DEPENDENCIES: [(&str, DepRequest); 4] = [
+        ("needs", |name, i| Request::QueryNeeds(i, name)),
+        ("wants", |name, i| Request::QueryWants(i, name)),
+        ("conflicts", |name, i| Request::QueryConflicts(i, name)),
+        ("groups", |name, i| Request::QueryGroups(i, name)),
+    ];
+
+    let mut failed = false;
+
+    print_key("name");
+    print_color(Color::Service, name);
+    print("\n");
+
+    // Bail early if the service doesn't exist rather than printing not-found for every field
+    let response = ipc_client.send_and_receive(Request::QueryByNameState(name));
+    let not_found = response.cmd_return_failed();
+    print_key("state");
+    println(response);
+    if not_found {
+        return true;
+    }
+
+    let response = ipc_client.send_and_receive(Request::QueryByNameTarget(name));
+    failed |= response.cmd_return_failed();
+    print_key("target");
+    println(response);
+
+    for (key, request_fn) in DEPENDENCIES {
+        print_key(key);
+        failed |= query_deps_for_service(ipc_client, name, &request_fn);
+    }
+
+    let response = ipc_client.send_and_receive(Request::QueryByNameLog(name));
+    failed |= response.cmd_return_failed();
+    print_key("log");
+    println(response);
+
+    print_key("logs_for");
+    failed |= query_deps_for_service(ipc_client, name, &|name, i| Request::QueryLogsFor(i, name));
+
+    for field in ConfigField::ALL {
+        let response = ipc_client.send_and_receive(Request::QueryConfig(field, name));
+        failed |= response.cmd_return_failed();
+        print_key(field.key());
+        println(response);
+    }
+
+    failed
+}
+
+fn print_key(key: &str) {
+    print(key);
+    print_color(Color::Glue, "=");
+}
+
+/// Like `cat`, but for every service if given `--all` or no services, and optionally as JSON
+pub fn cmd_describe(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
+    let [json, all] = argv.pop_flags([b"--json", b"--all"]);
+    if all && !argv.is_empty() {
+        abort_with_msg("--all cannot be combined with service names");
+    }
+
+    let describe = |ipc_client: &mut IpcClient, i: usize, name: &[u8]| {
+        match (json, i) {
+            (true, 0) => print("[\n"),
+            (true, _) => print(",\n"),
+            // Separate services with a blank line
+            (false, 0) => {}
+            (false, _) => print("\n"),
+        }
+        if json {
+            describe_service_json(ipc_client, name)
+        } else {
+            cat_service(ipc_client, name)
+        }
+    };
+
+    let mut failed = false;
+    let mut count: usize = 0;
+    if argv.is_empty() {
+        let names = ServiceNames::fetch(&mut ipc_client);
+        failed |= names.failed;
+        failed |= names.for_each(&mut ipc_client, |ipc_client, i, name| {
+            failed |= describe(ipc_client, i, name);
+            count += 1;
+        });
+    } else {
+        for (i, name) in argv.iter().enumerate() {
+            failed |= describe(&mut ipc_client, i, name.to_bytes());
+            count += 1;
+        }
+    }
+
+    if json {
+        print(if count == 0 { "[]\n" } else { "\n]\n" });
+    }
+
+    exit(if failed { 1 } else { 0 });
+}
+
+/// Helper to print every configuration field of a single service as a JSON object
+/// Returns "failed": true if service not found or a query failed
+fn describe_service_json(ipc_client: &mut IpcClient, name: &[u8]) -> bool {
+    type DepRequest = for<'a> fn(&'a [u8], usize) -> Request<'a>;
+    const LISTS: [(&str, DepRequest); 5] = [
+        ("needs", |name, i| Request::QueryNeeds(i, name)),
+        ("wants", |name, i| Request::QueryWants(i, name)),
+        ("conflicts", |name, i| Request::QueryConflicts(i, name)),
+        ("groups", |name, i| Request::QueryGroups(i, name)),
+        ("logs_for", |name, i| Request::QueryLogsFor(i, name)),
+    ];
+
+    let mut failed = false;
+
+    print("  {\"name\": ");
+    print_json_string(name);
+
+    let response = ipc_client.send_and_receive(Request::QueryByNameState(name));
+    if response.cmd_return_failed() {
+        print(", \"error\": ");
+        print_json_string(response_error_name(&response).as_bytes());
+        print("}");
+        return true;
+    }
+    print_json_key("state");
+    failed |= print_json_value(response);
+
+    let response = ipc_client.send_and_receive(Request::QueryByNameTarget(name));
+    print_json_key("target");
+    failed |= print_json_value(response);
+
+    for (key, request_fn) in LISTS {
+        print_json_key(key);
+        print("[");
+        for i in 0.. {
+            match ipc_client.send_and_receive(request_fn(name, i)) {
+                Response::Name(dep_name) => {
+                    if i > 0 {
+                        print(", ");
+                    }
+                    print_json_string(dep_name);
+                }
+                Response::FieldIsNone => break,
+                _ => {
+                    failed = true;
+                    break;
+                }
+            }
+        }
+        print("]");
+    }
+
+    let response = ipc_client.send_and_receive(Request::QueryByNameLog(name));
+    print_json_key("log");
+    failed |= print_json_value(response);
+
+    for field in ConfigField::ALL {
+        let response = ipc_client.send_and_receive(Request::QueryConfig(field, name));
+        print_json_key(field.key());
+        failed |= print_json_value(response);
+    }
+
+    print("}");
+    failed
+}
+
+fn print_json_key(key: &str) {
+    print(", ");
+    print_json_string(key.as_bytes());
+    print(": ");
+}
+
+/// Print a field's value as JSON, or null if the query failed
+/// Returns "failed": true if the query failed
+fn print_json_value(response: Response) -> bool {
+    match response {
+        Response::FieldIsNone => print("null"),
+        Response::Number(n) => print(n),
+        Response::Bool(b) => print(if b { "true" } else { "false" }),
+        Response::State(state) => print_json_string(state.name().as_bytes()),
+        Response::Target(target) => print_json_string(target.name().as_bytes()),
+        Response::Name(s) | Response::Path(s) | Response::EnvVar(s) => print_json_string(s),
+        _ => {
+            print("null");
+            return true;
+        }
+    }
+    false
+}
+
+/// The uncolored name `Response`'s `Print` impl shows for a failed query
+fn response_error_name(response: &Response) -> &'static str {
+    match response {
+        Response::ServiceNotFound => "not-found",
+        Response::InvalidRequest => "invalid-request",
+        _ => "failed",
+    }
+}
+
+/// Print bytes as a quoted JSON string, escaping quotes, backslashes, and control characters
+fn print_json_string(s: &[u8]) {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    print("\"");
+    let mut start = 0;
+    for (i, &c) in s.iter().enumerate() {
+        if c != b'"' && c != b'\\' && c >= 0x20 {
+            continue;
+        }
+        print(s.get(start..i).unwrap_or(&[]));
+        match c {
+            b'"' => print("\\\""),
+            b'\\' => print("\\\\"),
+            b'\n' => print("\\n"),
+            b'\t' => print("\\t"),
+            _ => {
+                let escape = [
+                    b'\\',
+                    b'u',
+                    b'0',
+                    b'0',
+                    HEX.get((c >> 4) as usize).copied().unwrap_or(b'0'),
+                    HEX.get((c & 0xf) as usize).copied().unwrap_or(b'0'),
+                ];
+                print(&escape[..]);
+            }
+        }
+        start = i + 1;
+    }
+    print(s.get(start..).unwrap_or(&[]));
+    print("\"");
+}
@@ -1,13 +1,17 @@
 use connate::constants::*;
+use connate::err::*;
 use connate::ipc::*;
 use connate::os::*;
 use connate::types::*;
 
 pub fn cmd_help(mut envp: Envp, config_lock_file: Option<&CStr>) -> ! {
     print(
-        r#"Usage: conctl [PID | CONNATE_LOCK_PATH] COMMAND [ARGS]
+        r#"Usage: conctl [--no-color] [--pid PID | --lock CONNATE_LOCK_PATH] COMMAND [ARGS]
+       conctl [--no-color] [PID | CONNATE_LOCK_PATH] COMMAND [ARGS]
 
 conctl finds the connate daemon by checking in order:
+- --pid PID or --lock CONNATE_LOCK_PATH, in which case the next argument is
+  always the command, even if it looks like a PID or path
 - If optional first arg starts with digit, indicates PID
 - If optional first arg starts with '.' or '/', indicates lock path
 - $CONNATE_PID"#,
@@ -46,27 +50,88 @@ implicitly applies to all services.  For commands which take `<services>`, one
 or more services must be specified.
 
 GENERAL QUERY COMMANDs:
-s, status  [services]  Prints status information
+s, status  [services]  Prints status information, followed by what services
+                       which are setting up, starting, or cleaning up last
+                       reported they are doing, e.g. via `step()`
+   status --filter <field>:<value> [services]
+                       Only print services whose state or target matches, e.g.
+                       `--filter state:starting` or `--filter target:down`.
+                       Repeated filters on one field match any of them; filters
+                       on different fields must all match.
+   status --failed [services]
+                       Shorthand for filtering to the failed, cannot-stop, and
+                       force-down states which exits non-zero if any match
+   status --since <secs> [services]
+                       Only print services which entered their current state
+                       within the last <secs> seconds, e.g. to see what just
+                       changed.  Accepts units as in `1h30m`.  Combines with
+                       --filter and --failed.
 l, list                List all services
+   list [--state] [--json]
+                       With --state, also print each service's current state.
+                       With --json, prints a JSON array.
    state   [services]  Print the current state
    target  [services]  Print the target state
 p, pid     [services]  Print the Process IDs
    code    [services]  Print the last exit code
-   attempt [services]  Print the number of attempts to start and stay up
+   attempt [services]  Print the number of attempts to start and stay up.
+                       Setting the target of a failed service resets this;
+                       the set target commands print the count it failed at.
    time    [services]  Print the time in the current state
+   get <service> <field>
+                       Print one of the fields above (state, target, pid,
+                       code, attempt, or time), log, since (when the
+                       current state was entered, in UTC), or progress,
+                       without a label
+   why     <service>   Print why a failed, retrying, or cannot-stop service
+                       got there, or why one is stuck waiting to stop, with a
+                       hint on what to check.  This is the last attempt's
+                       reason: a service failed for running out of retries
+                       reports why its final attempt failed (see attempt)
+   cat     <services>  Print the effective configuration as key=value lines
+   describe [--json] [--all | services]
+                       Like cat, but for every service if none are given.
+                       With --json, prints an array with an object per service.
+   usage   <services>  Print memory and CPU usage of the services' cgroups
+   analyze             Print how long each service took to start, slowest
+                       first, excluding time spent waiting on dependencies
+   env     <service>   Print the environment the service is executed with
 
 DEPENDENCY QUERY COMMANDS:
 needs      [services]  Print hard dependencies
 wants      [services]  Print soft dependencies
 conflicts  [services]  Print anti dependencies
 groups     [services]  Print group members
+blockers   [services]  Print services a waiting service is blocked on.  A
+                       service blocked for a while by services which will never
+                       unblock it fails, or keeps running if it is waiting to
+                       stop, after which this prints the culprit.
 log        [services]  Print log configuration
+logs-for   [services]  Print services whose output a logger service receives
 
 SET TARGET COMMANDs:
 u, up      <services>  Bring up service(s) and dependencies
 d, down    <services>  Bring down the service(s) and dependents
 r, restart <services>  Restart the service(s)
 o, once    <services>  Bring the service(s) up once (no retry)
+d, down    --all       Bring down every service
+r, restart --all       Restart every service not targeting down.  With --all,
+                       each service whose target changed is listed.
+u, up      --dry-run <services>
+                       Print the services whose targets would change and how,
+                       without changing anything.  Also accepted by down,
+                       restart, and once.  Each service is previewed on its own.
+u, up      <services> --timeout <secs>
+                       Wait for the services to settle as UP does, giving up
+                       after the timeout.  Also accepted by down, restart, and
+                       once.
+   isolate <group>     Bring up the group and everything it needs, wants, or
+                       groups, and bring down EVERY other service.  The
+                       configured system service and what it pulls up are
+                       left alone.  Like switching runlevels.
+   boot-target <name>  Isolate to the services of a configured TARGETS entry
+   single              Isolate to the configured EMERGENCY_SERVICES, e.g. a
+                       getty, for maintenance
 
 SET TARGET AND WAIT FOR SETTLE COMMANDS:
 U, UP      <services>  Bring up service(s) and dependencies
@@ -77,19 +142,66 @@ R, RESTART <services>  Restart the service(s)
                        then wait for service state to settle
 O, ONCE    <services>  Bring the service(s) up once (no retry)
                        then wait for service state to settle
+settle <service>=<target> [<service>=<target> ...]
+                       Set each service's target independently, e.g.
+                       `settle sshd=up cupsd=down`, then wait for service
+                       state to settle
+drain <service>        Bring down the service's dependents and wait for them to
+                       settle, then bring down the service and wait for it to
+                       settle.  The service is left up if a dependent fails
+                       to stop.
+boot-wait              Wait until every service targeted up has settled for the
+                       first time since connate started, then print
+                       `boot-complete up=<count> failed=<count>`.  Exits
+                       non-zero if any service had failed by then.  Checks
+                       every 100ms regardless of the settle feature.
+
+Settle commands accept a trailing `--timeout <secs>` after which they give up,
+print the services which have not yet settled, and exit non-zero.  Like
+`status --since`, the timeout may be given with units, e.g. `--timeout 2m`.
+While waiting, intermediate states are reported to stderr if it is a TTY.
+If connate was built without the settle feature, they instead re-query every
+unsettled service's state every 100ms, which costs an IPC round-trip per service
+each time and notices state changes up to 100ms late.
 
 MISCELLANEOUS COMMANDs:
 -h, --help, help      Print this help message
 P, PID                Print the Connate Process ID
+ping [--timeout <secs>]
+                      Check that connate is reachable and responding, exiting
+                      non-zero if it does not respond within the timeout
+                      (default 5 seconds).  Unlike other commands, never waits
+                      indefinitely for another conctl to release the lock.
+version               Print the conctl and running connate versions.  Other
+                      commands warn if their IPC protocols differ, except
+                      `exec` and `ready`.
+connate-info          Print connate's PID, the executable it is running, and the
+                      time since it was last exec'd.  An executable since replaced
+                      on disk, such as by an upgrade without `exec`, is shown
+                      with a " (deleted)" suffix.
+errno <number>        Print the symbolic name and description of an errno
+                      number, such as one found in connate's output.
 x, exec [path]        Instructs Connate to re‑execute itself (usually to
                       change configuration).  Optionally give it a new
                       executable path; otherwise, it re‑uses the file path that
                       was previously used to execute it.
+freeze <services>     Suspend every process in the service(s)' cgroups.  The
+                      services' state and target are left unchanged.
+thaw   <services>     Resume processes suspended by freeze
+force-reap <services> Release cannot-stop service(s) whose processes have since
+                      died, such as by being killed with privileges connate
+                      lacks.  They then fail or retry as though they had
+                      exited.  Fails if a process is still running, including
+                      an unrelated one which has since reused its PID.
 ready                 Notify connate that this service is ready. Called from
                       within a service process with `run = Run::Notify` to
                       signal that initialization is complete and dependencies
                       can now be fulfilled.
 
+Colors are only used when stdout is a TTY, and are disabled by --no-color or a
+non-empty $NO_COLOR.  24-bit colors are used if $COLORTERM is truecolor or
+24bit, 256 colors if $TERM ends in 256color, and 16 colors otherwise.
+
 Output formats are intended to be both human and machine readable, allowing for
 feeding one command's output back in as input.  For example:
     conctl needs sshd | xargs conctl status
@@ -113,6 +225,104 @@ pub fn cmd_connate_pid(pid: pid_t) -> ! {
     exit(0);
 }
 
+/// How long `ping` waits for connate by default, including waiting on any other client's lock
+const PING_TIMEOUT_MILLIS: i64 = 5000;
+
+pub fn cmd_ping(pid: pid_t, mut argv: Argv) -> ! {
+    let timeout_millis = super::pop_timeout(&mut argv).unwrap_or(PING_TIMEOUT_MILLIS);
+    if !argv.is_empty() {
+        abort_with_msg("ping only takes an optional --timeout <secs>");
+    }
+    let timeout_millis = timeout_millis.clamp(0, c_int::MAX as i64) as c_int;
+
+    let start = get_time_monotonic().or_abort("Unable to get current time");
+    let mut ipc_client = IpcClient::from_pid(pid);
+
+    // The timeout covers both waiting for the lock and the round-trip itself.  Any response, even
+    // from a connate predating ping, shows it is responsive.
+    let responsive = ipc_client.lock_within(timeout_millis) && {
+        let now = get_time_monotonic().or_abort("Unable to get current time");
+        let remaining = (timeout_millis as i64 - now.millis_since(start)).max(0) as c_int;
+        ipc_client
+            .send_and_receive_within(Request::Ping, remaining)
+            .is_some()
+    };
+
+    if responsive {
+        print_color(Color::Okay, "okay");
+        print("\n");
+        exit(0);
+    }
+    print_color(Color::Error, "not responding");
+    print("\n");
+    exit(1);
+}
+
+pub fn cmd_version(mut ipc_client: IpcClient) -> ! {
+    print_color(Color::Service, "conctl");
+    print_color(Color::Glue, ":");
+    print("  ");
+    print(VERSION);
+    print(" (ipc protocol ");
+    print(IPC_PROTOCOL_VERSION);
+    println(")");
+
+    print_color(Color::Service, "connate");
+    print_color(Color::Glue, ":");
+    print(" ");
+    match ipc_client.send_and_receive(Request::QueryVersion) {
+        Response::Version(protocol, version) => {
+            print(version);
+            print(" (ipc protocol ");
+            if protocol == IPC_PROTOCOL_VERSION {
+                print(protocol);
+            } else {
+                print_color(Color::Error, protocol);
+            }
+            println(")");
+            exit(if protocol == IPC_PROTOCOL_VERSION {
+                0
+            } else {
+                1
+            });
+        }
+        // connate predates version queries
+        _ => {
+            print_color(Color::Error, "unknown");
+            println(" (predates version queries)");
+            exit(1);
+        }
+    }
+}
+
+pub fn cmd_connate_info(mut ipc_client: IpcClient) -> ! {
+    let response = ipc_client.send_and_receive(Request::QueryConnateInfo);
+    let failed = !matches!(response, Response::ConnateInfo(..));
+    println(response);
+    exit(if failed { 1 } else { 0 });
+}
+
+pub fn cmd_errno(number: Option<&CStr>) -> ! {
+    let number = number.or_abort("No errno number specified");
+
+    // Accept the negated form syscalls return as well
+    let digits = number.to_bytes();
+    let digits = digits.strip_prefix(b"-").unwrap_or(digits);
+    let errno = Errno::new(digits.parse_pid().or_abort("Invalid errno number"));
+
+    let Some((name, description)) = errno.name_and_description() else {
+        print(digits);
+        println(": unknown errno");
+        exit(1);
+    };
+
+    print_color(Color::Error, name);
+    print_color(Color::Glue, ":");
+    print(" ");
+    println(description);
+    exit(0);
+}
+
 pub fn cmd_exec(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
     // IPC doesn't have an explicit Some/None.
     // Empty path implies None.
@@ -134,3 +344,104 @@ pub fn cmd_exec(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
         exit(0);
     }
 }
+
+/// Generic helper for freezing or thawing the cgroups of one or more services
+fn freeze_generic<'a, F>(
+    mut ipc_client: IpcClient,
+    argv: Argv<'a>,
+    request_fn: F,
+    action: &str,
+) -> !
+where
+    F: Fn(&'a [u8]) -> Request<'a>,
+{
+    let mut failed = false;
+
+    if argv.is_empty() {
+        abort_with_msg("No service specified");
+    }
+
+    let mut max_name_len: usize = 0;
+    for name in argv.iter() {
+        max_name_len = core::cmp::max(max_name_len, name.to_bytes().len());
+    }
+
+    for name in argv.iter() {
+        let name = name.to_bytes();
+        let response = ipc_client.send_and_receive(request_fn(name));
+
+        print_color(Color::Service, name);
+        print_color(Color::Glue, ":");
+        name.print_padding(max_name_len + 1);
+
+        match response {
+            Response::Okay => println(action),
+            Response::FieldIsNone => {
+                failed = true;
+                print_color(Color::Error, "no cgroup configured");
+                print("\n");
+            }
+            response => {
+                failed = true;
+                println(response);
+            }
+        }
+    }
+
+    exit(if failed { 1 } else { 0 });
+}
+
+#[inline]
+pub fn cmd_freeze(ipc_client: IpcClient, argv: Argv) -> ! {
+    freeze_generic(ipc_client, argv, Request::Freeze, "frozen")
+}
+
+#[inline]
+pub fn cmd_thaw(ipc_client: IpcClient, argv: Argv) -> ! {
+    freeze_generic(ipc_client, argv, Request::Thaw, "thawed")
+}
+
+/// Release CannotStop services whose processes have since died, such as by an administrator
+/// killing them with privileges connate lacks
+pub fn cmd_force_reap(mut ipc_client: IpcClient, argv: Argv) -> ! {
+    let mut failed = false;
+
+    if argv.is_empty() {
+        abort_with_msg("No service specified");
+    }
+
+    let mut max_name_len: usize = 0;
+    for name in argv.iter() {
+        max_name_len = core::cmp::max(max_name_len, name.to_bytes().len());
+    }
+
+    for name in argv.iter() {
+        let name = name.to_bytes();
+        let response = ipc_client.send_and_receive(Request::ForceReap(name));
+
+        print_color(Color::Service, name);
+        print_color(Color::Glue, ":");
+        name.print_padding(max_name_len + 1);
+
+        match response {
+            Response::Okay => println("reaped"),
+            Response::State(state) => {
+                failed = true;
+                print("not cannot-stop, is ");
+                println(state);
+            }
+            Response::Pid(pid) => {
+                failed = true;
+                print_color(Color::Error, "still running");
+                print(" as pid=");
+                println(pid);
+            }
+            response => {
+                failed = true;
+                println(response);
+            }
+        }
+    }
+
+    exit(if failed { 1 } else { 0 });
+}
@@ -2,6 +2,7 @@ mod dependency_query;
 mod general_query;
 mod miscellaneous;
 mod ready;
+mod service_names;
 mod set_target;
 mod settle;
 
@@ -9,6 +10,7 @@ pub use dependency_query::*;
 pub use general_query::*;
 pub use miscellaneous::*;
 pub use ready::*;
+pub use service_names::*;
 pub use set_target::*;
 pub use settle::*;
 
@@ -21,38 +23,62 @@ use connate::types::*;
 pub enum Cmd<'a> {
     Help(Envp<'a>, Option<&'a CStr>),
     ConnatePid(pid_t),
+    Ping(pid_t, Argv<'a>),
+    Errno(Option<&'a CStr>),
     Exec(IpcClient, Argv<'a>),
     Status(IpcClient, Argv<'a>),
-    List(IpcClient),
+    List(IpcClient, Argv<'a>),
     State(IpcClient, Argv<'a>),
     Target(IpcClient, Argv<'a>),
     Code(IpcClient, Argv<'a>),
     Pid(IpcClient, Argv<'a>),
     Attempt(IpcClient, Argv<'a>),
     Time(IpcClient, Argv<'a>),
+    Get(IpcClient, Argv<'a>),
+    Why(IpcClient, Argv<'a>),
     Needs(IpcClient, Argv<'a>),
     Wants(IpcClient, Argv<'a>),
     Conflicts(IpcClient, Argv<'a>),
     Groups(IpcClient, Argv<'a>),
+    Blockers(IpcClient, Argv<'a>),
+    LogsFor(IpcClient, Argv<'a>),
     Log(IpcClient, Argv<'a>),
-    Up(IpcClient, Argv<'a>),
-    Down(IpcClient, Argv<'a>),
-    Restart(IpcClient, Argv<'a>),
-    Once(IpcClient, Argv<'a>),
+    Cat(IpcClient, Argv<'a>),
+    Describe(IpcClient, Argv<'a>),
+    Usage(IpcClient, Argv<'a>),
+    Analyze(IpcClient, Argv<'a>),
+    Env(IpcClient, Argv<'a>),
+    Up(IpcClient, Argv<'a>, pid_t),
+    Down(IpcClient, Argv<'a>, pid_t),
+    Restart(IpcClient, Argv<'a>, pid_t),
+    Once(IpcClient, Argv<'a>, pid_t),
+    Isolate(IpcClient, Argv<'a>),
+    BootTarget(IpcClient, Argv<'a>),
+    Single(IpcClient, Argv<'a>),
+    Freeze(IpcClient, Argv<'a>),
+    Thaw(IpcClient, Argv<'a>),
+    ForceReap(IpcClient, Argv<'a>),
+    Settle(IpcClient, Argv<'a>, pid_t),
     SettleUp(IpcClient, Argv<'a>, pid_t),
     SettleDown(IpcClient, Argv<'a>, pid_t),
     SettleRestart(IpcClient, Argv<'a>, pid_t),
     SettleOnce(IpcClient, Argv<'a>, pid_t),
+    Drain(IpcClient, Argv<'a>, pid_t),
+    BootWait(IpcClient, Argv<'a>),
     Ready(IpcClient, pid_t),
+    Version(IpcClient),
+    ConnateInfo(IpcClient),
 }
 
 impl<'a> Cmd<'a> {
     pub fn new(mut argv: Argv<'a>, mut envp: Envp<'a>, config_lock_path: Option<&'a CStr>) -> Self {
         // The CLI format is:
         //
-        // conctl [PID | CONNATE_LOCK_PATH] cmd [ARGS]
+        // conctl [FLAGS] [PID | CONNATE_LOCK_PATH] cmd [ARGS]
         //
-        // The optional first argument determines how to locate the connate daemon:
+        // where FLAGS are any of `--no-color`, `--pid PID`, or `--lock CONNATE_LOCK_PATH`, in any
+        // order.  `--pid` and `--lock` explicitly locate the connate daemon, and the following
+        // argument is always the cmd.  Without either, the optional first argument after the flags:
         // - If it starts with a digit (0-9), it is interpreted as the connate PID
         // - If it starts with '.' or '/', it is interpreted as the lock file path
         //
@@ -65,28 +91,65 @@ impl<'a> Cmd<'a> {
         // Ignore argv[0]
         let _ = argv.pop();
 
-        // Find cmd:
+        // Consume leading flags
+        let mut no_color = false;
+        let mut cli_pid = None;
+        let mut lock_path = None;
+        while let Some(flag) = argv.first() {
+            match flag.to_bytes() {
+                b"--no-color" => no_color = true,
+                b"--pid" => {
+                    let _ = argv.pop();
+                    cli_pid = Some(argv.first().or_abort("--pid requires an argument"));
+                }
+                b"--lock" => {
+                    let _ = argv.pop();
+                    lock_path = Some(argv.first().or_abort("--lock requires an argument"));
+                }
+                _ => break,
+            }
+            let _ = argv.pop();
+        }
+        if cli_pid.is_some() && lock_path.is_some() {
+            abort_with_msg("--pid and --lock are mutually exclusive");
+        }
+
+        // Settle on color depth before anything is printed
+        init_colorize(envp.clone(), no_color);
+
+        // Find cmd.  Unless the daemon was located by flag:
         // - If first arg starts with digit, treat as PID
         // - If first arg starts with '.' or '/', treat as lock path
         // - Otherwise treat as cmd
         let arg = argv.pop().or_abort("No cmd specified.  See `--help`");
 
-        let (cli_pid, lock_path, cmd) = match arg.to_bytes().first() {
-            Some(b'0'..=b'9') => (Some(arg), None, argv.pop()),
-            Some(&b'.') | Some(&b'/') => (None, Some(arg), argv.pop()),
-            _ => (None, None, Some(arg)),
+        let cmd = if cli_pid.is_some() || lock_path.is_some() {
+            Some(arg)
+        } else {
+            match arg.to_bytes().first() {
+                Some(b'0'..=b'9') => {
+                    cli_pid = Some(arg);
+                    argv.pop()
+                }
+                Some(&b'.') | Some(&b'/') => {
+                    lock_path = Some(arg);
+                    argv.pop()
+                }
+                _ => Some(arg),
+            }
         };
         let cmd_str = cmd.or_abort("No cmd specified.  See `--help`");
 
         // Handle any cmds that don't require any resources at this point
         match cmd_str.to_bytes() {
             b"-" | b"-h" | b"--help" | b"help" => return Self::Help(envp, config_lock_path),
+            b"errno" => return Self::Errno(argv.pop()),
             _ => {}
         }
 
         // Determine connate's PID:
-        // 1. CLI PID argument (if digit)
-        // 2. CLI lock path (if starts with . or /)
+        // 1. `--pid` or CLI PID argument (if digit)
+        // 2. `--lock` or CLI lock path (if starts with . or /)
         // 3. $CONNATE_PID env var
         // 4. $CONNATE_LOCK_FILE env var
         // 5. config lock file
@@ -109,6 +172,8 @@ impl<'a> Cmd<'a> {
         // Handle any cmds that don't require IPC at this point
         match cmd_str.to_bytes() {
             b"PID" | b"P" => return Self::ConnatePid(pid),
+            // Locks and queries with a timeout itself rather than risk hanging on a hung connate
+            b"ping" => return Self::Ping(pid, argv),
             _ => {}
         }
 
@@ -116,30 +181,58 @@ impl<'a> Cmd<'a> {
         let mut ipc_client = IpcClient::from_pid(pid);
         ipc_client.lock_with_warning();
 
+        // Checking for a protocol mismatch costs a round-trip, so is skipped where not worthwhile
+        match cmd_str.to_bytes() {
+            // Reports on any mismatch itself
+            b"version" => return Self::Version(ipc_client),
+            // Re-executes connate, which is how a mismatch after an upgrade is resolved
+            b"exec" | b"x" => return Self::Exec(ipc_client, argv),
+            // Run by each `Ready::Notify` service as it starts, and its request predates versioning
+            b"ready" => return Self::Ready(ipc_client, pid),
+            _ => ipc_client.warn_on_version_mismatch(),
+        }
+
         match cmd_str.to_bytes() {
-            b"exec" | b"x" => Self::Exec(ipc_client, argv),
             b"status" | b"s" => Self::Status(ipc_client, argv),
-            b"list" | b"l" => Self::List(ipc_client),
+            b"list" | b"l" => Self::List(ipc_client, argv),
             b"state" => Self::State(ipc_client, argv),
             b"target" => Self::Target(ipc_client, argv),
             b"pid" | b"p" => Self::Pid(ipc_client, argv),
             b"code" => Self::Code(ipc_client, argv),
             b"attempt" => Self::Attempt(ipc_client, argv),
             b"time" => Self::Time(ipc_client, argv),
+            b"get" => Self::Get(ipc_client, argv),
+            b"why" => Self::Why(ipc_client, argv),
             b"needs" => Self::Needs(ipc_client, argv),
             b"wants" => Self::Wants(ipc_client, argv),
             b"conflicts" => Self::Conflicts(ipc_client, argv),
             b"groups" => Self::Groups(ipc_client, argv),
+            b"blockers" => Self::Blockers(ipc_client, argv),
+            b"logs-for" => Self::LogsFor(ipc_client, argv),
             b"log" => Self::Log(ipc_client, argv),
-            b"up" | b"u" => Self::Up(ipc_client, argv),
-            b"down" | b"d" => Self::Down(ipc_client, argv),
-            b"restart" | b"r" => Self::Restart(ipc_client, argv),
-            b"once" | b"o" => Self::Once(ipc_client, argv),
+            b"cat" => Self::Cat(ipc_client, argv),
+            b"describe" => Self::Describe(ipc_client, argv),
+            b"usage" => Self::Usage(ipc_client, argv),
+            b"analyze" => Self::Analyze(ipc_client, argv),
+            b"env" => Self::Env(ipc_client, argv),
+            b"up" | b"u" => Self::Up(ipc_client, argv, pid),
+            b"down" | b"d" => Self::Down(ipc_client, argv, pid),
+            b"restart" | b"r" => Self::Restart(ipc_client, argv, pid),
+            b"once" | b"o" => Self::Once(ipc_client, argv, pid),
+            b"isolate" => Self::Isolate(ipc_client, argv),
+            b"boot-target" => Self::BootTarget(ipc_client, argv),
+            b"single" => Self::Single(ipc_client, argv),
+            b"freeze" => Self::Freeze(ipc_client, argv),
+            b"thaw" => Self::Thaw(ipc_client, argv),
+            b"force-reap" => Self::ForceReap(ipc_client, argv),
+            b"settle" => Self::Settle(ipc_client, argv, pid),
             b"UP" | b"U" => Self::SettleUp(ipc_client, argv, pid),
             b"DOWN" | b"D" => Self::SettleDown(ipc_client, argv, pid),
             b"RESTART" | b"R" => Self::SettleRestart(ipc_client, argv, pid),
             b"ONCE" | b"O" => Self::SettleOnce(ipc_client, argv, pid),
-            b"ready" => Self::Ready(ipc_client, pid),
+            b"drain" => Self::Drain(ipc_client, argv, pid),
+            b"boot-wait" => Self::BootWait(ipc_client, argv),
+            b"connate-info" => Self::ConnateInfo(ipc_client),
             _ => abort_with_msg("Invalid cmd.  See `--help`"),
         }
     }
@@ -148,29 +241,51 @@ impl<'a> Cmd<'a> {
         match self {
             Cmd::Help(envp, config_lock_file) => cmd_help(envp, config_lock_file),
             Cmd::ConnatePid(pid) => cmd_connate_pid(pid),
+            Cmd::Ping(pid, argv) => cmd_ping(pid, argv),
+            Cmd::Errno(number) => cmd_errno(number),
             Cmd::Exec(pid, argv) => cmd_exec(pid, argv),
             Cmd::Status(ipc_client, argv) => cmd_status(ipc_client, argv),
-            Cmd::List(ipc_client) => cmd_list(ipc_client),
+            Cmd::List(ipc_client, argv) => cmd_list(ipc_client, argv),
             Cmd::State(ipc_client, argv) => cmd_state(ipc_client, argv),
             Cmd::Target(ipc_client, argv) => cmd_target(ipc_client, argv),
             Cmd::Pid(ipc_client, argv) => cmd_pid(ipc_client, argv),
             Cmd::Code(ipc_client, argv) => cmd_code(ipc_client, argv),
             Cmd::Attempt(ipc_client, argv) => cmd_attempt(ipc_client, argv),
             Cmd::Time(ipc_client, argv) => cmd_time(ipc_client, argv),
+            Cmd::Get(ipc_client, argv) => cmd_get(ipc_client, argv),
+            Cmd::Why(ipc_client, argv) => cmd_why(ipc_client, argv),
             Cmd::Needs(ipc_client, argv) => cmd_needs(ipc_client, argv),
             Cmd::Wants(ipc_client, argv) => cmd_wants(ipc_client, argv),
             Cmd::Conflicts(ipc_client, argv) => cmd_conflicts(ipc_client, argv),
             Cmd::Groups(ipc_client, argv) => cmd_groups(ipc_client, argv),
+            Cmd::Blockers(ipc_client, argv) => cmd_blockers(ipc_client, argv),
+            Cmd::LogsFor(ipc_client, argv) => cmd_logs_for(ipc_client, argv),
             Cmd::Log(ipc_client, argv) => cmd_log(ipc_client, argv),
-            Cmd::Up(ipc_client, argv) => cmd_up(ipc_client, argv),
-            Cmd::Down(ipc_client, argv) => cmd_down(ipc_client, argv),
-            Cmd::Restart(ipc_client, argv) => cmd_restart(ipc_client, argv),
-            Cmd::Once(ipc_client, argv) => cmd_once(ipc_client, argv),
+            Cmd::Cat(ipc_client, argv) => cmd_cat(ipc_client, argv),
+            Cmd::Describe(ipc_client, argv) => cmd_describe(ipc_client, argv),
+            Cmd::Usage(ipc_client, argv) => cmd_usage(ipc_client, argv),
+            Cmd::Analyze(ipc_client, argv) => cmd_analyze(ipc_client, argv),
+            Cmd::Env(ipc_client, argv) => cmd_env(ipc_client, argv),
+            Cmd::Up(ipc_client, argv, pid) => cmd_up(ipc_client, argv, pid),
+            Cmd::Down(ipc_client, argv, pid) => cmd_down(ipc_client, argv, pid),
+            Cmd::Restart(ipc_client, argv, pid) => cmd_restart(ipc_client, argv, pid),
+            Cmd::Once(ipc_client, argv, pid) => cmd_once(ipc_client, argv, pid),
+            Cmd::Isolate(ipc_client, argv) => cmd_isolate(ipc_client, argv),
+            Cmd::BootTarget(ipc_client, argv) => cmd_boot_target(ipc_client, argv),
+            Cmd::Single(ipc_client, argv) => cmd_single(ipc_client, argv),
+            Cmd::Freeze(ipc_client, argv) => cmd_freeze(ipc_client, argv),
+            Cmd::Thaw(ipc_client, argv) => cmd_thaw(ipc_client, argv),
+            Cmd::ForceReap(ipc_client, argv) => cmd_force_reap(ipc_client, argv),
+            Cmd::Settle(ipc_client, argv, pid) => cmd_settle(ipc_client, argv, pid),
             Cmd::SettleUp(ipc_client, argv, pid) => cmd_settle_up(ipc_client, argv, pid),
             Cmd::SettleDown(ipc_client, argv, pid) => cmd_settle_down(ipc_client, argv, pid),
             Cmd::SettleRestart(ipc_client, argv, pid) => cmd_settle_restart(ipc_client, argv, pid),
             Cmd::SettleOnce(ipc_client, argv, pid) => cmd_settle_once(ipc_client, argv, pid),
+            Cmd::Drain(ipc_client, argv, pid) => cmd_drain(ipc_client, argv, pid),
+            Cmd::BootWait(ipc_client, argv) => cmd_boot_wait(ipc_client, argv),
             Cmd::Ready(ipc_client, pid) => cmd_ready(ipc_client, pid),
+            Cmd::Version(ipc_client) => cmd_version(ipc_client),
+            Cmd::ConnateInfo(ipc_client) => cmd_connate_info(ipc_client),
         }
     }
 }
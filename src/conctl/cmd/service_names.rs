@@ -0,0 +1,145 @@
+use crate::internal::{SERVICE_COUNT, SERVICE_NAMES_LEN};
+use connate::ipc::*;
+use connate::util::BufWriter;
+
+/// The name of every service, fetched from connate once per conctl invocation
+///
+/// Commands which apply to all services need each name twice: once to find the padding width and
+/// again when printing.  Caching them avoids re-enumerating the services for the second pass.
+pub struct ServiceNames {
+    /// Names back-to-back in index order
+    ///
+    /// Sized for the services conctl was built with, such that names only need re-querying from a
+    /// connate built with a different config.
+    buf: [u8; SERVICE_NAMES_LEN],
+    /// Length of each cached name
+    lens: [u16; SERVICE_COUNT],
+    /// Number of names which fit in `buf`
+    cached: usize,
+    /// Number of services, including those whose names did not fit in `buf`
+    count: usize,
+    /// Length of the longest name
+    pub max_len: usize,
+    /// Whether enumerating the services failed part-way
+    pub failed: bool,
+}
+
+impl ServiceNames {
+    pub fn fetch(ipc_client: &mut IpcClient) -> Self {
+        let mut names = Self {
+            buf: [0u8; SERVICE_NAMES_LEN],
+            lens: [0u16; SERVICE_COUNT],
+            cached: 0,
+            count: 0,
+            max_len: 0,
+            failed: false,
+        };
+
+        let mut writer = BufWriter::new(&mut names.buf);
+        let mut cache_full = false;
+        names.failed = for_each_status(ipc_client, |name, _| {
+            names.max_len = core::cmp::max(names.max_len, name.len());
+            names.count += 1;
+
+            // Once a name does not fit, stop caching such that cached names stay contiguous
+            if cache_full {
+                return;
+            }
+            match names.lens.get_mut(names.cached) {
+                Some(len) if writer.push(name).is_ok() => {
+                    *len = name.len() as u16;
+                    names.cached += 1;
+                }
+                _ => cache_full = true,
+            }
+        });
+
+        names
+    }
+
+    /// Call `f` with the index and name of every service, in index order
+    ///
+    /// Names which did not fit in the cache are queried from connate.  Returns whether such a query
+    /// failed, after which the remaining services are skipped.
+    pub fn for_each<F>(&self, ipc_client: &mut IpcClient, mut f: F) -> bool
+    where
+        F: FnMut(&mut IpcClient, usize, &[u8]),
+    {
+        let mut start = 0;
+        for (i, &len) in self.lens.iter().take(self.cached).enumerate() {
+            let end = start + len as usize;
+            let Some(name) = self.buf.get(start..end) else {
+                return true;
+            };
+            f(ipc_client, i, name);
+            start = end;
+        }
+
+        let mut name_buf = [0u8; connate::constants::MSG_SVC_NAME_SIZE];
+        for i in self.cached..self.count {
+            // Copy the name out of `ipc_client`'s buffer so `f` may reuse the client
+            let mut writer = BufWriter::new(&mut name_buf);
+            match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
+                Response::Name(name) if writer.push(name).is_ok() => {}
+                // Longer than connate may send
+                Response::Name(_) => return true,
+                response => return response.cmd_return_failed(),
+            }
+            let name_len = writer.pos();
+            let Some(name) = name_buf.get(..name_len) else {
+                return true;
+            };
+            f(ipc_client, i, name);
+        }
+
+        false
+    }
+}
+
+/// Call `f` with the name and `Response::Status` of every service, in index order
+///
+/// Fetches as many services per round-trip as fit in a message.  Falls back to querying each
+/// service individually if connate predates `QueryStatusAll` or cannot batch a service.
+///
+/// Returns whether a query failed.
+pub fn for_each_status<F>(ipc_client: &mut IpcClient, mut f: F) -> bool
+where
+    F: FnMut(&[u8], Response),
+{
+    let mut start: usize = 0;
+    while let Response::StatusBatch(done, records) =
+        ipc_client.send_and_receive(Request::QueryStatusAll(start))
+    {
+        let mut count: usize = 0;
+        for record in StatusRecords::new(records) {
+            f(record.name, record.status());
+            count += 1;
+        }
+        if done {
+            return false;
+        }
+        if count == 0 {
+            break;
+        }
+        start += count;
+    }
+
+    for i in start.. {
+        // Status fields are copied out such that the name query may reuse the response buffer
+        let (state, target, pid, last_exit, time) =
+            match ipc_client.send_and_receive(Request::QueryByIndexStatus(i)) {
+                Response::Status(state, target, pid, last_exit, time) => {
+                    (state, target, pid, last_exit, time)
+                }
+                Response::ServiceNotFound => return false,
+                response => return response.cmd_return_failed(),
+            };
+        match ipc_client.send_and_receive(Request::QueryByIndexName(i)) {
+            Response::Name(name) => f(name, Response::Status(state, target, pid, last_exit, time)),
+            Response::ServiceNotFound => return false,
+            response => return response.cmd_return_failed(),
+        }
+    }
+
+    false
+}
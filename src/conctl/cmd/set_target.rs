@@ -1,23 +1,51 @@
+use super::{cmd_settle_down, cmd_settle_once, cmd_settle_restart, cmd_settle_up, for_each_status};
+use crate::internal::SERVICE_COUNT;
 use connate::err::*;
+use connate::internal_api::Target;
 use connate::ipc::*;
 use connate::os::*;
+use connate::types::pid_t;
 
 /// Generic helper for commands that set the target of one or more services
+///
+/// `all_target` is the target to set on every service if `--all` is given in place of service
+/// names, or None if `--all` is not supported for this command.
 fn set_target_generic<'a, F>(
     mut ipc_client: IpcClient,
-    argv: Argv<'a>,
+    mut argv: Argv<'a>,
     request_fn: F,
-    target: &str,
+    target: Target,
+    all_target: Option<Target>,
 ) -> !
 where
     F: Fn(&'a [u8]) -> Request<'a>,
 {
     let mut failed = false;
 
+    if argv
+        .first()
+        .is_some_and(|arg| arg.to_bytes() == b"--dry-run")
+    {
+        let _ = argv.pop();
+        preview_target(ipc_client, argv, target);
+    }
+
     if argv.is_empty() {
         abort_with_msg("No service specified");
     }
 
+    if argv.first().is_some_and(|arg| arg.to_bytes() == b"--all") {
+        let Some(all_target) = all_target else {
+            abort_with_msg(
+                "--all is not supported for this cmd, as conflicting services cannot all be up",
+            );
+        };
+        if argv.len() > 1 {
+            abort_with_msg("--all cannot be combined with service names");
+        }
+        set_target_all(ipc_client, all_target, target);
+    }
+
     // Calculate max name length for padding
     let mut max_name_len: usize = 0;
     for name in argv.iter() {
@@ -36,32 +64,288 @@ where
         if response.cmd_return_failed() {
             failed = true;
             println(response);
-        } else {
-            print("set target ");
-            print(target);
-            print("\n");
+            continue;
         }
+
+        print("set target ");
+        print(target.name());
+        // Service was broken out of Failed, which resets its attempt count
+        if let Response::AttemptCount(count) = response {
+            print(" (was failed after ");
+            print_color(Color::Transition, count);
+            print(if count == 1 {
+                " attempt)"
+            } else {
+                " attempts)"
+            });
+        }
+        print("\n");
     }
 
     exit(if failed { 1 } else { 0 });
 }
 
+/// Print the services whose targets would change, without changing anything
+///
+/// Each service is previewed independently of the others.
+fn preview_target(mut ipc_client: IpcClient, argv: Argv, target: Target) -> ! {
+    let mut failed = false;
+    let mut changes: usize = 0;
+
+    if argv.is_empty() {
+        abort_with_msg("No service specified");
+    }
+    if argv.first().is_some_and(|arg| arg.to_bytes() == b"--all") {
+        abort_with_msg("--dry-run cannot be combined with --all");
+    }
+
+    // First pass: find max name length of the affected services
+    let mut max_name_len: usize = 0;
+    for name in argv.iter() {
+        for n in 0.. {
+            match ipc_client.send_and_receive(Request::PreviewTarget(n, target, name.to_bytes())) {
+                Response::TargetChange(_, _, affected) => {
+                    max_name_len = core::cmp::max(max_name_len, affected.len());
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // Second pass: print with padding
+    for name in argv.iter() {
+        for n in 0.. {
+            match ipc_client.send_and_receive(Request::PreviewTarget(n, target, name.to_bytes())) {
+                Response::TargetChange(from, to, affected) => {
+                    print_color(Color::Service, affected);
+                    print_color(Color::Glue, ":");
+                    affected.print_padding(max_name_len + 1);
+                    print(from);
+                    print_color(Color::Glue, " -> ");
+                    print(to);
+                    print("\n");
+                    changes += 1;
+                }
+                // Past the last affected service
+                Response::FieldIsNone => break,
+                response => {
+                    failed = true;
+                    print_color(Color::Service, name);
+                    print_color(Color::Glue, ":");
+                    print(" ");
+                    println(response);
+                    break;
+                }
+            }
+        }
+    }
+
+    if changes == 0 && !failed {
+        println("no targets would change");
+    }
+
+    exit(if failed { 1 } else { 0 });
+}
+
+/// Set the target of every service in one request so connate applies it atomically
+///
+/// Given how much this may affect, e.g. taking down the system service, every service whose target
+/// changed is listed along with its previous target.
+fn set_target_all(mut ipc_client: IpcClient, all_target: Target, target: Target) -> ! {
+    // Targets before the change, by index, of the services it will affect
+    let mut previous: [Option<Target>; SERVICE_COUNT] = [None; SERVICE_COUNT];
+    let mut max_name_len: usize = 0;
+    let mut i: usize = 0;
+    let mut failed = for_each_status(&mut ipc_client, |name, response| {
+        if let Response::Status(_, from, ..) = response
+            && changes_target(from, all_target)
+            && let Some(previous) = previous.get_mut(i)
+        {
+            *previous = Some(from);
+            max_name_len = core::cmp::max(max_name_len, name.len());
+        }
+        i += 1;
+    });
+
+    let response = ipc_client.send_and_receive(Request::SetTargetAll(all_target));
+    if response.cmd_return_failed() {
+        println(response);
+        exit(1);
+    }
+
+    let mut i: usize = 0;
+    failed |= for_each_status(&mut ipc_client, |name, _| {
+        if let Some(Some(from)) = previous.get(i) {
+            print_color(Color::Service, name);
+            print_color(Color::Glue, ":");
+            name.print_padding(max_name_len + 1);
+            print(*from);
+            print_color(Color::Glue, " -> ");
+            print(all_target);
+            print("\n");
+        }
+        i += 1;
+    });
+
+    print_color(Color::Service, "all services");
+    print_color(Color::Glue, ":");
+    print(" set target ");
+    print(target.name());
+    print("\n");
+    exit(if failed { 1 } else { 0 });
+}
+
+/// Whether `SetTargetAll(all_target)` changes the target of a service whose target is `from`
+///
+/// Mirrors connate, which leaves services targeted down as they are when restarting everything.
+fn changes_target(from: Target, all_target: Target) -> bool {
+    match (from, all_target) {
+        (_, Target::Restart) => !matches!(from, Target::Down),
+        (from, all_target) => from.as_byte() != all_target.as_byte(),
+    }
+}
+
+/// Whether a trailing `--timeout <secs>` was given, upgrading the command to wait for its services
+/// to settle as the uppercase settle commands do
+fn waits_to_settle(argv: &Argv) -> bool {
+    let flag = argv.len().checked_sub(2).and_then(|i| argv.get(i));
+    if flag.is_none_or(|flag| flag.to_bytes() != b"--timeout") {
+        return false;
+    }
+    if argv
+        .first()
+        .is_some_and(|arg| matches!(arg.to_bytes(), b"--all" | b"--dry-run"))
+    {
+        abort_with_msg("--timeout cannot be combined with --all or --dry-run");
+    }
+    true
+}
+
 #[inline]
-pub fn cmd_up(ipc_client: IpcClient, argv: Argv) -> ! {
-    set_target_generic(ipc_client, argv, Request::SetTargetUp, "up")
+pub fn cmd_up(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
+    if waits_to_settle(&argv) {
+        cmd_settle_up(ipc_client, argv, connate_pid);
+    }
+    set_target_generic(ipc_client, argv, Request::SetTargetUp, Target::Up, None)
 }
 
 #[inline]
-pub fn cmd_down(ipc_client: IpcClient, argv: Argv) -> ! {
-    set_target_generic(ipc_client, argv, Request::SetTargetDown, "down")
+pub fn cmd_down(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
+    if waits_to_settle(&argv) {
+        cmd_settle_down(ipc_client, argv, connate_pid);
+    }
+    set_target_generic(
+        ipc_client,
+        argv,
+        Request::SetTargetDown,
+        Target::Down,
+        Some(Target::Down),
+    )
 }
 
 #[inline]
-pub fn cmd_restart(ipc_client: IpcClient, argv: Argv) -> ! {
-    set_target_generic(ipc_client, argv, Request::SetTargetRestart, "restart")
+pub fn cmd_restart(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
+    if waits_to_settle(&argv) {
+        cmd_settle_restart(ipc_client, argv, connate_pid);
+    }
+    set_target_generic(
+        ipc_client,
+        argv,
+        Request::SetTargetRestart,
+        Target::Restart,
+        Some(Target::Restart),
+    )
 }
 
 #[inline]
-pub fn cmd_once(ipc_client: IpcClient, argv: Argv) -> ! {
-    set_target_generic(ipc_client, argv, Request::SetTargetOnce, "once")
+pub fn cmd_once(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
+    if waits_to_settle(&argv) {
+        cmd_settle_once(ipc_client, argv, connate_pid);
+    }
+    set_target_generic(ipc_client, argv, Request::SetTargetOnce, Target::Once, None)
+}
+
+/// Bring up a group and everything it pulls up and bring down every other service
+pub fn cmd_isolate(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
+    let name = argv.pop().or_abort("No group specified");
+    if !argv.is_empty() {
+        abort_with_msg("isolate takes exactly one group");
+    }
+    let name = name.to_bytes();
+
+    print_color(Color::Service, name);
+    print_color(Color::Glue, ":");
+    print(" ");
+
+    // Refuse non-groups here to give a clearer error than connate's bare failure
+    match ipc_client.send_and_receive(Request::QueryGroups(0, name)) {
+        Response::Name(_) => {}
+        Response::FieldIsNone => {
+            print_color(Color::Error, "not a group");
+            print("\n");
+            exit(1);
+        }
+        response => {
+            println(response);
+            exit(1);
+        }
+    }
+
+    let response = ipc_client.send_and_receive(Request::Isolate(name));
+    if response.cmd_return_failed() {
+        println(response);
+        exit(1);
+    }
+
+    println("isolated");
+    exit(0);
+}
+
+/// Bring up the services of a `Config::TARGETS` entry and bring down every other service
+pub fn cmd_boot_target(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
+    let name = argv.pop().or_abort("No boot target specified");
+    if !argv.is_empty() {
+        abort_with_msg("boot-target takes exactly one target");
+    }
+    let name = name.to_bytes();
+
+    print(name);
+    print_color(Color::Glue, ":");
+    print(" ");
+
+    let response = ipc_client.send_and_receive(Request::IsolateBootTarget(name));
+    if response.cmd_return_failed() {
+        println(response);
+        exit(1);
+    }
+
+    println("isolated");
+    exit(0);
+}
+
+/// Bring up `Config::EMERGENCY_SERVICES` and bring down every other service
+pub fn cmd_single(mut ipc_client: IpcClient, argv: Argv) -> ! {
+    if !argv.is_empty() {
+        abort_with_msg("single takes no arguments");
+    }
+
+    print("single");
+    print_color(Color::Glue, ":");
+    print(" ");
+
+    match ipc_client.send_and_receive(Request::IsolateEmergency) {
+        Response::FieldIsNone => {
+            print_color(Color::Error, "no EMERGENCY_SERVICES configured");
+            print("\n");
+            exit(1);
+        }
+        response if response.cmd_return_failed() => {
+            println(response);
+            exit(1);
+        }
+        _ => {}
+    }
+
+    println("isolated");
+    exit(0);
 }
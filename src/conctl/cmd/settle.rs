@@ -1,179 +1,479 @@
+use crate::internal::SERVICE_COUNT;
 use connate::err::*;
+use connate::internal_api::{State, Target};
 use connate::ipc::*;
 use connate::os::*;
 use connate::syscall::{PollEvents, PollFd, poll};
 use connate::types::*;
-use connate::util::BufWriter;
+use connate::util::{BufWriter, parse_duration};
 use itoa::Integer; // ::MAX_STR_LEN
 
+/// How often to check for intermediate states while waiting to settle
+const PROGRESS_INTERVAL_MILLIS: i32 = 250;
+
+/// How often to query every pending service's state if connate was built without settle pipes
+///
+/// Each check is a round-trip per pending service, holding the IPC lock while it does so.
+const SETTLE_POLL_INTERVAL_MILLIS: i32 = 100;
+
+/// Space for the names of the dependents `drain` waits on
+const DRAIN_NAMES_BUF_SIZE: usize = 64 * 1024;
+
 /// Generic helper for settle commands that set target and wait for stable states
 ///
-/// Sets the target for all services, then blocks until each reaches a stable state.
-/// Exits with error if any service reaches Failed or CannotStop states.
+/// `request_fn` maps each argument to the service it names and the request setting its target.
+/// Sets the target for all services, then blocks until all reach a stable state.  If stderr is a
+/// TTY, each service's intermediate states are reported there as they change.
+/// Exits with error if any service reaches Failed or CannotStop states, or if an optional trailing
+/// `--timeout <secs>` elapses first.
+///
+/// If connate was built without the `settle` feature, falls back to periodically querying each
+/// pending service's state.
 fn settle_generic<'a, F>(
     mut ipc_client: IpcClient,
-    argv: Argv<'a>,
+    mut argv: Argv<'a>,
     connate_pid: pid_t,
     request_fn: F,
 ) -> !
 where
-    F: Fn(&'a [u8]) -> Request<'a>,
+    F: Fn(&'a [u8]) -> (&'a [u8], Request<'a>),
 {
+    let timeout_millis = pop_timeout(&mut argv);
+
     if argv.is_empty() {
         abort_with_msg("No service specified");
     }
 
-    // Calculate max name length for padding
+    // Calculate max name length for padding.  This also validates every argument before any
+    // target is set.
+    let mut names: [&[u8]; SERVICE_COUNT] = [&[]; SERVICE_COUNT];
+    let names = names
+        .get_mut(..argv.len())
+        .or_abort("More services specified than are configured");
     let mut max_name_len: usize = 0;
-    for name in argv.iter() {
-        max_name_len = core::cmp::max(max_name_len, name.to_bytes().len());
+    for (name, arg) in names.iter_mut().zip(argv.iter()) {
+        *name = request_fn(arg.to_bytes()).0;
+        max_name_len = core::cmp::max(max_name_len, name.len());
     }
 
     // Set target for all services first
-    for name in argv.iter() {
-        let request = request_fn(name.to_bytes());
+    for arg in argv.iter() {
+        let (name, request) = request_fn(arg.to_bytes());
         let response = ipc_client.send_and_receive(request);
         if response.cmd_return_failed() {
-            print_color(Color::Service, name.to_bytes());
-            print_color(Color::Glue, ":");
-            name.to_bytes().print_padding(max_name_len + 1);
+            print_name(name, max_name_len);
             println(response);
             exit(1);
         }
     }
 
-    // Wait for each service to reach a stable state, sequentially
+    let start = get_time_monotonic().or_abort("Unable to get current time");
+    let any_bad = wait_to_settle(
+        &mut ipc_client,
+        names,
+        max_name_len,
+        connate_pid,
+        start,
+        timeout_millis,
+    );
+    exit(if any_bad { 1 } else { 0 });
+}
+
+/// Block until every named service reaches a stable state, reporting each as it settles
+///
+/// Returns whether any settled into Failed or CannotStop.  Exits with error if `timeout_millis`
+/// elapses from `start` first.
+fn wait_to_settle(
+    ipc_client: &mut IpcClient,
+    names: &[&[u8]],
+    max_name_len: usize,
+    connate_pid: pid_t,
+    start: timespec,
+    timeout_millis: Option<i64>,
+) -> bool {
+    // Wait for all services to reach a stable state in parallel, reporting each as it settles
+    //
+    // Every pending service's settle pipe is polled at once such that a quickly settling service
+    // is reported without waiting on slower ones.
+    //
+    // TODO: How should we handle the state stabilizing to something other than the requested
+    // state? It's possible another `conctl` changes the target while we're waiting for
+    // stabilization.
+    let mut pollfds = [PollFd {
+        fd: -1, // poll() ignores negative FDs
+        events: PollEvents::POLLIN,
+        revents: PollEvents::empty(),
+    }; SERVICE_COUNT];
+    let pollfds = pollfds
+        .get_mut(..names.len())
+        .or_abort("More services specified than are configured");
+    let mut last_states: [Option<State>; SERVICE_COUNT] = [None; SERVICE_COUNT];
+    // Whether each service has yet to settle.  Without settle pipes, `pollfds` cannot tell.
+    let mut waiting = [false; SERVICE_COUNT];
+    let mut pending: usize = 0;
     let mut any_bad = false;
+    let mut settle_disabled = false;
+    let show_progress = STDERR.isatty();
 
-    for name in argv.iter() {
-        // Print "<service>: " (state will follow)
-        print_color(Color::Service, name.to_bytes());
-        print_color(Color::Glue, ":");
-        name.to_bytes().print_padding(max_name_len + 1);
-
-        // Query current state
-        let mut state =
-            match ipc_client.send_and_receive(Request::QueryByNameState(name.to_bytes())) {
-                Response::State(state) => state,
-                Response::ServiceNotFound => {
-                    print_color(Color::NotFound, "not-found");
-                    print("\n");
-                    exit(1);
-                }
-                _ => abort_with_msg("Unexpected response to QueryByNameState"),
-            };
+    for (((&name, pollfd), last_state), waiting) in names
+        .iter()
+        .zip(pollfds.iter_mut())
+        .zip(last_states.iter_mut())
+        .zip(waiting.iter_mut())
+    {
+        // Open the settle pipe before checking the state, as connate only notifies pipes which
+        // already exist: settling in between would otherwise go unnoticed
+        if !settle_disabled {
+            match open_settle_fd(ipc_client, name, max_name_len, connate_pid) {
+                Some(fd) => pollfd.fd = fd.as_raw(),
+                None => settle_disabled = true,
+            }
+        }
+
+        let state = query_state(ipc_client, name, max_name_len);
+        if state.stable() {
+            any_bad |= print_settled(name, max_name_len, state);
+            if pollfd.fd >= 0 {
+                let _ = Fd::from_raw(pollfd.fd).close();
+                pollfd.fd = -1;
+            }
+            continue;
+        }
+
+        *waiting = true;
+        pending += 1;
+        if show_progress {
+            print_progress(name, max_name_len, state);
+        }
+        *last_state = Some(state);
+    }
+
+    while pending > 0 {
+        // Release IPC lock before blocking poll to allow supervisors
+        // and other conctl instances to work while we're blocked
+        ipc_client.unlock();
 
-        // If not already stable, wait for stabilization
+        // Poll until the remaining time elapses, or with no timeout (-1) if none was requested.
         //
-        // TODO: How should we handle the state stabilizing to something other than the requested
-        // state? It's possible another `conctl` changes the target while we're waiting for
-        // stabilization.
-        if !state.stable() {
-            // Get settle pipe FD for this service
-            let settle_fd =
-                match ipc_client.send_and_receive(Request::QuerySettleFd(name.to_bytes())) {
-                    Response::SettleFd(fd) => fd,
-                    Response::SettleDisabled => {
-                        print_color(Color::Error, "settle-disabled");
-                        print("\n");
-                        abort_with_msg("Settle feature is disabled in this build of connate");
-                    }
-                    Response::ServiceNotFound => {
-                        print_color(Color::NotFound, "not-found");
-                        print("\n");
-                        exit(1);
-                    }
-                    _ => abort_with_msg("Unexpected response to QuerySettleFd"),
-                };
-
-            // Build path to /proc/<pid>/fd/<settle_fd>
-            const PATH_SIZE: usize = b"/proc/".len()
-                + pid_t::MAX_STR_LEN
-                + "/fd/".len()
-                + c_int::MAX_STR_LEN
-                + b"\0".len();
-            let mut buf = [0u8; PATH_SIZE];
-            let mut writer = BufWriter::new(&mut buf);
-            let mut itoa_buf = itoa::Buffer::new();
-
-            writer
-                .push(b"/proc/")
-                .and_then(|_| writer.push(itoa_buf.format(connate_pid).as_bytes()))
-                .and_then(|_| writer.push(b"/fd/"))
-                .and_then(|_| writer.push(itoa_buf.format(settle_fd).as_bytes()))
-                .and_then(|_| writer.push(b"\0"))
-                .or_abort("buffer overflow building settle FD path");
-
-            // Safety: We just built this buffer including the trailing null
-            let settle_path: &CStr =
-                unsafe { CStr::from_bytes_with_nul_unchecked(writer.as_slice()) };
-            let settle_pipe_fd =
-                Fd::open(settle_path, OpenFlags::O_RDONLY, 0).or_fs_abort("open", settle_path);
-
-            // Poll until readable, then re-check state
-            loop {
-                let mut pollfd = PollFd {
-                    fd: settle_pipe_fd.as_raw(),
-                    events: PollEvents::POLLIN,
-                    revents: PollEvents::empty(),
-                };
-
-                // Release IPC lock before blocking poll to allow supervisors
-                // and other conctl instances to work while we're blocked
-                ipc_client.unlock();
-
-                // Poll with no timeout (-1)
-                if unsafe { poll(core::slice::from_mut(&mut pollfd), -1) }
-                    .is_err_and(|e| e != Errno::EINTR)
-                {
-                    abort_with_msg("Unable to poll() on service settle fd");
-                }
+        // Settle pipes only become readable once a service is stable.  When reporting progress,
+        // or lacking settle pipes, wake periodically to check states as well.
+        let remaining = timeout_millis.map(|timeout| {
+            let now = get_time_monotonic().or_abort("Unable to get current time");
+            (timeout - now.millis_since(start)).clamp(0, i32::MAX as i64) as i32
+        });
+        let interval = match (settle_disabled, show_progress) {
+            (true, _) => Some(SETTLE_POLL_INTERVAL_MILLIS),
+            (false, true) => Some(PROGRESS_INTERVAL_MILLIS),
+            (false, false) => None,
+        };
+        let poll_timeout = match (remaining, interval) {
+            (Some(remaining), Some(interval)) => remaining.min(interval),
+            (Some(remaining), None) => remaining,
+            (None, Some(interval)) => interval,
+            (None, None) => -1,
+        };
+        if unsafe { poll(pollfds, poll_timeout) }.is_err_and(|e| e != Errno::EINTR) {
+            abort_with_msg("Unable to poll() on service settle fd");
+        }
 
-                // Re-acquire lock and query state
-                ipc_client.lock_quiet();
+        // Re-acquire lock and re-check any service whose settle pipe became readable, or every
+        // pending service if reporting progress or lacking settle pipes
+        ipc_client.lock_quiet();
 
-                state =
-                    match ipc_client.send_and_receive(Request::QueryByNameState(name.to_bytes())) {
-                        Response::State(s) => s,
-                        _ => abort_with_msg("Unexpected response to QueryByNameState"),
-                    };
+        for (((&name, pollfd), last_state), waiting) in names
+            .iter()
+            .zip(pollfds.iter_mut())
+            .zip(last_states.iter_mut())
+            .zip(waiting.iter_mut())
+        {
+            if !*waiting || (pollfd.revents.bits() == 0 && !show_progress && !settle_disabled) {
+                continue;
+            }
+            pollfd.revents = PollEvents::empty();
 
-                if state.stable() {
-                    break;
+            let state = query_state(ipc_client, name, max_name_len);
+            if state.stable() {
+                any_bad |= print_settled(name, max_name_len, state);
+                if pollfd.fd >= 0 {
+                    let _ = Fd::from_raw(pollfd.fd).close();
+                    pollfd.fd = -1;
                 }
+                *waiting = false;
+                pending -= 1;
+            } else if show_progress
+                && last_state.is_none_or(|last| last.as_byte() != state.as_byte())
+            {
+                print_progress(name, max_name_len, state);
+                *last_state = Some(state);
             }
+        }
 
-            let _ = settle_pipe_fd.close();
+        let timed_out = match timeout_millis {
+            Some(timeout) => {
+                let now = get_time_monotonic().or_abort("Unable to get current time");
+                pending > 0 && now.millis_since(start) >= timeout
+            }
+            None => false,
+        };
+        if timed_out {
+            for (&name, &waiting) in names.iter().zip(waiting.iter()) {
+                if !waiting {
+                    continue;
+                }
+                let state = query_state(ipc_client, name, max_name_len);
+                print_name(name, max_name_len);
+                print(state);
+                print(" ");
+                print_color(Color::Error, "timed-out");
+                print("\n");
+            }
+            exit(1);
         }
+    }
+
+    any_bad
+}
 
-        // Print state
-        println(state);
+/// Bring a service down only once everything depending on it has settled
+///
+/// The dependents `down` would bring down along with the service are brought down and waited on
+/// first, such that the service keeps serving them until they have stopped.  If any fails to stop,
+/// the service is left up.  An optional trailing `--timeout <secs>` covers both steps.
+pub fn cmd_drain(mut ipc_client: IpcClient, mut argv: Argv, connate_pid: pid_t) -> ! {
+    let timeout_millis = pop_timeout(&mut argv);
+    let name = argv.pop().or_abort("No service specified");
+    if !argv.is_empty() {
+        abort_with_msg("drain takes exactly one service");
+    }
+    let name = name.to_bytes();
 
-        // Track bad states
-        if state.bad() {
-            any_bad = true;
+    let mut buf = [0u8; DRAIN_NAMES_BUF_SIZE];
+    let start = get_time_monotonic().or_abort("Unable to get current time");
+    let drained = drain::<SERVICE_COUNT>(
+        &mut ipc_client,
+        name,
+        &mut buf,
+        |ipc_client, names, max_name_len| {
+            wait_to_settle(
+                ipc_client,
+                names,
+                max_name_len,
+                connate_pid,
+                start,
+                timeout_millis,
+            )
+        },
+        |name, max_name_len, response| {
+            print_name(name, max_name_len);
+            println(response);
+        },
+    );
+    match drained {
+        Drain::Down(any_bad) => exit(if any_bad { 1 } else { 0 }),
+        Drain::NotDrained => {
+            print_name(name, name.len());
+            print_color(Color::Error, "not drained, as a dependent did not stop");
+            print("\n");
+            exit(1);
         }
+        Drain::Refused => exit(1),
     }
+}
 
-    exit(if any_bad { 1 } else { 0 });
+/// Remove an optional trailing `--timeout <secs>` from argv, returning it in milliseconds
+pub fn pop_timeout(argv: &mut Argv) -> Option<i64> {
+    let flag_index = argv.len().checked_sub(2)?;
+    if argv.get(flag_index)?.to_bytes() != b"--timeout" {
+        return None;
+    }
+
+    let millis = argv
+        .pop_back()
+        .and_then(|secs| parse_duration(secs.to_bytes()).ok())
+        .and_then(|secs| secs.checked_mul(1000))
+        .or_abort("--timeout requires a duration such as 90, 90s, or 1h30m");
+    let _ = argv.pop_back();
+    Some(millis)
+}
+
+/// Block until connate reports boot has completed, then print how many services were Up and had
+/// failed at that point
+///
+/// Boot completes the first time every service targeted up or once has settled.  Exits non-zero
+/// if any service had failed by then, or if an optional `--timeout <secs>` elapses first.
+pub fn cmd_boot_wait(mut ipc_client: IpcClient, mut argv: Argv) -> ! {
+    let timeout_millis = pop_timeout(&mut argv);
+    if !argv.is_empty() {
+        abort_with_msg("boot-wait only takes an optional --timeout <secs>");
+    }
+    let start = get_time_monotonic().or_abort("Unable to get current time");
+
+    loop {
+        match ipc_client.send_and_receive(Request::QueryBootComplete) {
+            Response::BootComplete(up, failed) => {
+                println(Response::BootComplete(up, failed));
+                exit(if failed > 0 { 1 } else { 0 });
+            }
+            Response::BootPending => {}
+            Response::InvalidRequest => abort_with_msg("connate predates boot-wait"),
+            _ => abort_with_msg("Unexpected response to QueryBootComplete"),
+        }
+
+        let now = get_time_monotonic().or_abort("Unable to get current time");
+        let remaining = timeout_millis.map(|timeout| timeout - now.millis_since(start));
+        if remaining.is_some_and(|remaining| remaining <= 0) {
+            print(Response::BootPending);
+            print(" ");
+            print_color(Color::Error, "timed-out");
+            print("\n");
+            exit(1);
+        }
+
+        // Release IPC lock while waiting such that boot itself is not held up
+        ipc_client.unlock();
+        let interval = remaining.map_or(SETTLE_POLL_INTERVAL_MILLIS, |remaining| {
+            remaining.clamp(0, SETTLE_POLL_INTERVAL_MILLIS as i64) as i32
+        });
+        if unsafe { poll(&mut [], interval) }.is_err_and(|e| e != Errno::EINTR) {
+            abort_with_msg("Unable to poll() while waiting for boot");
+        }
+        ipc_client.lock_quiet();
+    }
+}
+
+/// Print "<service>: " padded such that following fields align
+fn print_name(name: &[u8], max_name_len: usize) {
+    print_color(Color::Service, name);
+    print_color(Color::Glue, ":");
+    name.print_padding(max_name_len + 1);
+}
+
+/// Report a service's intermediate state to stderr
+///
+/// Uncolored and on stderr so it neither interferes with stdout's final states nor needs
+/// stripping when piped.
+fn print_progress(name: &[u8], max_name_len: usize, state: State) {
+    eprint(name);
+    eprint(":");
+    for _ in name.len()..=max_name_len {
+        eprint(" ");
+    }
+    eprint(state.name());
+    eprint("...\n");
+}
+
+/// Print a service's settled state
+/// Returns "bad": true if the service settled into Failed or CannotStop
+fn print_settled(name: &[u8], max_name_len: usize, state: State) -> bool {
+    print_name(name, max_name_len);
+    println(state);
+    state.bad()
+}
+
+fn query_state(ipc_client: &mut IpcClient, name: &[u8], max_name_len: usize) -> State {
+    match ipc_client.send_and_receive(Request::QueryByNameState(name)) {
+        Response::State(state) => state,
+        Response::ServiceNotFound => {
+            print_name(name, max_name_len);
+            print_color(Color::NotFound, "not-found");
+            print("\n");
+            exit(1);
+        }
+        _ => abort_with_msg("Unexpected response to QueryByNameState"),
+    }
+}
+
+/// Open the read end of a service's settle pipe via /proc/<pid>/fd/<settle_fd>
+///
+/// Returns None if connate was built without the `settle` feature.
+fn open_settle_fd(
+    ipc_client: &mut IpcClient,
+    name: &[u8],
+    max_name_len: usize,
+    connate_pid: pid_t,
+) -> Option<Fd> {
+    // Get settle pipe FD for this service
+    let settle_fd = match ipc_client.send_and_receive(Request::QuerySettleFd(name)) {
+        Response::SettleFd(fd) => fd,
+        Response::SettleDisabled => return None,
+        Response::ServiceNotFound => {
+            print_name(name, max_name_len);
+            print_color(Color::NotFound, "not-found");
+            print("\n");
+            exit(1);
+        }
+        _ => abort_with_msg("Unexpected response to QuerySettleFd"),
+    };
+
+    // Build path to /proc/<pid>/fd/<settle_fd>
+    const PATH_SIZE: usize =
+        b"/proc/".len() + pid_t::MAX_STR_LEN + "/fd/".len() + c_int::MAX_STR_LEN + b"\0".len();
+    let mut buf = [0u8; PATH_SIZE];
+    let mut writer = BufWriter::new(&mut buf);
+    let mut itoa_buf = itoa::Buffer::new();
+
+    writer
+        .push(b"/proc/")
+        .and_then(|_| writer.push(itoa_buf.format(connate_pid).as_bytes()))
+        .and_then(|_| writer.push(b"/fd/"))
+        .and_then(|_| writer.push(itoa_buf.format(settle_fd).as_bytes()))
+        .and_then(|_| writer.push(b"\0"))
+        .or_abort("buffer overflow building settle FD path");
+
+    // Safety: We just built this buffer including the trailing null
+    let settle_path: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(writer.as_slice()) };
+    Some(Fd::open(settle_path, OpenFlags::O_RDONLY, 0).or_fs_abort("open", settle_path))
+}
+
+/// Split a `<service>=<target>` argument into the service name and the request setting its target
+fn target_pair_request(arg: &[u8]) -> (&[u8], Request<'_>) {
+    let (name, target) = arg
+        .iter()
+        .rposition(|&b| b == b'=')
+        .and_then(|pos| Some((arg.get(..pos)?, arg.get(pos + 1..)?)))
+        .filter(|(name, _)| !name.is_empty())
+        .or_abort("settle takes <service>=<target> arguments, e.g. `sshd=up`");
+    let target = Target::from_name(target)
+        .or_abort("settle targets must be one of up, down, restart, or once");
+
+    let request = match target {
+        Target::Up => Request::SetTargetUp(name),
+        Target::Down => Request::SetTargetDown(name),
+        Target::Restart => Request::SetTargetRestart(name),
+        Target::Once => Request::SetTargetOnce(name),
+    };
+    (name, request)
+}
+
+#[inline]
+pub fn cmd_settle(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
+    settle_generic(ipc_client, argv, connate_pid, target_pair_request)
 }
 
 #[inline]
 pub fn cmd_settle_up(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
-    settle_generic(ipc_client, argv, connate_pid, Request::SetTargetUp)
+    settle_generic(ipc_client, argv, connate_pid, |name| {
+        (name, Request::SetTargetUp(name))
+    })
 }
 
 #[inline]
 pub fn cmd_settle_down(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
-    settle_generic(ipc_client, argv, connate_pid, Request::SetTargetDown)
+    settle_generic(ipc_client, argv, connate_pid, |name| {
+        (name, Request::SetTargetDown(name))
+    })
 }
 
 #[inline]
 pub fn cmd_settle_restart(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
-    settle_generic(ipc_client, argv, connate_pid, Request::SetTargetRestart)
+    settle_generic(ipc_client, argv, connate_pid, |name| {
+        (name, Request::SetTargetRestart(name))
+    })
 }
 
 #[inline]
 pub fn cmd_settle_once(ipc_client: IpcClient, argv: Argv, connate_pid: pid_t) -> ! {
-    settle_generic(ipc_client, argv, connate_pid, Request::SetTargetOnce)
+    settle_generic(ipc_client, argv, connate_pid, |name| {
+        (name, Request::SetTargetOnce(name))
+    })
 }
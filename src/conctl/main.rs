@@ -1,5 +1,7 @@
 #![cfg_attr(not(test), no_std)]
 #![cfg_attr(not(test), no_main)]
+// `main` is compiled out under test, leaving everything it reaches unused
+#![cfg_attr(test, allow(dead_code, unused_imports))]
 
 // Implementation of features Rust core expects from libc
 //
@@ -12,33 +12,53 @@ impl Config for Connate {
     const DEFAULT_SERVICE: Service = Service {
         name: "unspecified-service-name",
         init_target: Target::Up,
+        start_if_cmdline: None,
         // Dependency entries
         needs: &[],
         wants: &[],
         conflicts: &[],
+        conflict_action: ConflictAction::Wait,
+        serialize_with: &[],
         groups: &[],
+        stop_last: false,
         // Execution entries
         setup: Run::None,
         run: Run::None,
         ready: Ready::Immediately,
         cleanup: Run::None,
         stop_all_children: false,
+        children_stop: ChildrenStop::Kill,
+        kill_mode: KillMode::Process,
         // Retry and timeout entries
         max_setup_time: Some(core::time::Duration::from_secs(30)),
         max_ready_time: Some(core::time::Duration::from_secs(10)),
         max_stop_time: Some(core::time::Duration::from_secs(10)),
         max_cleanup_time: Some(core::time::Duration::from_secs(10)),
+        max_wait_time: None,
+        up_stable_time: None,
         retry: Retry::AfterDoublingDelay {
             initial_delay: core::time::Duration::from_secs(1),
             max_attempt_count: Some(5),
+            max_delay: Some(core::time::Duration::from_secs(60)),
+            jitter: None,
         },
+        reset_attempts_on: ResetPolicy::UpStable,
+        success_exit_codes: &[],
+        fatal_exit_codes: &[],
         // Execution attribute entries
+        stdin: StdinSource::Null,
         log: Log::Inherit,
         env: &["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],
+        clear_env: true,
+        inherit_env: &[],
         user: None,
         group: None,
+        use_user_groups: false,
         chdir: None,
         no_new_privs: true,
+        new_session: true,
+        controlling_tty: None,
+        cgroup: None,
     };
 
     const SERVICES: &[Service] = &[];
@@ -1,6 +1,7 @@
 //! Configuration API / documentation.
 
 use crate::err::Errno;
+use core::ffi::{CStr, c_int};
 
 /// To configure connate, implement `trait Config` on this `struct Connate` in
 /// `src/config/config.rs`
@@ -35,7 +36,7 @@ pub trait Config {
     /// Default fields that can be used to avoid verbosely populating every field in every service.
     ///
     /// Overwrite in config.rs as desired then include in a given Service definition to implement
-    /// default field values.
+    /// default field values.  The `service!` helper macro includes it automatically.
     ///
     /// Examples:
     ///
@@ -58,34 +59,208 @@ pub trait Config {
     const DEFAULT_SERVICE: Service = Service {
         name: "unspecified-service-name",
         init_target: Target::Up,
+        start_if_cmdline: None,
         // Dependency entries
         needs: &[],
         wants: &[],
         conflicts: &[],
+        conflict_action: ConflictAction::Wait,
+        serialize_with: &[],
         groups: &[],
+        stop_last: false,
         // Execution entries
         setup: Run::None,
         run: Run::None,
         ready: Ready::Immediately,
         cleanup: Run::None,
         stop_all_children: false,
+        children_stop: ChildrenStop::Kill,
+        kill_mode: KillMode::Process,
         // Retry and timeout entries
         max_setup_time: Some(core::time::Duration::from_secs(10)),
         max_ready_time: Some(core::time::Duration::from_secs(10)),
         max_stop_time: Some(core::time::Duration::from_secs(2)),
         max_cleanup_time: Some(core::time::Duration::from_secs(10)),
+        max_wait_time: None,
+        up_stable_time: None,
         retry: Retry::Never,
+        reset_attempts_on: ResetPolicy::UpStable,
+        success_exit_codes: &[],
+        fatal_exit_codes: &[],
         // Execution attribute entries
+        stdin: StdinSource::Null,
         log: Log::Inherit,
         env: &[],
+        clear_env: true,
+        inherit_env: &[],
         user: None,
         group: None,
+        use_user_groups: false,
         chdir: None,
         no_new_privs: false,
+        new_session: true,
+        controlling_tty: None,
+        cgroup: None,
     };
 
     /// The list of services to run
+    ///
+    /// There is no fixed limit on how many.  In practice, the FDs connate holds must fit within the
+    /// `RLIMIT_NOFILE` of 1024 the kernel starts init with, which the build checks: two for each
+    /// logger's stdin pipe and, with `LINE_BUFFER_CONSOLE`, two for each service logging to
+    /// `Log::Inherit` or `Log::Service`, on top of a dozen of connate's own.  `conctl` waits take
+    /// two more per service waited on, and fall back to polling if those do not fit.
     const SERVICES: &'static [Service];
+
+    /// File descriptor numbers connate reserves for itself
+    ///
+    /// connate moves some of its file descriptors to fixed numbers so that they can be accessed
+    /// via `/proc/<connate-pid>/fd/<fd>` and survive re-exec.  If connate is launched in an
+    /// unusual environment which passes it file descriptors in this range, override these to
+    /// avoid collisions.
+    ///
+    /// Each must be unique and 10 or greater, keeping clear of stdin, stdout, stderr, and the FDs
+    /// connate opens before moving them into place.
+    const FIXED_FDS: FixedFds = FixedFds {
+        session_state: 100,
+        signal: 101,
+        lock_file: 102,
+        req_read: 110,
+        req_write: 111,
+        resp_read: 112,
+        resp_write: 113,
+    };
+
+    /// Service which controls the system's lifecycle when running as init (PID 1)
+    ///
+    /// Every other service is expected to depend on it, directly or indirectly, such that they all
+    /// go down with it.  Its `cleanup` should power off the system if its target is `Down` and
+    /// reboot it otherwise.  See `system` in the example init config.
+    ///
+    /// Signals sent to init act on this service:
+    /// - Ctrl-Alt-Del restarts it to cleanly reboot, if `CTRL_ALT_DEL` is `CtrlAltDel::Reboot`
+    /// - SIGPWR brings it down to cleanly power off.  UPS monitoring daemons such as apcupsd and
+    ///   nut can be configured to send SIGPWR to init on power failure.
+    ///
+    /// Examples:
+    ///
+    /// ```ignore
+    /// const SYSTEM_SERVICE: Option<&'static str> = None;
+    /// const SYSTEM_SERVICE: Option<&'static str> = Some("system");
+    /// ```
+    const SYSTEM_SERVICE: Option<&'static str> = None;
+
+    /// What the Ctrl-Alt-Del keystroke does when running as init (PID 1)
+    const CTRL_ALT_DEL: CtrlAltDel = CtrlAltDel::Kernel;
+
+    /// Shell which executes `Run::Shell` commands as `<SHELL> -c <command>`
+    ///
+    /// Must be an absolute path.
+    ///
+    /// Examples:
+    ///
+    /// ```ignore
+    /// const SHELL: &'static CStr = c"/bin/sh";
+    /// const SHELL: &'static CStr = c"/bin/busybox-sh";
+    /// ```
+    const SHELL: &'static CStr = c"/bin/sh";
+
+    /// Maximum number of services which may be in `SettingUp` or `Starting` at once
+    ///
+    /// Further services whose dependencies are satisfied are held in `WaitingToStart` until a
+    /// slot frees up.  This trades boot latency for lower peak load, e.g. on slow disks.  Time
+    /// spent waiting for a slot does not count against `max_wait_time`.  If None, unlimited.
+    const MAX_CONCURRENT_STARTS: Option<usize> = None;
+
+    /// How long a waiting service may be blocked by services which will never unblock it before it
+    /// gives up
+    ///
+    /// For example, a service needing one which failed.  A service waiting to start moves to
+    /// Failed.  A service waiting to stop keeps running and reports what it is blocked on, as
+    /// killing it out from under a dependent which is still up would break stop ordering.
+    const BLOCKED_TIME: core::time::Duration =
+        core::time::Duration::from_millis(crate::constants::DEFAULT_BLOCKED_TIME_MILLIS as u64);
+
+    /// Whether to pass `Log::Inherit` and `Log::Service` services' output through connate a line at
+    /// a time
+    ///
+    /// Services writing to the console at once, such as during a parallel boot, otherwise
+    /// interleave mid-line with each other and with connate's own messages.  Likewise, services
+    /// sharing a logger may run into each other's partial lines.  When set, each such service's
+    /// stdout and stderr are a pipe which connate reads, writing each complete line in one piece
+    /// to its own stdout or the logger's stdin.  Services with a `controlling_tty` are left alone.
+    ///
+    /// This costs a copy of every line through connate, which forwards output between handling
+    /// other events:
+    /// - A service writing faster than connate forwards blocks once its pipe fills (64KiB by
+    ///   default).  Lines a slow console such as a serial line cannot take at once are dropped
+    ///   rather than slowing connate.
+    /// - Services see a pipe rather than a terminal, so programs may drop colors or buffer their
+    ///   output until exit rather than writing it a line at a time.
+    /// - Lines longer than 512 bytes are split, stderr is merged into stdout, and a partial last
+    ///   line is ended with a newline when the process exits or connate re-execs.
+    /// - connate holds both ends of each such service's pipe, which count against its limit of
+    ///   1024 open FDs; see `SERVICES`.
+    const LINE_BUFFER_CONSOLE: bool = false;
+
+    /// Named sets of services to switch between, akin to runlevels
+    ///
+    /// `conctl boot-target <name>` brings up the listed services and everything they need, want,
+    /// or group, and brings down every other service.  `SYSTEM_SERVICE` and everything it pulls
+    /// up are left alone.
+    ///
+    /// Examples:
+    ///
+    /// ```ignore
+    /// const TARGETS: &'static [(&'static str, &'static [&'static str])] = &[
+    ///     ("rescue", &["agetty-tty1"]),
+    ///     ("multi-user", &["agetty-tty1", "agetty-tty2", "sshd", "crond"]),
+    /// ];
+    /// ```
+    const TARGETS: &'static [(&'static str, &'static [&'static str])] = &[];
+
+    /// Services to keep for maintenance, such as a getty to log in on
+    ///
+    /// `conctl single` brings up these services and everything they need, want, or group, and
+    /// brings down every other service, dropping the system to an emergency mode much like a
+    /// `single` boot.  As with `TARGETS`, `SYSTEM_SERVICE` and everything it pulls up are left
+    /// alone.  If empty, `conctl single` is refused rather than leaving no way to log in.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// const EMERGENCY_SERVICES: &'static [&'static str] = &["agetty-tty1"];
+    /// ```
+    const EMERGENCY_SERVICES: &'static [&'static str] = &[];
+}
+
+/// What the Ctrl-Alt-Del keystroke does when running as init (PID 1)
+pub enum CtrlAltDel {
+    /// The kernel immediately reboots without stopping any services.  This is the kernel's
+    /// default.
+    Kernel,
+    /// Nothing
+    Ignore,
+    /// Cleanly reboot by restarting `Config::SYSTEM_SERVICE`
+    Reboot,
+}
+
+/// File descriptor numbers connate reserves for itself
+pub struct FixedFds {
+    /// memfd holding session state across re-exec
+    pub session_state: i32,
+    /// signalfd for signals connate handles
+    pub signal: i32,
+    /// Lock file, if `LOCK_FILE` is configured
+    pub lock_file: i32,
+    /// Read end of the IPC request pipe
+    pub req_read: i32,
+    /// Write end of the IPC request pipe
+    pub req_write: i32,
+    /// Read end of the IPC response pipe
+    pub resp_read: i32,
+    /// Write end of the IPC response pipe
+    pub resp_write: i32,
 }
 
 /// A service definition
@@ -96,6 +271,20 @@ pub struct Service {
     /// - When connate first starts
     /// - When connate re-execs itself and sees a new service
     pub init_target: Target,
+    /// Only start the service if the given parameter, e.g. `rescue`, is on the kernel command
+    /// line.  If it is absent, the service's target is `Down` when connate starts, regardless of
+    /// `init_target`.  Matches whole whitespace-separated parameters.  If None, `init_target`
+    /// always applies.
+    ///
+    /// Read from `/proc/cmdline`.  As init, connate usually starts before `/proc` is mounted, in
+    /// which case it checks its own arguments instead: the kernel passes parameters it does not
+    /// recognize and which lack an `=`, such as `single`, on to init.
+    ///
+    /// Connate has no single-user mode of its own; `single` is a parameter like any other.  To
+    /// boot into a rescue shell, gate it on `single` and gate the usual services on a parameter
+    /// of their own, or isolate a boot target with `conctl boot-target`.  `conctl single` drops a
+    /// running system to `EMERGENCY_SERVICES` instead.
+    pub start_if_cmdline: Option<&'static str>,
     //
     // Dependency entries
     //
@@ -106,9 +295,29 @@ pub struct Service {
     pub wants: &'static [&'static str],
     /// Services which must be down or failed for this service to start.
     pub conflicts: &'static [&'static str],
+    /// What this service does about `conflicts` which are up when it starts on its own, such as at
+    /// boot, on retry, or when brought up by a service needing it
+    ///
+    /// Defaults to `ConflictAction::Wait`.  Explicitly setting this service's target to up always
+    /// sets its conflicts' targets down.
+    pub conflict_action: ConflictAction,
+    /// Services this service waits to finish starting before it starts itself.
+    ///
+    /// Services without dependencies between them otherwise start in parallel.  This orders two
+    /// which should not, e.g. because both hammer the same disk while starting.  It does not bring
+    /// the other service up or require it to come up.
+    pub serialize_with: &'static [&'static str],
     /// Services which inherit this service's target state when it changes.
     /// Useful to start/stop related services in one go.
     pub groups: &'static [&'static str],
+    /// Whether every other service must be down, failed, or unable to stop before this service
+    /// stops
+    ///
+    /// Bringing it down brings every other service down.  This guarantees its `cleanup` runs last,
+    /// such as to remount root read-only before powering off, without every service having to
+    /// depend on it.  At most one service may set this, and it may not need, want, or log to
+    /// other services, as they would have to outlast it.
+    pub stop_last: bool,
     //
     // Execution entries
     //
@@ -143,8 +352,13 @@ pub struct Service {
     /// If true, stops all processes spawned by the service.
     /// If false, only stops "main" process; allows "non-main" process to continue untracked.
     ///
-    /// This adds a small amount of overhead for a supervisor process.
+    /// This adds a small amount of overhead for a supervisor process.  With a `cgroup`,
+    /// `kill_mode` achieves the same without a supervisor.
     pub stop_all_children: bool,
+    /// How `stop_all_children` stops the processes left once the main process is gone
+    pub children_stop: ChildrenStop,
+    /// Which processes are signaled when stopping the service
+    pub kill_mode: KillMode,
     //
     // Retry and timeout entries
     //
@@ -160,15 +374,54 @@ pub struct Service {
     /// The maximum amount of time a service's `.cleanup` may run before it is assumed to be hanging
     /// and forcibly killed.
     pub max_cleanup_time: Option<core::time::Duration>,
+    /// The maximum amount of time a service may wait in WaitingToStart for its dependencies, or in
+    /// WaitingToStop for its dependents, before giving up.
+    ///
+    /// Surfaces stalls such as a `needs` dependency which keeps failing.  A service waiting to
+    /// start moves to Failed.  A service waiting to stop keeps running, as its dependents still
+    /// rely on it, and reports the timeout via `conctl why`.
+    ///
+    /// None indicates waiting forever.
+    pub max_wait_time: Option<core::time::Duration>,
+    /// How long a retried service must stay Up before it is considered stable and its attempt
+    /// count resets.  A crash within this window counts as another failed attempt.
+    ///
+    /// Raise it for services which warm up slowly and tend to crash shortly after starting.  If
+    /// None, defaults to one second.
+    pub up_stable_time: Option<core::time::Duration>,
     /// The retry strategy should a Service fail
     pub retry: Retry,
+    /// When the attempt count which `retry`'s `max_attempt_count` limits resets
+    ///
+    /// Manually setting the service's target always resets it.
+    pub reset_attempts_on: ResetPolicy,
+    /// Non-zero exit codes which count as success, as exit code 0 always does
+    ///
+    /// Useful for programs which use non-zero exit codes for non-error conditions, e.g. fsck
+    /// returning 1 to indicate it corrected errors.  They apply wherever an exit may succeed:
+    /// `.setup` exiting, `.run` exiting under `Target::Once`, and `ResetPolicy::CleanExit`.  Any
+    /// other exit of `.run` is a failure regardless.
+    ///
+    /// Codes above 128 are rejected, as connate reports death by a signal as 128 plus its number.
+    /// The exception is `PANIC_EXIT_CODE` (255), which a panicking `Run::Fn` exits with.
+    pub success_exit_codes: &'static [c_int],
+    /// Exit codes which indicate retrying would be pointless
+    ///
+    /// If `.setup` or `.run` exits with one of these, the service skips `.retry` and goes straight
+    /// to Failed.  Codes above 128 are rejected as for `success_exit_codes`.
+    pub fatal_exit_codes: &'static [c_int],
     //
     // Execution attribute entries
     //
+    /// Where this service's stdin comes from
+    ///
+    /// Services which other services log to read from their logging pipe instead, so must leave
+    /// this as `StdinSource::Null`.
+    pub stdin: StdinSource,
     /// How to handle this service's stdout and stderr
     pub log: Log,
-    /// The environment variables to set for the service's execution Run::Exec and Run::Shell
-    /// entries.  Is ignored by Run::Fn() entries.
+    /// The environment variables to set for the service's execution Run::Exec, Run::Shell, and
+    /// Run::Script entries.  Is ignored by Run::Fn() entries.
     ///
     /// Populate as a list of VAR=VALUE, e.g.
     /// ```ignore
@@ -178,6 +431,17 @@ pub struct Service {
     /// ],
     /// ```
     pub env: &'static [&'static str],
+    /// If true, `env` is the service's entire environment.  If false, `env` is layered over the
+    /// environment connate itself was started with, with `env` taking precedence.
+    ///
+    /// Leaving this true avoids accidentally leaking connate's environment into services.
+    pub clear_env: bool,
+    /// Variables of connate's own environment to pass on to the service despite `clear_env`,
+    /// e.g. `&["TZ", "LANG"]`.  Variables connate was not started with are left unset.
+    ///
+    /// Useful for a few ambient variables in user sessions without re-declaring the whole
+    /// environment in `env`.  Requires `clear_env`, as otherwise everything is passed on anyway.
+    pub inherit_env: &'static [&'static str],
     /// Run the service processes as the given user.  If None, retains connate daemon user.
     ///
     /// Requires root.  Intended to be used by an init / system-wide service manager to drop
@@ -188,10 +452,47 @@ pub struct Service {
     /// Requires root.  Intended to be used by an init / system-wide service manager to drop
     /// permissions for a given service.
     pub group: Option<&'static str>,
+    /// If true, run the service processes with all of `user`'s groups, as a login would: the
+    /// primary group from `/etc/passwd` and every `/etc/group` entry listing the user become the
+    /// supplementary groups, and the primary group is used if `group` is None.  If false, connate
+    /// daemon's supplementary groups are retained.
+    ///
+    /// Requires `user`.  Group membership is resolved at build time, so rebuild after changing
+    /// it.
+    pub use_user_groups: bool,
     /// Set the service's working directory. If None, retains connate daemon's working directory.
     pub chdir: Option<&'static str>,
     /// Prevent the service and its children from gaining new privileges.
+    ///
+    /// This includes via setuid and setgid binaries, which then run without the privileges they
+    /// expect.  The `host-checks` feature warns about such `setup`, `run`, and `cleanup` binaries.
     pub no_new_privs: bool,
+    /// If true, run the service processes in a new session, detached from any controlling
+    /// terminal connate has.  Each service is then its own process group, apart from connate's
+    /// and every other service's.
+    ///
+    /// If false, the service stays in connate's session and process group, keeping connate's
+    /// controlling terminal if it has one.  It then takes part in that terminal's job control:
+    /// keys such as Ctrl-C and Ctrl-Z signal it along with connate, and it is stopped if it
+    /// reads from the terminal while another process group is in the foreground.  Intended for
+    /// terminal services such as gettys which manage the terminal themselves.
+    pub new_session: bool,
+    /// Open the given terminal, e.g. `/dev/tty1`, make it the service's controlling terminal, and
+    /// use it for stdin, stdout, and stderr.  If None, the service has no controlling terminal
+    /// unless it acquires one itself.
+    ///
+    /// For minimal gettys which expect init to set up the terminal.  Fuller gettys such as agetty
+    /// do this themselves given the terminal's name.  Requires `new_session`, and replaces `stdin`
+    /// and `log`, which must be left as `StdinSource::Null` and `Log::Inherit`.
+    pub controlling_tty: Option<&'static str>,
+    /// Place the service's processes in the given cgroup v2 directory, e.g.
+    /// `/sys/fs/cgroup/connate/sshd`.  Created if it does not already exist.  If None, processes
+    /// remain in connate's cgroup.  No two services may share a cgroup.
+    ///
+    /// Requires a mounted cgroup2 filesystem and permission to write to it.  Enables `conctl
+    /// usage` to report the service's resource consumption and `conctl freeze`/`conctl thaw` to
+    /// suspend and resume it.
+    pub cgroup: Option<&'static str>,
 }
 
 pub enum Target {
@@ -204,6 +505,9 @@ pub enum Target {
     Restart,
     /// The service's immediate target state is Up.  Once Down or Failed, its target changes to
     /// Down.
+    ///
+    /// Failures are not retried.  A `.run` which exits successfully goes Down through `.cleanup`
+    /// rather than failing.
     Once,
 }
 
@@ -219,9 +523,28 @@ pub enum Run {
     Exec(&'static [&'static str]),
     /// Run command in a shell
     ///
-    /// Effectively `/bin/sh -c <command>`
+    /// Effectively `/bin/sh -c <command>`, using the shell configured by `Config::SHELL`
     Shell(&'static str),
+    /// Run code with the given interpreter
+    ///
+    /// `interpreter` is the interpreter's full filepath (no $PATH searching) followed by any
+    /// arguments, ending with the flag which takes the code to run.  `code` is passed as the final
+    /// argument.  `Shell(cmd)` is equivalent to
+    /// `Script { interpreter: &[SHELL, "-c"], code: cmd }`.
+    ///
+    /// Example:
+    /// Script {
+    ///     interpreter: &["/usr/bin/python3", "-c"],
+    ///     code: "import socket; socket.create_connection(('localhost', 80))",
+    /// },
+    Script {
+        interpreter: &'static [&'static str],
+        code: &'static str,
+    },
     /// Run the given function
+    ///
+    /// Slow functions may report what they are doing for `conctl status` with the `step()` or
+    /// `report_progress()` helpers.
     Fn(fn() -> Result<(), Errno>),
 }
 
@@ -264,9 +587,46 @@ pub enum Stop {
     Fn(fn() -> Result<(), Errno>),
 }
 
+/// How `stop_all_children` stops the processes left once the main process is gone
+pub enum ChildrenStop {
+    /// SIGKILL them immediately
+    Kill,
+    /// Send the signal, then SIGKILL any still running once the duration elapses
+    ///
+    /// Useful for child processes which need to shut down gracefully.  This happens while the
+    /// service is stopping, and thus should fit within `max_stop_time`.  Should connate force the
+    /// service down, the remaining children are sent SIGKILL right away.
+    Signal(crate::types::Signal, core::time::Duration),
+}
+
+/// Which processes to signal when stopping a service, mirroring systemd's `KillMode`
+///
+/// `ControlGroup` and `Mixed` require `cgroup` and replace `stop_all_children`.
+pub enum KillMode {
+    /// Only signal the main process.  Other processes are left running unless `stop_all_children`
+    /// is set.
+    Process,
+    /// SIGTERM every process in the service's cgroup to stop it, then SIGKILL any left once the
+    /// main process exits or the stop times out.
+    ControlGroup,
+    /// SIGTERM only the main process to stop it, then SIGKILL every process left in the service's
+    /// cgroup once the main process exits or the stop times out.
+    Mixed,
+}
+
 /// Retry strategy
 pub enum Retry {
     Never,
+    /// Retry as soon as the service fails, without any delay
+    ///
+    /// Each retry still waits for connate's next pass through its main loop, such that a service
+    /// which fails on every attempt cannot keep connate from handling signals and requests.
+    Immediate {
+        /// Maximum number of times to attempt to start and remain up.
+        ///
+        /// None indicates no limit.
+        max_attempt_count: Option<u32>,
+    },
     AfterFixed {
         /// Retry after a fixed duration
         after: core::time::Duration,
@@ -274,6 +634,12 @@ pub enum Retry {
         ///
         /// None indicates no limit.
         max_attempt_count: Option<u32>,
+        /// Randomly shift each retry delay by up to plus or minus this amount.
+        ///
+        /// Useful to avoid many services which depend on the same failing service from retrying in
+        /// lockstep.  This is best-effort: the randomness is not cryptographic quality, and the
+        /// resulting delay is never less than zero.
+        jitter: Option<core::time::Duration>,
     },
     AfterDoublingDelay {
         /// First retry is after this delay
@@ -284,9 +650,52 @@ pub enum Retry {
         ///
         /// None indicates no limit.
         max_attempt_count: Option<u32>,
+        /// Upper bound on the doubled delay.
+        ///
+        /// None indicates no limit, in which case the delay may grow to days after enough
+        /// attempts.  Jitter, if any, is applied after this bound.
+        max_delay: Option<core::time::Duration>,
+        /// Randomly shift each retry delay by up to plus or minus this amount.
+        ///
+        /// See `AfterFixed`'s `jitter`.
+        jitter: Option<core::time::Duration>,
     },
 }
 
+/// What a starting service does about conflicting services which are up
+pub enum ConflictAction {
+    /// Wait for them to go down on their own, failing once `max_wait_time` elapses
+    Wait,
+    /// Set their targets down, along with those of services depending on them, like systemd's
+    /// `Conflicts=`
+    Stop,
+}
+
+/// When a retried service's attempt count resets
+pub enum ResetPolicy {
+    /// Once the service has stayed Up for `up_stable_time`
+    UpStable,
+    /// Whenever `.run` exits 0 or with one of `success_exit_codes`
+    ///
+    /// Useful for services which are expected to exit and be rerun, where only consecutive
+    /// failures should count against `max_attempt_count`.
+    CleanExit,
+    /// Never; every failure counts until the target is manually set again
+    Never,
+}
+
+/// Where a service's stdin comes from
+pub enum StdinSource {
+    /// Read from /dev/null
+    ///
+    /// Recommended for most services, as many daemons misbehave if given a terminal on stdin.
+    Null,
+    /// Inherit connate's stdin
+    Inherit,
+    /// Read from the given file path
+    File(&'static str),
+}
+
 /// Logging configuration for a service
 ///
 /// Determines where the service's stdout and stderr output should be sent.
@@ -299,10 +708,19 @@ pub enum Log {
     /// before logging infrastructure is available.
     Inherit,
     /// Log to a given file path
+    ///
+    /// When connate exits after shutting down, it fsyncs every such file so that logs survive an
+    /// immediate power off.  As init, connate does not exit; the system service's cleanup should
+    /// `sync()` before shutting down or rebooting, as the example init config does.
     File {
         /// stdout and stderr are redirected to this path
         ///
-        /// File is created if it does not already exist
+        /// File is created if it does not already exist.  It is opened before dropping to `user`
+        /// and `group`, so only connate itself needs to be able to write to it or create it.
+        ///
+        /// `%i` is replaced with the service's instance, which is whatever follows the first `@`
+        /// in its name.  This lets instances of a service share one definition, e.g. `agetty@tty1`
+        /// logging to `/var/log/agetty-%i.log` writes to `/var/log/agetty-tty1.log`.
         path: &'static str,
         /// Whether to append to or overwrite a preexisting file
         mode: FileMode,
@@ -313,7 +731,12 @@ pub enum Log {
     ///
     /// - This service's stdout and stderr will be redirected to the specified service's stdin
     /// - The logger service will be implicitly added to the needs list (must start before this service)
-    /// - The logger service will be implicitly added to the groups list (stops with this service)
+    /// - The logger service will only stop after this service has stopped, such that final log
+    ///   lines are not lost
+    /// - Stopping the logger service stops this service, but not vice versa, as a logger may be
+    ///   shared by several services.  Add the logger to `groups` to have it stop with this service.
+    /// - Services sharing a logger write to the same pipe, so a partial line left by one may run
+    ///   into another's output.  `Config::LINE_BUFFER_CONSOLE` keeps each line whole.
     Service(&'static str),
 }
 
@@ -331,6 +754,9 @@ pub enum FilePerm {
     Public,
     /// File is only readable by owner (`0o600`)
     Private,
+    /// File has the given permission bits, e.g. `0o640` for a log-reading group.  Must not exceed
+    /// `0o777`.
+    Mode(u32),
 }
 
 /// The states a service can be in.
@@ -369,8 +795,8 @@ pub enum State {
     // ================
     /// The service has failed and is waiting for `retry_period` to attempt to go Up again.
     Retrying,
-    /// The service has failed and exhausted `max_attempt_count` attempts.  Manual intervention is
-    /// required.
+    /// The service has failed and either exhausted `max_attempt_count` attempts or exited with one
+    /// of its `fatal_exit_codes`.  Manual intervention is required.
     Failed,
     /// The service did not stop when expected to.  We're forcing it down via SIGKILL.
     /// Waiting for either process to exit or timeout indicating we can't kill it.
@@ -9,36 +9,85 @@ use crate::{exec, run};
 impl Config for Connate {
     const LOCK_FILE: Option<&'static str> = None;
 
+    const SYSTEM_SERVICE: Option<&'static str> = Some("system");
+
+    const CTRL_ALT_DEL: CtrlAltDel = CtrlAltDel::Reboot;
+
+    // Early boot steps run in parallel and all write to the console
+    const LINE_BUFFER_CONSOLE: bool = true;
+
+    const TARGETS: &'static [(&'static str, &'static [&'static str])] = &[
+        ("rescue", &["agetty-tty1"]),
+        (
+            "multi-user",
+            &[
+                "agetty-tty1",
+                "agetty-tty2",
+                "agetty-tty3",
+                "agetty-tty4",
+                "agetty-tty5",
+                "agetty-tty6",
+                "chrony",
+                "sshd",
+                "cupsd",
+                "crond",
+            ],
+        ),
+    ];
+
+    // `conctl single` keeps a login on the first console
+    const EMERGENCY_SERVICES: &'static [&'static str] = &["agetty-tty1"];
+
     const DEFAULT_SERVICE: Service = Service {
         name: "unspecified-service-name",
         init_target: Target::Up,
+        start_if_cmdline: None,
         // Dependency entries
         needs: &[],
         wants: &[],
         conflicts: &[],
+        conflict_action: ConflictAction::Wait,
+        serialize_with: &[],
         groups: &[],
+        stop_last: false,
         // Execution entries
         setup: Run::None,
         run: Run::None,
         ready: Ready::Immediately,
         cleanup: Run::None,
         stop_all_children: false,
+        children_stop: ChildrenStop::Kill,
+        kill_mode: KillMode::Process,
         // Retry and timeout entries
         max_setup_time: Some(core::time::Duration::from_secs(30)),
         max_ready_time: Some(core::time::Duration::from_secs(10)),
         max_stop_time: Some(core::time::Duration::from_secs(10)),
         max_cleanup_time: Some(core::time::Duration::from_secs(10)),
+        max_wait_time: None,
+        up_stable_time: None,
         retry: Retry::AfterDoublingDelay {
             initial_delay: core::time::Duration::from_secs(1),
             max_attempt_count: Some(5),
+            max_delay: Some(core::time::Duration::from_secs(60)),
+            jitter: None,
         },
+        reset_attempts_on: ResetPolicy::UpStable,
+        success_exit_codes: &[],
+        fatal_exit_codes: &[],
         // Execution attribute entries
+        stdin: StdinSource::Null,
         log: Log::Inherit,
         env: &["PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin"],
+        clear_env: true,
+        inherit_env: &[],
         user: None,
         group: None,
+        use_user_groups: false,
         chdir: None,
         no_new_privs: true,
+        new_session: true,
+        controlling_tty: None,
+        cgroup: None,
     };
 
     const SERVICES: &[Service] = &[
@@ -46,14 +95,19 @@ impl Config for Connate {
         // System - manages system lifecycle
         // =============================================================================
         //
-        // - Everything should (directly or indirectly) depend on this service so that all other
-        // services go down when this one goes down.
+        // - It stops last, such that all other services go down when this one goes down and its
+        //   cleanup runs once they have.
         // - This service's `cleanup` checks its own target when stopping:
         //   - If this stops with target=Down, the system shuts down.
         //   - If this stops with target=Reboot, the system reboots.
+        // - It is the SYSTEM_SERVICE, such that Ctrl-Alt-Del restarts it to reboot the system and
+        //   SIGPWR (power failure) brings it down to power off the system.
         Service {
             name: "system",
+            stop_last: true,
             cleanup: Run::Fn(|| {
+                // Also flushes services' `Log::File` logs, which connate only does itself when it
+                // exits rather than rebooting
                 let _ = step("Syncing filesystems", || sync());
 
                 let _ = step("Remounting root read-only", || {
@@ -548,7 +602,7 @@ fn random_seed_save() -> Result<(), Errno> {
     )?;
 
     if let Some(data) = buf.get(..n) {
-        let _ = fd.write(data);
+        let _ = fd.write_all(data);
     }
     let _ = fd.close();
 
@@ -3,6 +3,7 @@
 //! These functions provide higher-level abstractions for common patterns in config files.
 
 use crate::config::Target;
+use crate::constants::PROGRESS_TIMEOUT_MILLIS;
 use crate::err::Errno;
 use crate::ipc::{IpcClient, Request, Response};
 use crate::os::*;
@@ -11,11 +12,14 @@ use core::ffi::c_int;
 
 /// Wrap operation(s) in a step which prints their start and resulting success or failure
 ///
+/// Within a service, the step is also reported to connate as the service's progress.
+///
 /// Example:
 ///   step("Create /tmp/example", || mkdir(c"/tmp/example", 0o755) );
 pub fn step(msg: &str, f: fn() -> Result<(), Errno>) -> Result<(), Errno> {
     print_color(Color::Dim, "[ ] ");
     println(msg);
+    report_progress(msg);
     match f() {
         Ok(()) => {
             print_color(Color::Okay, "[X] ");
@@ -25,18 +29,52 @@ pub fn step(msg: &str, f: fn() -> Result<(), Errno>) -> Result<(), Errno> {
         Err(e) => {
             print_color(Color::Error, "[!] ");
             print(msg);
-            if let Some(e) = e.description() {
-                print_color(Color::Error, " FAILED: ");
-                println(e);
-            } else {
-                print_color(Color::Error, " FAILED!");
-                println("");
-            }
+            print_color(Color::Error, " FAILED: ");
+            println(e);
             Err(e)
         }
     }
 }
 
+/// Report what the service is doing, which `conctl status` shows while it is setting up, starting,
+/// or cleaning up
+///
+/// Best-effort: does nothing when not called from a service's process, or if connate is too busy
+/// to take the message promptly.  Messages are cut short at 64 bytes.
+///
+/// Example:
+///   report_progress("fsck /dev/sda2");
+pub fn report_progress(msg: &str) {
+    // Connate is the parent, or the grandparent under a supervisor process
+    let parent = getppid();
+    let ipc_client = IpcClient::try_from_pid(parent).or_else(|| {
+        read_proc_stat_ppid(parent)
+            .ok()
+            .and_then(IpcClient::try_from_pid)
+    });
+    if let Some(mut ipc_client) = ipc_client {
+        let _ = report_progress_with(&mut ipc_client, msg);
+        ipc_client.close();
+    }
+}
+
+/// Report progress over an existing IPC client
+///
+/// Variant of [`report_progress`] which can be unit tested against an `ipc::MockConnate`.  Returns
+/// whether connate took the message.
+pub fn report_progress_with(ipc_client: &mut IpcClient, msg: &str) -> bool {
+    if !ipc_client.lock_within(PROGRESS_TIMEOUT_MILLIS) {
+        return false;
+    }
+    let request = Request::ServiceProgress(getpid(), msg.as_bytes());
+    let took = matches!(
+        ipc_client.send_and_receive_within(request, PROGRESS_TIMEOUT_MILLIS),
+        Some(Response::Okay)
+    );
+    ipc_client.unlock();
+    took
+}
+
 /// Convert user friendly `[&str; N]` to `[&CStr.to_ptr(); N+1]` with trailing null as needed by
 /// execve().
 #[macro_export]
@@ -69,7 +107,7 @@ macro_rules! exec {
 
         let pathname = unsafe { CStr::from_bytes_with_nul_unchecked(concat!($first, "\0").as_bytes()) };
         let argv = $crate::cargv!([$first $(, $rest)*]);
-        unsafe { crate::syscall::execve(pathname, argv.as_ptr(), core::ptr::null()).map(|_|()) }
+        unsafe { $crate::syscall::execve(pathname, argv.as_ptr(), core::ptr::null()).map(|_|()) }
     }};
 }
 
@@ -107,6 +145,40 @@ macro_rules! run {
     }};
 }
 
+/// Build a `Service` from only the fields which differ from `DEFAULT_SERVICE`
+///
+/// Appends `..Self::DEFAULT_SERVICE` such that it can't be forgotten, and thus must be used within
+/// `impl Config`.  Expands to a plain struct literal, so it may be used in `const` context such as
+/// `SERVICES`.
+///
+/// Example:
+/// ```ignore
+/// use crate::service;
+///
+/// service! {
+///     name: "sshd",
+///     needs: &["network"],
+///     run: Run::Exec(&["/usr/sbin/sshd", "-D"]),
+/// }
+///
+/// // is equivalent to
+/// Service {
+///     name: "sshd",
+///     needs: &["network"],
+///     run: Run::Exec(&["/usr/sbin/sshd", "-D"]),
+///     ..Self::DEFAULT_SERVICE
+/// }
+/// ```
+#[macro_export]
+macro_rules! service {
+    ($($field:ident: $value:expr),* $(,)?) => {
+        Service {
+            $($field: $value,)*
+            ..Self::DEFAULT_SERVICE
+        }
+    };
+}
+
 /// Look up a service's target
 ///
 /// This only works when called from a non-daemon service, as daemons are tracked with a supervisor
@@ -126,8 +198,15 @@ macro_rules! run {
 /// }
 /// ```
 pub fn get_service_target(name: &str) -> Option<Target> {
-    let connate_pid = getppid();
-    let mut ipc_client = IpcClient::from_pid(connate_pid);
+    let mut ipc_client = IpcClient::from_pid(getppid());
+    get_service_target_with(&mut ipc_client, name)
+}
+
+/// Query the target state of a service over an existing IPC client
+///
+/// Variant of [`get_service_target`] for helpers which take the client as a parameter such that
+/// they can be unit tested against an `ipc::MockConnate` (see the `test-utils` feature).
+pub fn get_service_target_with(ipc_client: &mut IpcClient, name: &str) -> Option<Target> {
     let request = Request::QueryByNameTarget(name.as_bytes());
     ipc_client.lock_quiet();
     let target = match ipc_client.send_and_receive(request) {
@@ -209,8 +288,9 @@ pub fn read_file(path: &CStr, buf: &mut [u8]) -> Result<usize, Errno> {
 /// Write content to an existing file.
 pub fn write_file(path: &CStr, content: &[u8]) -> Result<(), Errno> {
     let fd = Fd::open(path, OpenFlags::O_WRONLY, 0)?;
-    let _ = fd.write(content)?;
-    fd.close()
+    let result = fd.write_all(content);
+    let _ = fd.close();
+    result
 }
 
 pub fn copy(input: &CStr, output: &CStr) -> Result<(), Errno> {
@@ -224,7 +304,95 @@ pub fn copy(input: &CStr, output: &CStr) -> Result<(), Errno> {
             return Ok(());
         }
         if let Some(buf) = buf.get(0..n) {
-            output.write(&buf)?;
+            output.write_all(buf)?;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, Run, Service};
+    use crate::constants::PROGRESS_MSG_SIZE;
+    use crate::internal_api;
+    use crate::ipc::MockConnate;
+
+    fn respond(request: Request) -> Response<'static> {
+        match request {
+            Request::QueryByNameTarget(b"xorg") => Response::Target(internal_api::Target::Up),
+            Request::QueryByNameTarget(b"wayland") => Response::Target(internal_api::Target::Down),
+            Request::ServiceProgress(_, b"fsck /dev/sda2") => Response::Okay,
+            Request::ServiceProgress(_, msg) if msg.len() == PROGRESS_MSG_SIZE => Response::Okay,
+            _ => Response::ServiceNotFound,
+        }
+    }
+
+    #[test]
+    fn get_service_target_maps_responses() {
+        let (_connate, mut ipc_client) = MockConnate::spawn(respond);
+
+        assert!(matches!(
+            get_service_target_with(&mut ipc_client, "xorg"),
+            Some(Target::Up)
+        ));
+        assert!(matches!(
+            get_service_target_with(&mut ipc_client, "wayland"),
+            Some(Target::Down)
+        ));
+        assert!(get_service_target_with(&mut ipc_client, "missing").is_none());
+    }
+
+    #[test]
+    fn report_progress_sends_message() {
+        let (_connate, mut ipc_client) = MockConnate::spawn(respond);
+
+        assert!(report_progress_with(&mut ipc_client, "fsck /dev/sda2"));
+        assert!(!report_progress_with(&mut ipc_client, "unexpected"));
+        // Cut short rather than rejected
+        let long = [b'x'; PROGRESS_MSG_SIZE * 2];
+        let long = core::str::from_utf8(&long).unwrap();
+        assert!(report_progress_with(&mut ipc_client, long));
+    }
+
+    struct TestConfig {}
+
+    impl Config for TestConfig {
+        const LOCK_FILE: Option<&'static str> = None;
+        const SERVICES: &'static [Service] = &[
+            crate::service! {
+                name: "sshd",
+                needs: &["network"],
+                run: Run::Exec(&["/usr/sbin/sshd", "-D"]),
+            },
+            Service {
+                name: "sshd",
+                needs: &["network"],
+                run: Run::Exec(&["/usr/sbin/sshd", "-D"]),
+                ..Self::DEFAULT_SERVICE
+            },
+            crate::service! { name: "gui" },
+        ];
+    }
+
+    #[test]
+    fn service_macro_matches_longhand() {
+        let [short, long, minimal] = TestConfig::SERVICES else {
+            panic!("unexpected service count");
+        };
+
+        assert_eq!(short.name, long.name);
+        assert_eq!(short.needs, long.needs);
+        assert!(matches!(
+            (&short.run, &long.run),
+            (Run::Exec(a), Run::Exec(b)) if a == b
+        ));
+        assert_eq!(short.wants, long.wants);
+        assert_eq!(short.no_new_privs, long.no_new_privs);
+        assert!(matches!(short.init_target, Target::Up));
+
+        // Unspecified fields come from DEFAULT_SERVICE
+        assert_eq!(minimal.name, "gui");
+        assert!(minimal.needs.is_empty());
+        assert!(matches!(minimal.run, Run::None));
+    }
+}
@@ -1,5 +1,7 @@
 #![cfg_attr(not(test), no_std)]
 #![cfg_attr(not(test), no_main)]
+// `main` is compiled out under test, leaving everything it reaches unused
+#![cfg_attr(test, allow(dead_code, unused_imports))]
 
 // Implementation of features Rust core expects from libc
 //
@@ -21,24 +23,14 @@ mod libc_shim;
 #[path = "../internal.rs"]
 mod internal;
 
-mod handle_request;
-mod handle_signal;
-mod next_state;
-mod poll;
-mod session;
 mod setup;
-mod spawn;
 
-use crate::handle_request::*;
-use crate::handle_signal::*;
-use crate::next_state::*;
-use crate::poll::*;
-use crate::session::*;
 use crate::setup::*;
+use connate::config::Connate;
 use connate::err::*;
-use connate::internal_api::*;
 use connate::ipc::*;
 use connate::os::*;
+use connate::supervisor::*;
 
 /// # Safety
 ///
@@ -46,56 +38,47 @@ use connate::os::*;
 #[cfg(not(test))]
 #[unsafe(no_mangle)]
 pub unsafe extern "C" fn main(
-    _argc: isize,
-    _argv: *const *const core::ffi::c_char,
-    _envp: *const *const core::ffi::c_char,
+    argc: isize,
+    argv: *const *const core::ffi::c_char,
+    envp: *const *const core::ffi::c_char,
 ) -> isize {
     let now = get_time_monotonic().or_abort("Unable to get current time");
 
+    // Safety: ABI-provided argc and argv remain valid for the life of the process
+    let argv = unsafe { Argv::from_raw(argc, argv) };
+
     // Load services from .bss
     //
     // Safety:
     // - This occurs very early on; no threading could occur.
     // - Occurs exactly once
-    let svcs = unsafe { internal::SERVICES.initialize(now) };
+    let svcs = unsafe { internal::SERVICES.initialize(now, &argv) };
+
+    // Retain our environment for services which inherit it and across re-exec
+    //
+    // Safety: ABI-provided envp remains valid for the life of the process
+    unsafe { set_startup_envp(envp) };
 
     // Setup process properties
     //
     // These are idempotent and can be called redundantly when resuming a session
+    check_fixed_fds_free();
     acquire_lock_file();
     block_signals().or_abort("Unable to block signals");
     set_child_subreaper().or_abort("Unable to set PR_SET_CHILD_SUBREAPER");
+    seed_rng(now);
+    configure_ctrl_alt_del();
 
     // Resume or initialize file descriptors
     let mut ipc_server = IpcServer::try_resume().unwrap_or_else(IpcServer::new);
-    let mut signalfd = resume_or_new_signalfd();
-    let mut session_fd = SessionFd::resume_or_new(svcs, &mut ipc_server);
-
-    let mut shutting_down = false;
-    let mut poll = Poll::new(&signalfd, &ipc_server);
-
-    // Main loop
-    loop {
-        let now = get_time_monotonic().or_abort("Unable to get current time");
-
-        // Handle state transitions
-        while let Some(i) = svcs.find_dirty_index() {
-            NextState::new(svcs, i, now).apply(svcs, i, now);
-        }
-
-        // Handle shutting down
-        if shutting_down && svcs.all_down_or_err() {
-            exit(if svcs.any_bad() { 1 } else { 0 });
-        }
+    let signalfd = resume_or_new_signalfd();
+    let session_fd = SessionFd::resume_or_new::<internal::ServiceNames, _>(svcs, &mut ipc_server);
 
-        // Sleep until an event occurs, then handle event
-        let (timeout_ms, timeout_svc) = calculate_poll_timeout(svcs, now);
-        match poll.poll(timeout_ms) {
-            PollFdReady::TimeoutExpired => timeout_svc.map_or((), |svc| svc.dirty = true),
-            PollFdReady::SignalFd => {
-                handle_signal(&mut signalfd, svcs, &mut shutting_down, &mut session_fd)
-            }
-            PollFdReady::Request => handle_request(svcs, &mut ipc_server, &mut session_fd, now),
-        }
-    }
+    run::<internal::ServiceNames, _>(
+        svcs,
+        ipc_server,
+        signalfd,
+        session_fd,
+        Settings::from_config::<Connate>(),
+    )
 }
@@ -1,6 +1,30 @@
+use connate::config::{Config, Connate, CtrlAltDel};
 use connate::constants::*;
 use connate::err::*;
 use connate::os::*;
+use connate::supervisor::{lock_instance, unexpected_fixed_fd};
+use connate::types::*;
+use connate::util::seed_random;
+
+/// Ensure none of connate's fixed FDs were inherited from whatever launched it
+///
+/// When resuming a session via re-exec, these are expected to be populated by the prior connate
+/// instance, and are checked to be of the kind it would have left.  Otherwise, anything already
+/// open at these numbers would be silently clobbered when we move our own FDs into place.
+pub fn check_fixed_fds_free() {
+    if Fd::from_raw(FD_SESSION_STATE).is_valid() {
+        if let Some(fd) = unexpected_fixed_fd() {
+            abort_fixed_fd_unexpected(fd);
+        }
+        return;
+    }
+
+    for fd in FIXED_FDS {
+        if Fd::from_raw(fd).is_valid() {
+            abort_fixed_fd_in_use(fd);
+        }
+    }
+}
 
 /// Acquire lock file (if configured)
 ///
@@ -11,26 +35,30 @@ pub fn acquire_lock_file() {
         return;
     };
 
-    let flags = OpenFlags::O_RDWR | OpenFlags::O_CREAT;
-    let fd = Fd::open(path, flags, 0o600)
-        .or_fs_abort("open", path)
-        .move_to(FD_LOCK_FILE)
-        .or_abort("Unable to dup lock file FD");
-
-    match fd.lock_nonblocking() {
-        Ok(()) => {}
-        Err(err) if err == Errno::EACCES || err == Errno::EAGAIN => match fd.get_locking_pid() {
-            Ok(Some(pid)) => abort_lock_held_by_pid(path, pid),
-            Ok(None) => abort_acquire_lock(path, None),
-            Err(err) => abort_acquire_lock(path, Some(err)),
-        },
-        Err(err) => abort_acquire_lock(path, Some(err)),
+    let _ = lock_instance(path, FD_LOCK_FILE);
+}
+
+/// Have the kernel send SIGINT on Ctrl-Alt-Del rather than immediately rebooting, if configured
+///
+/// Only applies to init (PID 1), as the kernel only ever signals PID 1.  Set every time such that a
+/// re-exec into a new config takes effect.
+pub fn configure_ctrl_alt_del() {
+    if getpid() != 1 {
+        return;
     }
+
+    let reboots = matches!(<Connate as Config>::CTRL_ALT_DEL, CtrlAltDel::Kernel);
+    // Best effort; failing leaves the kernel's default of immediately rebooting
+    let _ = set_ctrl_alt_del_reboots(reboots);
 }
 
 pub fn resume_or_new_signalfd() -> SignalFd {
     if Fd::from_raw(FD_SIGNAL).is_valid() {
-        SignalFd::from_raw(FD_SIGNAL)
+        let signalfd = SignalFd::from_raw(FD_SIGNAL);
+        signalfd
+            .update_signals()
+            .or_abort("Unable to update signalfd");
+        signalfd
     } else {
         SignalFd::new()
             .or_abort("Unable to create signalfd")
@@ -38,3 +66,22 @@ pub fn resume_or_new_signalfd() -> SignalFd {
             .or_abort("Unable to move signalfd")
     }
 }
+
+/// Seed the pseudo-random number generator used for retry jitter
+///
+/// Prefers /dev/urandom, but this may not yet be available very early in boot.  In that case, fall
+/// back to the current time and PID which is sufficient to de-synchronize retries.
+pub fn seed_rng(now: timespec) {
+    let mut buf = [0u8; size_of::<u64>()];
+    let from_urandom = Fd::open(c"/dev/urandom", OpenFlags::O_RDONLY, 0).and_then(|fd| {
+        let n = fd.read(&mut buf);
+        let _ = fd.close();
+        n
+    });
+
+    let seed = match from_urandom {
+        Ok(n) if n == buf.len() => u64::from_ne_bytes(buf),
+        _ => ((now.tv_sec as u64) << 32) ^ (now.tv_nsec as u64) ^ (getpid() as u64),
+    };
+    seed_random(seed);
+}
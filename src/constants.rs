@@ -1,3 +1,5 @@
+use crate::config::{Config, Connate};
+use crate::internal_api::Target;
 use crate::ipc::RequestHeader;
 use crate::types::{StrLen, c_int, pid_t};
 
@@ -8,31 +10,48 @@ pub const PIPE_BUF: usize = 4096;
 // Fixed FD numbers
 // - Allows IPC via /proc/<connate-pid>/fd/<fd>
 // - Allows accessing FDs after exec() such as memfd to load session
-pub const FD_SESSION_STATE: i32 = 100;
-pub const FD_SESSION_STATE_STR: &[u8] = b"100";
-pub const FD_SIGNAL: i32 = 101;
-pub const FD_SIGNAL_STR: &[u8] = b"101";
-pub const FD_LOCK_FILE: i32 = 102;
-pub const FD_REQ_READ: i32 = 110;
-pub const FD_REQ_READ_STR: &[u8] = b"110";
-pub const FD_REQ_WRITE: i32 = 111;
-pub const FD_REQ_WRITE_STR: &[u8] = b"111";
-pub const FD_RESP_READ: i32 = 112;
-pub const FD_RESP_READ_STR: &[u8] = b"112";
-pub const FD_RESP_WRITE: i32 = 113;
-pub const FD_RESP_WRITE_STR: &[u8] = b"113";
+// - Configured via `Config::FIXED_FDS`
+pub const FD_SESSION_STATE: i32 = <Connate as Config>::FIXED_FDS.session_state;
+pub const FD_SIGNAL: i32 = <Connate as Config>::FIXED_FDS.signal;
+pub const FD_LOCK_FILE: i32 = <Connate as Config>::FIXED_FDS.lock_file;
+pub const FD_REQ_READ: i32 = <Connate as Config>::FIXED_FDS.req_read;
+pub const FD_REQ_WRITE: i32 = <Connate as Config>::FIXED_FDS.req_write;
+pub const FD_RESP_READ: i32 = <Connate as Config>::FIXED_FDS.resp_read;
+pub const FD_RESP_WRITE: i32 = <Connate as Config>::FIXED_FDS.resp_write;
+
+/// All fixed FD numbers, for bulk operations such as closing them in forked children
+pub const FIXED_FDS: [i32; 7] = [
+    FD_SESSION_STATE,
+    FD_SIGNAL,
+    FD_LOCK_FILE,
+    FD_REQ_READ,
+    FD_REQ_WRITE,
+    FD_RESP_READ,
+    FD_RESP_WRITE,
+];
+
+/// Version of connate and conctl, embedded at build time
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// IPC wire protocol version
+///
+/// Bump this whenever a change to `Request` or `Response` would cause mismatched conctl and connate
+/// builds to misinterpret each other.  The layout of `Request::QueryVersion` and
+/// `Response::Version` must never change such that this can always be compared.
+pub const IPC_PROTOCOL_VERSION: u32 = 4;
 
 /// IPC messages are no more than PIPE_BUF size to ensure they're atomic which allows us to
 /// simplify IPC logic.
 pub const MSG_SIZE: usize = PIPE_BUF;
 
-/// Service name size constraint is determined by worst-case IPC request: dependency queries which
-/// both specify the service name and the usize index of the dependency
+/// Service name size constraint is determined by worst-case IPC request: target previews which
+/// specify the service name, the target, and the usize index of the affected service
 ///
-/// header(1) + usize(8) + str_length(2) + name(?) <= PIPE_BUF(4096)
+/// header(1) + usize(8) + target(1) + str_length(2) + name(?) <= PIPE_BUF(4096)
 pub const MSG_SVC_NAME_SIZE: usize = PIPE_BUF // Message size limit
     - size_of::<RequestHeader>() // Request header byte
-    - size_of::<usize>() // Dependency index
+    - size_of::<usize>() // Dependency or affected service index
+    - size_of::<Target>() // Target to preview
     - size_of::<StrLen>(); // String length prefix
 
 /// Path size constraint is determined by worst-case IPC request: that which requires a null
@@ -47,6 +66,37 @@ pub const MSG_PATH_SIZE: usize = PIPE_BUF // Message size limit
     - size_of::<StrLen>() // String length prefix
     - size_of::<u8>(); // Trailing null
 
+/// Space for the packed records of a `Response::StatusBatch`
+///
+/// header(1) + done(1) + str_length(2) + records(?) <= PIPE_BUF(4096)
+pub const MSG_STATUS_BATCH_SIZE: usize = PIPE_BUF // Message size limit
+    - size_of::<u8>() // Response header byte
+    - size_of::<bool>() // Whether this is the final batch
+    - size_of::<StrLen>(); // Records length prefix
+
+/// Space for connate's executable path in a `Response::ConnateInfo`
+///
+/// header(1) + pid(4) + time(8) + str_length(2) + path(?) <= PIPE_BUF(4096)
+pub const MSG_EXE_PATH_SIZE: usize = PIPE_BUF // Message size limit
+    - size_of::<u8>() // Response header byte
+    - size_of::<pid_t>() // connate's PID
+    - size_of::<i64>() // Time since exec
+    - size_of::<StrLen>(); // Path length prefix
+
+/// Longest cgroup interface file name connate appends to a service's configured cgroup path
+pub const CGROUP_FILE_NAME_MAX_LEN: usize = "/memory.current".len();
+
+/// Soft `RLIMIT_NOFILE` the kernel starts init with, and so the most FDs connate can count on
+/// holding open at once
+pub const DEFAULT_NOFILE_LIMIT: usize = 1024;
+
+/// Longest progress message connate retains for a service.  Longer reports are cut short.
+pub const PROGRESS_MSG_SIZE: usize = 64;
+
+/// Largest kernel command line connate reads, matching the kernel's largest `COMMAND_LINE_SIZE`
+/// across architectures
+pub const CMDLINE_SIZE: usize = 4096;
+
 // IPC messages for Option values can use `-1` as a sentinel value for None if it isn't a valid
 // Some() value.
 
@@ -63,9 +113,40 @@ pub const MSG_PID_NONE_SENTINEL: pid_t = -1;
 /// - If a process calls `exit(-1)` then exit code becomes 255.
 pub const MSG_EXIT_CODE_NONE_SENTINEL: c_int = -1;
 
+/// IPC message sentinel value for a service which has never run a process, and so lacks an exit
+/// code for a different reason than one whose process has yet to exit
+pub const MSG_EXIT_CODE_NEVER_RUN_SENTINEL: c_int = -2;
+
+/// Exit code of a process which panicked
+///
+/// Distinct from the errno a failing `Run::Fn` exits with, as no errno is this large, and from the
+/// `128 + signal` connate reports for a killed process, as there is no signal 127.  Rust's std uses
+/// 101, which is ENETUNREACH.
+pub const PANIC_EXIT_CODE: c_int = 255;
+
 // Hard-coded timeouts
+/// Default for how long a retried service must stay up before its attempt count resets
 pub const UP_TIME_MILLIS: i64 = 1_000;
 pub const FORCED_DOWN_TIME_MILLIS: i64 = 1_000;
+/// Default for how long a service waits on blockers which will never unblock it before giving up
+pub const DEFAULT_BLOCKED_TIME_MILLIS: i64 = 10_000;
+/// How long conctl waits on one other process holding connate's request pipe lock before it gives
+/// up on that process as hung or stopped
+pub const LOCK_HOLDER_TIMEOUT_MILLIS: i64 = 30_000;
+/// How long a service reporting progress waits on connate before carrying on without it
+pub const PROGRESS_TIMEOUT_MILLIS: c_int = 250;
+
+// Reaping
+/// Most dead children reaped per SIGCHLD before connate gets back to other events
+///
+/// Any left over are reaped after re-raising SIGCHLD, which interleaves with pending requests.
+pub const REAP_BATCH_SIZE: usize = 64;
+
+// Console
+/// Longest line `Config::LINE_BUFFER_CONSOLE` forwards in one piece; longer lines are split
+pub const CONSOLE_LINE_SIZE: usize = 512;
+/// Most reads from one service's console pipe per poll() before connate gets back to other events
+pub const CONSOLE_READ_BATCH_SIZE: usize = 16;
 
 // Environment variables
 pub const LOCK_FILE_ENVVAR: &[u8] = b"CONNATE_LOCK_FILE";
@@ -14,11 +14,46 @@
 //! specialized functions here for expected multi-field errors such as printing the file path
 //! associated with an error message.
 
+use crate::constants::PANIC_EXIT_CODE;
 use crate::os::*;
 use crate::types::*;
+use crate::util::BufWriter;
+use itoa::Integer; // ::MAX_STR_LEN
 
 pub type Errno = syscalls::Errno;
 
+/// Prints the human-readable description of the errno, falling back to its number for any errno
+/// unknown to the `syscalls` crate such that the underlying cause is never silently dropped.
+impl Print for Errno {
+    fn print(&self, fd: Fd) {
+        match self.description() {
+            Some(desc) => {
+                let _ = fd.write(desc.as_bytes());
+            }
+            None => {
+                // One write, as with every other `Print`
+                let mut buf = [0u8; "errno ".len() + i32::MAX_STR_LEN];
+                let mut writer = BufWriter::new(&mut buf);
+                let mut itoa_buf = itoa::Buffer::new();
+                if writer
+                    .push(b"errno ")
+                    .and_then(|_| writer.push(itoa_buf.format(self.into_raw()).as_bytes()))
+                    .is_ok()
+                {
+                    let _ = fd.write(writer.as_slice());
+                }
+            }
+        }
+    }
+
+    fn print_len(&self) -> usize {
+        match self.description() {
+            Some(desc) => desc.len(),
+            None => "errno ".len() + self.into_raw().print_len(),
+        }
+    }
+}
+
 /// - If pid 1 (only occurs as connate), try to re-exec every 60 seconds
 /// - Otherwise, exit immediately
 fn abort() -> ! {
@@ -69,10 +104,8 @@ impl<T> OrAbortResult<T> for Result<T, Errno> {
 
         eprint("ERROR: ");
         eprint(msg);
-        if let Some(e) = e.description() {
-            eprint(": ");
-            eprint(e);
-        }
+        eprint(": ");
+        eprint(e);
         eprint("\n");
 
         abort();
@@ -88,10 +121,8 @@ impl<T> OrAbortResult<T> for Result<T, Errno> {
         eprint(operation);
         eprint(" ");
         eprint(path);
-        if let Some(e) = e.description() {
-            eprint(": ");
-            eprint(e);
-        }
+        eprint(": ");
+        eprint(e);
         eprint("\n");
 
         abort();
@@ -131,6 +162,44 @@ impl<T> OrAbortOption<T> for Option<T> {
     }
 }
 
+/// Forwards `{}`-formatted output to stderr
+///
+/// Only used for panic messages, which may be formatted by code outside of our control.
+struct StderrFmt;
+
+impl core::fmt::Write for StderrFmt {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        eprint(s);
+        Ok(())
+    }
+}
+
+/// Report a panic to stderr and exit with `PANIC_EXIT_CODE`
+///
+/// Connate itself should never panic, but user config such as a `Run::Fn` may in a forked child.
+/// Its stderr is the service's log, and connate sees a failed run like any other.
+pub fn exit_on_panic(
+    message: impl core::fmt::Display,
+    location: Option<&core::panic::Location>,
+) -> ! {
+    use core::fmt::Write;
+
+    eprint("panicked");
+    if let Some(loc) = location {
+        eprint(" at ");
+        eprint(loc.file());
+        eprint(":");
+        eprint(loc.line());
+        eprint(":");
+        eprint(loc.column());
+    }
+    eprint(": ");
+    let _ = write!(StderrFmt, "{message}");
+    eprint("\n");
+
+    exit(PANIC_EXIT_CODE)
+}
+
 pub fn abort_lock_held_by_pid(path: &CStr, pid: pid_t) -> ! {
     eprint("ERROR: Lock path ");
     eprint(path);
@@ -145,7 +214,7 @@ pub fn abort_acquire_lock(path: &CStr, errno: Option<Errno>) -> ! {
     eprint("ERROR: Unable to acquire lock at ");
     eprint(path);
 
-    if let Some(e) = errno.and_then(|e| e.description()) {
+    if let Some(e) = errno {
         eprint(": ");
         eprint(e);
     }
@@ -154,3 +223,97 @@ pub fn abort_acquire_lock(path: &CStr, errno: Option<Errno>) -> ! {
 
     abort();
 }
+
+pub fn abort_not_connate(pid: pid_t) -> ! {
+    eprint("ERROR: PID ");
+    eprint(pid);
+    eprintln(
+        " does not hold connate's IPC pipes and so is not connate. If connate is not running as init, point conctl at it with a PID or lock file argument, $CONNATE_PID, or $CONNATE_LOCK_FILE.",
+    );
+
+    abort();
+}
+
+pub fn abort_lock_holder_hung(holder: Option<pid_t>, timeout_millis: i64) -> ! {
+    eprint("ERROR: ");
+    if let Some(pid) = holder {
+        eprint("PID ");
+        eprint(pid);
+    } else {
+        eprint("Another process");
+    }
+    eprint(" held connate's request pipe lock for over ");
+    eprint(timeout_millis / 1000);
+    eprintln(
+        " seconds and may be hung or stopped. Kill it, or resume it with SIGCONT, then retry.",
+    );
+
+    abort();
+}
+
+pub fn abort_fixed_fd_in_use(fd: c_int) -> ! {
+    eprint("ERROR: File descriptor ");
+    eprint(fd);
+    eprintln(
+        " is reserved by connate but was already open at startup. Close it before launching connate or change Config::FIXED_FDS.",
+    );
+
+    abort();
+}
+
+pub fn abort_fixed_fd_unexpected(fd: c_int) -> ! {
+    eprint("ERROR: File descriptor ");
+    eprint(fd);
+    eprintln(
+        " is reserved by connate but holds something other than what a prior connate left there. If this is not a re-exec, close it before launching connate or change Config::FIXED_FDS.",
+    );
+
+    abort();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_errnos_have_descriptions() {
+        for errno in [
+            Errno::EPERM,
+            Errno::ENOENT,
+            Errno::ESRCH,
+            Errno::EINTR,
+            Errno::EIO,
+            Errno::E2BIG,
+            Errno::ENOEXEC,
+            Errno::EBADF,
+            Errno::ECHILD,
+            Errno::EAGAIN,
+            Errno::ENOMEM,
+            Errno::EACCES,
+            Errno::EBUSY,
+            Errno::EEXIST,
+            Errno::ENOTDIR,
+            Errno::EISDIR,
+            Errno::EINVAL,
+            Errno::EMFILE,
+            Errno::ENOSPC,
+            Errno::EROFS,
+            Errno::EPIPE,
+            Errno::ENAMETOOLONG,
+            Errno::ENOSYS,
+            Errno::ELOOP,
+        ] {
+            assert!(
+                errno.description().is_some(),
+                "{errno:?} lacks a description"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_errno_prints_number() {
+        let errno = Errno::new(4000);
+        assert!(errno.description().is_none());
+        assert_eq!(errno.print_len(), "errno 4000".len());
+    }
+}
@@ -6,9 +6,11 @@
 //! - Complex time types converted to milliseconds
 //! - System call oriented pointers
 
+use crate::constants::PROGRESS_MSG_SIZE;
 use crate::err::*;
 use crate::ipc::*;
 use crate::os::*;
+use crate::supervisor::ConsolePipe;
 use crate::types::*;
 
 pub struct Service {
@@ -22,13 +24,40 @@ pub struct Service {
     pub supervisor_pid: Option<pid_t>,
     /// Logger stdin
     pub stdin_pipe: Option<(Fd, Fd)>,
+    /// Pipe `Log::Inherit` and `Log::Service` output goes through with
+    /// `Config::LINE_BUFFER_CONSOLE`
+    pub console_pipe: Option<ConsolePipe>,
     /// Number of times service has tried to start
     pub attempt_count: u32,
-    /// Return value of last "main" process
+    /// Random offset applied to the current retry delay
+    ///
+    /// Chosen when entering Retrying so that the delay is stable while waiting.
+    pub retry_jitter_millis: i64,
+    /// Return value of last "main" process, or None while the current one runs
     pub exit_code: Option<c_int>,
+    /// Whether `exit_code` is the expected result of connate stopping the service, in which case
+    /// a non-zero value does not indicate a problem
+    pub exit_expected: bool,
+    /// Whether a process has ever been spawned for the service, distinguishing a service which
+    /// has never run from one whose current process has yet to exit
+    pub has_run: bool,
+    /// Why the service last failed, retried, or could not be stopped, or why it is stuck waiting to
+    /// stop.  Only reported while it is in one of those states.
+    pub fail_reason: Option<FailReason>,
+    /// What the current setup or run process last reported it is doing, such as via `step()`.
+    /// Not retained across state changes or re-exec.
+    pub progress: Option<Progress>,
+    /// Index of the service which blocked this one from starting or stopping, if it failed while
+    /// waiting or is stuck waiting to stop.  Not retained across re-exec.
+    pub blocked_by: Option<usize>,
     /// Time service entered current state
     /// Delta from current time provides time spent in state
     pub time: timespec,
+    /// Time service last entered SettingUp, i.e. began starting
+    pub start_time: timespec,
+    /// Milliseconds from entering SettingUp to reaching Up, as of the last start which got that
+    /// far
+    pub start_duration_millis: Option<i64>,
     /// Readiness flag: true when external process informs us service is ready to transition to Up
     /// - Supervisor will set ready when daemonizing process daemonizes
     /// - Run::Notify will send Request::Ready signal (re-using same pid)
@@ -47,12 +76,16 @@ pub struct ServiceConfig {
     pub name: &'static [u8],
     pub index: usize,
     pub init_target: Target,
+    /// Kernel command line parameter without which the service starts `Down`
+    pub start_if_cmdline: Option<&'static [u8]>,
     //
     // Dependency entries
     //
     pub needs: &'static [usize],
     pub wants: &'static [usize],
     pub conflicts: &'static [usize],
+    pub conflict_action: ConflictAction,
+    pub serialize_with: &'static [usize],
     pub stop_dependencies: &'static [usize],
     pub groups: &'static [usize],
     /// Services which should have their target set upward when this service's target is set to
@@ -74,6 +107,11 @@ pub struct ServiceConfig {
     pub ready: Ready,
     pub cleanup: Run,
     pub stop_all_children: bool,
+    /// Signal first sent to children left by the main process when `stop_all_children` is set
+    pub children_stop_signal: Signal,
+    /// Time children have to exit after `children_stop_signal` before being sent SIGKILL
+    pub children_stop_time_millis: c_int,
+    pub kill_mode: KillMode,
     //
     // Retry and timeout entries
     //
@@ -82,18 +120,36 @@ pub struct ServiceConfig {
     pub max_ready_time_millis: Option<c_int>,
     pub max_stop_time_millis: Option<c_int>,
     pub max_cleanup_time_millis: Option<c_int>,
+    pub max_wait_time_millis: Option<c_int>,
+    /// With the default already applied
+    pub up_stable_time_millis: c_int,
     pub retry_wait_period_millis: c_int,
     pub retry_wait_multiplier: c_int, // either 1 or 2
+    pub max_delay_millis: Option<c_int>,
+    pub retry_jitter_millis: c_int,
     pub max_attempt_count: Option<u32>,
+    pub reset_attempts_on: ResetPolicy,
+    pub success_exit_codes: &'static [c_int],
+    pub fatal_exit_codes: &'static [c_int],
     //
     // Execution attribute entries
     //
+    pub stdin: StdinSource,
     pub log: Log,
+    pub env: &'static [&'static CStr],
+    pub clear_env: bool,
+    /// Variables of connate's environment to pass on despite `clear_env`
+    pub inherit_env: &'static [&'static [u8]],
     pub is_logger: bool,
     pub uid: Option<uid_t>,
     pub gid: Option<gid_t>,
+    /// Supplementary groups to replace connate's with, if any
+    pub supplementary_gids: Option<&'static [gid_t]>,
     pub no_new_privs: bool,
+    pub new_session: bool,
+    pub controlling_tty: Option<&'static CStr>,
     pub chdir: Option<&'static CStr>,
+    pub cgroup: Option<&'static CStr>,
 }
 
 #[repr(u8)]
@@ -131,8 +187,8 @@ pub enum State {
     // ================
     /// The service has failed and is waiting for `retry_period` to attempt to go Up again.
     Retrying = b'r',
-    /// The service has failed and exhausted `max_attempt_count` attempts.  Manual intervention is
-    /// required.
+    /// The service has failed and either exhausted `max_attempt_count` attempts or exited with one
+    /// of its `fatal_exit_codes`.  Manual intervention is required.
     Failed = b'f',
     /// The service did not stop when expected to.  We're forcing it down via SIGKILL.
     /// Waiting for either process to exit or timeout indicating we can't kill it.
@@ -198,7 +254,47 @@ pub enum Ready {
     Daemonize,
 }
 
+/// Which processes to signal when stopping a service
+#[derive(Copy, Clone)]
+pub enum KillMode {
+    /// Only the main process
+    Process,
+    /// SIGTERM then SIGKILL every process in the cgroup
+    ControlGroup,
+    /// SIGTERM the main process, then SIGKILL every process in the cgroup
+    Mixed,
+}
+
+/// What a starting service does about conflicting services which are up
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ConflictAction {
+    /// Wait for them to go down
+    Wait,
+    /// Set their targets, and those of their dependents, down
+    Stop,
+}
+
+/// When a retried service's attempt count resets
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ResetPolicy {
+    /// Once Up for `up_stable_time_millis`
+    UpStable,
+    /// Whenever the run exits 0 or with one of `success_exit_codes`
+    CleanExit,
+    /// Only when the target is manually set
+    Never,
+}
+
 impl Service {
+    /// The service's last exit, as reported by `status`
+    pub fn last_exit(&self) -> LastExit {
+        match self.exit_code {
+            Some(code) => LastExit::Code(code, self.exit_expected),
+            None if self.has_run => LastExit::NotYet,
+            None => LastExit::NeverRun,
+        }
+    }
+
     pub fn has_pid(&self) -> bool {
         self.pid.is_some() || self.supervisor_pid.is_some()
     }
@@ -217,28 +313,87 @@ impl Service {
         Some(write_fd.clone())
     }
 
+    /// Whether the attempt count resets once Up for `up_stable_time_millis`
+    pub fn awaiting_up_stable(&self) -> bool {
+        self.attempt_count > 0 && self.cfg.reset_attempts_on == ResetPolicy::UpStable
+    }
+
     /// Calculate retry delay in milliseconds for current attempt
     pub fn retry_delay_millis(&self) -> i64 {
-        (self.cfg.retry_wait_period_millis as i64).saturating_mul(
-            self.cfg
-                .retry_wait_multiplier
-                .saturating_pow(self.attempt_count.saturating_sub(1)) as i64,
+        backoff_millis(
+            self.cfg.retry_wait_period_millis,
+            self.cfg.retry_wait_multiplier,
+            self.attempt_count,
+            self.cfg.max_delay_millis,
         )
+        .saturating_add(self.retry_jitter_millis)
+        .max(0)
+    }
+}
+
+/// Convert a configured duration to the milliseconds poll(2) takes
+///
+/// Returns None if the duration does not fit in a `c_int`.
+pub fn duration_millis(duration: core::time::Duration) -> Option<c_int> {
+    c_int::try_from(duration.as_millis()).ok()
+}
+
+/// Calculate the retry delay for a given attempt before jitter is applied
+///
+/// `period * multiplier^(attempt_count-1)`, clamped to `max_delay` if set.
+fn backoff_millis(
+    period: c_int,
+    multiplier: c_int,
+    attempt_count: u32,
+    max_delay: Option<c_int>,
+) -> i64 {
+    let delay = (period as i64)
+        .saturating_mul(multiplier.saturating_pow(attempt_count.saturating_sub(1)) as i64);
+    match max_delay {
+        Some(max) => delay.min(max as i64),
+        None => delay,
     }
 }
 
 pub trait ServiceArray {
     fn all_down_or_err(&self) -> bool;
     fn any_bad(&self) -> bool;
+    fn all_targets_settled(&self) -> bool;
     fn find_dirty_index(&self) -> Option<usize>;
     fn find_by_pid_mut(&mut self, pid: pid_t) -> Option<&mut Service>;
     fn find_by_supervisor_pid_mut(&mut self, pid: pid_t) -> Option<&mut Service>;
     fn find_by_direct_or_supervisor_pid_mut(&mut self, pid: pid_t) -> Option<&mut Service>;
-    // Available via ServiceArrayExt trait in src/internal.rs :
+    // Available via ServiceArrayFind trait below:
     // fn find_by_name(&self, name: &[u8]) -> Option<&Service>;
     // fn find_by_name_mut(&mut self, name: &[u8]) -> Option<&mut Service>;
 }
 
+/// Maps a service name to its index in the services array
+///
+/// The generated config implements this with compile-time knowledge of service names.
+pub trait ServiceLookup {
+    fn index_of(name: &[u8]) -> Option<usize>;
+    /// Indices of the services listed in the named `Config::TARGETS` entry
+    fn boot_target(name: &[u8]) -> Option<&'static [usize]>;
+    /// Indices of the services listed in `Config::EMERGENCY_SERVICES`
+    fn emergency_target() -> &'static [usize];
+}
+
+pub trait ServiceArrayFind {
+    fn find_by_name<L: ServiceLookup>(&self, name: &[u8]) -> Option<&Service>;
+    fn find_by_name_mut<L: ServiceLookup>(&mut self, name: &[u8]) -> Option<&mut Service>;
+}
+
+impl<const N: usize> ServiceArrayFind for [Service; N] {
+    fn find_by_name<L: ServiceLookup>(&self, name: &[u8]) -> Option<&Service> {
+        L::index_of(name).and_then(|i| self.get(i))
+    }
+
+    fn find_by_name_mut<L: ServiceLookup>(&mut self, name: &[u8]) -> Option<&mut Service> {
+        L::index_of(name).and_then(|i| self.get_mut(i))
+    }
+}
+
 impl<const N: usize> ServiceArray for &mut [Service; N] {
     fn all_down_or_err(&self) -> bool {
         self.iter()
@@ -250,6 +405,13 @@ impl<const N: usize> ServiceArray for &mut [Service; N] {
             .any(|svc| matches!(svc.state, State::Failed | State::CannotStop))
     }
 
+    /// Whether every service targeted Up or Once has reached a stable state
+    fn all_targets_settled(&self) -> bool {
+        self.iter()
+            .filter(|svc| matches!(svc.target, Target::Up | Target::Once))
+            .all(|svc| svc.state.stable())
+    }
+
     fn find_dirty_index(&self) -> Option<usize> {
         self.iter()
             .enumerate()
@@ -264,9 +426,26 @@ impl<const N: usize> ServiceArray for &mut [Service; N] {
         self.iter_mut().find(|svc| svc.supervisor_pid == Some(pid))
     }
 
-    fn find_by_direct_or_supervisor_pid_mut(&mut self, pid: pid_t) -> Option<&mut Service> {
-        self.iter_mut()
-            .find(|svc| svc.pid == Some(pid) || svc.supervisor_pid == Some(pid))
+    /// Find the service whose direct or supervisor pid is `pid` or its nearest ancestor
+    ///
+    /// The ancestry is walked via /proc up to connate or init.  This covers a nested process
+    /// reporting itself rather than connate's direct child, including during the Starting window
+    /// in which only the supervisor pid is known.
+    fn find_by_direct_or_supervisor_pid_mut(&mut self, mut pid: pid_t) -> Option<&mut Service> {
+        let connate_pid = getpid();
+        let i = loop {
+            if let Some(i) = self
+                .iter()
+                .position(|svc| svc.pid == Some(pid) || svc.supervisor_pid == Some(pid))
+            {
+                break i;
+            }
+            pid = read_proc_stat_ppid(pid).ok()?;
+            if pid <= 1 || pid == connate_pid {
+                return None;
+            }
+        };
+        self.get_mut(i)
     }
 }
 
@@ -305,42 +484,78 @@ impl State {
     pub fn bad(&self) -> bool {
         matches!(self, State::Failed | State::CannotStop)
     }
+
+    /// Check if a state runs a `.setup`, `.run`, or `.cleanup` process whose progress is tracked
+    pub fn reports_progress(&self) -> bool {
+        matches!(self, State::SettingUp | State::Starting | State::CleaningUp)
+    }
+}
+
+impl State {
+    pub const ALL: [Self; 12] = [
+        State::Down,
+        State::WaitingToStart,
+        State::SettingUp,
+        State::Starting,
+        State::Up,
+        State::WaitingToStop,
+        State::Stopping,
+        State::CleaningUp,
+        State::Retrying,
+        State::Failed,
+        State::ForceDown,
+        State::CannotStop,
+    ];
+
+    /// Look up a state by its human-readable name
+    pub fn from_name(name: &[u8]) -> Result<Self, Errno> {
+        Self::ALL
+            .into_iter()
+            .find(|state| state.name().as_bytes() == name)
+            .ok_or(Errno::EINVAL)
+    }
+
+    /// Human-readable name, as printed by conctl
+    pub fn name(&self) -> &'static str {
+        match *self {
+            State::Down => "down",
+            State::WaitingToStart => "waiting-to-start",
+            State::SettingUp => "setting-up",
+            State::Starting => "starting",
+            State::Up => "up",
+            State::WaitingToStop => "waiting-to-stop",
+            State::Stopping => "stopping",
+            State::CleaningUp => "cleaning-up",
+            State::Retrying => "retrying",
+            State::Failed => "failed",
+            State::ForceDown => "force-down",
+            State::CannotStop => "cannot-stop",
+        }
+    }
 }
 
 impl Print for State {
     fn print(&self, _fd: Fd) {
         use Color::*;
-        match *self {
-            State::Down => print_color(Dim, "down"),
-            State::WaitingToStart => print_color(Transition, "waiting-to-start"),
-            State::SettingUp => print_color(Transition, "setting-up"),
-            State::Starting => print_color(Transition, "starting"),
-            State::Up => print_color(Okay, "up"),
-            State::WaitingToStop => print_color(Transition, "waiting-to-stop"),
-            State::Stopping => print_color(Transition, "stopping"),
-            State::CleaningUp => print_color(Transition, "cleaning-up"),
-            State::Retrying => print_color(Transition, "retrying"),
-            State::Failed => print_color(Error, "failed"),
-            State::ForceDown => print_color(Error, "force-down"),
-            State::CannotStop => print_color(Error, "cannot-stop"),
-        }
+        let color = match *self {
+            State::Down => Dim,
+            State::Up => Okay,
+            State::Failed | State::ForceDown => Error,
+            // connate has given up on stopping it, so it needs an operator: see force-reap
+            State::CannotStop => Critical,
+            State::WaitingToStart
+            | State::SettingUp
+            | State::Starting
+            | State::WaitingToStop
+            | State::Stopping
+            | State::CleaningUp
+            | State::Retrying => Transition,
+        };
+        print_color(color, self.name());
     }
 
     fn print_len(&self) -> usize {
-        match *self {
-            State::Down => "down".len(),
-            State::WaitingToStart => "waiting-to-start".len(),
-            State::SettingUp => "setting-up".len(),
-            State::Starting => "starting".len(),
-            State::Up => "up".len(),
-            State::WaitingToStop => "waiting-to-stop".len(),
-            State::Stopping => "stopping".len(),
-            State::CleaningUp => "cleaning-up".len(),
-            State::Retrying => "retrying".len(),
-            State::Failed => "failed".len(),
-            State::ForceDown => "force-down".len(),
-            State::CannotStop => "cannot-stop".len(),
-        }
+        self.name().len()
     }
 }
 
@@ -360,28 +575,461 @@ impl<'a> Target {
     }
 }
 
+impl Target {
+    pub const ALL: [Self; 4] = [Target::Down, Target::Up, Target::Restart, Target::Once];
+
+    /// Look up a target by its human-readable name
+    pub fn from_name(name: &[u8]) -> Result<Self, Errno> {
+        Self::ALL
+            .into_iter()
+            .find(|target| target.name().as_bytes() == name)
+            .ok_or(Errno::EINVAL)
+    }
+
+    /// Human-readable name, as printed by conctl
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Target::Up => "up",
+            Target::Down => "down",
+            Target::Once => "once",
+            Target::Restart => "restart",
+        }
+    }
+}
+
 impl Print for Target {
     fn print(&self, _fd: Fd) {
         use Color::*;
-        use {print, print_color};
-        match *self {
-            Target::Up => print("up"),
-            Target::Down => print_color(Dim, "down"),
-            Target::Once => print("once"),
-            Target::Restart => print_color(Transition, "restart"),
+        let color = match *self {
+            Target::Up | Target::Once => None,
+            Target::Down => Some(Dim),
+            Target::Restart => Some(Transition),
+        };
+        match color {
+            Some(color) => print_color(color, self.name()),
+            None => print(self.name()),
         }
     }
 
     fn print_len(&self) -> usize {
+        self.name().len()
+    }
+}
+
+/// A progress message reported by a service's process
+pub struct Progress {
+    msg: [u8; PROGRESS_MSG_SIZE],
+    len: usize,
+}
+
+impl Progress {
+    /// Copy `msg`, cutting it short at `PROGRESS_MSG_SIZE`
+    pub fn new(msg: &[u8]) -> Self {
+        let mut progress = Self {
+            msg: [0; PROGRESS_MSG_SIZE],
+            len: 0,
+        };
+        for (dst, &src) in progress.msg.iter_mut().zip(msg) {
+            *dst = src;
+            progress.len += 1;
+        }
+        progress
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.msg.get(..self.len).unwrap_or(&[])
+    }
+}
+
+/// Why a service last failed, was set to retry, or could not be stopped
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum FailReason {
+    /// Forking or executing the setup, run, or cleanup process failed
+    SpawnFailed = b'p',
+    /// The setup process exited unsuccessfully
+    SetupFailed = b's',
+    /// The run process exited, whether before or after becoming ready
+    RunExited = b'r',
+    /// A process outlived its `max_*_time` and was killed
+    TimedOut = b't',
+    /// Dependencies were not satisfied within `max_wait_time`
+    WaitTimedOut = b'w',
+    /// Dependencies could never be satisfied
+    Deadlocked = b'd',
+    /// A process survived SIGKILL
+    CannotStop = b'k',
+}
+
+impl FailReason {
+    pub fn as_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Self, Errno> {
+        match byte {
+            b'p' => Ok(FailReason::SpawnFailed),
+            b's' => Ok(FailReason::SetupFailed),
+            b'r' => Ok(FailReason::RunExited),
+            b't' => Ok(FailReason::TimedOut),
+            b'w' => Ok(FailReason::WaitTimedOut),
+            b'd' => Ok(FailReason::Deadlocked),
+            b'k' => Ok(FailReason::CannotStop),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+
+    /// Human-readable name, as printed by conctl
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FailReason::SpawnFailed => "spawn-failed",
+            FailReason::SetupFailed => "setup-failed",
+            FailReason::RunExited => "run-exited",
+            FailReason::TimedOut => "timed-out",
+            FailReason::WaitTimedOut => "wait-timed-out",
+            FailReason::Deadlocked => "deadlocked",
+            FailReason::CannotStop => "cannot-stop",
+        }
+    }
+
+    /// What happened and where to look next, as printed by `conctl why`
+    pub fn description(&self) -> &'static str {
         match *self {
-            Target::Up => "up".len(),
-            Target::Down => "down".len(),
-            Target::Once => "once".len(),
-            Target::Restart => "restart".len(),
+            FailReason::SpawnFailed => {
+                "could not start a process; check the executable, user, group, chdir, and log path"
+            }
+            FailReason::SetupFailed => "setup exited unsuccessfully; check the setup command",
+            FailReason::RunExited => "the service's process exited; check the service's log",
+            FailReason::TimedOut => {
+                "a process took longer than its max_*_time and was killed; check what it waits on"
+            }
+            FailReason::WaitTimedOut => {
+                "dependencies were not ready within max_wait_time; see `conctl blockers`"
+            }
+            FailReason::Deadlocked => {
+                "dependencies or dependents can never be satisfied; see `conctl blockers`"
+            }
+            FailReason::CannotStop => {
+                "a process survived SIGKILL; check for uninterruptible sleep or a lack of permission"
+            }
         }
     }
 }
 
+impl Print for FailReason {
+    fn print(&self, _fd: Fd) {
+        print_color(Color::Error, self.name());
+    }
+
+    fn print_len(&self) -> usize {
+        self.name().len()
+    }
+}
+
+/// A scalar service configuration field which can be queried over IPC
+///
+/// List-type fields such as `needs` have their own index-based queries.
+#[derive(Copy, Clone)]
+#[repr(u8)]
+pub enum ConfigField {
+    InitTarget = b't',
+    StartIfCmdline = b'b',
+    ConflictAction = b'o',
+    StopAllChildren = b'k',
+    KillMode = b'K',
+    ChildrenStopSignal = b'x',
+    ChildrenStopTime = b'X',
+    MaxSetupTime = b's',
+    MaxReadyTime = b'r',
+    MaxStopTime = b'p',
+    MaxCleanupTime = b'c',
+    MaxWaitTime = b'w',
+    UpStableTime = b'U',
+    RetryDelay = b'd',
+    RetryMultiplier = b'm',
+    RetryMaxDelay = b'D',
+    RetryJitter = b'j',
+    MaxAttempts = b'a',
+    ResetAttemptsOn = b'R',
+    Stdin = b'i',
+    ClearEnv = b'e',
+    IsLogger = b'l',
+    Uid = b'u',
+    Gid = b'g',
+    NoNewPrivs = b'n',
+    NewSession = b'S',
+    ControllingTty = b'T',
+    Chdir = b'C',
+    Cgroup = b'G',
+}
+
+impl ConfigField {
+    /// Every field, in the order `conctl cat` prints them
+    pub const ALL: [Self; 29] = [
+        Self::InitTarget,
+        Self::StartIfCmdline,
+        Self::ConflictAction,
+        Self::StopAllChildren,
+        Self::KillMode,
+        Self::ChildrenStopSignal,
+        Self::ChildrenStopTime,
+        Self::MaxSetupTime,
+        Self::MaxReadyTime,
+        Self::MaxStopTime,
+        Self::MaxCleanupTime,
+        Self::MaxWaitTime,
+        Self::UpStableTime,
+        Self::RetryDelay,
+        Self::RetryMultiplier,
+        Self::RetryMaxDelay,
+        Self::RetryJitter,
+        Self::MaxAttempts,
+        Self::ResetAttemptsOn,
+        Self::Stdin,
+        Self::ClearEnv,
+        Self::IsLogger,
+        Self::Uid,
+        Self::Gid,
+        Self::NoNewPrivs,
+        Self::NewSession,
+        Self::ControllingTty,
+        Self::Chdir,
+        Self::Cgroup,
+    ];
+
+    pub fn as_byte(&self) -> u8 {
+        *self as u8
+    }
+
+    pub fn from_byte(byte: u8) -> Result<Self, Errno> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.as_byte() == byte)
+            .ok_or(Errno::EINVAL)
+    }
+
+    /// Key used when printing the field
+    pub fn key(&self) -> &'static str {
+        match self {
+            Self::InitTarget => "init_target",
+            Self::StartIfCmdline => "start_if_cmdline",
+            Self::ConflictAction => "conflict_action",
+            Self::StopAllChildren => "stop_all_children",
+            Self::KillMode => "kill_mode",
+            Self::ChildrenStopSignal => "children_stop_signal",
+            Self::ChildrenStopTime => "children_stop_time_ms",
+            Self::MaxSetupTime => "max_setup_time_ms",
+            Self::MaxReadyTime => "max_ready_time_ms",
+            Self::MaxStopTime => "max_stop_time_ms",
+            Self::MaxCleanupTime => "max_cleanup_time_ms",
+            Self::MaxWaitTime => "max_wait_time_ms",
+            Self::UpStableTime => "up_stable_time_ms",
+            Self::RetryDelay => "retry_delay_ms",
+            Self::RetryMultiplier => "retry_multiplier",
+            Self::RetryMaxDelay => "retry_max_delay_ms",
+            Self::RetryJitter => "retry_jitter_ms",
+            Self::MaxAttempts => "max_attempts",
+            Self::ResetAttemptsOn => "reset_attempts_on",
+            Self::Stdin => "stdin",
+            Self::ClearEnv => "clear_env",
+            Self::IsLogger => "is_logger",
+            Self::Uid => "uid",
+            Self::Gid => "gid",
+            Self::NoNewPrivs => "no_new_privs",
+            Self::NewSession => "new_session",
+            Self::ControllingTty => "controlling_tty",
+            Self::Chdir => "chdir",
+            Self::Cgroup => "cgroup",
+        }
+    }
+}
+
+#[cfg(test)]
+impl ServiceConfig {
+    /// A service which runs nothing, for tests to override via struct update syntax
+    pub const TEST_DEFAULT: ServiceConfig = ServiceConfig {
+        name: b"test",
+        index: 0,
+        init_target: Target::Up,
+        start_if_cmdline: None,
+        needs: &[],
+        wants: &[],
+        conflicts: &[],
+        conflict_action: ConflictAction::Wait,
+        serialize_with: &[],
+        stop_dependencies: &[],
+        groups: &[],
+        target_up_propagate_up: &[],
+        target_up_propagate_down: &[],
+        target_down_propagate_down: &[],
+        propagate_dirty: &[],
+        setup: Run::None,
+        run: Run::None,
+        ready: Ready::Immediately,
+        cleanup: Run::None,
+        stop_all_children: false,
+        children_stop_signal: Signal::SIGKILL,
+        children_stop_time_millis: 0,
+        kill_mode: KillMode::Process,
+        max_setup_time_millis: None,
+        max_ready_time_millis: None,
+        max_stop_time_millis: None,
+        max_cleanup_time_millis: None,
+        max_wait_time_millis: None,
+        up_stable_time_millis: 1_000,
+        retry_wait_period_millis: 1_000,
+        retry_wait_multiplier: 1,
+        max_delay_millis: None,
+        retry_jitter_millis: 0,
+        max_attempt_count: Some(3),
+        reset_attempts_on: ResetPolicy::UpStable,
+        success_exit_codes: &[0],
+        fatal_exit_codes: &[],
+        stdin: StdinSource::Null,
+        log: Log::None,
+        env: &[],
+        clear_env: false,
+        inherit_env: &[],
+        is_logger: false,
+        uid: None,
+        gid: None,
+        supplementary_gids: None,
+        no_new_privs: false,
+        new_session: true,
+        controlling_tty: None,
+        chdir: None,
+        cgroup: None,
+    };
+}
+
+#[cfg(test)]
+impl Service {
+    /// A service in `state` which has never run, targeted up and entering `state` at time zero
+    pub fn test(cfg: &'static ServiceConfig, state: State) -> Self {
+        const ZERO: timespec = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        Self {
+            state,
+            target: Target::Up,
+            pid: None,
+            supervisor_pid: None,
+            stdin_pipe: None,
+            console_pipe: None,
+            attempt_count: 0,
+            retry_jitter_millis: 0,
+            exit_code: None,
+            exit_expected: false,
+            has_run: false,
+            fail_reason: None,
+            progress: None,
+            blocked_by: None,
+            time: ZERO,
+            start_time: ZERO,
+            start_duration_millis: None,
+            ready: false,
+            dirty: false,
+            #[cfg(feature = "settle")]
+            settle_pipe: None,
+            cfg,
+        }
+    }
+}
+
+impl ServiceConfig {
+    pub fn field_as_response(&'static self, field: ConfigField) -> Response<'static> {
+        let millis = |ms: Option<c_int>| match ms {
+            Some(ms) => Response::Number(ms as i64),
+            None => Response::FieldIsNone,
+        };
+
+        match field {
+            ConfigField::InitTarget => Response::Target(self.init_target),
+            ConfigField::StartIfCmdline => match self.start_if_cmdline {
+                Some(param) => Response::Name(param),
+                None => Response::FieldIsNone,
+            },
+            ConfigField::ConflictAction => match self.conflict_action {
+                ConflictAction::Wait => Response::Name(b"wait"),
+                ConflictAction::Stop => Response::Name(b"stop"),
+            },
+            ConfigField::StopAllChildren => Response::Bool(self.stop_all_children),
+            ConfigField::KillMode => match self.kill_mode {
+                KillMode::Process => Response::Name(b"process"),
+                KillMode::ControlGroup => Response::Name(b"control-group"),
+                KillMode::Mixed => Response::Name(b"mixed"),
+            },
+            ConfigField::ChildrenStopSignal => {
+                Response::Name(self.children_stop_signal.name().as_bytes())
+            }
+            ConfigField::ChildrenStopTime => {
+                Response::Number(self.children_stop_time_millis as i64)
+            }
+            ConfigField::MaxSetupTime => millis(self.max_setup_time_millis),
+            ConfigField::MaxReadyTime => millis(self.max_ready_time_millis),
+            ConfigField::MaxStopTime => millis(self.max_stop_time_millis),
+            ConfigField::MaxCleanupTime => millis(self.max_cleanup_time_millis),
+            ConfigField::MaxWaitTime => millis(self.max_wait_time_millis),
+            ConfigField::UpStableTime => Response::Number(self.up_stable_time_millis as i64),
+            ConfigField::RetryDelay => Response::Number(self.retry_wait_period_millis as i64),
+            ConfigField::RetryMultiplier => Response::Number(self.retry_wait_multiplier as i64),
+            ConfigField::RetryMaxDelay => millis(self.max_delay_millis),
+            ConfigField::RetryJitter => Response::Number(self.retry_jitter_millis as i64),
+            ConfigField::MaxAttempts => match self.max_attempt_count {
+                Some(count) => Response::Number(count as i64),
+                None => Response::FieldIsNone,
+            },
+            ConfigField::ResetAttemptsOn => match self.reset_attempts_on {
+                ResetPolicy::UpStable => Response::Name(b"up-stable"),
+                ResetPolicy::CleanExit => Response::Name(b"clean-exit"),
+                ResetPolicy::Never => Response::Name(b"never"),
+            },
+            ConfigField::Stdin => match self.stdin {
+                StdinSource::Null => Response::Name(b"null"),
+                StdinSource::Inherit => Response::Name(b"inherit"),
+                StdinSource::File(path) => Response::Path(path.to_bytes()),
+            },
+            ConfigField::ClearEnv => Response::Bool(self.clear_env),
+            ConfigField::IsLogger => Response::Bool(self.is_logger),
+            ConfigField::Uid => match self.uid {
+                Some(uid) => Response::Number(uid as i64),
+                None => Response::FieldIsNone,
+            },
+            ConfigField::Gid => match self.gid {
+                Some(gid) => Response::Number(gid as i64),
+                None => Response::FieldIsNone,
+            },
+            ConfigField::NoNewPrivs => Response::Bool(self.no_new_privs),
+            ConfigField::NewSession => Response::Bool(self.new_session),
+            ConfigField::ControllingTty => match self.controlling_tty {
+                Some(path) => Response::Path(path.to_bytes()),
+                None => Response::FieldIsNone,
+            },
+            ConfigField::Chdir => match self.chdir {
+                Some(path) => Response::Path(path.to_bytes()),
+                None => Response::FieldIsNone,
+            },
+            ConfigField::Cgroup => match self.cgroup {
+                Some(path) => Response::Path(path.to_bytes()),
+                None => Response::FieldIsNone,
+            },
+        }
+    }
+}
+
+/// Internal stdin configuration
+///
+/// This is the build-time processed version of the user-facing `crate::config::StdinSource` enum.
+pub enum StdinSource {
+    /// Read from /dev/null
+    Null,
+    /// Inherit connate's stdin
+    Inherit,
+    /// Read from the given file path
+    File(&'static CStr),
+}
+
 /// Internal logging configuration
 ///
 /// This is the build-time processed version of the user-facing `crate::service::Log` enum.
@@ -418,3 +1066,71 @@ impl Log {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_fixed() {
+        assert_eq!(backoff_millis(1000, 1, 1, None), 1000);
+        assert_eq!(backoff_millis(1000, 1, 10, None), 1000);
+    }
+
+    #[test]
+    fn test_backoff_doubling() {
+        assert_eq!(backoff_millis(1000, 2, 1, None), 1000);
+        assert_eq!(backoff_millis(1000, 2, 2, None), 2000);
+        assert_eq!(backoff_millis(1000, 2, 4, None), 8000);
+    }
+
+    #[test]
+    fn test_backoff_clamped() {
+        assert_eq!(backoff_millis(1000, 2, 6, Some(60_000)), 32_000);
+        assert_eq!(backoff_millis(1000, 2, 7, Some(60_000)), 60_000);
+        assert_eq!(backoff_millis(1000, 2, 100, Some(60_000)), 60_000);
+    }
+
+    #[test]
+    fn test_duration_millis_bounds() {
+        use core::time::Duration;
+
+        assert_eq!(duration_millis(Duration::from_secs(5)), Some(5000));
+        assert_eq!(
+            duration_millis(Duration::from_millis(i32::MAX as u64)),
+            Some(i32::MAX)
+        );
+        // Rather than wrapping to a negative, and thus infinite, poll timeout
+        assert_eq!(
+            duration_millis(Duration::from_millis(i32::MAX as u64 + 1)),
+            None
+        );
+        assert_eq!(
+            duration_millis(Duration::from_secs(30 * 24 * 60 * 60)),
+            None
+        );
+    }
+
+    #[test]
+    fn state_and_target_names_round_trip() {
+        for state in State::ALL {
+            assert!(
+                State::from_name(state.name().as_bytes()).map(|s| s.as_byte())
+                    == Ok(state.as_byte())
+            );
+        }
+        for target in Target::ALL {
+            assert!(
+                Target::from_name(target.name().as_bytes()).map(|t| t.as_byte())
+                    == Ok(target.as_byte())
+            );
+        }
+        assert!(State::from_name(b"Up").is_err());
+        assert!(Target::from_name(b"sideways").is_err());
+    }
+
+    #[test]
+    fn test_backoff_saturates_without_clamp() {
+        assert_eq!(backoff_millis(1000, 2, 100, None), 1000 * i32::MAX as i64);
+    }
+}
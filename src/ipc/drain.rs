@@ -0,0 +1,246 @@
+//! Client side of `conctl drain`, which stops a service only once its dependents have settled
+
+use crate::err::*;
+use crate::internal_api::Target;
+use crate::ipc::{IpcClient, Request, Response};
+use crate::util::BufWriter;
+
+/// How far `drain()` got
+#[derive(Debug, PartialEq, Eq)]
+pub enum Drain {
+    /// The service was brought down after its dependents.  Whether it settled badly.
+    Down(bool),
+    /// A dependent settled badly, so the service was left up
+    NotDrained,
+    /// connate refused a request, which was passed to `refused`
+    Refused,
+}
+
+/// Bring the service `name` down only once everything depending on it has settled
+///
+/// The dependents setting `name` down would bring down along with it are set down first, and
+/// `wait` is called to block until they settle.  Only if none settled badly is `name` itself set
+/// down and waited on.
+///
+/// - `names_buf` holds the dependents' names, as each response reuses `ipc_client`'s buffer.
+/// - `N` is the most dependents there may be, i.e. the number of services.
+/// - `wait` is given the services to wait on and the width to pad their names to, and returns
+///   whether any settled into Failed or CannotStop.
+/// - `refused` is given the service a request was about, the same width, and connate's response.
+pub fn drain<const N: usize>(
+    ipc_client: &mut IpcClient,
+    name: &[u8],
+    names_buf: &mut [u8],
+    mut wait: impl FnMut(&mut IpcClient, &[&[u8]], usize) -> bool,
+    mut refused: impl FnMut(&[u8], usize, Response),
+) -> Drain {
+    let mut lens = [0usize; N];
+    let mut count = 0;
+    let mut writer = BufWriter::new(names_buf);
+    for n in 0.. {
+        match ipc_client.send_and_receive(Request::PreviewTarget(n, Target::Down, name)) {
+            Response::TargetChange(_, _, affected) if affected == name => {}
+            Response::TargetChange(_, _, affected) => {
+                let len = lens
+                    .get_mut(count)
+                    .or_abort("More dependents than services are configured");
+                writer
+                    .push(affected)
+                    .or_abort("Dependent names do not fit in the drain buffer");
+                *len = affected.len();
+                count += 1;
+            }
+            // Past the last affected service
+            Response::FieldIsNone => break,
+            response => {
+                refused(name, name.len(), response);
+                return Drain::Refused;
+            }
+        }
+    }
+
+    let mut dependents: [&[u8]; N] = [&[]; N];
+    let mut offset = 0;
+    for (dependent, &len) in dependents.iter_mut().zip(lens.iter().take(count)) {
+        *dependent = writer
+            .as_slice()
+            .get(offset..offset + len)
+            .or_abort("Invalid dependent name length");
+        offset += len;
+    }
+    let dependents = dependents.get(..count).or_abort("Invalid dependent count");
+    let max_name_len = dependents.iter().fold(name.len(), |max, dependent| {
+        core::cmp::max(max, dependent.len())
+    });
+
+    for &dependent in dependents {
+        let response = ipc_client.send_and_receive(Request::SetTargetDown(dependent));
+        if response.cmd_return_failed() {
+            refused(dependent, max_name_len, response);
+            return Drain::Refused;
+        }
+    }
+    if wait(ipc_client, dependents, max_name_len) {
+        return Drain::NotDrained;
+    }
+
+    let response = ipc_client.send_and_receive(Request::SetTargetDown(name));
+    if response.cmd_return_failed() {
+        refused(name, max_name_len, response);
+        return Drain::Refused;
+    }
+    Drain::Down(wait(ipc_client, &[name], max_name_len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal_api::State;
+    use crate::ipc::MockConnate;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    // The mock answers from a forked process of its own, so these are its state alone
+    static APP_DOWN: AtomicBool = AtomicBool::new(false);
+    static DB_DOWN: AtomicBool = AtomicBool::new(false);
+
+    /// `app` depends on `db`; `broken` fails to stop
+    fn respond(request: Request) -> Response<'static> {
+        let target = |down: &AtomicBool| match down.load(Ordering::Relaxed) {
+            true => Target::Down,
+            false => Target::Up,
+        };
+        match request {
+            Request::PreviewTarget(0, Target::Down, b"db") => {
+                Response::TargetChange(Target::Up, Target::Down, b"db")
+            }
+            Request::PreviewTarget(1, Target::Down, b"db") => {
+                Response::TargetChange(Target::Up, Target::Down, b"app")
+            }
+            Request::PreviewTarget(0, Target::Down, b"lone") => {
+                Response::TargetChange(Target::Up, Target::Down, b"lone")
+            }
+            Request::PreviewTarget(0, Target::Down, b"shared") => {
+                Response::TargetChange(Target::Up, Target::Down, b"broken")
+            }
+            Request::PreviewTarget(_, Target::Down, b"db" | b"lone" | b"shared") => {
+                Response::FieldIsNone
+            }
+            Request::SetTargetDown(b"app") => {
+                APP_DOWN.store(true, Ordering::Relaxed);
+                Response::Okay
+            }
+            Request::SetTargetDown(b"db") => {
+                DB_DOWN.store(true, Ordering::Relaxed);
+                Response::Okay
+            }
+            Request::SetTargetDown(b"lone" | b"broken" | b"shared") => Response::Okay,
+            Request::QueryByNameTarget(b"app") => Response::Target(target(&APP_DOWN)),
+            Request::QueryByNameTarget(b"db") => Response::Target(target(&DB_DOWN)),
+            Request::QueryByNameState(b"broken") => Response::State(State::CannotStop),
+            Request::QueryByNameState(_) => Response::State(State::Down),
+            _ => Response::ServiceNotFound,
+        }
+    }
+
+    fn target(ipc_client: &mut IpcClient, name: &[u8]) -> Target {
+        match ipc_client.send_and_receive(Request::QueryByNameTarget(name)) {
+            Response::Target(target) => target,
+            _ => panic!("unexpected response"),
+        }
+    }
+
+    /// Stands in for waiting to settle, reporting whether any of `names` is stuck
+    fn any_bad(ipc_client: &mut IpcClient, names: &[&[u8]]) -> bool {
+        names.iter().any(|name| {
+            matches!(
+                ipc_client.send_and_receive(Request::QueryByNameState(name)),
+                Response::State(State::Failed | State::CannotStop)
+            )
+        })
+    }
+
+    #[test]
+    fn dependents_are_brought_down_first() {
+        let (_connate, mut ipc_client) = MockConnate::spawn(respond);
+        let mut buf = [0u8; 64];
+        let mut waits = 0;
+
+        let drained = drain::<4>(
+            &mut ipc_client,
+            b"db",
+            &mut buf,
+            |ipc_client, names, width| {
+                waits += 1;
+                assert_eq!(width, b"app".len());
+                match waits {
+                    1 => {
+                        assert_eq!(names, [&b"app"[..]]);
+                        assert!(matches!(target(ipc_client, b"app"), Target::Down));
+                        assert!(matches!(target(ipc_client, b"db"), Target::Up));
+                    }
+                    _ => {
+                        assert_eq!(names, [&b"db"[..]]);
+                        assert!(matches!(target(ipc_client, b"db"), Target::Down));
+                    }
+                }
+                any_bad(ipc_client, names)
+            },
+            |_, _, _| panic!("unexpected refusal"),
+        );
+        assert_eq!(drained, Drain::Down(false));
+        assert_eq!(waits, 2);
+
+        // Without dependents, the wait on them is trivially over
+        let drained = drain::<4>(
+            &mut ipc_client,
+            b"lone",
+            &mut buf,
+            |ipc_client, names, _| any_bad(ipc_client, names),
+            |_, _, _| panic!("unexpected refusal"),
+        );
+        assert_eq!(drained, Drain::Down(false));
+    }
+
+    #[test]
+    fn service_is_left_up_if_a_dependent_does_not_stop() {
+        let (_connate, mut ipc_client) = MockConnate::spawn(respond);
+        let mut buf = [0u8; 64];
+        let mut waits = 0;
+
+        let drained = drain::<4>(
+            &mut ipc_client,
+            b"shared",
+            &mut buf,
+            |ipc_client, names, _| {
+                waits += 1;
+                assert_eq!(names, [&b"broken"[..]]);
+                any_bad(ipc_client, names)
+            },
+            |_, _, _| panic!("unexpected refusal"),
+        );
+        assert_eq!(drained, Drain::NotDrained);
+        // Never set down, so never waited on
+        assert_eq!(waits, 1);
+    }
+
+    #[test]
+    fn refusal_is_reported() {
+        let (_connate, mut ipc_client) = MockConnate::spawn(respond);
+        let mut buf = [0u8; 64];
+        let mut refusals = 0;
+
+        let drained = drain::<4>(
+            &mut ipc_client,
+            b"missing",
+            &mut buf,
+            |_, _, _| panic!("nothing to wait on"),
+            |name, _, response| {
+                refusals += 1;
+                assert_eq!(name, b"missing");
+                assert!(matches!(response, Response::ServiceNotFound));
+            },
+        );
+        assert_eq!(drained, Drain::Refused);
+        assert_eq!(refusals, 1);
+    }
+}
@@ -1,11 +1,70 @@
 use crate::constants::*;
 use crate::err::*;
 use crate::ipc::{Request, Response};
-use crate::os::{Fd, OpenFlags, eprint};
+use crate::os::{Fd, OpenFlags, eprint, eprintln, get_time_monotonic, readlink};
+use crate::syscall::{PollEvents, PollFd, poll};
 use crate::types::*;
 use crate::util::{BufWriter, memzero};
 use itoa::Integer; // ::MAX_STR_LEN
 
+const PROC_FD_PATH_SIZE: usize = b"/proc/".len()
+    + pid_t::MAX_STR_LEN // pid number
+    + b"/fd/".len()
+    + c_int::MAX_STR_LEN // fd number
+    + size_of::<u8>(); // trailing null
+
+/// Build `/proc/<pid>/fd/<fd>` in `buf`
+fn proc_fd_path(buf: &mut [u8; PROC_FD_PATH_SIZE], pid: pid_t, fd: c_int) -> &CStr {
+    let mut itoa_buf = itoa::Buffer::new();
+    let mut itoa_fd_buf = itoa::Buffer::new();
+    let mut writer = BufWriter::new(buf);
+    writer
+        .push(b"/proc/")
+        .and_then(|_| writer.push(itoa_buf.format(pid).as_bytes()))
+        .and_then(|_| writer.push(b"/fd/"))
+        .and_then(|_| writer.push(itoa_fd_buf.format(fd).as_bytes()))
+        .and_then(|_| writer.push(b"\0"))
+        // This should be unreachable
+        .or_abort("buffer overflow");
+    let len = writer.pos();
+
+    // Safety: We just built this buffer including the trailing null
+    unsafe { CStr::from_bytes_with_nul_unchecked(buf.get(..len).unwrap_or(&[0])) }
+}
+
+/// Whether `pid` holds both ends of both of connate's IPC pipes at the fixed fds
+///
+/// Nothing other than connate is likely to, which makes this a cheap check that `pid` is connate
+/// before sending it anything.
+fn holds_ipc_pipes(pid: pid_t) -> Result<bool, Errno> {
+    for (read_fd, write_fd) in [(FD_REQ_READ, FD_REQ_WRITE), (FD_RESP_READ, FD_RESP_WRITE)] {
+        let mut read_buf = [0u8; PROC_FD_PATH_SIZE];
+        let mut write_buf = [0u8; PROC_FD_PATH_SIZE];
+        let read_target = fd_target(pid, read_fd, &mut read_buf)?;
+        let write_target = fd_target(pid, write_fd, &mut write_buf)?;
+        if !read_target.starts_with(b"pipe:") || read_target != write_target {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Read what `pid`'s `fd` refers to, e.g. `pipe:[1234]`, into `buf`
+///
+/// An fd which isn't open reads as empty.
+fn fd_target(pid: pid_t, fd: c_int, buf: &mut [u8; PROC_FD_PATH_SIZE]) -> Result<&[u8], Errno> {
+    let mut path_buf = [0u8; PROC_FD_PATH_SIZE];
+    let path = proc_fd_path(&mut path_buf, pid, fd);
+    let len = match readlink(path, buf) {
+        Err(Errno::ENOENT) => 0,
+        result => result?,
+    };
+    Ok(buf.get(..len).unwrap_or(&[]))
+}
+
+/// How often to retry while another process holds the request pipe lock
+const LOCK_RETRY_INTERVAL_MILLIS: c_int = 10;
+
 /// cctl and supervisor side of communication channel to connate
 pub struct IpcClient {
     fd_req_write: Fd,
@@ -15,60 +74,29 @@ pub struct IpcClient {
 
 impl<'a> IpcClient {
     pub fn from_pid(connate_pid: pid_t) -> Self {
-        const COMM_PATH_SIZE: usize = b"/proc/".len()
-            + pid_t::MAX_STR_LEN // pid number
-            + b"/fd/".len()
-            + c_int::MAX_STR_LEN // fd number
-            + size_of::<u8>(); // trailing null
-        let mut buf = [0u8; COMM_PATH_SIZE];
-        let mut writer = BufWriter::new(&mut buf);
-
-        let mut itoa_buf = itoa::Buffer::new();
-        let pid_str = itoa_buf.format(connate_pid);
-
-        // Build path: /proc/<pid>/fd/<response-read-fd>\0
-        writer
-            .push(b"/proc/")
-            .and_then(|_| writer.push(pid_str.as_bytes()))
-            .and_then(|_| writer.push(b"/fd/"))
-            .and_then(|_| writer.push(FD_RESP_READ_STR))
-            .and_then(|_| writer.push(b"\0"))
-            // This should be unreachable
-            .or_abort("buffer overflow");
-
-        // Safety: We just built this buffer including the trailing null
-        let read_fd_path: &CStr = unsafe { CStr::from_bytes_with_nul_unchecked(writer.as_slice()) };
-        // Open in non-blocking mode to avoid blocking if connate isn't running and to allow draining
+        // Opening another process's fds could otherwise write requests into whatever it happens
+        // to hold at these numbers, such as when defaulting to a PID 1 which isn't connate.
+        match holds_ipc_pipes(connate_pid) {
+            Ok(true) => {}
+            Ok(false) => abort_not_connate(connate_pid),
+            Err(e) => {
+                let mut buf = [0u8; PROC_FD_PATH_SIZE];
+                let path = proc_fd_path(&mut buf, connate_pid, FD_REQ_READ);
+                Err::<(), Errno>(e).or_fs_abort("readlink", path);
+            }
+        }
+
+        let mut buf = [0u8; PROC_FD_PATH_SIZE];
+        let read_fd_path = proc_fd_path(&mut buf, connate_pid, FD_RESP_READ);
+        // Open in non-blocking mode to avoid blocking if connate isn't running
         let fd_resp_read = Fd::open(read_fd_path, OpenFlags::O_RDONLY | OpenFlags::O_NONBLOCK, 0)
             .or_fs_abort("open", read_fd_path);
 
-        // Build path: /proc/<pid>/fd/<request-write-fd>\0
-        writer.reset();
-        writer
-            .push(b"/proc/")
-            .and_then(|_| writer.push(pid_str.as_bytes()))
-            .and_then(|_| writer.push(b"/fd/"))
-            .and_then(|_| writer.push(FD_REQ_WRITE_STR))
-            .and_then(|_| writer.push(b"\0"))
-            // This should be unreachable
-            .or_abort("buffer overflow");
-
-        // Safety: buffer was zero-initialized, guaranteeing following unread byte is null
-        let write_fd_path: &CStr =
-            unsafe { CStr::from_bytes_with_nul_unchecked(writer.as_slice()) };
+        let mut buf = [0u8; PROC_FD_PATH_SIZE];
+        let write_fd_path = proc_fd_path(&mut buf, connate_pid, FD_REQ_WRITE);
         let fd_req_write =
             Fd::open(write_fd_path, OpenFlags::O_RDWR, 0).or_fs_abort("open", write_fd_path);
 
-        // Clear any stale data in the response pipe while it's still non-blocking
-        let mut drain_buf = [0u8; MSG_SIZE];
-        loop {
-            match fd_resp_read.read(&mut drain_buf) {
-                Ok(_) => continue,           // Keep draining
-                Err(Errno::EAGAIN) => break, // Pipe is empty (EWOULDBLOCK is same value)
-                Err(_) => break,             // Other error, stop trying
-            }
-        }
-
         // Switch to blocking mode to wait for connate's response to our request
         fd_resp_read
             .set_blocking()
@@ -81,7 +109,73 @@ impl<'a> IpcClient {
         }
     }
 
-    #[cfg(test)]
+    /// Like [`Self::from_pid`], but returns `None` rather than aborting if `connate_pid` is not
+    /// connate or its pipes can't be opened
+    ///
+    /// For best-effort messages from within services, which may be unable to see connate's fds
+    /// after dropping privileges.  Pair with [`Self::close`] in processes which may connect
+    /// repeatedly.
+    pub fn try_from_pid(connate_pid: pid_t) -> Option<Self> {
+        if !holds_ipc_pipes(connate_pid).unwrap_or(false) {
+            return None;
+        }
+
+        let mut buf = [0u8; PROC_FD_PATH_SIZE];
+        let read_fd_path = proc_fd_path(&mut buf, connate_pid, FD_RESP_READ);
+        let fd_resp_read =
+            Fd::open(read_fd_path, OpenFlags::O_RDONLY | OpenFlags::O_NONBLOCK, 0).ok()?;
+
+        let mut buf = [0u8; PROC_FD_PATH_SIZE];
+        let write_fd_path = proc_fd_path(&mut buf, connate_pid, FD_REQ_WRITE);
+        let fd_req_write = match Fd::open(write_fd_path, OpenFlags::O_RDWR, 0) {
+            Ok(fd) => fd,
+            Err(_) => {
+                let _ = fd_resp_read.close();
+                return None;
+            }
+        };
+
+        let client = Self {
+            fd_req_write,
+            fd_resp_read,
+            buf: [0u8; MSG_SIZE],
+        };
+        match client.fd_resp_read.set_blocking() {
+            Ok(()) => Some(client),
+            Err(_) => {
+                client.close();
+                None
+            }
+        }
+    }
+
+    /// Discard responses left in the pipe by clients which gave up waiting on them
+    ///
+    /// Must only be called while locked, as before then they may be the response another client
+    /// is waiting on.  Each of the `lock_*()` methods does so once it holds the lock.
+    fn drain_stale(&mut self) {
+        let mut pollfd = PollFd {
+            fd: self.fd_resp_read.as_raw(),
+            events: PollEvents::POLLIN,
+            revents: PollEvents::empty(),
+        };
+        // Polled first such that this works whether or not the pipe blocks
+        while let Ok(1) = unsafe { poll(core::slice::from_mut(&mut pollfd), 0) } {
+            if !pollfd.revents.contains(PollEvents::POLLIN)
+                || self.fd_resp_read.read(&mut self.buf).is_err()
+            {
+                break;
+            }
+        }
+    }
+
+    /// Close the pipes, releasing the lock if held
+    pub fn close(self) {
+        let _ = self.fd_req_write.close();
+        let _ = self.fd_resp_read.close();
+    }
+
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn new_test(fd_req_write: Fd, fd_resp_read: Fd) -> Self {
         Self {
             fd_req_write,
@@ -97,37 +191,162 @@ impl<'a> IpcClient {
     }
 
     pub fn lock_with_warning(&mut self) {
-        match self.fd_req_write.lock_nonblocking() {
-            Ok(()) => {}
-            Err(err) if err == Errno::EACCES || err == Errno::EAGAIN => {
-                let locking_pid = self
-                    .fd_req_write
-                    .get_locking_pid()
-                    .or_fs_abort("get lock holder", c"connate request pipe");
-
-                if let Some(pid) = locking_pid {
-                    eprint("WARNING: Waiting for PID ");
-                    eprint(pid);
-                    eprint(" to release lock... ");
-                } else {
-                    eprint("WARNING: Waiting for another process to release lock... ");
-                }
-
-                self.fd_req_write
-                    .lock_blocking()
-                    .or_fs_abort("lock", c"connate request pipe");
+        if self.try_lock() {
+            self.drain_stale();
+            return;
+        }
+
+        if let Some(pid) = self.lock_holder() {
+            eprint("WARNING: Waiting for PID ");
+            eprint(pid);
+            eprint(" to release lock... ");
+        } else {
+            eprint("WARNING: Waiting for another process to release lock... ");
+        }
+
+        if let Err(holder) = self.lock_unless_held_for(LOCK_HOLDER_TIMEOUT_MILLIS) {
+            eprintln("");
+            abort_lock_holder_hung(holder, LOCK_HOLDER_TIMEOUT_MILLIS);
+        }
+        self.drain_stale();
+    }
+
+    /// Lock, giving up if another process holds the lock for longer than `timeout_millis`
+    ///
+    /// Returns whether the lock was acquired.
+    pub fn lock_within(&mut self, timeout_millis: c_int) -> bool {
+        let start = get_time_monotonic().or_abort("Unable to get current time");
+        loop {
+            if self.try_lock() {
+                self.drain_stale();
+                return true;
+            }
+
+            let now = get_time_monotonic().or_abort("Unable to get current time");
+            if now.millis_since(start) >= timeout_millis as i64 {
+                return false;
+            }
+            // Sleep
+            let _ = unsafe { poll(&mut [], LOCK_RETRY_INTERVAL_MILLIS) };
+        }
+    }
+
+    /// Lock, giving up if any single process holds the lock for longer than `timeout_millis`
+    ///
+    /// Unlike [`Self::lock_within`], the wait restarts whenever the lock changes hands, so a queue
+    /// of other conctl invocations may take as long as it needs.  A holder which dies has its lock
+    /// released by the kernel and so is never waited on.  Returns the PID of the holder which
+    /// didn't release it in time, if it could be determined.
+    fn lock_unless_held_for(&mut self, timeout_millis: i64) -> Result<(), Option<pid_t>> {
+        let mut holder = self.lock_holder();
+        let mut since = get_time_monotonic().or_abort("Unable to get current time");
+        loop {
+            if self.try_lock() {
+                return Ok(());
             }
-            Err(e) => Err::<(), Errno>(e).or_fs_abort("lock", c"connate request pipe"),
+
+            let now = get_time_monotonic().or_abort("Unable to get current time");
+            let current = self.lock_holder();
+            if current != holder {
+                holder = current;
+                since = now;
+            } else if now.millis_since(since) >= timeout_millis {
+                return Err(holder);
+            }
+            // Sleep
+            let _ = unsafe { poll(&mut [], LOCK_RETRY_INTERVAL_MILLIS) };
         }
     }
 
+    /// Take the lock if no other process holds it
+    fn try_lock(&mut self) -> bool {
+        match self.fd_req_write.lock_nonblocking() {
+            Ok(()) => true,
+            Err(Errno::EACCES | Errno::EAGAIN) => false,
+            Err(e) => Err::<bool, Errno>(e).or_fs_abort("lock", c"connate request pipe"),
+        }
+    }
+
+    /// PID of the process holding the lock, if any
+    fn lock_holder(&self) -> Option<pid_t> {
+        self.fd_req_write
+            .get_locking_pid()
+            .or_fs_abort("get lock holder", c"connate request pipe")
+    }
+
     pub fn lock_quiet(&mut self) {
         self.fd_req_write
             .lock_blocking()
             .or_fs_abort("lock", c"connate request pipe");
+        self.drain_stale();
+    }
+
+    /// Warn if connate speaks a different IPC protocol version than we do
+    ///
+    /// This may occur after a partial upgrade where conctl was replaced but connate was not yet
+    /// re-exec'd, or vice versa.
+    pub fn warn_on_version_mismatch(&mut self) {
+        match self.send_and_receive(Request::QueryVersion) {
+            Response::Version(protocol, _) if protocol == IPC_PROTOCOL_VERSION => {}
+            Response::Version(_, version) => {
+                eprint("WARNING: connate version ");
+                eprint(version);
+                eprint(" uses a different IPC protocol than conctl version ");
+                eprint(VERSION);
+                eprintln(".  Responses may be misinterpreted.");
+            }
+            // Predates version queries
+            _ => {
+                eprint("WARNING: connate is older than conctl version ");
+                eprint(VERSION);
+                eprintln(
+                    " and may use a different IPC protocol.  Responses may be misinterpreted.",
+                );
+            }
+        }
     }
 
     pub fn send_and_receive(&'a mut self, request: Request) -> Response<'a> {
+        self.send(request);
+        self.receive()
+    }
+
+    /// Like `send_and_receive()`, but gives up if connate does not respond within `timeout_millis`
+    ///
+    /// A late response is left in the pipe, where the next client to take the lock drains it.
+    ///
+    /// Responses carry nothing tying them to their request, so this only holds if connate has
+    /// answered by the time the next client locks.  Should it answer later still, the next client
+    /// reads the stale response in place of its own.  Only use this where giving up matters more
+    /// than that risk, such as a liveness check or a best-effort progress report.
+    pub fn send_and_receive_within(
+        &'a mut self,
+        request: Request,
+        timeout_millis: c_int,
+    ) -> Option<Response<'a>> {
+        self.send(request);
+
+        let start = get_time_monotonic().or_abort("Unable to get current time");
+        let mut pollfd = PollFd {
+            fd: self.fd_resp_read.as_raw(),
+            events: PollEvents::POLLIN,
+            revents: PollEvents::empty(),
+        };
+        loop {
+            let now = get_time_monotonic().or_abort("Unable to get current time");
+            let remaining = (timeout_millis as i64 - now.millis_since(start)).max(0) as c_int;
+            match unsafe { poll(core::slice::from_mut(&mut pollfd), remaining) } {
+                Ok(0) => return None,
+                Ok(_) => break,
+                Err(Errno::EINTR) => continue,
+                Err(e) => Err::<(), Errno>(e).or_fs_abort("poll", c"connate response pipe"),
+            }
+        }
+
+        Some(self.receive())
+    }
+
+    fn send(&mut self, request: Request) {
         let msg_len = request
             .serialize(&mut self.buf)
             .or_abort("Unable to serialize request to connate");
@@ -139,7 +358,9 @@ impl<'a> IpcClient {
             .fd_req_write
             .write(self.buf.get(..msg_len).or_abort("Invalid message length"))
             .or_fs_abort("write", c"connate request pipe");
+    }
 
+    fn receive(&'a mut self) -> Response<'a> {
         memzero(&mut self.buf);
 
         // Read response from pipe. We read into the full buffer; the sender may send less than
@@ -162,3 +383,87 @@ impl<'a> IpcClient {
 //         todo!();
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::*;
+
+    /// Fork a process which takes the lock on `fd_req_write` and holds it for `hold_millis`, or
+    /// until killed if negative, then exits without unlocking
+    fn spawn_lock_holder(fd_req_write: &Fd, hold_millis: c_int) -> pid_t {
+        let (ready_read, ready_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        match fork().unwrap() {
+            ForkResult::Child => {
+                fd_req_write.lock_blocking().unwrap();
+                let _ = ready_write.write(b"x");
+                let _ = unsafe { poll(&mut [], hold_millis) };
+                exit(0);
+            }
+            ForkResult::Parent(pid) => {
+                let _ = ready_write.close();
+                let _ = ready_read.read(&mut [0u8; 1]).unwrap();
+                let _ = ready_read.close();
+                pid
+            }
+        }
+    }
+
+    fn client() -> IpcClient {
+        let (_, fd_req_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let (fd_resp_read, _) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        IpcClient::new_test(fd_req_write, fd_resp_read)
+    }
+
+    /// Write `response` into the response pipe as connate would
+    fn respond(fd_resp_write: &Fd, response: Response) {
+        let mut buf = [0u8; MSG_SIZE];
+        let len = response.serialize(&mut buf).unwrap();
+        fd_resp_write.write(&buf[..len]).unwrap();
+    }
+
+    #[test]
+    fn late_response_is_not_read_by_next_command() {
+        let (_fd_req_read, fd_req_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let (fd_resp_read, fd_resp_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+
+        // A ping gives up on a busy connate, which answers once the pinging client is gone
+        let mut pinger = IpcClient::new_test(fd_req_write.clone(), fd_resp_read.clone());
+        pinger.lock_quiet();
+        assert!(pinger.send_and_receive_within(Request::Ping, 10).is_none());
+        pinger.unlock();
+        respond(&fd_resp_write, Response::Okay);
+
+        // The next command sees its own response rather than the ping's
+        let mut next = IpcClient::new_test(fd_req_write, fd_resp_read);
+        next.lock_quiet();
+        respond(&fd_resp_write, Response::Bool(true));
+        assert!(matches!(
+            next.send_and_receive(Request::QueryVersion),
+            Response::Bool(true)
+        ));
+    }
+
+    #[test]
+    fn lock_proceeds_once_holder_dies() {
+        let mut ipc_client = client();
+        let pid = spawn_lock_holder(&ipc_client.fd_req_write, 100);
+        assert_eq!(ipc_client.lock_holder(), Some(pid));
+
+        // The holder exits without unlocking well before the timeout
+        assert_eq!(ipc_client.lock_unless_held_for(10_000), Ok(()));
+        let _ = waitpid(pid, WaitPidOptions::empty());
+    }
+
+    #[test]
+    fn lock_gives_up_on_hung_holder() {
+        let mut ipc_client = client();
+        let pid = spawn_lock_holder(&ipc_client.fd_req_write, -1);
+
+        assert_eq!(ipc_client.lock_unless_held_for(100), Err(Some(pid)));
+
+        let _ = kill(pid, Signal::SIGKILL);
+        let _ = waitpid(pid, WaitPidOptions::empty());
+        assert_eq!(ipc_client.lock_unless_held_for(100), Ok(()));
+    }
+}
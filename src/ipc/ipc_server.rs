@@ -27,7 +27,7 @@ impl<'b> IpcServer {
         }
     }
 
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn new_test(fd_req_read: Fd, fd_resp_write: Fd) -> Self {
         Self {
             fd_req_read,
@@ -41,6 +41,8 @@ impl<'b> IpcServer {
             && Fd::from_raw(FD_REQ_WRITE).is_valid()
             && Fd::from_raw(FD_RESP_READ).is_valid()
             && Fd::from_raw(FD_RESP_WRITE).is_valid()
+            // `receive()` relies on this, which pipes inherited from older versions may lack
+            && Fd::from_raw(FD_REQ_READ).set_nonblocking().is_ok()
         {
             Some(Self {
                 fd_req_read: Fd::from_raw(FD_REQ_READ),
@@ -61,18 +63,25 @@ impl<'b> IpcServer {
         &self.fd_req_read
     }
 
-    pub fn receive(&mut self) -> Request<'_> {
+    /// Read a request, if one is available
+    ///
+    /// Never blocks, as the request pipe is non-blocking: a client which locks the pipe then never
+    /// writes, or dies first, must not stall connate.  Returns `None` if there was nothing to read,
+    /// in which case there is no client awaiting a response.  A truncated request deserializes as
+    /// `Request::Invalid`, which must still be responded to.
+    pub fn receive(&mut self) -> Option<Request<'_>> {
         // Read request from pipe. We read into the full buffer; the sender may send less than
         // MSG_SIZE bytes, but read() will return whatever is available. The deserializer handles
         // variable-length messages based on the message header and structure.
-        let msg_len = self
-            .fd_req_read
-            .read(&mut self.buf)
-            .or_fs_abort("read", c"connate request pipe");
+        let msg_len = match self.fd_req_read.read(&mut self.buf) {
+            Ok(0) | Err(Errno::EAGAIN | Errno::EINTR) => return None,
+            Ok(n) => n,
+            Err(e) => Err::<usize, Errno>(e).or_fs_abort("read", c"connate request pipe"),
+        };
 
         match self.buf.get(0..msg_len) {
-            Some(buf) => Request::deserialize(buf),
-            None => Request::Invalid,
+            Some(buf) => Some(Request::deserialize(buf)),
+            None => Some(Request::Invalid),
         }
     }
 
@@ -105,3 +114,42 @@ fn get_pipe(fd_read_target: c_int, fd_write_target: c_int) -> (Fd, Fd) {
 
     (read_fd, write_fd)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::*;
+
+    #[test]
+    fn client_which_locks_then_exits_does_not_stall_receive() {
+        // Non-blocking, as are connate's own pipes
+        let (fd_req_read, fd_req_write) =
+            Fd::new_pipe(OpenFlags::O_CLOEXEC | OpenFlags::O_NONBLOCK).unwrap();
+        let (fd_resp_read, fd_resp_write) =
+            Fd::new_pipe(OpenFlags::O_CLOEXEC | OpenFlags::O_NONBLOCK).unwrap();
+        let mut ipc_server = IpcServer::new_test(fd_req_read, fd_resp_write);
+
+        // Open the pipe anew as a client does, such that the lock goes away with the client
+        match fork().unwrap() {
+            ForkResult::Child => {
+                let client_fd = fd_req_write.reopen(OpenFlags::O_RDWR).unwrap();
+                client_fd.lock_blocking().unwrap();
+                exit(0);
+            }
+            ForkResult::Parent(pid) => {
+                waitpid(pid, WaitPidOptions::empty()).unwrap();
+            }
+        }
+
+        // Nothing to read, so nothing to respond to
+        assert!(ipc_server.receive().is_none());
+        assert_eq!(fd_resp_read.read(&mut [0u8; MSG_SIZE]), Err(Errno::EAGAIN));
+
+        // The next client is served as usual
+        fd_req_write.lock_nonblocking().unwrap();
+        let mut buf = [0u8; MSG_SIZE];
+        let len = Request::Ping.serialize(&mut buf).unwrap();
+        fd_req_write.write(&buf[..len]).unwrap();
+        assert!(matches!(ipc_server.receive(), Some(Request::Ping)));
+    }
+}
@@ -0,0 +1,70 @@
+//! Stand-in for connate's side of IPC
+//!
+//! Allows unit testing code which queries connate, such as config helpers called from `Run::Fn`,
+//! without booting a real init.  Only available in tests or with the `test-utils` feature.
+//!
+//! Example:
+//! ```ignore
+//! let (_connate, mut ipc_client) = MockConnate::spawn(|request| match request {
+//!     Request::QueryByNameTarget(b"xorg") => Response::Target(Target::Up),
+//!     _ => Response::ServiceNotFound,
+//! });
+//! assert!(matches!(get_service_target_with(&mut ipc_client, "xorg"), Some(config::Target::Up)));
+//! ```
+
+use crate::err::*;
+use crate::ipc::{IpcClient, IpcServer, Request, Response};
+use crate::os::*;
+use crate::types::*;
+
+/// A forked process answering IPC requests in place of connate
+///
+/// The process is killed and reaped when this is dropped.
+pub struct MockConnate {
+    pid: pid_t,
+}
+
+impl MockConnate {
+    /// Fork a fake connate which answers every request with `respond(request)`
+    ///
+    /// Returns the fake connate along with a client connected to it.
+    pub fn spawn(respond: fn(Request) -> Response<'static>) -> (Self, IpcClient) {
+        // Unlike connate's pipes these block, as there is no event loop on either side.
+        let (fd_req_read, fd_req_write) =
+            Fd::new_pipe(OpenFlags::O_CLOEXEC).or_fs_abort("create", c"mock request pipe");
+        let (fd_resp_read, fd_resp_write) =
+            Fd::new_pipe(OpenFlags::O_CLOEXEC).or_fs_abort("create", c"mock response pipe");
+
+        match fork().or_abort("Unable to fork mock connate") {
+            ForkResult::Child => {
+                // Close the client's ends such that the client going away is seen as EOF/EPIPE,
+                // which ends the loop below.
+                let _ = fd_req_write.close();
+                let _ = fd_resp_read.close();
+
+                let mut ipc_server = IpcServer::new_test(fd_req_read, fd_resp_write);
+                while let Some(request) = ipc_server.receive() {
+                    let response = respond(request);
+                    ipc_server.respond(response);
+                }
+                exit(0);
+            }
+            ForkResult::Parent(pid) => {
+                let _ = fd_req_read.close();
+                let _ = fd_resp_write.close();
+
+                (
+                    Self { pid },
+                    IpcClient::new_test(fd_req_write, fd_resp_read),
+                )
+            }
+        }
+    }
+}
+
+impl Drop for MockConnate {
+    fn drop(&mut self) {
+        let _ = kill(self.pid, Signal::SIGKILL);
+        let _ = waitpid(self.pid, WaitPidOptions::empty());
+    }
+}
@@ -1,10 +1,21 @@
 //! Inter-process communication between connate, conctl, and daemon supervisor
+//!
+//! Parts of conctl's commands which are worth unit testing, such as `drain()`, live here rather
+//! than in conctl, as the conctl binary is linked without the startup files a test harness needs.
 
+mod drain;
 mod ipc_client;
 mod ipc_server;
+#[cfg(any(test, feature = "test-utils"))]
+mod mock;
 mod request;
 mod response;
+mod status_filter;
+pub use drain::*;
 pub use ipc_client::*;
 pub use ipc_server::*;
+#[cfg(any(test, feature = "test-utils"))]
+pub use mock::*;
 pub use request::*;
 pub use response::*;
+pub use status_filter::*;
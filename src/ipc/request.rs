@@ -1,5 +1,6 @@
 use crate::constants::*;
 use crate::err::*;
+use crate::internal_api::{ConfigField, Target};
 use crate::types::{StrLen, pid_t};
 use crate::util::BufWriter;
 use core::ffi::CStr;
@@ -62,6 +63,10 @@ macro_rules! request_defs {
     (@pat $variant:ident ( $a:ty, $b:ty )) => {
         Request::$variant(_, _)
     };
+    // 3 args
+    (@pat $variant:ident ( $a:ty, $b:ty, $c:ty )) => {
+        Request::$variant(_, _, _)
+    };
 }
 
 // IPC Requests
@@ -78,15 +83,32 @@ request_defs! {
     QueryByIndexExitCode(usize) = b'e';
     QueryByIndexAttemptCount(usize) = b'c';
     QueryByIndexTime(usize) = b'i';
+    // Milliseconds the service took from beginning to start to being up, the last time it did
+    QueryStartDuration(usize) = b'U';
+
+    // Query the name and status of every service from the given index on, packed into as few
+    // responses as fit.  Saves `status` and `list` a round-trip per service.
+    QueryStatusAll(usize) = b'W';
 
     // Queries by name
     QueryByNameStatus(&'a [u8]) = b'A';
+    // Like `QueryByNameStatus`, but answered with the service's name alongside its status
+    QueryByNameNamedStatus(&'a [u8]) = b'N';
     QueryByNameState(&'a [u8]) = b'S';
     QueryByNameTarget(&'a [u8]) = b'T';
     QueryByNamePid(&'a [u8]) = b'P';
     QueryByNameExitCode(&'a [u8]) = b'E';
     QueryByNameAttemptCount(&'a [u8]) = b'C';
     QueryByNameTime(&'a [u8]) = b'I';
+    // When the service entered its current state, by the wall clock
+    QueryByNameSince(&'a [u8]) = b'J';
+    // Why the service failed, if it is failed, retrying, or cannot stop
+    QueryFailReason(&'a [u8]) = b'X';
+    // Query what a setting up or starting service last reported it is doing
+    QueryProgress(&'a [u8]) = b'Z';
+
+    // Query resource usage of a service's cgroup by name
+    QueryByNameUsage(&'a [u8]) = b'M';
 
     // Queries about dependency information
     // - &'a [u8] is service name
@@ -97,6 +119,14 @@ request_defs! {
     QueryGroups(usize, &'a [u8]) = b'g';
     QueryByIndexLog(usize) = b'l';
     QueryByNameLog(&'a [u8]) = b'L';
+    QueryEnv(usize, &'a [u8]) = b'V';
+    // Services a waiting service is blocked on, or that blocked it if it failed while waiting
+    QueryBlockers(usize, &'a [u8]) = b'B';
+    // Services whose output a logger service receives
+    QueryLogsFor(usize, &'a [u8]) = b'O';
+
+    // Query a scalar config field by service name
+    QueryConfig(ConfigField, &'a [u8]) = b'k';
 
     // Set target by service name
     SetTargetUp(&'a [u8]) = b'u';
@@ -104,16 +134,57 @@ request_defs! {
     SetTargetRestart(&'a [u8]) = b'r';
     SetTargetOnce(&'a [u8]) = b'o';
 
+    // Suspend or resume every process in a service's cgroup by name
+    //
+    // Leaves the service's state and target untouched.
+    Freeze(&'a [u8]) = b'F';
+    Thaw(&'a [u8]) = b'H';
+
+    // Re-check the PID of a CannotStop service by name, releasing it if its process has died
+    ForceReap(&'a [u8]) = b'#';
+
+    // Set target of all services in one pass
+    SetTargetAll(Target) = b'b';
+
+    // Query the services whose targets would change if the named service's target were set,
+    // without changing anything
+    // - usize is index into the list of affected services
+    PreviewTarget(usize, Target, &'a [u8]) = b'D';
+
+    // Set target of a group and everything it pulls up to Up and every other service to Down
+    Isolate(&'a [u8]) = b'j';
+    // Isolate to the services of a named `Config::TARGETS` entry
+    IsolateBootTarget(&'a [u8]) = b'h';
+    // Isolate to the services of `Config::EMERGENCY_SERVICES`
+    IsolateEmergency = b'!';
+
     // Query the settle pipe FD for a service by name
     //
     // Returns the read-end FD number which conctl can poll() on to wait for stable state.
     // Creates the settle pipe lazily if it doesn't exist.
     QuerySettleFd(&'a [u8]) = b'q';
 
+    // Query connate's version and IPC protocol version
+    //
+    // Layout must never change; see `IPC_PROTOCOL_VERSION`.
+    QueryVersion = b'v';
+
+    // No-op to check connate is responsive
+    Ping = b'K';
+
+    // Query connate's PID, executable path, and time since it was last exec'd
+    QueryConnateInfo = b'Q';
+
+    // Query whether boot has completed: every service targeted up has settled at least once
+    // since connate was last exec'd
+    QueryBootComplete = b'R';
+
     // Messages from service or supervisor about readiness
     ServiceStarting(pid_t, &'a [u8]) = b'G';
     ServiceReady(pid_t) = b'y';
     DaemonReady(pid_t, &'a [u8]) = b'Y';
+    // Message from a service's process about what it is doing, e.g. from `step()`
+    ServiceProgress(pid_t, &'a [u8]) = b'%';
 
     // An invalid request
     //
@@ -130,7 +201,17 @@ impl<'a> Request<'a> {
         writer.push(&[header])?;
 
         match self {
-            Request::Invalid => {}
+            Request::Invalid
+            | Request::QueryVersion
+            | Request::Ping
+            | Request::QueryConnateInfo
+            | Request::QueryBootComplete
+            | Request::IsolateEmergency => {}
+
+            // target (Target)
+            Request::SetTargetAll(target) => {
+                writer.push(&[target.as_byte()])?;
+            }
 
             // pid (pid_t)
             Request::ServiceReady(pid) => {
@@ -146,23 +227,35 @@ impl<'a> Request<'a> {
             | Request::QueryByIndexAttemptCount(n)
             | Request::QueryByIndexExitCode(n)
             | Request::QueryByIndexTime(n)
+            | Request::QueryStartDuration(n)
+            | Request::QueryStatusAll(n)
             | Request::QueryByIndexLog(n) => {
                 writer.push(&n.to_le_bytes())?;
             }
 
             // Service name (&[u8])
             Request::QueryByNameStatus(name)
+            | Request::QueryByNameNamedStatus(name)
             | Request::QueryByNameState(name)
             | Request::QueryByNameTarget(name)
             | Request::QueryByNamePid(name)
             | Request::QueryByNameAttemptCount(name)
             | Request::QueryByNameExitCode(name)
             | Request::QueryByNameTime(name)
+            | Request::QueryByNameSince(name)
+            | Request::QueryFailReason(name)
+            | Request::QueryProgress(name)
+            | Request::QueryByNameUsage(name)
             | Request::QueryByNameLog(name)
             | Request::SetTargetUp(name)
             | Request::SetTargetDown(name)
             | Request::SetTargetRestart(name)
             | Request::SetTargetOnce(name)
+            | Request::Isolate(name)
+            | Request::IsolateBootTarget(name)
+            | Request::Freeze(name)
+            | Request::Thaw(name)
+            | Request::ForceReap(name)
             | Request::QuerySettleFd(name) => {
                 debug_assert!(name.len() <= MSG_SVC_NAME_SIZE);
                 let len = name.len() as StrLen;
@@ -186,9 +279,31 @@ impl<'a> Request<'a> {
             Request::QueryNeeds(index, name)
             | Request::QueryWants(index, name)
             | Request::QueryConflicts(index, name)
-            | Request::QueryGroups(index, name) => {
+            | Request::QueryGroups(index, name)
+            | Request::QueryEnv(index, name)
+            | Request::QueryBlockers(index, name)
+            | Request::QueryLogsFor(index, name) => {
+                debug_assert!(name.len() <= MSG_SVC_NAME_SIZE);
+                writer.push(&index.to_le_bytes())?;
+                let len = name.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(name)?;
+            }
+
+            // index (usize) + target (Target) + name (&[u8])
+            Request::PreviewTarget(index, target, name) => {
                 debug_assert!(name.len() <= MSG_SVC_NAME_SIZE);
                 writer.push(&index.to_le_bytes())?;
+                writer.push(&[target.as_byte()])?;
+                let len = name.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(name)?;
+            }
+
+            // config field (ConfigField) + name (&[u8])
+            Request::QueryConfig(field, name) => {
+                debug_assert!(name.len() <= MSG_SVC_NAME_SIZE);
+                writer.push(&[field.as_byte()])?;
                 let len = name.len() as StrLen;
                 writer.push(&len.to_le_bytes())?;
                 writer.push(name)?;
@@ -202,6 +317,15 @@ impl<'a> Request<'a> {
                 writer.push(&len.to_le_bytes())?;
                 writer.push(name)?;
             }
+
+            // pid (pid) + message (&[u8]), cut short as connate would
+            Request::ServiceProgress(pid, msg) => {
+                let msg = msg.get(..PROGRESS_MSG_SIZE).unwrap_or(msg);
+                writer.push(&pid.to_le_bytes())?;
+                let len = msg.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(msg)?;
+            }
         }
 
         Ok(writer.pos())
@@ -214,6 +338,15 @@ impl<'a> Request<'a> {
         let mut offset = size_of::<u8>();
 
         macro_rules! read {
+            (u8) => {{
+                let byte = match buf.get(offset) {
+                    Some(s) => s,
+                    None => return Request::Invalid,
+                };
+                offset += 1;
+                *byte
+            }};
+
             (&str) => {{
                 // Read out str_length first
                 const LEN: usize = core::mem::size_of::<StrLen>();
@@ -281,27 +414,63 @@ impl<'a> Request<'a> {
             Ok(RH::QueryByIndexExitCode) => R::QueryByIndexExitCode(read!(usize)),
             Ok(RH::QueryByIndexAttemptCount) => R::QueryByIndexAttemptCount(read!(usize)),
             Ok(RH::QueryByIndexTime) => R::QueryByIndexTime(read!(usize)),
+            Ok(RH::QueryStartDuration) => R::QueryStartDuration(read!(usize)),
+            Ok(RH::QueryStatusAll) => R::QueryStatusAll(read!(usize)),
             Ok(RH::QueryByNameStatus) => R::QueryByNameStatus(read!(&str)),
+            Ok(RH::QueryByNameNamedStatus) => R::QueryByNameNamedStatus(read!(&str)),
             Ok(RH::QueryByNameState) => R::QueryByNameState(read!(&str)),
             Ok(RH::QueryByNameTarget) => R::QueryByNameTarget(read!(&str)),
             Ok(RH::QueryByNamePid) => R::QueryByNamePid(read!(&str)),
             Ok(RH::QueryByNameExitCode) => R::QueryByNameExitCode(read!(&str)),
             Ok(RH::QueryByNameAttemptCount) => R::QueryByNameAttemptCount(read!(&str)),
             Ok(RH::QueryByNameTime) => R::QueryByNameTime(read!(&str)),
+            Ok(RH::QueryByNameSince) => R::QueryByNameSince(read!(&str)),
+            Ok(RH::QueryFailReason) => R::QueryFailReason(read!(&str)),
+            Ok(RH::QueryProgress) => R::QueryProgress(read!(&str)),
+            Ok(RH::QueryByNameUsage) => R::QueryByNameUsage(read!(&str)),
             Ok(RH::QueryNeeds) => R::QueryNeeds(read!(usize), read!(&str)),
             Ok(RH::QueryWants) => R::QueryWants(read!(usize), read!(&str)),
             Ok(RH::QueryConflicts) => R::QueryConflicts(read!(usize), read!(&str)),
             Ok(RH::QueryGroups) => R::QueryGroups(read!(usize), read!(&str)),
+            Ok(RH::QueryEnv) => R::QueryEnv(read!(usize), read!(&str)),
+            Ok(RH::QueryBlockers) => R::QueryBlockers(read!(usize), read!(&str)),
+            Ok(RH::QueryLogsFor) => R::QueryLogsFor(read!(usize), read!(&str)),
             Ok(RH::QueryByIndexLog) => R::QueryByIndexLog(read!(usize)),
             Ok(RH::QueryByNameLog) => R::QueryByNameLog(read!(&str)),
+            Ok(RH::QueryConfig) => match ConfigField::from_byte(read!(u8)) {
+                Ok(field) => R::QueryConfig(field, read!(&str)),
+                Err(_) => R::Invalid,
+            },
             Ok(RH::SetTargetUp) => R::SetTargetUp(read!(&str)),
             Ok(RH::SetTargetDown) => R::SetTargetDown(read!(&str)),
             Ok(RH::SetTargetRestart) => R::SetTargetRestart(read!(&str)),
             Ok(RH::SetTargetOnce) => R::SetTargetOnce(read!(&str)),
+            Ok(RH::Isolate) => R::Isolate(read!(&str)),
+            Ok(RH::IsolateBootTarget) => R::IsolateBootTarget(read!(&str)),
+            Ok(RH::IsolateEmergency) => R::IsolateEmergency,
+            Ok(RH::Freeze) => R::Freeze(read!(&str)),
+            Ok(RH::Thaw) => R::Thaw(read!(&str)),
+            Ok(RH::ForceReap) => R::ForceReap(read!(&str)),
+            Ok(RH::PreviewTarget) => {
+                let index = read!(usize);
+                match Target::from_byte(read!(u8)) {
+                    Ok(target) => R::PreviewTarget(index, target, read!(&str)),
+                    Err(_) => R::Invalid,
+                }
+            }
+            Ok(RH::SetTargetAll) => match Target::from_byte(read!(u8)) {
+                Ok(target) => R::SetTargetAll(target),
+                Err(_) => R::Invalid,
+            },
             Ok(RH::QuerySettleFd) => R::QuerySettleFd(read!(&str)),
+            Ok(RH::QueryVersion) => R::QueryVersion,
+            Ok(RH::Ping) => R::Ping,
+            Ok(RH::QueryConnateInfo) => R::QueryConnateInfo,
+            Ok(RH::QueryBootComplete) => R::QueryBootComplete,
             Ok(RH::ServiceStarting) => R::ServiceStarting(read!(pid_t), read!(&str)),
             Ok(RH::ServiceReady) => R::ServiceReady(read!(pid_t)),
             Ok(RH::DaemonReady) => R::DaemonReady(read!(pid_t), read!(&str)),
+            Ok(RH::ServiceProgress) => R::ServiceProgress(read!(pid_t), read!(&str)),
             Ok(RH::Invalid) | Err(()) => R::Invalid,
         }
     }
@@ -1,9 +1,12 @@
 use crate::constants::*;
 use crate::err::Errno;
-use crate::internal_api::{State, Target};
+use crate::internal_api::{FailReason, State, Target};
 use crate::os::{Print, print, print_color};
 use crate::types::{StrLen, c_int, pid_t};
-use crate::util::BufWriter;
+use crate::util::{
+    BufWriter, DURATION_UNITS, duration_first_unit, duration_units, fmt_bytes, fmt_duration,
+    fmt_duration_unit, fmt_timestamp,
+};
 
 // Macro to define both the `enum Response` and `enum ResponseHeader` without typo-prone duplication
 macro_rules! response_defs {
@@ -75,6 +78,10 @@ macro_rules! response_defs {
     (@pat $variant:ident ( $a:ty, $b:ty, $c:ty, $d:ty, $e:ty )) => {
         Response::$variant(_, _, _, _, _)
     };
+    // 6 args
+    (@pat $variant:ident ( $a:ty, $b:ty, $c:ty, $d:ty, $e:ty, $f:ty )) => {
+        Response::$variant(_, _, _, _, _, _)
+    };
 }
 
 // IPC Responses
@@ -88,16 +95,47 @@ response_defs! {
     SettleDisabled = b'Q';
 
     // Response to query about field(s)
-    Status(State, Target, Option<pid_t>, Option<c_int>, i64) = b'S';
+    //
+    // Exit codes are accompanied by whether they were the expected result of connate stopping
+    // the service.
+    Status(State, Target, Option<pid_t>, LastExit, i64) = b'S';
+    // A `Status` preceded by the name of the service it describes, such that it can be
+    // correlated with the service without relying on the order of requests
+    NamedStatus(&'a [u8], State, Target, Option<pid_t>, LastExit, i64) = b'A';
+    // Packed `StatusRecord`s and whether they run through the final service
+    StatusBatch(bool, &'a [u8]) = b'W';
     State(State) = b's';
+    FailReason(FailReason) = b'F';
     Target(Target) = b't';
+    // A service's current target and the target it would be set to
+    TargetChange(Target, Target, &'a [u8]) = b'C';
     Pid(pid_t) = b'p';
-    ExitCode(c_int) = b'e';
+    ExitCode(c_int, bool) = b'e';
     AttemptCount(u64) = b'c';
     Time(i64) = b'T';
+    // Seconds since the Unix epoch
+    Timestamp(i64) = b'D';
     Name(&'a [u8]) = b'n';
     Path(&'a [u8]) = b'P';
+    // A `VAR=VALUE` environment variable
+    EnvVar(&'a [u8]) = b'E';
+    // What a service's process last reported it is doing
+    Progress(&'a [u8]) = b'M';
+    Number(i64) = b'N';
+    Bool(bool) = b'B';
     SettleFd(c_int) = b'q';
+    // Cgroup memory use in bytes and CPU time in microseconds
+    Usage(u64, u64) = b'U';
+    // IPC protocol version and connate version string
+    //
+    // Layout must never change; see `IPC_PROTOCOL_VERSION`.
+    Version(u32, &'a [u8]) = b'v';
+    // connate's PID, seconds since it was last exec'd, and the executable path
+    ConnateInfo(pid_t, i64, &'a [u8]) = b'I';
+    // Boot has yet to complete
+    BootPending = b'r';
+    // How many services were Up and how many had failed when boot completed
+    BootComplete(u32, u32) = b'R';
 }
 
 impl<'a> Response<'a> {
@@ -113,31 +151,59 @@ impl<'a> Response<'a> {
             | Response::ServiceNotFound
             | Response::FieldIsNone
             | Response::InvalidRequest
-            | Response::SettleDisabled => {}
+            | Response::SettleDisabled
+            | Response::BootPending => {}
             Response::State(state) => writer.push(&[state.as_byte()])?,
+            Response::FailReason(reason) => writer.push(&[reason.as_byte()])?,
 
             Response::Target(target) => writer.push(&[target.as_byte()])?,
 
+            Response::TargetChange(from, to, name) => {
+                debug_assert!(name.len() <= MSG_SVC_NAME_SIZE);
+                writer.push(&[from.as_byte(), to.as_byte()])?;
+                let len = name.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(name)?;
+            }
+
             Response::Pid(pid) => writer.push(&pid.to_le_bytes())?,
 
             Response::SettleFd(fd) => writer.push(&fd.to_le_bytes())?,
 
-            Response::ExitCode(code) => writer.push(&code.to_le_bytes())?,
+            Response::ExitCode(code, expected) => {
+                writer.push(&code.to_le_bytes())?;
+                writer.push(&[expected as u8])?;
+            }
 
             Response::AttemptCount(count) => writer.push(&count.to_le_bytes())?,
 
             Response::Time(time) => writer.push(&time.to_le_bytes())?,
+            Response::Timestamp(time) => writer.push(&time.to_le_bytes())?,
 
-            Response::Status(state, target, pid, code, time) => {
-                writer.push(&[state.as_byte()])?;
-                writer.push(&[target.as_byte()])?;
-                // Serialize Option<pid_t> with sentinel for None
-                let pid_wire: pid_t = pid.unwrap_or(MSG_PID_NONE_SENTINEL);
-                writer.push(&pid_wire.to_le_bytes())?;
-                // Serialize Option<c_int> with sentinel for None
-                let code_wire: c_int = code.unwrap_or(MSG_EXIT_CODE_NONE_SENTINEL);
-                writer.push(&code_wire.to_le_bytes())?;
-                writer.push(&time.to_le_bytes())?;
+            Response::Number(n) => writer.push(&n.to_le_bytes())?,
+
+            Response::Bool(b) => writer.push(&[b as u8])?,
+
+            Response::Usage(memory, cpu) => {
+                writer.push(&memory.to_le_bytes())?;
+                writer.push(&cpu.to_le_bytes())?;
+            }
+
+            Response::BootComplete(up, failed) => {
+                writer.push(&up.to_le_bytes())?;
+                writer.push(&failed.to_le_bytes())?;
+            }
+
+            Response::Status(state, target, pid, last_exit, time) => {
+                push_status(&mut writer, state, target, pid, last_exit, time)?;
+            }
+
+            Response::NamedStatus(name, state, target, pid, last_exit, time) => {
+                debug_assert!(name.len() <= MSG_SVC_NAME_SIZE);
+                let len = name.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(name)?;
+                push_status(&mut writer, state, target, pid, last_exit, time)?;
             }
 
             Response::Name(name) => {
@@ -150,7 +216,7 @@ impl<'a> Response<'a> {
                 writer.push(name)?;
             }
 
-            Response::Path(path) => {
+            Response::Path(path) | Response::EnvVar(path) | Response::Progress(path) => {
                 // Should be checked at compile-time
                 //
                 // If somehow it fails at --release runtime, writer will return EOVERFLOW
@@ -159,6 +225,36 @@ impl<'a> Response<'a> {
                 writer.push(&len.to_le_bytes())?;
                 writer.push(path)?;
             }
+
+            Response::StatusBatch(done, records) => {
+                // Should be guaranteed by the `StatusBatchWriter` which packed the records
+                //
+                // If somehow it fails at --release runtime, writer will return EOVERFLOW
+                debug_assert!(records.len() <= MSG_STATUS_BATCH_SIZE);
+                writer.push(&[done as u8])?;
+                let len = records.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(records)?;
+            }
+
+            Response::Version(protocol, version) => {
+                writer.push(&protocol.to_le_bytes())?;
+                let len = version.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(version)?;
+            }
+
+            Response::ConnateInfo(pid, time, exe) => {
+                // Ensured by connate reading the path into a buffer of this size
+                //
+                // If somehow it fails at --release runtime, writer will return EOVERFLOW
+                debug_assert!(exe.len() <= MSG_EXE_PATH_SIZE);
+                writer.push(&pid.to_le_bytes())?;
+                writer.push(&time.to_le_bytes())?;
+                let len = exe.len() as StrLen;
+                writer.push(&len.to_le_bytes())?;
+                writer.push(exe)?;
+            }
         }
 
         Ok(writer.pos())
@@ -217,25 +313,49 @@ impl<'a> Response<'a> {
             Ok(RH::FieldIsNone) => Ok(R::FieldIsNone),
             Ok(RH::InvalidRequest) => Ok(R::InvalidRequest),
             Ok(RH::SettleDisabled) => Ok(R::SettleDisabled),
-            Ok(RH::Status) => {
+            Ok(header @ (RH::Status | RH::NamedStatus)) => {
+                let name = match header {
+                    RH::NamedStatus => Some(read!(&str)),
+                    _ => None,
+                };
                 let state = State::from_byte(read!(u8))?;
                 let target = Target::from_byte(read!(u8))?;
                 let pid_wire = read!(pid_t);
                 let pid = (pid_wire != MSG_PID_NONE_SENTINEL).then_some(pid_wire);
                 let code_wire = read!(c_int);
-                let code = (code_wire != MSG_EXIT_CODE_NONE_SENTINEL).then_some(code_wire);
+                let last_exit = LastExit::from_wire(code_wire, read!(u8) != 0);
                 let time = read!(i64);
-                Ok(R::Status(state, target, pid, code, time))
+                Ok(match name {
+                    Some(name) => R::NamedStatus(name, state, target, pid, last_exit, time),
+                    None => R::Status(state, target, pid, last_exit, time),
+                })
             }
+            Ok(RH::StatusBatch) => Ok(R::StatusBatch(read!(u8) != 0, read!(&str))),
             Ok(RH::State) => Ok(R::State(State::from_byte(read!(u8))?)),
+            Ok(RH::FailReason) => Ok(R::FailReason(FailReason::from_byte(read!(u8))?)),
             Ok(RH::Target) => Ok(R::Target(Target::from_byte(read!(u8))?)),
+            Ok(RH::TargetChange) => Ok(R::TargetChange(
+                Target::from_byte(read!(u8))?,
+                Target::from_byte(read!(u8))?,
+                read!(&str),
+            )),
             Ok(RH::Pid) => Ok(R::Pid(read!(pid_t))),
             Ok(RH::SettleFd) => Ok(R::SettleFd(read!(c_int))),
-            Ok(RH::ExitCode) => Ok(R::ExitCode(read!(c_int))),
+            Ok(RH::ExitCode) => Ok(R::ExitCode(read!(c_int), read!(u8) != 0)),
             Ok(RH::AttemptCount) => Ok(R::AttemptCount(read!(u64))),
             Ok(RH::Time) => Ok(R::Time(read!(i64))),
+            Ok(RH::Timestamp) => Ok(R::Timestamp(read!(i64))),
+            Ok(RH::Number) => Ok(R::Number(read!(i64))),
+            Ok(RH::Bool) => Ok(R::Bool(read!(u8) != 0)),
+            Ok(RH::Usage) => Ok(R::Usage(read!(u64), read!(u64))),
             Ok(RH::Name) => Ok(R::Name(read!(&str))),
             Ok(RH::Path) => Ok(R::Path(read!(&str))),
+            Ok(RH::EnvVar) => Ok(R::EnvVar(read!(&str))),
+            Ok(RH::Progress) => Ok(R::Progress(read!(&str))),
+            Ok(RH::Version) => Ok(R::Version(read!(u32), read!(&str))),
+            Ok(RH::ConnateInfo) => Ok(R::ConnateInfo(read!(pid_t), read!(i64), read!(&str))),
+            Ok(RH::BootPending) => Ok(R::BootPending),
+            Ok(RH::BootComplete) => Ok(R::BootComplete(read!(u32), read!(u32))),
             Err(()) => Err(Errno::EINVAL),
         }
     }
@@ -284,7 +404,7 @@ impl<'a> Print for Response<'a> {
             Response::InvalidRequest => print_color(Error, "invalid-request"),
             Response::SettleDisabled => print_color(Error, "settle-disabled"),
             Response::SettleFd(fd) => print(fd),
-            Response::Status(state, target, pid, code, time) => {
+            Response::Status(state, target, pid, last_exit, time) => {
                 print("state");
                 print_color(Glue, "=");
                 print(state);
@@ -299,29 +419,92 @@ impl<'a> Print for Response<'a> {
                 }
                 print(" code");
                 print_color(Glue, "=");
-                match code {
-                    Some(v) if v == 0 => print_color(Okay, v),
-                    Some(v) => print_color(Error, v),
-                    None => print_color(Dim, "N/A"),
-                }
+                print(last_exit);
                 print(" time");
                 print_color(Glue, "=");
                 print_time(time);
             }
+            Response::NamedStatus(name, state, target, pid, last_exit, time) => {
+                print_color(Service, name);
+                print_color(Glue, ":");
+                print(" ");
+                print(Response::Status(state, target, pid, last_exit, time));
+            }
+            Response::StatusBatch(_, _) => print("status-batch"),
             Response::State(state) => print(state),
+            Response::FailReason(reason) => print(reason),
             Response::Target(target) => print(target),
-            Response::Pid(pid) => print(pid),
-            Response::ExitCode(code) => {
-                if code == 0 {
-                    print_color(Okay, code)
-                } else {
-                    print_color(Error, code)
-                }
+            Response::TargetChange(from, to, name) => {
+                print_color(Service, name);
+                print_color(Glue, ":");
+                print(" ");
+                print(from);
+                print_color(Glue, " -> ");
+                print(to);
             }
+            Response::Pid(pid) => print(pid),
+            Response::ExitCode(code, expected) => print_exit_code(code, expected),
             Response::AttemptCount(count) => print_color(Transition, count),
             Response::Time(time) => print_time(time),
+            Response::Timestamp(time) => {
+                let mut buf = [0u8; TIME_BUF_SIZE];
+                let mut writer = BufWriter::new(&mut buf);
+                if fmt_timestamp(&mut writer, time).is_ok() {
+                    print(writer.as_slice());
+                }
+            }
+            Response::Number(n) => print(n),
+            Response::Bool(true) => print("true"),
+            Response::Bool(false) => print_color(Dim, "false"),
+            Response::Usage(memory, cpu) => {
+                print("memory");
+                print_color(Glue, "=");
+                let mut buf = [0u8; TIME_BUF_SIZE];
+                let mut writer = BufWriter::new(&mut buf);
+                if fmt_bytes(&mut writer, memory).is_ok() {
+                    print(writer.as_slice());
+                }
+                print(" cpu");
+                print_color(Glue, "=");
+                print_time(usec_to_secs(cpu));
+            }
             Response::Name(name) => print_color(Service, name),
             Response::Path(path) => print_color(Service, path),
+            Response::EnvVar(var) => match var.iter().position(|&c| c == b'=') {
+                Some(eq_idx) => {
+                    print(var.get(..eq_idx).unwrap_or(var));
+                    print_color(Glue, "=");
+                    print(var.get(eq_idx + 1..).unwrap_or(&[]));
+                }
+                None => print(var),
+            },
+            Response::Progress(msg) => print(msg),
+            Response::Version(_, version) => print(version),
+            Response::ConnateInfo(pid, time, exe) => {
+                print("pid");
+                print_color(Glue, "=");
+                print(pid);
+                print(" exe");
+                print_color(Glue, "=");
+                print_color(Path, exe);
+                print(" time");
+                print_color(Glue, "=");
+                print_time(time);
+            }
+            Response::BootPending => print_color(Transition, "boot-pending"),
+            Response::BootComplete(up, failed) => {
+                print_color(Okay, "boot-complete");
+                print(" up");
+                print_color(Glue, "=");
+                print(up);
+                print(" failed");
+                print_color(Glue, "=");
+                if failed > 0 {
+                    print_color(Error, failed);
+                } else {
+                    print(failed);
+                }
+            }
         }
     }
 
@@ -334,16 +517,12 @@ impl<'a> Print for Response<'a> {
             Response::InvalidRequest => "invalid-request".len(),
             Response::SettleDisabled => "settle-disabled".len(),
             Response::SettleFd(fd) => fd.print_len(),
-            Response::Status(state, target, pid, code, time) => {
+            Response::Status(state, target, pid, last_exit, time) => {
                 // "state=" + state + " target=" + target + " pid=" + pid + " code=" + val + " time=" + time
                 let pid_len = match pid {
                     Some(p) => p.print_len(),
                     None => "N/A".len(),
                 };
-                let val_len = match code {
-                    Some(v) => v.print_len(),
-                    None => "N/A".len(),
-                };
                 "state=".len()
                     + state.print_len()
                     + " target=".len()
@@ -351,38 +530,92 @@ impl<'a> Print for Response<'a> {
                     + " pid=".len()
                     + pid_len
                     + " code=".len()
-                    + val_len
+                    + last_exit.print_len()
                     + " time=".len()
                     + time_print_len(time)
             }
+            Response::NamedStatus(name, state, target, pid, last_exit, time) => {
+                name.len()
+                    + ": ".len()
+                    + Response::Status(state, target, pid, last_exit, time).print_len()
+            }
+            Response::StatusBatch(_, _) => "status-batch".len(),
             Response::State(state) => state.print_len(),
+            Response::FailReason(reason) => reason.print_len(),
             Response::Target(target) => target.print_len(),
+            Response::TargetChange(from, to, name) => {
+                name.len() + ": ".len() + from.print_len() + " -> ".len() + to.print_len()
+            }
             Response::Pid(pid) => pid.print_len(),
-            Response::ExitCode(code) => code.print_len(),
+            Response::ExitCode(code, _) => code.print_len(),
             Response::AttemptCount(count) => count.print_len(),
             Response::Time(time) => time_print_len(time),
+            Response::Timestamp(time) => {
+                let mut buf = [0u8; TIME_BUF_SIZE];
+                let mut writer = BufWriter::new(&mut buf);
+                let _ = fmt_timestamp(&mut writer, time);
+                writer.pos()
+            }
+            Response::Number(n) => n.print_len(),
+            Response::Bool(true) => "true".len(),
+            Response::Bool(false) => "false".len(),
+            Response::Usage(memory, cpu) => {
+                let mut buf = [0u8; TIME_BUF_SIZE];
+                let mut writer = BufWriter::new(&mut buf);
+                let _ = fmt_bytes(&mut writer, memory);
+                "memory=".len() + writer.pos() + " cpu=".len() + time_print_len(usec_to_secs(cpu))
+            }
             Response::Name(name) => name.len(),
             Response::Path(path) => path.len(),
+            Response::EnvVar(var) => var.len(),
+            Response::Progress(msg) => msg.len(),
+            Response::Version(_, version) => version.len(),
+            Response::ConnateInfo(pid, time, exe) => {
+                "pid=".len()
+                    + pid.print_len()
+                    + " exe=".len()
+                    + exe.len()
+                    + " time=".len()
+                    + time_print_len(time)
+            }
+            Response::BootPending => "boot-pending".len(),
+            Response::BootComplete(up, failed) => {
+                "boot-complete up=".len() + up.print_len() + " failed=".len() + failed.print_len()
+            }
         }
     }
 }
 
 impl<'a> Response<'a> {
+    /// Separate the service name a `NamedStatus` carries from its `Status`
+    ///
+    /// Other responses are returned unchanged without a name.
+    pub fn split_name(self) -> (Option<&'a [u8]>, Self) {
+        match self {
+            Response::NamedStatus(name, state, target, pid, last_exit, time) => (
+                Some(name),
+                Response::Status(state, target, pid, last_exit, time),
+            ),
+            response => (None, response),
+        }
+    }
+
     /// Get individual field lengths for Status response
     /// Returns (state_len, target_len, pid_len, return_len) if Status variant, None otherwise
     pub fn status_field_lens(&self) -> Option<(usize, usize, usize, usize)> {
         use crate::os::Print;
         match *self {
-            Response::Status(state, target, pid, code, _time) => {
+            Response::Status(state, target, pid, last_exit, _time) => {
                 let pid_len = match pid {
                     Some(p) => p.print_len(),
                     None => "N/A".len(),
                 };
-                let val_len = match code {
-                    Some(v) => v.print_len(),
-                    None => "N/A".len(),
-                };
-                Some((state.print_len(), target.print_len(), pid_len, val_len))
+                Some((
+                    state.print_len(),
+                    target.print_len(),
+                    pid_len,
+                    last_exit.print_len(),
+                ))
             }
             _ => None,
         }
@@ -394,7 +627,7 @@ impl<'a> Response<'a> {
         use crate::os::Print;
 
         match self {
-            Response::Status(state, target, pid, code, time) => {
+            Response::Status(state, target, pid, last_exit, time) => {
                 print("state");
                 print_color(Glue, "=");
                 print(state);
@@ -417,20 +650,8 @@ impl<'a> Response<'a> {
                 }
                 print(" code");
                 print_color(Glue, "=");
-                match code {
-                    Some(v) if v == 0 => {
-                        print_color(Okay, v);
-                        v.print_padding(widths.exit_code);
-                    }
-                    Some(v) => {
-                        print_color(Error, v);
-                        v.print_padding(widths.exit_code);
-                    }
-                    None => {
-                        print_color(Dim, "N/A");
-                        "N/A".print_padding(widths.exit_code);
-                    }
-                }
+                print(last_exit);
+                last_exit.print_padding(widths.exit_code);
                 // time is last field, no padding
                 print(" time");
                 print_color(Glue, "=");
@@ -441,85 +662,284 @@ impl<'a> Response<'a> {
     }
 }
 
-fn time_print_len(seconds: i64) -> usize {
-    let days = seconds / 86400;
-    let hours = (seconds % 86400) / 3600;
-    let minutes = (seconds % 3600) / 60;
-    let secs = seconds % 60;
-
-    let mut buf = itoa::Buffer::new();
-
-    if days > 0 {
-        buf.format(days).len() + 1 + 2 + 1 + 2 + 1 + 2 + 1 // Xd00h00m00s
-    } else if hours > 0 {
-        buf.format(hours).len() + 1 + 2 + 1 + 2 + 1 // Xh00m00s
-    } else if minutes > 0 {
-        buf.format(minutes).len() + 1 + 2 + 1 // Xm00s
-    } else {
-        buf.format(secs).len() + 1 // Xs
+/// One service's entry in a `Response::StatusBatch`
+pub struct StatusRecord<'a> {
+    pub name: &'a [u8],
+    pub state: State,
+    pub target: Target,
+    pub pid: Option<pid_t>,
+    pub last_exit: LastExit,
+    pub time: i64,
+}
+
+impl<'a> StatusRecord<'a> {
+    /// str_length(2) + name(?) + state(1) + target(1) + pid(4) + code(4) + expected(1) + time(8)
+    const fn serialized_len(&self) -> usize {
+        size_of::<StrLen>()
+            + self.name.len()
+            + size_of::<u8>()
+            + size_of::<u8>()
+            + size_of::<pid_t>()
+            + size_of::<c_int>()
+            + size_of::<bool>()
+            + size_of::<i64>()
+    }
+
+    /// The record's fields as the `Response::Status` a by-index or by-name query would return
+    pub fn status(&self) -> Response<'a> {
+        Response::Status(self.state, self.target, self.pid, self.last_exit, self.time)
     }
 }
 
-fn print_time(seconds: i64) {
-    use crate::os::Color::{TimeDay, TimeHour, TimeMinute, TimeSecond};
+/// Packs `StatusRecord`s for a `Response::StatusBatch`
+pub struct StatusBatchWriter {
+    buf: [u8; MSG_STATUS_BATCH_SIZE],
+    pos: usize,
+}
 
-    // Print %dd%dh%dm%ds format with leading zeros on fields that have following fields
-    // Each time unit (number + suffix) is printed in its own shade of gray
-    let days = seconds / 86400;
-    let hours = (seconds % 86400) / 3600;
-    let minutes = (seconds % 3600) / 60;
-    let secs = seconds % 60;
-
-    let mut buf = itoa::Buffer::new();
-
-    if days > 0 {
-        print_color(TimeDay, buf.format(days).as_bytes());
-        print_color(TimeDay, "d");
-        // Hours field has following fields (m and s), so add leading zero if needed
-        if hours < 10 {
-            print_color(TimeHour, "0");
+#[allow(clippy::new_without_default)]
+impl StatusBatchWriter {
+    pub fn new() -> Self {
+        Self {
+            buf: [0u8; MSG_STATUS_BATCH_SIZE],
+            pos: 0,
         }
-        print_color(TimeHour, buf.format(hours).as_bytes());
-        print_color(TimeHour, "h");
-        // Minutes field has following field (s), so add leading zero if needed
-        if minutes < 10 {
-            print_color(TimeMinute, "0");
+    }
+
+    /// Append a record, returning an error without writing anything if it does not fit
+    pub fn push(&mut self, record: &StatusRecord) -> Result<(), Errno> {
+        if self.pos + record.serialized_len() > self.buf.len() {
+            return Err(Errno::EOVERFLOW);
         }
-        print_color(TimeMinute, buf.format(minutes).as_bytes());
-        print_color(TimeMinute, "m");
-        // Seconds is last field, add leading zero
-        if secs < 10 {
-            print_color(TimeSecond, "0");
+
+        let mut writer = BufWriter::new(self.buf.get_mut(self.pos..).ok_or(Errno::EOVERFLOW)?);
+        writer.push(&(record.name.len() as StrLen).to_le_bytes())?;
+        writer.push(record.name)?;
+        writer.push(&[record.state.as_byte(), record.target.as_byte()])?;
+        writer.push(&record.pid.unwrap_or(MSG_PID_NONE_SENTINEL).to_le_bytes())?;
+        let (code_wire, expected) = record.last_exit.to_wire();
+        writer.push(&code_wire.to_le_bytes())?;
+        writer.push(&[expected as u8])?;
+        writer.push(&record.time.to_le_bytes())?;
+        self.pos += writer.pos();
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == 0
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.buf.get(..self.pos).unwrap_or(&[])
+    }
+}
+
+/// Iterates over the `StatusRecord`s packed in a `Response::StatusBatch`
+///
+/// Stops early at the first malformed record.
+pub struct StatusRecords<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> StatusRecords<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    fn take<const LEN: usize>(&mut self) -> Option<[u8; LEN]> {
+        let (bytes, rest) = self.buf.split_at_checked(LEN)?;
+        self.buf = rest;
+        bytes.try_into().ok()
+    }
+}
+
+impl<'a> Iterator for StatusRecords<'a> {
+    type Item = StatusRecord<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let name_len = StrLen::from_le_bytes(self.take()?) as usize;
+        let (name, rest) = self.buf.split_at_checked(name_len)?;
+        self.buf = rest;
+        let [state, target] = self.take()?;
+        let pid = pid_t::from_le_bytes(self.take()?);
+        let exit_code = c_int::from_le_bytes(self.take()?);
+        let [exit_expected] = self.take()?;
+        let time = i64::from_le_bytes(self.take()?);
+
+        Some(StatusRecord {
+            name,
+            state: State::from_byte(state).ok()?,
+            target: Target::from_byte(target).ok()?,
+            pid: (pid != MSG_PID_NONE_SENTINEL).then_some(pid),
+            last_exit: LastExit::from_wire(exit_code, exit_expected != 0),
+            time,
+        })
+    }
+}
+
+fn push_status(
+    writer: &mut BufWriter,
+    state: State,
+    target: Target,
+    pid: Option<pid_t>,
+    last_exit: LastExit,
+    time: i64,
+) -> Result<(), Errno> {
+    writer.push(&[state.as_byte()])?;
+    writer.push(&[target.as_byte()])?;
+    // Serialize Option<pid_t> with sentinel for None
+    let pid_wire: pid_t = pid.unwrap_or(MSG_PID_NONE_SENTINEL);
+    writer.push(&pid_wire.to_le_bytes())?;
+    let (code_wire, expected) = last_exit.to_wire();
+    writer.push(&code_wire.to_le_bytes())?;
+    writer.push(&[expected as u8])?;
+    writer.push(&time.to_le_bytes())
+}
+
+/// A service's last exit as reported by `status`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LastExit {
+    /// No process has been spawned for the service
+    NeverRun,
+    /// The service's current process has yet to exit
+    NotYet,
+    /// The exit code of the service's last process, and whether connate stopping it caused it
+    Code(c_int, bool),
+}
+
+impl LastExit {
+    /// The exit code and expected flag as sent over IPC, with sentinels for the code-less cases
+    fn to_wire(self) -> (c_int, bool) {
+        match self {
+            LastExit::NeverRun => (MSG_EXIT_CODE_NEVER_RUN_SENTINEL, false),
+            LastExit::NotYet => (MSG_EXIT_CODE_NONE_SENTINEL, false),
+            LastExit::Code(code, expected) => (code, expected),
         }
-        print_color(TimeSecond, buf.format(secs).as_bytes());
-        print_color(TimeSecond, "s");
-    } else if hours > 0 {
-        print_color(TimeHour, buf.format(hours).as_bytes());
-        print_color(TimeHour, "h");
-        // Minutes field has following field (s), so add leading zero if needed
-        if minutes < 10 {
-            print_color(TimeMinute, "0");
+    }
+
+    fn from_wire(code: c_int, expected: bool) -> Self {
+        match code {
+            MSG_EXIT_CODE_NEVER_RUN_SENTINEL => LastExit::NeverRun,
+            MSG_EXIT_CODE_NONE_SENTINEL => LastExit::NotYet,
+            code => LastExit::Code(code, expected),
         }
-        print_color(TimeMinute, buf.format(minutes).as_bytes());
-        print_color(TimeMinute, "m");
-        // Seconds is last field, add leading zero
-        if secs < 10 {
-            print_color(TimeSecond, "0");
+    }
+}
+
+impl Print for LastExit {
+    fn print(&self, _fd: crate::os::Fd) {
+        use crate::os::Color::*;
+        match *self {
+            LastExit::NeverRun => print_color(Dim, "never-run"),
+            LastExit::NotYet => print_color(Dim, "N/A"),
+            LastExit::Code(code, expected) => print_exit_code(code, expected),
         }
-        print_color(TimeSecond, buf.format(secs).as_bytes());
-        print_color(TimeSecond, "s");
-    } else if minutes > 0 {
-        print_color(TimeMinute, buf.format(minutes).as_bytes());
-        print_color(TimeMinute, "m");
-        // Seconds has no following field but minutes is before it, so add leading zero
-        if secs < 10 {
-            print_color(TimeSecond, "0");
+    }
+
+    fn print_len(&self) -> usize {
+        match *self {
+            LastExit::NeverRun => "never-run".len(),
+            LastExit::NotYet => "N/A".len(),
+            LastExit::Code(code, _) => code.print_len(),
         }
-        print_color(TimeSecond, buf.format(secs).as_bytes());
-        print_color(TimeSecond, "s");
+    }
+}
+
+/// Print an exit code colored by whether it indicates a problem
+///
+/// A service exiting because connate stopped it is not a problem, even if the code is non-zero.
+fn print_exit_code(code: c_int, expected: bool) {
+    use crate::os::Color::*;
+    if code == 0 {
+        print_color(Okay, code)
+    } else if expected {
+        print(code)
     } else {
-        // Only seconds, no leading zero needed
-        print_color(TimeSecond, buf.format(secs).as_bytes());
-        print_color(TimeSecond, "s");
+        print_color(Error, code)
+    }
+}
+
+fn usec_to_secs(usec: u64) -> i64 {
+    i64::try_from(usec / 1_000_000).unwrap_or(i64::MAX)
+}
+
+fn time_print_len(seconds: i64) -> usize {
+    let mut buf = [0u8; TIME_BUF_SIZE];
+    let mut writer = BufWriter::new(&mut buf);
+    let _ = fmt_duration(&mut writer, seconds);
+    writer.pos()
+}
+
+fn print_time(seconds: i64) {
+    use crate::os::Color::{TimeDay, TimeHour, TimeMinute, TimeSecond};
+
+    // Print %dd%dh%dm%ds format with leading zeros on fields that follow another field
+    // Each time unit (number + suffix) is printed in its own shade of gray
+    const COLORS: [crate::os::Color; 4] = [TimeDay, TimeHour, TimeMinute, TimeSecond];
+
+    let units = duration_units(seconds);
+    let first = duration_first_unit(&units);
+
+    for (i, (value, (_, suffix))) in units.iter().zip(DURATION_UNITS.iter()).enumerate() {
+        if i < first {
+            continue;
+        }
+        let mut buf = [0u8; TIME_BUF_SIZE];
+        let mut writer = BufWriter::new(&mut buf);
+        if fmt_duration_unit(&mut writer, *value, suffix, i > first).is_ok()
+            && let Some(&color) = COLORS.get(i)
+        {
+            print_color(color, writer.as_slice());
+        }
+    }
+}
+
+/// Large enough for any formatted i64 duration
+const TIME_BUF_SIZE: usize = 64;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::{IpcClient, MockConnate, Request};
+
+    fn respond(request: Request) -> Response<'static> {
+        let (name, state): (&'static [u8], State) = match request {
+            Request::QueryByNameNamedStatus(b"sshd") => (b"sshd", State::Up),
+            Request::QueryByNameNamedStatus(b"getty") => (b"getty", State::Down),
+            _ => return Response::ServiceNotFound,
+        };
+        Response::NamedStatus(name, state, Target::Up, None, LastExit::NeverRun, 0)
+    }
+
+    fn query<'a>(
+        ipc_client: &'a mut IpcClient,
+        name: &'a [u8],
+    ) -> (Option<&'a [u8]>, Response<'a>) {
+        ipc_client
+            .send_and_receive(Request::QueryByNameNamedStatus(name))
+            .split_name()
+    }
+
+    #[test]
+    fn named_status_correlates_duplicate_names() {
+        let (_connate, mut ipc_client) = MockConnate::spawn(respond);
+
+        for name in [&b"sshd"[..], b"getty", b"sshd", b"missing", b"sshd"] {
+            let (echoed, response) = query(&mut ipc_client, name);
+            match response {
+                Response::Status(state, ..) => {
+                    assert_eq!(echoed, Some(name));
+                    match name {
+                        b"sshd" => assert!(matches!(state, State::Up)),
+                        _ => assert!(matches!(state, State::Down)),
+                    }
+                }
+                Response::ServiceNotFound => {
+                    assert_eq!(name, b"missing");
+                    assert_eq!(echoed, None);
+                }
+                _ => panic!("unexpected response"),
+            }
+        }
     }
 }
@@ -0,0 +1,156 @@
+//! Which services `conctl status` prints, as selected by its `--failed`, `--filter`, and `--since`
+//! flags
+
+use crate::err::*;
+use crate::internal_api::{State, Target};
+use crate::ipc::Response;
+use crate::os::Argv;
+use crate::util::parse_duration;
+
+/// Which services `status` prints, as selected by leading flags
+///
+/// Filters on the same field are OR'd together while filters on different fields are AND'd.
+#[derive(Default)]
+pub struct StatusFilter {
+    /// Bitmask over `State::ALL` of states to show, or 0 to show any state
+    pub states: u16,
+    /// Bitmask over `Target::ALL` of targets to show, or 0 to show any target
+    pub targets: u8,
+    /// Only show services which entered their current state at most this many seconds ago
+    pub since_secs: Option<i64>,
+    /// `--failed` was given and thus any match is a health check failure
+    pub failed: bool,
+}
+
+impl StatusFilter {
+    /// Consume any leading filter flags from argv
+    pub fn parse(argv: &mut Argv) -> Self {
+        let mut filter = Self::default();
+        while let Some(arg) = argv.first() {
+            match arg.to_bytes() {
+                b"--failed" => filter.add_failed(),
+                b"--filter" => {
+                    let _ = argv.pop();
+                    let spec = argv.first().or_abort("--filter requires an argument");
+                    filter.add(spec.to_bytes());
+                }
+                b"--since" => {
+                    let _ = argv.pop();
+                    let since = argv.first().or_abort("--since requires an argument");
+                    let secs = parse_duration(since.to_bytes())
+                        .or_abort("--since requires a duration such as 90, 90s, or 1h30m");
+                    filter.since_secs = Some(secs);
+                }
+                _ => break,
+            }
+            let _ = argv.pop();
+        }
+        filter
+    }
+
+    /// Apply `--failed`
+    pub fn add_failed(&mut self) {
+        self.failed = true;
+        self.states |=
+            state_bit(State::Failed) | state_bit(State::CannotStop) | state_bit(State::ForceDown);
+    }
+
+    /// Add a `field:value` filter
+    pub fn add(&mut self, spec: &[u8]) {
+        let mut parts = spec.splitn(2, |&b| b == b':');
+        match (parts.next(), parts.next()) {
+            (Some(b"state"), Some(value)) => {
+                let state = State::from_name(value).or_abort("Unknown state in --filter");
+                self.states |= state_bit(state);
+            }
+            (Some(b"target"), Some(value)) => {
+                let target = Target::from_name(value).or_abort("Unknown target in --filter");
+                self.targets |= target_bit(target);
+            }
+            _ => abort_with_msg("Invalid --filter, expected state:<state> or target:<target>"),
+        }
+    }
+
+    /// Whether a status response should be printed
+    pub fn matches(&self, response: &Response) -> bool {
+        let Response::Status(state, target, _, _, time) = response else {
+            // Always show errors
+            return true;
+        };
+
+        (self.states == 0 || self.states & state_bit(*state) != 0)
+            && (self.targets == 0 || self.targets & target_bit(*target) != 0)
+            && self.since_secs.is_none_or(|secs| *time <= secs)
+    }
+}
+
+fn state_bit(state: State) -> u16 {
+    State::ALL
+        .iter()
+        .position(|s| s.as_byte() == state.as_byte())
+        .map_or(0, |i| 1 << i)
+}
+
+fn target_bit(target: Target) -> u8 {
+    Target::ALL
+        .iter()
+        .position(|t| t.as_byte() == target.as_byte())
+        .map_or(0, |i| 1 << i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipc::LastExit;
+
+    fn status(state: State, target: Target, time: i64) -> Response<'static> {
+        Response::Status(state, target, None, LastExit::NeverRun, time)
+    }
+
+    #[test]
+    fn since_combines_with_filter() {
+        let mut filter = StatusFilter {
+            since_secs: Some(60),
+            ..StatusFilter::default()
+        };
+        filter.add(b"state:up");
+        filter.add(b"target:up");
+
+        assert!(filter.matches(&status(State::Up, Target::Up, 60)));
+        // Too long ago
+        assert!(!filter.matches(&status(State::Up, Target::Up, 61)));
+        // Recent, but filtered out
+        assert!(!filter.matches(&status(State::Down, Target::Up, 0)));
+        assert!(!filter.matches(&status(State::Up, Target::Down, 0)));
+    }
+
+    #[test]
+    fn since_combines_with_failed() {
+        let mut filter = StatusFilter {
+            since_secs: Some(300),
+            ..StatusFilter::default()
+        };
+        filter.add_failed();
+
+        assert!(filter.failed);
+        assert!(filter.matches(&status(State::Failed, Target::Up, 10)));
+        assert!(filter.matches(&status(State::CannotStop, Target::Down, 300)));
+        assert!(!filter.matches(&status(State::Failed, Target::Up, 301)));
+        assert!(!filter.matches(&status(State::Up, Target::Up, 10)));
+
+        // --failed ORs with a --filter on state
+        filter.add(b"state:starting");
+        assert!(filter.matches(&status(State::Starting, Target::Up, 10)));
+        assert!(!filter.matches(&status(State::Starting, Target::Up, 301)));
+    }
+
+    #[test]
+    fn errors_always_match() {
+        let mut filter = StatusFilter {
+            since_secs: Some(0),
+            ..StatusFilter::default()
+        };
+        filter.add_failed();
+        assert!(filter.matches(&Response::ServiceNotFound));
+    }
+}
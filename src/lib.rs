@@ -24,6 +24,7 @@ pub mod err;
 pub mod internal_api;
 pub mod ipc;
 pub mod os;
+pub mod supervisor;
 pub mod syscall;
 pub mod types;
 pub mod util;
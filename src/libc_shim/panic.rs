@@ -1,4 +1,4 @@
-use connate::os::{Print, eprint, eprintln, exit};
+use connate::err::exit_on_panic;
 
 /// Panic handler
 ///
@@ -9,25 +9,6 @@ use connate::os::{Print, eprint, eprintln, exit};
 ///   use the main connate binary, and so a panic handler is necessary for user code
 #[cfg_attr(not(test), panic_handler)]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    #[cfg(debug_assertions)]
-    {
-        eprint("Panic!");
-        if let Some(e) = info.message().as_str() {
-            eprint(" ");
-            eprint(e);
-        }
-        eprint("\n");
-        if let Some(loc) = info.location() {
-            eprint("File: ");
-            eprint(loc.file());
-            eprint(":");
-            eprint(loc.line());
-            eprint(":");
-            eprint(loc.column());
-            eprint("\n");
-        }
-    }
-
     // If this code path shows up in the resulting binary, it means the compiler thinks a panic is
     // possible.
     //
@@ -39,6 +20,8 @@ fn panic(info: &core::panic::PanicInfo) -> ! {
     //
     // This should be done with a minimal config.rs, as user config.rs are allowed to include
     // panics in forked-off processes that will not bring down the main process.
-    eprintln("unexpected panic");
-    exit(1);
+    //
+    // The message and location are always printed, as a panicking `Run::Fn` is otherwise
+    // impossible to debug from its log.
+    exit_on_panic(info.message(), info.location())
 }
@@ -57,4 +57,52 @@ impl<'a> Argv<'a> {
         self.raw = tail;
         Some(head_cstr)
     }
+
+    #[inline]
+    pub fn pop_back(&mut self) -> Option<&'a CStr> {
+        let (&last, init) = self.raw.split_last()?;
+        // SAFETY: caller guaranteed argv pointers are valid for reads during 'a.
+        let last_cstr = unsafe { CStr::from_ptr(last) };
+        self.raw = init;
+        Some(last_cstr)
+    }
+
+    /// Consume leading arguments which are among `flags`, in any order, returning which were given
+    pub fn pop_flags<const N: usize>(&mut self, flags: [&[u8]; N]) -> [bool; N] {
+        let mut given = [false; N];
+        while let Some(i) = self
+            .first()
+            .and_then(|arg| flags.iter().position(|&flag| flag == arg.to_bytes()))
+        {
+            if let Some(given) = given.get_mut(i) {
+                *given = true;
+            }
+            let _ = self.pop();
+        }
+        given
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pop_flags_accepts_any_order() {
+        const FLAGS: [&[u8]; 2] = [b"--json", b"--all"];
+        for args in [
+            [c"--json".as_ptr(), c"--all".as_ptr(), c"svc".as_ptr()],
+            [c"--all".as_ptr(), c"--json".as_ptr(), c"svc".as_ptr()],
+        ] {
+            let mut argv = unsafe { Argv::from_raw(args.len() as isize, args.as_ptr()) };
+            assert_eq!(argv.pop_flags(FLAGS), [true, true]);
+            assert_eq!(argv.first(), Some(c"svc"));
+        }
+
+        // Flags after the first other argument are left alone
+        let args = [c"--all".as_ptr(), c"svc".as_ptr(), c"--json".as_ptr()];
+        let mut argv = unsafe { Argv::from_raw(args.len() as isize, args.as_ptr()) };
+        assert_eq!(argv.pop_flags(FLAGS), [false, true]);
+        assert_eq!(argv.len(), 2);
+    }
 }
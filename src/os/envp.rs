@@ -1,5 +1,15 @@
 use core::ffi::{CStr, c_char};
 use core::marker::PhantomData;
+use core::sync::atomic::{AtomicPtr, Ordering};
+
+/// Most environment variables a service inheriting connate's environment is given
+///
+/// Bounds the on-stack array the merged environment is built in.  Inherited variables beyond this
+/// are dropped.
+pub const MAX_MERGED_ENV_COUNT: usize = 256;
+
+/// The environment connate was started with, or null if unknown
+static STARTUP_ENVP: AtomicPtr<*const c_char> = AtomicPtr::new(core::ptr::null_mut());
 
 #[derive(Clone)]
 pub struct Envp<'a> {
@@ -50,3 +60,259 @@ impl<'a> Iterator for Envp<'a> {
         }
     }
 }
+
+/// Record connate's ABI-provided envp such that it can later be passed on
+///
+/// # Safety
+/// - `envp` must be a valid null-terminated array of pointers which remains valid for the life of
+///   the process, as the ABI-provided envp in main() does.
+pub unsafe fn set_startup_envp(envp: *const *const c_char) {
+    STARTUP_ENVP.store(envp.cast_mut(), Ordering::Relaxed);
+}
+
+/// The environment connate was started with, or null if `set_startup_envp()` was never called
+pub fn startup_envp() -> *const *const c_char {
+    STARTUP_ENVP.load(Ordering::Relaxed)
+}
+
+/// Iterate over the raw `VAR=VALUE` entries of a null-terminated envp array, which may be null
+#[derive(Clone)]
+pub struct RawEnvp {
+    cur: *const *const c_char,
+}
+
+impl RawEnvp {
+    /// # Safety
+    /// - `envp` must be null or a valid null-terminated array of pointers to null-terminated
+    ///   strings which remain valid while iterating.
+    pub unsafe fn from_raw(envp: *const *const c_char) -> Self {
+        Self { cur: envp }
+    }
+}
+
+impl Iterator for RawEnvp {
+    type Item = *const c_char;
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cur.is_null() {
+            return None;
+        }
+        // Safety: from_raw() callers guarantee the array is valid and null-terminated
+        let p = unsafe { *self.cur };
+        if p.is_null() {
+            return None;
+        }
+        self.cur = unsafe { self.cur.add(1) };
+        Some(p)
+    }
+}
+
+/// The `VAR` portion of a `VAR=VALUE` entry
+///
+/// # Safety
+/// - `entry` must be a valid null-terminated string
+unsafe fn env_var_name<'a>(entry: *const c_char) -> &'a [u8] {
+    let bytes = unsafe { CStr::from_ptr(entry) }.to_bytes();
+    match bytes.iter().position(|&c| c == b'=') {
+        Some(eq_idx) => bytes.get(..eq_idx).unwrap_or(bytes),
+        None => bytes,
+    }
+}
+
+/// The environment of a service which inherits some or all of connate's environment
+///
+/// Yields every entry of `overrides`, followed by each `inherited` entry whose variable is not
+/// overridden and, if `only` is given, is one of its variables.
+#[derive(Clone)]
+pub struct MergedEnvp<'a, O> {
+    overrides: O,
+    remaining_overrides: O,
+    inherited: RawEnvp,
+    only: Option<&'a [&'a [u8]]>,
+}
+
+impl<'a, O: Iterator<Item = *const c_char> + Clone> MergedEnvp<'a, O> {
+    /// # Safety
+    /// - Every entry yielded by `overrides` must be a valid null-terminated string.
+    pub unsafe fn new(overrides: O, inherited: RawEnvp, only: Option<&'a [&'a [u8]]>) -> Self {
+        Self {
+            remaining_overrides: overrides.clone(),
+            overrides,
+            inherited,
+            only,
+        }
+    }
+}
+
+impl<O: Iterator<Item = *const c_char> + Clone> Iterator for MergedEnvp<'_, O> {
+    type Item = *const c_char;
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.remaining_overrides.next() {
+            return Some(entry);
+        }
+
+        loop {
+            let entry = self.inherited.next()?;
+            let name = unsafe { env_var_name(entry) };
+            if self.only.is_some_and(|only| !only.contains(&name)) {
+                continue;
+            }
+            let overridden = self
+                .overrides
+                .clone()
+                .any(|o| unsafe { env_var_name(o) } == name);
+            if !overridden {
+                return Some(entry);
+            }
+        }
+    }
+}
+
+/// Build the envp for a service which inherits connate's environment, or only the `only`
+/// variables of it, into `out`
+///
+/// Entries of the `MergedEnvp` which do not fit in `out` (less its trailing null) are dropped.
+/// Returns a pointer to `out` for execve().
+///
+/// # Safety
+/// - `overrides` and `inherited` must each be null or a valid null-terminated array of pointers to
+///   null-terminated strings.
+pub unsafe fn merge_envp(
+    overrides: *const *const c_char,
+    inherited: *const *const c_char,
+    only: Option<&[&[u8]]>,
+    out: &mut [*const c_char],
+) -> *const *const c_char {
+    let capacity = out.len().saturating_sub(1); // trailing null
+    let merged = unsafe {
+        MergedEnvp::new(
+            RawEnvp::from_raw(overrides),
+            RawEnvp::from_raw(inherited),
+            only,
+        )
+    };
+
+    let mut len: usize = 0;
+    for (slot, entry) in out.iter_mut().zip(merged.take(capacity)) {
+        *slot = entry;
+        len += 1;
+    }
+
+    if let Some(slot) = out.get_mut(len) {
+        *slot = core::ptr::null();
+    }
+    out.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::*;
+    use crate::syscall::execve;
+
+    fn entries(envp: *const *const c_char) -> Vec<&'static [u8]> {
+        RawEnvp { cur: envp }
+            .map(|p| unsafe { CStr::from_ptr(p) }.to_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn merge_overrides_inherited() {
+        let overrides = [
+            c"HOME=/var/empty".as_ptr(),
+            c"FOO=bar".as_ptr(),
+            core::ptr::null(),
+        ];
+        let inherited = [
+            c"HOME=/root".as_ptr(),
+            c"LANG=C".as_ptr(),
+            core::ptr::null(),
+        ];
+        let mut out = [core::ptr::null(); 8];
+
+        let merged = unsafe { merge_envp(overrides.as_ptr(), inherited.as_ptr(), None, &mut out) };
+        assert_eq!(
+            entries(merged),
+            [&b"HOME=/var/empty"[..], b"FOO=bar", b"LANG=C"]
+        );
+    }
+
+    #[test]
+    fn merge_truncates_to_capacity() {
+        let overrides = [c"A=1".as_ptr(), core::ptr::null()];
+        let inherited = [c"B=2".as_ptr(), c"C=3".as_ptr(), core::ptr::null()];
+        let mut out = [core::ptr::null(); 3];
+
+        let merged = unsafe { merge_envp(overrides.as_ptr(), inherited.as_ptr(), None, &mut out) };
+        assert_eq!(entries(merged), [&b"A=1"[..], b"B=2"]);
+
+        let merged = unsafe { merge_envp(core::ptr::null(), core::ptr::null(), None, &mut out) };
+        assert!(entries(merged).is_empty());
+    }
+
+    #[test]
+    fn merge_inherits_only_listed() {
+        let overrides = [c"TZ=UTC".as_ptr(), c"FOO=bar".as_ptr(), core::ptr::null()];
+        let inherited = [
+            c"TZ=Europe/Berlin".as_ptr(),
+            c"LANG=C".as_ptr(),
+            c"HOME=/root".as_ptr(),
+            core::ptr::null(),
+        ];
+        let mut out = [core::ptr::null(); 8];
+
+        let only: &[&[u8]] = &[b"TZ", b"LANG"];
+        let merged =
+            unsafe { merge_envp(overrides.as_ptr(), inherited.as_ptr(), Some(only), &mut out) };
+        assert_eq!(entries(merged), [&b"TZ=UTC"[..], b"FOO=bar", b"LANG=C"]);
+    }
+
+    /// Exec a child with `envp` and return what it sees in /proc/self/environ
+    fn child_environ(envp: *const *const c_char) -> Vec<u8> {
+        let (read, write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        match fork().unwrap() {
+            ForkResult::Child => {
+                let _ = read.close();
+                let _ = write.dup(1, OpenFlags::empty());
+                let argv = [
+                    c"cat".as_ptr(),
+                    c"/proc/self/environ".as_ptr(),
+                    core::ptr::null(),
+                ];
+                let _ = unsafe { execve(c"/bin/cat", argv.as_ptr(), envp) };
+                exit(127);
+            }
+            ForkResult::Parent(pid) => {
+                let _ = write.close();
+                let mut environ = Vec::new();
+                let mut buf = [0u8; 4096];
+                while let Ok(len @ 1..) = read.read(&mut buf) {
+                    environ.extend_from_slice(&buf[..len]);
+                }
+                let _ = waitpid(pid, WaitPidOptions::empty());
+                environ
+            }
+        }
+    }
+
+    #[test]
+    fn child_environment_is_controlled() {
+        let overrides = [c"CONNATE_TEST=service".as_ptr(), core::ptr::null()];
+        let inherited = [
+            c"CONNATE_TEST=connate".as_ptr(),
+            c"CONNATE_LEAK=1".as_ptr(),
+            core::ptr::null(),
+        ];
+
+        // Cleared: exactly the service's env
+        assert_eq!(child_environ(overrides.as_ptr()), b"CONNATE_TEST=service\0");
+
+        // Inherited: the service's env over connate's
+        let mut out = [core::ptr::null(); 8];
+        let merged = unsafe { merge_envp(overrides.as_ptr(), inherited.as_ptr(), None, &mut out) };
+        assert_eq!(
+            child_environ(merged),
+            b"CONNATE_TEST=service\0CONNATE_LEAK=1\0"
+        );
+    }
+}
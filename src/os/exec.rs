@@ -6,8 +6,11 @@ pub fn exec_filepath(path: &CStr) -> Result<(), Errno> {
     // Provide an argv[0] so that /proc/<pid>/cmdline shows the binary name
     let argv: [*const core::ffi::c_char; 2] = [path.as_ptr(), core::ptr::null()];
 
-    // SAFETY: argv is a properly null-terminated array of null-terminated C strings
-    unsafe { crate::syscall::execve(path, argv.as_ptr(), core::ptr::null()).map(|_| ()) }
+    // Pass on our environment such that services inheriting it see the same one after re-exec
+    //
+    // SAFETY: argv is a properly null-terminated array of null-terminated C strings and the startup
+    // envp is either null or ABI-provided
+    unsafe { crate::syscall::execve(path, argv.as_ptr(), crate::os::startup_envp()).map(|_| ()) }
 }
 
 /// Exec /proc/self/exe to re-initialize binary
@@ -1,6 +1,9 @@
+use super::stat::{S_IFIFO, S_IFMT, S_IFREG};
 use crate::err::*;
 use crate::syscall::*;
 use crate::types::*;
+use crate::util::BufWriter;
+use itoa::Integer; // ::MAX_STR_LEN
 
 pub const STDIN: Fd = Fd(0);
 pub const STDOUT: Fd = Fd(1);
@@ -12,6 +15,18 @@ pub use crate::syscall::{MemfdFlags, OpenFlags, SeekWhence};
 #[derive(Clone)]
 pub struct Fd(c_int);
 
+/// What an open file descriptor refers to, as far as telling connate's own FDs apart
+#[derive(Clone, Copy, PartialEq)]
+pub enum FdKind {
+    Pipe,
+    /// A file with no name in any filesystem, such as from memfd_create()
+    Memfd,
+    /// A file with a name in some filesystem
+    File,
+    /// Anything else, such as a signalfd or socket
+    Other,
+}
+
 impl Fd {
     pub fn open(path: &CStr, flags: OpenFlags, mode: c_int) -> Result<Self, Errno> {
         unsafe { openat(AT_FDCWD, path, flags, mode).map(Self) }
@@ -25,10 +40,46 @@ impl Fd {
         unsafe { write(self.0, buf) }
     }
 
+    /// Write all of `buf`, continuing after short writes
+    ///
+    /// Pipes and terminals may accept only part of a write larger than PIPE_BUF, such as when the
+    /// reader falls behind or a signal interrupts the write.  If the FD is non-blocking, waits for
+    /// it to become writable rather than giving up with EAGAIN part-way through `buf`.
+    ///
+    /// Only for writes whose loss would be worse than blocking.  Where dropping data is preferable,
+    /// such as notifications to pipes nobody may be reading, use a single `write()`.
+    pub fn write_all(&self, mut buf: &[u8]) -> Result<(), Errno> {
+        while !buf.is_empty() {
+            match self.write(buf) {
+                // Only possible if the FD cannot take any more, e.g. a full device
+                Ok(0) => return Err(Errno::EIO),
+                Ok(n) => buf = buf.get(n..).ok_or(Errno::EINVAL)?,
+                Err(Errno::EINTR) => {}
+                Err(Errno::EAGAIN) => {
+                    let mut fds = [PollFd {
+                        fd: self.0,
+                        events: PollEvents::POLLOUT,
+                        revents: PollEvents::empty(),
+                    }];
+                    match unsafe { poll(&mut fds, -1) } {
+                        Ok(_) | Err(Errno::EINTR) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
     pub fn close(self) -> Result<(), Errno> {
         unsafe { close(self.0) }
     }
 
+    pub fn fsync(&self) -> Result<(), Errno> {
+        unsafe { fsync(self.0) }
+    }
+
     pub fn ftruncate(&self, length: off_t) -> Result<(), Errno> {
         unsafe { ftruncate(self.0, length) }
     }
@@ -105,6 +156,51 @@ impl Fd {
         Ok(())
     }
 
+    pub fn set_nonblocking(&self) -> Result<(), Errno> {
+        // Get current flags
+        let flags = unsafe { fcntl_flags(self.0, FcntlCmd::F_GETFL, 0) }?;
+
+        // Set O_NONBLOCK
+        let new_flags = flags | OpenFlags::O_NONBLOCK.bits();
+
+        // Set new flags
+        unsafe { fcntl_flags(self.0, FcntlCmd::F_SETFL, new_flags) }?;
+
+        Ok(())
+    }
+
+    /// Set whether the FD is closed across exec
+    pub fn set_cloexec(&self, cloexec: bool) -> Result<(), Errno> {
+        let flags = unsafe { fcntl_flags(self.0, FcntlCmd::F_GETFD, 0) }?;
+        let new_flags = if cloexec {
+            flags | FD_CLOEXEC
+        } else {
+            flags & !FD_CLOEXEC
+        };
+        unsafe { fcntl_flags(self.0, FcntlCmd::F_SETFD, new_flags) }?;
+
+        Ok(())
+    }
+
+    /// Open what the FD refers to anew via /proc/self/fd/<fd>
+    ///
+    /// Unlike `dup()`, the new FD has its own file status flags such as O_NONBLOCK.  Requires /proc
+    /// to be mounted.
+    pub fn reopen(&self, flags: OpenFlags) -> Result<Self, Errno> {
+        const PATH_SIZE: usize = b"/proc/self/fd/".len() + c_int::MAX_STR_LEN + b"\0".len();
+        let mut buf = [0u8; PATH_SIZE];
+        let mut writer = BufWriter::new(&mut buf);
+        let mut itoa_buf = itoa::Buffer::new();
+
+        writer.push(b"/proc/self/fd/")?;
+        writer.push(itoa_buf.format(self.0).as_bytes())?;
+        writer.push(b"\0")?;
+
+        // Safety: We just built this buffer including the trailing null
+        let path = unsafe { CStr::from_bytes_with_nul_unchecked(writer.as_slice()) };
+        Self::open(path, flags, 0)
+    }
+
     pub fn isatty(&self) -> bool {
         // If ioctl errors, it's not a terminal; otherwise, it is.
         //
@@ -113,6 +209,27 @@ impl Fd {
         unsafe { ioctl(self.0, IoctlRequest::TCGETS, buf.as_mut_ptr() as usize) }.is_ok()
     }
 
+    /// Make the terminal the calling process's controlling terminal
+    ///
+    /// The caller must lead a session which has no controlling terminal yet.  Does not steal a
+    /// terminal which is already another session's controlling terminal.
+    pub fn set_controlling_tty(&self) -> Result<(), Errno> {
+        unsafe { ioctl(self.0, IoctlRequest::TIOCSCTTY, 0) }.map(|_| ())
+    }
+
+    /// Returns EBADF if the FD is not open
+    pub fn kind(&self) -> Result<FdKind, Errno> {
+        let mut statbuf = Stat::default();
+        unsafe { fstatat(self.0, c"", &mut statbuf, AT_EMPTY_PATH) }?;
+
+        Ok(match statbuf.st_mode & S_IFMT {
+            S_IFIFO => FdKind::Pipe,
+            S_IFREG if statbuf.st_nlink == 0 => FdKind::Memfd,
+            S_IFREG => FdKind::File,
+            _ => FdKind::Other,
+        })
+    }
+
     pub fn is_valid(&self) -> bool {
         // fcntl F_GETFL returns EBADF for invalid FDs; works for all FD types including pipes
         unsafe { fcntl_flags(self.0, FcntlCmd::F_GETFL, 0) }.is_ok()
@@ -131,6 +248,11 @@ impl Fd {
     }
 
     pub fn move_to(self, new_fd: c_int) -> Result<Self, Errno> {
+        // dup3() rejects duplicating onto itself with EINVAL.  Clear close-on-exec as it would.
+        if self.0 == new_fd {
+            self.set_cloexec(false)?;
+            return Ok(self);
+        }
         let new = self.dup(new_fd, OpenFlags::empty())?;
         self.close()?;
         Ok(new)
@@ -163,3 +285,64 @@ impl Fd {
 //         self.close();
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::{ForkResult, WaitPidOptions, exit, fork, waitpid, wexitstatus, wifexited};
+
+    #[test]
+    fn write_all_continues_after_short_writes() {
+        // Several times a pipe's default capacity, such that a non-blocking write is cut short and
+        // then fails with EAGAIN until the reader catches up
+        const LEN: usize = 256 * 1024;
+        let (read_fd, write_fd) =
+            Fd::new_pipe(OpenFlags::O_CLOEXEC | OpenFlags::O_NONBLOCK).unwrap();
+        read_fd.set_blocking().unwrap();
+
+        match fork().unwrap() {
+            ForkResult::Child => {
+                let _ = write_fd.close();
+                let mut expected: usize = 0;
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = read_fd.read(&mut buf).unwrap_or(0);
+                    if n == 0 {
+                        break;
+                    }
+                    for &byte in buf.get(..n).unwrap_or(&[]) {
+                        if byte != (expected % 251) as u8 {
+                            exit(1);
+                        }
+                        expected += 1;
+                    }
+                }
+                exit(if expected == LEN { 0 } else { 2 });
+            }
+            ForkResult::Parent(pid) => {
+                let _ = read_fd.close();
+                let mut data = [0u8; LEN];
+                for (i, byte) in data.iter_mut().enumerate() {
+                    *byte = (i % 251) as u8;
+                }
+                write_fd.write_all(&data).unwrap();
+                let _ = write_fd.close();
+
+                let (_, status) = waitpid(pid, WaitPidOptions::empty()).unwrap();
+                assert!(wifexited(status));
+                assert_eq!(wexitstatus(status), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn move_to_own_number_keeps_fd_open() {
+        let (read_fd, write_fd) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let raw = read_fd.as_raw();
+        let moved = read_fd.move_to(raw).unwrap();
+        assert_eq!(moved.as_raw(), raw);
+        assert!(moved.is_valid());
+        let _ = moved.close();
+        let _ = write_fd.close();
+    }
+}
@@ -5,3 +5,12 @@ use crate::types::{Signal, pid_t};
 pub fn kill(pid: pid_t, sig: Signal) -> Result<(), Errno> {
     unsafe { crate::syscall::kill(pid, sig as i32) }
 }
+
+/// Whether a process with this PID exists, via the null signal
+///
+/// Lacking permission to signal the process still means it exists, as does being a zombie.  This
+/// cannot tell whether the PID has been reused by another process since.
+#[inline]
+pub fn process_exists(pid: pid_t) -> bool {
+    !matches!(unsafe { crate::syscall::kill(pid, 0) }, Err(Errno::ESRCH))
+}
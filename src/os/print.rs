@@ -1,6 +1,6 @@
 //! Print framework
 
-use crate::os::{Fd, STDERR, STDOUT};
+use crate::os::{Envp, Fd, STDERR, STDOUT};
 use crate::types::pid_t;
 use core::ffi::CStr;
 use core::sync::atomic::{AtomicU8, Ordering};
@@ -8,22 +8,35 @@ use core::sync::atomic::{AtomicU8, Ordering};
 // ANSI color codes
 const RESET: &[u8] = b"\x1b[0m";
 const RED: &[u8] = b"\x1b[31m";
+const BOLD_REVERSE_RED: &[u8] = b"\x1b[1;7;31m";
 const GREEN: &[u8] = b"\x1b[32m";
 const YELLOW: &[u8] = b"\x1b[33m";
 const CYAN: &[u8] = b"\x1b[36m";
 const LIGHT_GRAY: &[u8] = b"\x1b[2m";
 const DARK_GRAY: &[u8] = b"\x1b[90m";
+const WHITE: &[u8] = b"\x1b[37m";
+const BRIGHT_WHITE: &[u8] = b"\x1b[97m";
+
+// 256-color palette grays
 const GRAY_250: &[u8] = b"\x1b[38;5;250m";
 const GRAY_248: &[u8] = b"\x1b[38;5;248m";
 const GRAY_246: &[u8] = b"\x1b[38;5;246m";
 const GRAY_244: &[u8] = b"\x1b[38;5;244m";
 
+// 24-bit truecolor grays
+const RGB_GRAY_230: &[u8] = b"\x1b[38;2;230;230;230m";
+const RGB_GRAY_195: &[u8] = b"\x1b[38;2;195;195;195m";
+const RGB_GRAY_160: &[u8] = b"\x1b[38;2;160;160;160m";
+const RGB_GRAY_125: &[u8] = b"\x1b[38;2;125;125;125m";
+
 /// Color options for terminal output
 #[derive(Clone, Copy)]
 pub enum Color {
     Okay,
     Warning,
     Error,
+    /// Errors needing an operator's attention, such as a process connate cannot stop
+    Critical,
     Transition,
     Service,
     Path,
@@ -37,21 +50,35 @@ pub enum Color {
 }
 
 impl Color {
-    const fn code(self) -> &'static [u8] {
-        match self {
-            Color::Okay => GREEN,
-            Color::Warning => YELLOW,
-            Color::Error => RED,
-            Color::Transition => YELLOW,
-            Color::Service => CYAN,
-            Color::Path => GREEN,
-            Color::Dim => LIGHT_GRAY,
-            Color::Glue => DARK_GRAY,
-            Color::NotFound => RED,
-            Color::TimeDay => GRAY_250,
-            Color::TimeHour => GRAY_248,
-            Color::TimeMinute => GRAY_246,
-            Color::TimeSecond => GRAY_244,
+    /// Escape sequence for this color at the given terminal color depth
+    ///
+    /// Only the time gradient needs more than the basic 16 colors.  Everything else uses the basic
+    /// ANSI codes irrespective of depth so it follows the terminal's configured theme.
+    const fn code(self, mode: u8) -> &'static [u8] {
+        match (self, mode) {
+            (Color::Okay, _) => GREEN,
+            (Color::Warning, _) => YELLOW,
+            (Color::Error, _) => RED,
+            (Color::Critical, _) => BOLD_REVERSE_RED,
+            (Color::Transition, _) => YELLOW,
+            (Color::Service, _) => CYAN,
+            (Color::Path, _) => GREEN,
+            (Color::Dim, _) => LIGHT_GRAY,
+            (Color::Glue, _) => DARK_GRAY,
+            (Color::NotFound, _) => RED,
+            (Color::TimeDay, COLOR_TRUECOLOR) => RGB_GRAY_230,
+            (Color::TimeHour, COLOR_TRUECOLOR) => RGB_GRAY_195,
+            (Color::TimeMinute, COLOR_TRUECOLOR) => RGB_GRAY_160,
+            (Color::TimeSecond, COLOR_TRUECOLOR) => RGB_GRAY_125,
+            (Color::TimeDay, COLOR_256) => GRAY_250,
+            (Color::TimeHour, COLOR_256) => GRAY_248,
+            (Color::TimeMinute, COLOR_256) => GRAY_246,
+            (Color::TimeSecond, COLOR_256) => GRAY_244,
+            // The basic colors only have two grays, so the dim attribute stands in for a third
+            (Color::TimeDay, _) => BRIGHT_WHITE,
+            (Color::TimeHour, _) => WHITE,
+            (Color::TimeMinute, _) => LIGHT_GRAY,
+            (Color::TimeSecond, _) => DARK_GRAY,
         }
     }
 }
@@ -59,34 +86,66 @@ impl Color {
 // Cached colorization state
 // 0 = uninitialized
 // 1 = no colors
-// 2 = colors enabled (TTY)
+// 2 = basic 16 colors
+// 3 = 256 colors
+// 4 = 24-bit truecolor
 const COLOR_UNINITIALIZED: u8 = 0;
 const COLOR_DISABLED: u8 = 1;
-const COLOR_ENABLED: u8 = 2;
+const COLOR_16: u8 = 2;
+const COLOR_256: u8 = 3;
+const COLOR_TRUECOLOR: u8 = 4;
 
-static SHOULD_COLORIZE: AtomicU8 = AtomicU8::new(COLOR_UNINITIALIZED);
+static COLOR_MODE: AtomicU8 = AtomicU8::new(COLOR_UNINITIALIZED);
 
-/// Check if colors should be used in output
+/// Determine and cache the color depth used for output
 ///
-/// Returns true if STDOUT is a TTY.
-/// Result is cached after init_colorize() is called.
-fn should_colorize() -> bool {
-    match SHOULD_COLORIZE.load(Ordering::Relaxed) {
-        COLOR_ENABLED => true,
-        COLOR_DISABLED => false,
-        _ => {
-            let is_tty = STDOUT.isatty();
-            SHOULD_COLORIZE.store(
-                if is_tty {
-                    COLOR_ENABLED
-                } else {
-                    COLOR_DISABLED
-                },
-                Ordering::Relaxed,
-            );
-            is_tty
+/// Colors are disabled if `no_color` is set (e.g. via `--no-color`), if `$NO_COLOR` is set to a
+/// non-empty value, or if STDOUT is not a TTY.  Otherwise, `$COLORTERM` and `$TERM` are inspected
+/// to pick the richest color depth the terminal advertises, downgrading to the basic 16 colors if
+/// neither advertises anything more.
+pub fn init_colorize(envp: Envp, no_color: bool) {
+    let mut no_color_env = false;
+    let mut truecolor = false;
+    let mut color_256 = false;
+
+    for (var, value) in envp {
+        let value = value.to_bytes();
+        match var {
+            b"NO_COLOR" => no_color_env = !value.is_empty(),
+            b"COLORTERM" => truecolor = value == b"truecolor" || value == b"24bit",
+            b"TERM" => color_256 = value.ends_with(b"256color"),
+            _ => {}
         }
     }
+
+    let mode = if no_color || no_color_env || !STDOUT.isatty() {
+        COLOR_DISABLED
+    } else if truecolor {
+        COLOR_TRUECOLOR
+    } else if color_256 {
+        COLOR_256
+    } else {
+        COLOR_16
+    };
+    COLOR_MODE.store(mode, Ordering::Relaxed);
+}
+
+/// Color depth to use for output
+///
+/// If init_colorize() was not called, falls back to 256 colors if STDOUT is a TTY.
+fn color_mode() -> u8 {
+    match COLOR_MODE.load(Ordering::Relaxed) {
+        COLOR_UNINITIALIZED => {
+            let mode = if STDOUT.isatty() {
+                COLOR_256
+            } else {
+                COLOR_DISABLED
+            };
+            COLOR_MODE.store(mode, Ordering::Relaxed);
+            mode
+        }
+        mode => mode,
+    }
 }
 
 pub fn print<T: Print>(s: T) {
@@ -108,8 +167,9 @@ pub fn eprintln<T: Print>(s: T) {
 }
 
 pub fn print_color<T: Print>(color: Color, s: T) {
-    if should_colorize() {
-        color.print(STDOUT);
+    let mode = color_mode();
+    if mode != COLOR_DISABLED {
+        color.code(mode).print(STDOUT);
         s.print(STDOUT);
         RESET.print(STDOUT);
     } else {
@@ -117,6 +177,12 @@ pub fn print_color<T: Print>(color: Color, s: T) {
     }
 }
 
+/// Best-effort output to an FD
+///
+/// Each `print()` is a single `write()`, and a short write drops the rest rather than retrying.
+/// This avoids splitting a print across several writes which other output could land between; it
+/// does not keep a write to a blocking FD from waiting on a stalled terminal.  connate's forwarded
+/// console output goes through a non-blocking FD for that; see `open_console_out()`.
 pub trait Print {
     fn print(&self, fd: Fd);
     fn print_len(&self) -> usize;
@@ -137,16 +203,6 @@ pub trait Print {
     }
 }
 
-impl Print for Color {
-    fn print(&self, fd: Fd) {
-        let _ = fd.write(self.code());
-    }
-
-    fn print_len(&self) -> usize {
-        0
-    }
-}
-
 impl Print for &[u8] {
     fn print(&self, fd: Fd) {
         let _ = fd.write(self);
@@ -207,6 +263,16 @@ impl Print for u64 {
     }
 }
 
+impl Print for i64 {
+    fn print(&self, fd: Fd) {
+        let _ = fd.write(itoa::Buffer::new().format(*self).as_bytes());
+    }
+
+    fn print_len(&self) -> usize {
+        itoa::Buffer::new().format(*self).len()
+    }
+}
+
 impl Print for pid_t {
     fn print(&self, fd: Fd) {
         let _ = fd.write(itoa::Buffer::new().format(*self).as_bytes());
@@ -1,6 +1,6 @@
 pub use crate::syscall::{
-    LINUX_REBOOT_CMD_HALT, LINUX_REBOOT_CMD_POWER_OFF, LINUX_REBOOT_CMD_RESTART,
-    LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2,
+    LINUX_REBOOT_CMD_CAD_OFF, LINUX_REBOOT_CMD_CAD_ON, LINUX_REBOOT_CMD_HALT,
+    LINUX_REBOOT_CMD_POWER_OFF, LINUX_REBOOT_CMD_RESTART, LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2,
 };
 use core::ptr;
 use syscalls::Errno;
@@ -49,3 +49,18 @@ pub fn reboot() -> Result<(), Errno> {
         )
     }
 }
+
+/// Choose what the Ctrl-Alt-Del keystroke does
+///
+/// If `reboots` is true, the kernel immediately reboots the system (the kernel's default).
+/// Otherwise, the kernel sends SIGINT to init (PID 1) to handle as it sees fit.
+///
+/// Requires CAP_SYS_BOOT capability.
+pub fn set_ctrl_alt_del_reboots(reboots: bool) -> Result<(), Errno> {
+    let cmd = if reboots {
+        LINUX_REBOOT_CMD_CAD_ON
+    } else {
+        LINUX_REBOOT_CMD_CAD_OFF
+    };
+    unsafe { crate::syscall::reboot(LINUX_REBOOT_MAGIC1, LINUX_REBOOT_MAGIC2, cmd, ptr::null()) }
+}
@@ -23,6 +23,17 @@ pub fn setgid(gid: gid_t) -> Result<(), Errno> {
     unsafe { crate::syscall::setgid(gid) }
 }
 
+/// Set the supplementary group IDs of the calling process
+///
+/// # Safety
+///
+/// Requires appropriate privileges (typically root)
+#[inline]
+pub fn setgroups(gids: &[gid_t]) -> Result<(), Errno> {
+    // SAFETY: Caller must have appropriate privileges.  The kernel only reads `gids.len()` IDs.
+    unsafe { crate::syscall::setgroups(gids) }
+}
+
 /// Set real, effective, and saved user IDs
 ///
 /// # Safety
@@ -9,16 +9,29 @@ pub struct SignalFd(c_int);
 
 impl SignalFd {
     pub fn new() -> Result<Self, Errno> {
+        // We do not SFD_CLOEXEC here to ensure the signalfd survives a re-exec.
+        let flags = SignalFdFlags::empty();
+        let fd = unsafe { signalfd(-1, &Self::signals(), flags)? };
+        Ok(Self(fd))
+    }
+
+    /// Replace the set of signals this signalfd reports with the current one
+    ///
+    /// A signalfd resumed across re-exec retains the set of the prior executable, which may
+    /// predate newly handled signals.
+    pub fn update_signals(&self) -> Result<(), Errno> {
+        let flags = SignalFdFlags::empty();
+        unsafe { signalfd(self.0, &Self::signals(), flags).map(|_| ()) }
+    }
+
+    fn signals() -> sigset_t {
         let mut signals = sigset_t::new_empty_set();
         signals |= Signal::SIGHUP;
         signals |= Signal::SIGINT;
         signals |= Signal::SIGTERM;
         signals |= Signal::SIGCHLD;
-
-        // We do not SFD_CLOEXEC here to ensure the signalfd survives a re-exec.
-        let flags = SignalFdFlags::empty();
-        let fd = unsafe { signalfd(-1, &signals, flags)? };
-        Ok(Self(fd))
+        signals |= Signal::SIGPWR;
+        signals
     }
 
     pub fn read_siginfo(&mut self) -> Result<SigInfo, Errno> {
@@ -10,6 +10,12 @@ pub const S_IFMT: mode_t = 0o170000;
 /// Directory file type bit
 pub const S_IFDIR: mode_t = 0o040000;
 
+/// Regular file type bit
+pub const S_IFREG: mode_t = 0o100000;
+
+/// FIFO (pipe) file type bit
+pub const S_IFIFO: mode_t = 0o010000;
+
 /// User execute permission bit
 pub const S_IXUSR: mode_t = 0o100;
 
@@ -34,6 +40,12 @@ pub fn is_dir(path: &CStr) -> Result<bool, Errno> {
     Ok((statbuf.st_mode & S_IFMT) == S_IFDIR)
 }
 
+#[inline]
+pub fn is_regular_file(path: &CStr) -> Result<bool, Errno> {
+    let statbuf = stat(path)?;
+    Ok((statbuf.st_mode & S_IFMT) == S_IFREG)
+}
+
 #[inline]
 pub fn is_executable(path: &CStr) -> Result<bool, Errno> {
     let statbuf = stat(path)?;
@@ -18,3 +18,19 @@ pub fn get_time_monotonic() -> Result<timespec, Errno> {
     unsafe { clock_gettime(ClockId::CLOCK_MONOTONIC_COARSE, &mut tp) }?;
     Ok(tp)
 }
+
+/// Get the wall-clock time, in seconds and nanoseconds since the Unix epoch
+///
+/// Uses CLOCK_REALTIME_COARSE, for the same reason as [`get_time_monotonic`].  Unlike the
+/// monotonic clock this may jump in either direction, e.g. when NTP first syncs on a system without
+/// an RTC, so it must only be used to display absolute times and never to measure intervals.
+#[inline]
+pub fn get_time_realtime() -> Result<timespec, Errno> {
+    let mut tp = timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // Safety: Only concern is that `tp` is a valid pointer, which we've just created.
+    unsafe { clock_gettime(ClockId::CLOCK_REALTIME_COARSE, &mut tp) }?;
+    Ok(tp)
+}
@@ -0,0 +1,76 @@
+//! Services gated on the kernel command line
+//!
+//! A service with `start_if_cmdline` only keeps its `init_target` if the given parameter is on
+//! the kernel command line, allowing e.g. a rescue mode to be chosen at boot without rebuilding.
+
+use crate::constants::*;
+use crate::internal_api::*;
+use crate::os::*;
+
+/// Set the target of services whose `start_if_cmdline` parameter is absent to `Down`
+///
+/// Reads `/proc/cmdline` once.  If it cannot be read, such as when connate is init and /proc is
+/// not yet mounted, `argv` is checked instead, as the kernel passes init the parameters it does
+/// not recognize.  Only these lack parameters containing `=`, which the kernel passes as
+/// environment variables instead.
+///
+/// Any session resumed after a re-exec overrides these targets, so this only affects services
+/// which are new to connate.
+pub fn apply_start_if_cmdline(svcs: &mut [Service], argv: &Argv) {
+    if !svcs.iter().any(|svc| svc.cfg.start_if_cmdline.is_some()) {
+        return;
+    }
+
+    let mut buf = [0u8; CMDLINE_SIZE];
+    let cmdline = read_cmdline(&mut buf);
+
+    for svc in svcs.iter_mut() {
+        let Some(param) = svc.cfg.start_if_cmdline else {
+            continue;
+        };
+        let present = match cmdline {
+            Some(cmdline) => cmdline_has_param(cmdline, param),
+            // Skip argv[0], which is connate itself
+            None => argv.iter().skip(1).any(|arg| arg.to_bytes() == param),
+        };
+        if !present {
+            svc.target = Target::Down;
+        }
+    }
+}
+
+fn read_cmdline(buf: &mut [u8; CMDLINE_SIZE]) -> Option<&[u8]> {
+    let fd = Fd::open(
+        c"/proc/cmdline",
+        OpenFlags::O_RDONLY | OpenFlags::O_CLOEXEC,
+        0,
+    )
+    .ok()?;
+    let result = fd.read(buf);
+    let _ = fd.close();
+    buf.get(..result.ok()?)
+}
+
+/// Whether `param` is one of the whitespace-separated parameters in `cmdline`
+fn cmdline_has_param(cmdline: &[u8], param: &[u8]) -> bool {
+    cmdline
+        .split(|b| b.is_ascii_whitespace())
+        .any(|word| word == param)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn params_match_whole_words_only() {
+        let cmdline = b"BOOT_IMAGE=/vmlinuz root=/dev/sda1 ro rescue quiet\n";
+        assert!(cmdline_has_param(cmdline, b"rescue"));
+        assert!(cmdline_has_param(cmdline, b"quiet"));
+        assert!(cmdline_has_param(cmdline, b"root=/dev/sda1"));
+        assert!(!cmdline_has_param(cmdline, b"res"));
+        assert!(!cmdline_has_param(cmdline, b"root"));
+        assert!(!cmdline_has_param(cmdline, b"single"));
+        assert!(!cmdline_has_param(b"", b"single"));
+    }
+}
@@ -0,0 +1,348 @@
+//! Line-buffered console output
+//!
+//! With `Config::LINE_BUFFER_CONSOLE`, services which would otherwise inherit connate's stdout and
+//! stderr instead write to a pipe per service.  Connate reads these pipes from its main loop and
+//! writes each complete line to its own stdout with a single write, such that services starting
+//! in parallel cannot interleave within a line, either with each other or with connate's own
+//! messages.
+//!
+//! Lines are written without blocking, through connate's own non-blocking open of its stdout.  A
+//! line which does not fit, such as when a terminal is stalled or a logger falls behind, is
+//! dropped whole rather than left waiting on or cut short.
+//!
+//! Services logging to another service go through a pipe of their own in the same way, with each
+//! complete line written to their logger's shared stdin pipe instead.  A partial line left by one
+//! process is then ended before another producer's output, rather than running into it.
+
+use crate::config::{Config, Connate};
+use crate::constants::*;
+use crate::err::*;
+use crate::internal_api::*;
+use crate::os::*;
+use core::sync::atomic::{AtomicI32, Ordering};
+
+// A line and the newline ending it are written together, which a pipe takes all or none of
+const _: () = assert!(CONSOLE_LINE_SIZE < PIPE_BUF);
+
+/// Where lines for connate's stdout are written; see `open_console_out()`
+static CONSOLE_OUT: AtomicI32 = AtomicI32::new(1);
+
+/// A service's console pipe along with whatever partial line has been read from it
+pub struct ConsolePipe {
+    read_fd: Fd,
+    write_fd: Fd,
+    /// Room for a line and a newline to end it with
+    line: [u8; CONSOLE_LINE_SIZE + 1],
+    len: usize,
+}
+
+impl ConsolePipe {
+    /// Create a pipe whose write end becomes a service's stdout and stderr
+    ///
+    /// Both ends are close-on-exec such that only the service's own processes hold the write end.
+    /// Connate holds it too, such that the read end never sees EOF and can always be polled.
+    pub fn new() -> Result<Self, Errno> {
+        let (read_fd, write_fd) = Fd::new_pipe(OpenFlags::O_CLOEXEC)?;
+        if let Err(e) = read_fd.set_nonblocking() {
+            let _ = read_fd.close();
+            let _ = write_fd.close();
+            return Err(e);
+        }
+        Ok(Self::from_fds(read_fd, write_fd))
+    }
+
+    /// Resume a pipe from before a re-exec
+    pub fn from_fds(read_fd: Fd, write_fd: Fd) -> Self {
+        Self {
+            read_fd,
+            write_fd,
+            line: [0; CONSOLE_LINE_SIZE + 1],
+            len: 0,
+        }
+    }
+
+    pub fn read_fd(&self) -> &Fd {
+        &self.read_fd
+    }
+
+    pub fn write_fd(&self) -> &Fd {
+        &self.write_fd
+    }
+
+    /// Read what the service has written and write any complete lines to `out`
+    ///
+    /// Reads a bounded amount such that a service flooding its pipe cannot starve connate.
+    pub fn forward(&mut self, out: &Fd) {
+        for _ in 0..CONSOLE_READ_BATCH_SIZE {
+            let Some(free) = self.line.get_mut(self.len..CONSOLE_LINE_SIZE) else {
+                return;
+            };
+            match self.read_fd.read(free) {
+                Ok(n) if n > 0 => {
+                    self.len += n;
+                    self.write_lines(out);
+                }
+                // Drained, or nothing to read after all
+                _ => return,
+            }
+        }
+    }
+
+    /// Forward everything read so far, ending any partial line
+    ///
+    /// Used when the service's process exits or connate is about to stop reading, such that a
+    /// partial last line does not run into whatever is written next.
+    pub fn finish(&mut self, out: &Fd) {
+        self.forward(out);
+        if self.len > 0 {
+            self.end_line(out);
+        }
+        self.len = 0;
+    }
+
+    /// Write out complete lines, keeping any partial last line for later
+    ///
+    /// A line which fills the buffer without ending is written out as though it ended.
+    fn write_lines(&mut self, out: &Fd) {
+        let mut complete = 0;
+        while let Some(rest) = self.line.get(complete..self.len)
+            && let Some(end) = rest.iter().position(|&b| b == b'\n')
+        {
+            if let Some(line) = rest.get(..=end) {
+                write_line(out, line);
+            }
+            complete += end + 1;
+        }
+        if complete == 0 {
+            if self.len == CONSOLE_LINE_SIZE {
+                self.end_line(out);
+                self.len = 0;
+            }
+            return;
+        }
+
+        // Move the partial line to the front
+        let partial = self.len - complete;
+        for i in 0..partial {
+            if let Some(&b) = self.line.get(complete + i)
+                && let Some(dst) = self.line.get_mut(i)
+            {
+                *dst = b;
+            }
+        }
+        self.len = partial;
+    }
+
+    /// Write out the partial line held, ending it with a newline
+    fn end_line(&mut self, out: &Fd) {
+        if let Some(newline) = self.line.get_mut(self.len) {
+            *newline = b'\n';
+        }
+        if let Some(line) = self.line.get(..=self.len) {
+            write_line(out, line);
+        }
+    }
+}
+
+/// Write a whole line with a single non-blocking write, or drop it
+///
+/// A line no longer than `PIPE_BUF` is written to a pipe all at once or not at all, so it is never
+/// split around another's output.  A terminal may take part of it.
+fn write_line(out: &Fd, line: &[u8]) {
+    let _ = out.write(line);
+}
+
+/// Open connate's stdout anew, non-blocking, for console lines to be written to
+///
+/// Connate's stdout itself is left blocking, as services and the terminal's other users share it.
+/// Without /proc to reopen it through, such as early in boot, connate's stdout is made
+/// non-blocking instead.
+pub fn open_console_out() {
+    if !<Connate as Config>::LINE_BUFFER_CONSOLE {
+        return;
+    }
+    let flags = OpenFlags::O_WRONLY | OpenFlags::O_NONBLOCK | OpenFlags::O_CLOEXEC;
+    match STDOUT.reopen(flags) {
+        Ok(fd) => CONSOLE_OUT.store(fd.into_raw(), Ordering::Relaxed),
+        Err(_) => {
+            let _ = STDOUT.set_nonblocking();
+        }
+    }
+}
+
+/// Whether the service's output goes through a `ConsolePipe`
+///
+/// A controlling terminal takes the place of stdout and stderr, so is left alone.
+pub fn uses_console_pipe(cfg: &ServiceConfig) -> bool {
+    <Connate as Config>::LINE_BUFFER_CONSOLE
+        && matches!(cfg.log, Log::Inherit | Log::Service(_))
+        && cfg.controlling_tty.is_none()
+}
+
+/// Where complete lines read from the service's console pipe are written
+///
+/// The logger's stdin pipe for services logging to one, else connate's stdout.  Output from
+/// before a logger's pipe exists, or after it is gone, goes to stdout as it would have without the
+/// console pipe.
+pub fn console_out<const N: usize>(svcs: &[Service; N], i: usize) -> Fd {
+    svcs.get(i)
+        .and_then(|svc| svc.logger_fd(svcs))
+        .unwrap_or_else(|| Fd::from_raw(CONSOLE_OUT.load(Ordering::Relaxed)))
+}
+
+/// Create a logger's stdin pipe
+///
+/// With `LINE_BUFFER_CONSOLE`, connate writes producers' lines into the pipe itself, so its write
+/// end is non-blocking such that a logger which falls behind has lines dropped rather than
+/// stalling connate.  Producers writing to the pipe directly open it anew to block as usual.
+pub fn new_logger_pipe() -> Result<(Fd, Fd), Errno> {
+    let (read_fd, write_fd) = Fd::new_pipe(OpenFlags::O_CLOEXEC)?;
+    if <Connate as Config>::LINE_BUFFER_CONSOLE
+        && let Err(e) = write_fd.set_nonblocking()
+    {
+        let _ = read_fd.close();
+        let _ = write_fd.close();
+        return Err(e);
+    }
+    Ok((read_fd, write_fd))
+}
+
+/// Create the service's console pipe if it needs one and does not yet have one
+///
+/// The pipe lasts as long as connate, across the service's restarts.  If creating it fails, the
+/// process inherits connate's stdout and stderr instead.
+pub fn create_console_pipe(svc: &mut Service) {
+    if svc.console_pipe.is_none() && uses_console_pipe(svc.cfg) {
+        svc.console_pipe = ConsolePipe::new().ok();
+    }
+}
+
+/// End any partial lines before connate exits or re-execs
+///
+/// Unread output stays in the pipes for a re-exec'd connate to pick up.
+pub fn flush_console_pipes<const N: usize>(svcs: &mut [Service; N]) {
+    for i in 0..N {
+        let out = console_out(svcs, i);
+        if let Some(pipe) = svcs.get_mut(i).and_then(|svc| svc.console_pipe.as_mut()) {
+            pipe.finish(&out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_all(fd: &Fd) -> String {
+        let mut buf = [0u8; 4096];
+        let n = fd.read(&mut buf).unwrap();
+        String::from_utf8(buf[..n].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn lines_from_different_services_stay_whole() {
+        let (out_read, out_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let mut a = ConsolePipe::new().unwrap();
+        let mut b = ConsolePipe::new().unwrap();
+
+        // Each service writes its line in pieces, as `step()` does, interleaved with the other
+        a.write_fd().write(b"[ ] ").unwrap();
+        a.forward(&out_write);
+        b.write_fd().write(b"[ ] ").unwrap();
+        b.forward(&out_write);
+        a.write_fd().write(b"mount /proc\n[X] ").unwrap();
+        a.forward(&out_write);
+        b.write_fd().write(b"hostname\n").unwrap();
+        b.forward(&out_write);
+        a.write_fd().write(b"mount /proc\n").unwrap();
+        a.forward(&out_write);
+
+        assert_eq!(
+            read_all(&out_read),
+            "[ ] mount /proc\n[ ] hostname\n[X] mount /proc\n"
+        );
+    }
+
+    #[test]
+    fn long_and_unfinished_lines_are_ended() {
+        let (out_read, out_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let mut pipe = ConsolePipe::new().unwrap();
+
+        let long = [b'x'; CONSOLE_LINE_SIZE + 3];
+        pipe.write_fd().write(&long).unwrap();
+        pipe.forward(&out_write);
+        let split = read_all(&out_read);
+        assert_eq!(split.len(), CONSOLE_LINE_SIZE + 1);
+        assert!(split.ends_with("x\n"));
+
+        // The rest of the long line, then a partial line when the process exits
+        pipe.write_fd().write(b"\nno newline").unwrap();
+        pipe.finish(&out_write);
+        assert_eq!(read_all(&out_read), "xxx\nno newline\n");
+
+        // Nothing left over to repeat
+        pipe.finish(&out_write);
+        pipe.write_fd().write(b"next\n").unwrap();
+        pipe.forward(&out_write);
+        assert_eq!(read_all(&out_read), "next\n");
+    }
+
+    #[test]
+    fn lines_are_dropped_rather_than_waited_on() {
+        let flags = OpenFlags::O_CLOEXEC | OpenFlags::O_NONBLOCK;
+        let (out_read, out_write) = Fd::new_pipe(flags).unwrap();
+        let mut pipe = ConsolePipe::new().unwrap();
+
+        // Fill the output, as a stalled terminal or a logger which fell behind would leave it
+        let fill = [b'.'; PIPE_BUF];
+        while out_write.write(&fill).is_ok() {}
+
+        pipe.write_fd().write(b"dropped\npartial").unwrap();
+        pipe.finish(&out_write);
+        let mut buf = [0u8; PIPE_BUF];
+        while out_read.read(&mut buf).is_ok_and(|n| n > 0) {}
+
+        // Once there is room again, lines go through whole
+        pipe.write_fd().write(b"kept\n").unwrap();
+        pipe.forward(&out_write);
+        assert_eq!(read_all(&out_read), "kept\n");
+    }
+
+    #[test]
+    fn producers_share_a_logger_a_line_at_a_time() {
+        const LOGGER_CFG: ServiceConfig = ServiceConfig {
+            name: b"logger",
+            is_logger: true,
+            ..ServiceConfig::TEST_DEFAULT
+        };
+        const PRODUCER_CFG: ServiceConfig = ServiceConfig {
+            name: b"producer",
+            log: Log::Service(0),
+            ..ServiceConfig::TEST_DEFAULT
+        };
+        let (logger_read, logger_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let mut svcs = [
+            Service::test(&LOGGER_CFG, State::Up),
+            Service::test(&PRODUCER_CFG, State::Up),
+            Service::test(&PRODUCER_CFG, State::Up),
+        ];
+        svcs[0].stdin_pipe = Some((logger_read.clone(), logger_write.clone()));
+        assert_eq!(console_out(&svcs, 0).as_raw(), STDOUT.as_raw());
+        assert_eq!(console_out(&svcs, 1).as_raw(), logger_write.as_raw());
+
+        let mut a = ConsolePipe::new().unwrap();
+        let mut b = ConsolePipe::new().unwrap();
+        let out = console_out(&svcs, 1);
+
+        // One producer exits mid-line while the other carries on.  Neither runs into the other,
+        // and a complete last line is not followed by an empty one.
+        a.write_fd().write(b"partial").unwrap();
+        a.forward(&out);
+        b.write_fd().write(b"whole\n").unwrap();
+        b.forward(&out);
+        a.finish(&out);
+        b.finish(&out);
+
+        assert_eq!(read_all(&logger_read), "whole\npartial\n");
+    }
+}
@@ -0,0 +1,934 @@
+use super::cgroup::{read_cgroup_usage, set_cgroup_frozen};
+use super::console::flush_console_pipes;
+use super::next_state::*;
+use super::session::*;
+use super::set_pipes_cloexec;
+use crate::config::{Config, Connate};
+use crate::constants::{IPC_PROTOCOL_VERSION, MSG_EXE_PATH_SIZE, MSG_PATH_SIZE, VERSION};
+use crate::internal_api::*;
+use crate::ipc::*;
+use crate::os::*;
+use crate::types::*;
+
+/// Handle an IPC request from conctl or a supervisor
+pub fn handle_request<L: ServiceLookup, const N: usize>(
+    mut svcs: &mut [Service; N],
+    ipc_server: &mut IpcServer,
+    session_fd: &mut SessionFd,
+    now: timespec,
+    exec_time: timespec,
+    boot_summary: Option<(u32, u32)>,
+) {
+    use Target::*;
+
+    let mut exe_buf = [0u8; MSG_EXE_PATH_SIZE];
+    // Nothing to respond to, e.g. a client locked the pipe then died before writing
+    let Some(request) = ipc_server.receive() else {
+        return;
+    };
+    let response = match request {
+        Request::Exec(cstr) => {
+            // Save state into memfd before exec'ing
+            flush_console_pipes(svcs);
+            if set_pipes_cloexec(svcs, false).is_err() || session_fd.save(svcs).is_err() {
+                let _ = set_pipes_cloexec(svcs, true);
+                ipc_server.respond(Response::Failed);
+                return;
+            }
+            // Exec the new binary.
+            //
+            // If this returns, exec failed.
+            // (successful exec replaces the process and never returns)
+            let _ = if cstr.is_empty() {
+                crate::os::exec_self()
+            } else {
+                crate::os::exec_filepath(cstr)
+            };
+            let _ = set_pipes_cloexec(svcs, true);
+            ipc_server.respond(Response::Failed);
+            return;
+        }
+        Request::QueryByIndexStatus(i) => match svcs.get(i) {
+            Some(svc) => Response::Status(
+                svc.state,
+                svc.target,
+                svc.pid,
+                svc.last_exit(),
+                now.secs_since(svc.time),
+            ),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryStatusAll(start) => {
+            let mut batch = StatusBatchWriter::new();
+            let mut done = true;
+            for svc in svcs.iter().skip(start) {
+                let record = StatusRecord {
+                    name: svc.cfg.name,
+                    state: svc.state,
+                    target: svc.target,
+                    pid: svc.pid,
+                    last_exit: svc.last_exit(),
+                    time: now.secs_since(svc.time),
+                };
+                if batch.push(&record).is_err() {
+                    done = false;
+                    break;
+                }
+            }
+            // A name too long to share a message with the batch framing leaves conctl to fall
+            // back to per-service queries
+            if batch.is_empty() && !done {
+                ipc_server.respond(Response::Failed);
+            } else {
+                ipc_server.respond(Response::StatusBatch(done, batch.as_slice()));
+            }
+            return;
+        }
+        Request::QueryByIndexName(i) => match svcs.get(i) {
+            Some(svc) => Response::Name(svc.cfg.name),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByIndexState(i) => match svcs.get(i) {
+            Some(svc) => Response::State(svc.state),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByIndexTarget(i) => match svcs.get(i) {
+            Some(svc) => Response::Target(svc.target),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryStartDuration(i) => match svcs.get(i).map(|svc| svc.start_duration_millis) {
+            Some(Some(millis)) => Response::Number(millis),
+            Some(None) => Response::FieldIsNone,
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByIndexPid(i) => match svcs.get(i).map(|svc| svc.pid) {
+            Some(Some(pid)) => Response::Pid(pid),
+            Some(None) => Response::FieldIsNone,
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByIndexExitCode(i) => match svcs.get(i) {
+            Some(Service {
+                exit_code: Some(value),
+                exit_expected,
+                ..
+            }) => Response::ExitCode(*value, *exit_expected),
+            Some(_) => Response::FieldIsNone,
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByIndexAttemptCount(i) => match svcs.get(i) {
+            Some(svc) => Response::AttemptCount(svc.attempt_count as u64),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByIndexTime(i) => match svcs.get(i) {
+            Some(svc) => Response::Time(now.secs_since(svc.time)),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameStatus(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => Response::Status(
+                svc.state,
+                svc.target,
+                svc.pid,
+                svc.last_exit(),
+                now.secs_since(svc.time),
+            ),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameNamedStatus(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => Response::NamedStatus(
+                svc.cfg.name,
+                svc.state,
+                svc.target,
+                svc.pid,
+                svc.last_exit(),
+                now.secs_since(svc.time),
+            ),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameState(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => Response::State(svc.state),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameTarget(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => Response::Target(svc.target),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNamePid(name) => match svcs.find_by_name::<L>(name).map(|svc| svc.pid) {
+            Some(Some(pid)) => Response::Pid(pid),
+            Some(None) => Response::FieldIsNone,
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameExitCode(name) => match svcs.find_by_name::<L>(name) {
+            Some(Service {
+                exit_code: Some(value),
+                exit_expected,
+                ..
+            }) => Response::ExitCode(*value, *exit_expected),
+            Some(_) => Response::FieldIsNone,
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameAttemptCount(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => Response::AttemptCount(svc.attempt_count as u64),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameTime(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => Response::Time(now.secs_since(svc.time)),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameSince(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match get_time_realtime() {
+                Ok(realtime) => Response::Timestamp(wall_clock_at(svc.time, now, realtime)),
+                Err(_) => Response::Failed,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryFailReason(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match (svc.state, svc.fail_reason) {
+                (State::Failed | State::Retrying | State::CannotStop, Some(reason)) => {
+                    Response::FailReason(reason)
+                }
+                // Stuck waiting to stop, but left running
+                (State::WaitingToStop, Some(reason)) if svc.blocked_by.is_some() => {
+                    Response::FailReason(reason)
+                }
+                _ => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryProgress(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match &svc.progress {
+                Some(progress) => Response::Progress(progress.as_bytes()),
+                None => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameUsage(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match svc.cfg.cgroup.map(read_cgroup_usage) {
+                Some(Ok(usage)) => Response::Usage(usage.memory_bytes, usage.cpu_usec),
+                Some(Err(_)) => Response::Failed,
+                None => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryNeeds(i, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match svc.cfg.needs.get(i).and_then(|&i| svcs.get(i)) {
+                Some(dep) => Response::Name(dep.cfg.name),
+                None => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryWants(i, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match svc.cfg.wants.get(i).and_then(|&i| svcs.get(i)) {
+                Some(dep) => Response::Name(dep.cfg.name),
+                None => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryConflicts(i, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match svc.cfg.conflicts.get(i).and_then(|&i| svcs.get(i)) {
+                Some(dep) => Response::Name(dep.cfg.name),
+                None => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryGroups(i, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match svc.cfg.groups.get(i).and_then(|&i| svcs.get(i)) {
+                Some(dep) => Response::Name(dep.cfg.name),
+                None => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryBlockers(i, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => match svc
+                .blocked_by
+                // A Failed service reports what it gave up waiting on, while a waiting one's
+                // blockers are computed live
+                .filter(|_| matches!(svc.state, State::Failed))
+                .into_iter()
+                .chain(blockers(svc, svcs))
+                .nth(i)
+                .and_then(|i| svcs.get(i))
+            {
+                Some(dep) => Response::Name(dep.cfg.name),
+                None => Response::FieldIsNone,
+            },
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryLogsFor(i, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => {
+                let index = svc.cfg.index;
+                match svcs
+                    .iter()
+                    .filter(|src| matches!(src.cfg.log, Log::Service(log) if log == index))
+                    .nth(i)
+                {
+                    Some(src) => Response::Name(src.cfg.name),
+                    None => Response::FieldIsNone,
+                }
+            }
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryEnv(i, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => env_var_response(svc.cfg, i),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByIndexLog(i) => match svcs.get(i) {
+            Some(svc) => svc.cfg.log.as_response(svcs),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryByNameLog(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => svc.cfg.log.as_response(svcs),
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryConfig(field, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => svc.cfg.field_as_response(field),
+            None => Response::ServiceNotFound,
+        },
+        Request::IsolateBootTarget(name) => match L::boot_target(name) {
+            Some(members) => isolate::<L, N>(svcs, members, now),
+            None => Response::ServiceNotFound,
+        },
+        Request::IsolateEmergency => match L::emergency_target() {
+            // Bringing down everything with nothing to log in on is unlikely to be intended
+            [] => Response::FieldIsNone,
+            members => isolate::<L, N>(svcs, members, now),
+        },
+        Request::Freeze(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => set_frozen(svc.cfg.cgroup, true),
+            None => Response::ServiceNotFound,
+        },
+        Request::Thaw(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => set_frozen(svc.cfg.cgroup, false),
+            None => Response::ServiceNotFound,
+        },
+        Request::ForceReap(name) => match svcs.find_by_name_mut::<L>(name) {
+            Some(svc) => force_reap(svc),
+            None => Response::ServiceNotFound,
+        },
+        Request::SetTargetUp(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => set_target(svcs, svc.cfg.index, now, Up),
+            None => Response::ServiceNotFound,
+        },
+        Request::SetTargetDown(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => set_target(svcs, svc.cfg.index, now, Down),
+            None => Response::ServiceNotFound,
+        },
+        Request::SetTargetRestart(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => set_target(svcs, svc.cfg.index, now, Restart),
+            None => Response::ServiceNotFound,
+        },
+        Request::SetTargetOnce(name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => set_target(svcs, svc.cfg.index, now, Once),
+            None => Response::ServiceNotFound,
+        },
+        Request::SetTargetAll(target) => set_target_all(svcs, now, target),
+        Request::PreviewTarget(n, target, name) => match svcs.find_by_name::<L>(name) {
+            Some(svc) => preview_target(svcs, svc.cfg.index, target, n),
+            None => Response::ServiceNotFound,
+        },
+        Request::Isolate(name) => match svcs.find_by_name::<L>(name) {
+            // Only groups make sense to isolate; anything else is likely a typo with sweeping
+            // consequences.
+            Some(svc) if svc.cfg.groups.is_empty() => Response::Failed,
+            Some(svc) => isolate::<L, N>(svcs, &[svc.cfg.index], now),
+            None => Response::ServiceNotFound,
+        },
+        #[cfg(feature = "settle")]
+        Request::QuerySettleFd(name) => match svcs.find_by_name_mut::<L>(name) {
+            Some(svc) => {
+                // Create settle pipe lazily if it doesn't exist
+                if svc.settle_pipe.is_none() {
+                    match Fd::new_pipe(OpenFlags::O_NONBLOCK) {
+                        Ok(pipe) => svc.settle_pipe = Some(pipe),
+                        Err(_) => {
+                            ipc_server.respond(Response::Failed);
+                            return;
+                        }
+                    }
+                }
+                // Return the read FD number
+                match svc.settle_pipe {
+                    Some((ref read_fd, _)) => Response::SettleFd(read_fd.as_raw()),
+                    None => Response::Failed,
+                }
+            }
+            None => Response::ServiceNotFound,
+        },
+        #[cfg(not(feature = "settle"))]
+        Request::QuerySettleFd(_) => Response::SettleDisabled,
+        // A supervisor has forked the service process and provided us the pid.  Look up via name.
+        Request::ServiceStarting(pid, name) => match svcs.find_by_name_mut::<L>(name) {
+            Some(svc) => {
+                if pid < 2 {
+                    Response::InvalidRequest
+                } else {
+                    svc.pid = Some(pid);
+                    svc.dirty = true;
+                    Response::Okay
+                }
+            }
+            None => Response::ServiceNotFound,
+        },
+        // Service ran `conctl ready` or `notify_ready()`.  Notably, this doesn't require
+        // submitting the service's own name.
+        //
+        // These mechanisms searches up the process tree until it finds connate, then provides its
+        // ancestor closest to connate.  This could be either the service's main pid or the
+        // supervisor.  Should the pid instead be of a nested process, its ancestry is searched
+        // for the service.
+        Request::ServiceReady(pid) => match svcs.find_by_direct_or_supervisor_pid_mut(pid) {
+            Some(svc) => {
+                svc.ready = true;
+                svc.dirty = true;
+                Response::Okay
+            }
+            None => Response::ServiceNotFound,
+        },
+        // A service's process reported what it is doing.  As with `ServiceReady`, the service is
+        // found via the process's ancestry.
+        //
+        // Only retained through the slow transitional states `conctl status` shows it for.
+        Request::ServiceProgress(pid, msg) => {
+            match svcs.find_by_direct_or_supervisor_pid_mut(pid) {
+                Some(svc) => {
+                    if svc.state.reports_progress() {
+                        svc.progress = Some(Progress::new(msg));
+                    }
+                    Response::Okay
+                }
+                None => Response::ServiceNotFound,
+            }
+        }
+        // A supervisor witnessed its service daemonize, which both indicates readiness and updates
+        // the pid.
+        Request::DaemonReady(pid, name) => match svcs.find_by_name_mut::<L>(name) {
+            Some(svc) => {
+                if pid < 2 {
+                    Response::InvalidRequest
+                } else {
+                    svc.pid = Some(pid);
+                    svc.ready = true;
+                    svc.dirty = true;
+                    Response::Okay
+                }
+            }
+            None => Response::ServiceNotFound,
+        },
+        Request::QueryVersion => Response::Version(IPC_PROTOCOL_VERSION, VERSION.as_bytes()),
+        Request::Ping => Response::Okay,
+        Request::QueryConnateInfo => {
+            // An upgraded binary replaced on disk shows as "<path> (deleted)".  A path too long
+            // for the buffer is cut short rather than failing the whole query.
+            let len = readlink(c"/proc/self/exe", &mut exe_buf).unwrap_or(0);
+            Response::ConnateInfo(
+                getpid(),
+                now.secs_since(exec_time),
+                exe_buf.get(..len).unwrap_or(&[]),
+            )
+        }
+        Request::QueryBootComplete => match boot_summary {
+            Some((up, failed)) => Response::BootComplete(up, failed),
+            None => Response::BootPending,
+        },
+        Request::Invalid => Response::InvalidRequest,
+    };
+
+    ipc_server.respond(response);
+}
+
+/// Set the target of every service in one pass
+///
+/// Restart only applies to services which are not targeting Down.  Restarting an intentionally
+/// stopped service would instead start it, which is not what an operator restarting everything
+/// wants.
+pub fn set_target_all<'a, const N: usize>(
+    svcs: &mut [Service; N],
+    now: timespec,
+    target: Target,
+) -> Response<'a> {
+    for i in 0..N {
+        let Some(svc) = svcs.get(i) else {
+            return Response::ServiceNotFound;
+        };
+        if matches!(target, Target::Restart) && matches!(svc.target, Target::Down) {
+            continue;
+        }
+
+        let response = set_target(svcs, i, now, target);
+        if response.cmd_return_failed() {
+            return response;
+        }
+    }
+
+    Response::Okay
+}
+
+/// Set the target of every root and everything it pulls up to Up and every other service to Down
+///
+/// The configured system service and everything it pulls up are left as they are such that
+/// isolating never tears down the system itself.
+pub fn isolate<'a, L: ServiceLookup, const N: usize>(
+    svcs: &mut [Service; N],
+    roots: &[usize],
+    now: timespec,
+) -> Response<'a> {
+    // target_up_propagate_up is already the transitive closure of everything a service pulls up
+    let mut keep = [false; N];
+    let system = <Connate as Config>::SYSTEM_SERVICE
+        .and_then(|name| svcs.find_by_name::<L>(name.as_bytes()))
+        .map(|svc| svc.cfg.index);
+    for &root in roots.iter().chain(system.iter()) {
+        let Some(cfg) = svcs.get(root).map(|svc| svc.cfg) else {
+            return Response::ServiceNotFound;
+        };
+        for &i in core::iter::once(&root).chain(cfg.target_up_propagate_up) {
+            if let Some(keep) = keep.get_mut(i) {
+                *keep = true;
+            }
+        }
+    }
+
+    // Every dependent of a service brought down here is itself brought down here, so there is no
+    // need to propagate.  Doing so would also bring down kept members of groups which are not.
+    for (i, &keep) in keep.iter().enumerate() {
+        let Some(svc) = svcs.get(i) else {
+            return Response::ServiceNotFound;
+        };
+        if keep || matches!(svc.target, Target::Down) {
+            continue;
+        }
+        if matches!(svc.state, State::Failed) {
+            NextState::Down.apply(svcs, i, now);
+        }
+        let Some(svc) = svcs.get_mut(i) else {
+            return Response::ServiceNotFound;
+        };
+        svc.target = Target::Down;
+        svc.dirty = true;
+    }
+
+    for &root in roots {
+        let response = set_target(svcs, root, now, Target::Up);
+        if response.cmd_return_failed() {
+            return response;
+        }
+    }
+
+    Response::Okay
+}
+
+/// Set the target of a service, propagating to its dependencies and dependents as needed
+///
+/// If the service was Failed, responds with the number of attempts it made before failing.
+/// Breaking out of Failed resets `attempt_count` such that retry logic starts afresh, so this is
+/// the last chance to report it.
+pub fn set_target<'a, const N: usize>(
+    svcs: &mut [Service; N],
+    index: usize,
+    now: timespec,
+    target: Target,
+) -> Response<'a> {
+    let Some(svc) = svcs.get(index) else {
+        return Response::ServiceNotFound;
+    };
+
+    // If the service was in a failed state, it will not automatically transition.
+    // Explicitly (re)setting the target here breaks it out of the failed state
+    let failed_attempt_count = matches!(svc.state, State::Failed).then_some(svc.attempt_count);
+    if failed_attempt_count.is_some() {
+        NextState::Down.apply(svcs, index, now);
+    }
+
+    let Some(plan) = TargetPlan::new(svcs, index, target) else {
+        return Response::ServiceNotFound;
+    };
+
+    // Update targets and note as dirty
+    for (svc, (&target, &touched)) in svcs
+        .iter_mut()
+        .zip(plan.targets.iter().zip(plan.touched.iter()))
+    {
+        if touched {
+            svc.target = target;
+            svc.dirty = true;
+        }
+    }
+
+    match failed_attempt_count {
+        Some(count) => Response::AttemptCount(count as u64),
+        None => Response::Okay,
+    }
+}
+
+/// The wall-clock time, in seconds since the Unix epoch, at monotonic time `then`
+///
+/// Derived from how long ago `then` was rather than recorded when it happened, such that a
+/// realtime clock which jumps (e.g. NTP syncing late in boot) moves past timestamps along with it
+/// instead of leaving them stranded in 1970.
+fn wall_clock_at(then: timespec, now: timespec, realtime: timespec) -> i64 {
+    realtime.tv_sec.saturating_sub(now.secs_since(then))
+}
+
+/// The targets every service would have after setting the target of one service
+///
+/// Computed separately from applying it such that `conctl --dry-run` can preview the change.
+pub struct TargetPlan<const N: usize> {
+    pub targets: [Target; N],
+    /// Whether the service's target is (re)set, even if to its current value
+    pub touched: [bool; N],
+}
+
+impl<const N: usize> TargetPlan<N> {
+    /// Returns None if a service index is out of range
+    pub fn new(svcs: &[Service; N], index: usize, target: Target) -> Option<Self> {
+        let mut plan = Self {
+            targets: svcs.each_ref().map(|svc| svc.target),
+            touched: [false; N],
+        };
+        let cfg = svcs.get(index)?.cfg;
+
+        plan.set(index, target)?;
+
+        // Propagate target changes to dependents and dependencies to ensure this service isn't
+        // blocked on proceeding to its new target
+        match target {
+            Target::Up | Target::Once => {
+                // When going Up or Once:
+                // - All dependencies (needs/wants/groups/log service) should go Up
+                // - All conflicts should go Down
+                for &i in cfg.target_up_propagate_up {
+                    plan.set(i, Target::Up)?;
+                }
+                for &i in cfg.target_up_propagate_down {
+                    plan.set(i, Target::Down)?;
+                }
+            }
+            Target::Down => {
+                // When going Down
+                // - All dependents (services that need/want/group this) should go Down
+                for &i in cfg.target_down_propagate_down {
+                    plan.set(i, Target::Down)?;
+                }
+            }
+            Target::Restart => {
+                // When Restarting:
+                // - We're immediately going down, but we'll eventually go back up.
+                // - To handle going down, all dependents should immediately go Down.
+                //   - However, they may then go back up after this service goes back up.
+                //   - If the dependent target is Up, change to Restart so that it'll go down to
+                //   unblock us going down, but go back up after this service does and resume the
+                //   prior upward target.
+                //   - Otherwise, go down.
+                // - To handle going back up, all dependencies should eventually go Up.
+                //   - This may be immediately Up or after going down i.e. Restart.
+                // - To handle going back up, all conflicts should go Down.
+                for &i in cfg.target_down_propagate_down {
+                    match plan.targets.get(i)? {
+                        Target::Down | Target::Restart => {}
+                        Target::Up => plan.set(i, Target::Restart)?,
+                        Target::Once => plan.set(i, Target::Down)?,
+                    }
+                }
+                for &i in cfg.target_up_propagate_up {
+                    match plan.targets.get(i)? {
+                        Target::Up | Target::Restart | Target::Once => {}
+                        Target::Down => plan.set(i, Target::Up)?,
+                    }
+                }
+                for &i in cfg.target_up_propagate_down {
+                    plan.set(i, Target::Down)?;
+                }
+            }
+        }
+
+        // Group members inherit the new target
+        for &i in cfg.groups {
+            plan.set(i, target)?;
+        }
+
+        Some(plan)
+    }
+
+    fn set(&mut self, i: usize, target: Target) -> Option<()> {
+        *self.targets.get_mut(i)? = target;
+        *self.touched.get_mut(i)? = true;
+        Some(())
+    }
+
+    /// The `n`th service whose target would change, along with its new target
+    pub fn nth_change(&self, svcs: &[Service; N], n: usize) -> Option<(usize, Target)> {
+        svcs.iter()
+            .zip(self.targets.iter())
+            .enumerate()
+            .filter(|(_, (svc, target))| svc.target.as_byte() != target.as_byte())
+            .map(|(i, (_, &target))| (i, target))
+            .nth(n)
+    }
+}
+
+/// The `n`th service whose target would change if the service's target were set
+///
+/// FieldIsNone once past the last such service.
+fn preview_target<const N: usize>(
+    svcs: &[Service; N],
+    index: usize,
+    target: Target,
+    n: usize,
+) -> Response<'static> {
+    let Some(plan) = TargetPlan::new(svcs, index, target) else {
+        return Response::ServiceNotFound;
+    };
+    match plan
+        .nth_change(svcs, n)
+        .and_then(|(i, to)| Some((svcs.get(i)?, to)))
+    {
+        Some((svc, to)) => Response::TargetChange(svc.target, to, svc.cfg.name),
+        None => Response::FieldIsNone,
+    }
+}
+
+/// Freeze or thaw a service's cgroup without touching its state or target
+fn set_frozen<'a>(cgroup: Option<&CStr>, frozen: bool) -> Response<'a> {
+    match cgroup.map(|cgroup| set_cgroup_frozen(cgroup, frozen)) {
+        Some(Ok(())) => Response::Okay,
+        Some(Err(_)) => Response::Failed,
+        None => Response::FieldIsNone,
+    }
+}
+
+/// Release a CannotStop service whose process has since died, e.g. killed by an administrator
+///
+/// connate only learns of the exits of its own descendants, so a process which went away some
+/// other way would otherwise leave the service CannotStop indefinitely.  Responds with the state
+/// if the service is not CannotStop, or with the PID if its process or supervisor still exists.
+///
+/// Existence is checked by PID alone, so if the PID has since been reused by an unrelated process
+/// the service is refused as though its own were still running.  Releasing it then requires
+/// waiting for that process to exit.  This errs toward leaving the service CannotStop rather than
+/// respawning it while a process it may have leaked still runs.
+fn force_reap(svc: &mut Service) -> Response<'static> {
+    if !matches!(svc.state, State::CannotStop) {
+        return Response::State(svc.state);
+    }
+    if let Some(pid) = [svc.pid, svc.supervisor_pid]
+        .into_iter()
+        .flatten()
+        .find(|&pid| process_exists(pid))
+    {
+        return Response::Pid(pid);
+    }
+    svc.pid = None;
+    svc.supervisor_pid = None;
+    // Fails or retries as though the process had exited on its own
+    svc.dirty = true;
+    Response::Okay
+}
+
+/// The `i`th variable of the environment the service's processes are executed with
+fn env_var_response(cfg: &'static ServiceConfig, i: usize) -> Response<'static> {
+    let var = if cfg.clear_env && cfg.inherit_env.is_empty() {
+        cfg.env.get(i).map(|var| var.to_bytes())
+    } else {
+        // Mirror merge_envp() as called when spawning the service
+        //
+        // Safety: config CStrs are static and the startup envp is ABI-provided
+        let merged = unsafe {
+            MergedEnvp::new(
+                cfg.env.iter().map(|var| var.as_ptr()),
+                RawEnvp::from_raw(startup_envp()),
+                cfg.clear_env.then_some(cfg.inherit_env),
+            )
+        };
+        merged
+            .take(MAX_MERGED_ENV_COUNT)
+            .nth(i)
+            .map(|var| unsafe { CStr::from_ptr(var) }.to_bytes())
+    };
+
+    match var {
+        Some(var) if var.len() <= MSG_PATH_SIZE => Response::EnvVar(var),
+        // Inherited from connate and too long to fit in a response
+        Some(_) => Response::Failed,
+        None => Response::FieldIsNone,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: ServiceConfig = ServiceConfig {
+        name: b"getty",
+        init_target: Target::Down,
+        ..ServiceConfig::TEST_DEFAULT
+    };
+
+    const NOW: timespec = timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    fn service(state: State, attempt_count: u32) -> Service {
+        Service {
+            attempt_count,
+            exit_code: Some(1),
+            has_run: true,
+            ..Service::test(&CFG, state)
+        }
+    }
+
+    #[test]
+    fn up_on_failed_service_reports_attempt_count() {
+        let mut svcs = [service(State::Failed, 3)];
+
+        let response = set_target(&mut svcs, 0, NOW, Target::Up);
+        assert!(matches!(response, Response::AttemptCount(3)));
+
+        // The count is reset such that the service gets a fresh set of retries
+        let [svc] = &svcs;
+        assert!(matches!(svc.state, State::Down));
+        assert!(matches!(svc.target, Target::Up));
+        assert_eq!(svc.attempt_count, 0);
+        assert!(svc.dirty);
+
+        // Nothing to report once it is no longer failed
+        let response = set_target(&mut svcs, 0, NOW, Target::Up);
+        assert!(matches!(response, Response::Okay));
+    }
+
+    #[test]
+    fn last_exit_distinguishes_never_run_from_running() {
+        let mut svc = service(State::Down, 0);
+        svc.exit_code = None;
+        svc.has_run = false;
+        assert_eq!(svc.last_exit(), LastExit::NeverRun);
+
+        // Spawned, but the first process has yet to exit
+        svc.has_run = true;
+        assert_eq!(svc.last_exit(), LastExit::NotYet);
+
+        svc.exit_code = Some(0);
+        assert_eq!(svc.last_exit(), LastExit::Code(0, false));
+    }
+
+    #[test]
+    fn boot_completes_once_targeted_services_settle() {
+        let mut svcs = [
+            service(State::Up, 0),
+            service(State::Starting, 0),
+            service(State::Stopping, 0),
+        ];
+        // Services on their way down have no bearing on boot
+        svcs[2].target = Target::Down;
+        let svcs = &mut svcs;
+        assert!(!svcs.all_targets_settled());
+
+        svcs[1].state = State::Failed;
+        assert!(svcs.all_targets_settled());
+    }
+
+    /// Fork a chain of `depth` processes, returning the first's pid, the last's pid, and an fd
+    ///
+    /// Every process in the chain exits once the returned fd is closed, after reaping its own
+    /// child.
+    fn fork_chain(depth: usize) -> (pid_t, pid_t, Fd) {
+        let (release_read, release_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let (pid_read, pid_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let first = match fork().unwrap() {
+            ForkResult::Child => {
+                let mut child = None;
+                for _ in 1..depth {
+                    match fork().unwrap() {
+                        ForkResult::Child => child = None,
+                        ForkResult::Parent(pid) => {
+                            child = Some(pid);
+                            break;
+                        }
+                    }
+                }
+                // Only the last process reports its pid
+                if child.is_none() {
+                    let _ = pid_write.write_all(&getpid().to_ne_bytes());
+                }
+                let _ = pid_write.close();
+                let _ = release_write.close();
+                let _ = release_read.read(&mut [0u8; 1]);
+                if let Some(pid) = child {
+                    let _ = waitpid(pid, WaitPidOptions::empty());
+                }
+                exit(0);
+            }
+            ForkResult::Parent(pid) => pid,
+        };
+        let _ = pid_write.close();
+        let _ = release_read.close();
+
+        let mut buf = [0u8; size_of::<pid_t>()];
+        assert_eq!(pid_read.read(&mut buf).unwrap(), buf.len());
+        let _ = pid_read.close();
+        (first, pid_t::from_ne_bytes(buf), release_write)
+    }
+
+    #[test]
+    fn ready_from_nested_process_finds_service() {
+        // Only the supervisor's pid is known while Starting a Daemonize service
+        let (supervisor, nested, release) = fork_chain(3);
+
+        let mut svcs = [service(State::Starting, 0)];
+        let [svc] = &mut svcs;
+        svc.supervisor_pid = Some(supervisor);
+        let mut svcs = &mut svcs;
+
+        let found = svcs
+            .find_by_direct_or_supervisor_pid_mut(nested)
+            .map(|svc| svc.cfg.index);
+        let direct = svcs
+            .find_by_direct_or_supervisor_pid_mut(supervisor)
+            .map(|svc| svc.cfg.index);
+        // Not descended from any service; the walk ends at init
+        let unrelated = svcs
+            .find_by_direct_or_supervisor_pid_mut(getpid())
+            .map(|svc| svc.cfg.index);
+
+        let _ = release.close();
+        let _ = waitpid(supervisor, WaitPidOptions::empty()).unwrap();
+
+        assert_eq!(found, Some(0));
+        assert_eq!(direct, Some(0));
+        assert_eq!(unrelated, None);
+    }
+
+    #[test]
+    fn force_reap_releases_only_dead_processes() {
+        let (pid, _, release) = fork_chain(1);
+        let mut svc = service(State::CannotStop, 1);
+        svc.pid = Some(pid);
+
+        assert!(matches!(force_reap(&mut svc), Response::Pid(p) if p == pid));
+        assert_eq!(svc.pid, Some(pid));
+        assert!(!svc.dirty);
+
+        // Reaped elsewhere, as when not connate's child
+        let _ = release.close();
+        let _ = waitpid(pid, WaitPidOptions::empty()).unwrap();
+        assert!(matches!(force_reap(&mut svc), Response::Okay));
+        assert_eq!(svc.pid, None);
+        assert!(svc.dirty);
+
+        // A supervisor still running keeps the service CannotStop too
+        let (pid, _, release) = fork_chain(1);
+        let mut svc = service(State::CannotStop, 1);
+        svc.supervisor_pid = Some(pid);
+        assert!(matches!(force_reap(&mut svc), Response::Pid(p) if p == pid));
+        assert_eq!(svc.supervisor_pid, Some(pid));
+        assert!(!svc.dirty);
+
+        let _ = release.close();
+        let _ = waitpid(pid, WaitPidOptions::empty()).unwrap();
+        assert!(matches!(force_reap(&mut svc), Response::Okay));
+        assert!(!svc.has_pid());
+        assert!(svc.dirty);
+
+        let mut svc = service(State::Up, 0);
+        assert!(matches!(force_reap(&mut svc), Response::State(State::Up)));
+    }
+}
@@ -0,0 +1,348 @@
+use super::console::{console_out, flush_console_pipes};
+use super::handle_request::set_target;
+use super::session::*;
+use super::set_pipes_cloexec;
+use crate::config::{Config, Connate, CtrlAltDel};
+use crate::constants::REAP_BATCH_SIZE;
+use crate::err::*;
+use crate::internal_api::*;
+use crate::os::*;
+use crate::types::*;
+
+pub fn handle_signal<L: ServiceLookup, const N: usize>(
+    signalfd: &mut SignalFd,
+    svcs: &mut [Service; N],
+    shutting_down: &mut bool,
+    session_fd: &mut SessionFd,
+    now: timespec,
+) {
+    match signalfd.read_signal() {
+        // As init, the kernel sends SIGINT on Ctrl-Alt-Del if configured to do so
+        Ok(Signal::SIGINT) if getpid() == 1 => {
+            if matches!(<Connate as Config>::CTRL_ALT_DEL, CtrlAltDel::Reboot) {
+                set_system_service_target::<L, N>(svcs, now, Target::Restart);
+            }
+        }
+        // As init, UPS daemons such as apcupsd and nut send SIGPWR on power failure
+        Ok(Signal::SIGPWR) => {
+            if getpid() == 1 {
+                set_system_service_target::<L, N>(svcs, now, Target::Down);
+            }
+        }
+        // Shutdown request, such as a user session's connate at logout
+        Ok(Signal::SIGINT) | Ok(Signal::SIGTERM) => {
+            request_shutdown(svcs, shutting_down, getpid() == 1)
+        }
+        // Config reload request
+        Ok(Signal::SIGHUP) => {
+            flush_console_pipes(svcs);
+            if set_pipes_cloexec(svcs, false).is_ok() && session_fd.save(svcs).is_ok() {
+                let _ = exec_self();
+            }
+            let _ = set_pipes_cloexec(svcs, true);
+        }
+        // Child process died
+        Ok(Signal::SIGCHLD) => {
+            // Only a single SIGCHLD is pending for any number of dead children.  If the batch
+            // filled up, re-raise it such that the rest are reaped on a later pass rather than
+            // left as zombies.
+            if handle_sigchld(svcs) == REAP_BATCH_SIZE {
+                let _ = kill(getpid(), Signal::SIGCHLD);
+            }
+        }
+        // SIGKILL cannot be caught/handled. If we receive it, process just dies.
+        Ok(Signal::SIGKILL) => unsafe { core::hint::unreachable_unchecked() },
+        // Ignore unknown signals
+        Ok(Signal::UNRECOGNIZED) | Err(_) => {}
+    }
+}
+
+/// Bring every service down, after which the main loop exits
+///
+/// Ignored as init, where exiting would panic the kernel.  Rebooting or powering off goes through
+/// the system service instead.
+fn request_shutdown(svcs: &mut [Service], shutting_down: &mut bool, is_init: bool) {
+    if is_init {
+        return;
+    }
+    for svc in svcs.iter_mut() {
+        svc.target = Target::Down;
+        svc.dirty = true;
+    }
+    *shutting_down = true;
+}
+
+/// Cleanly reboot (Restart) or power off (Down) via the configured system service, whose cleanup
+/// performs the reboot or power off
+fn set_system_service_target<L: ServiceLookup, const N: usize>(
+    svcs: &mut [Service; N],
+    now: timespec,
+    target: Target,
+) {
+    let Some(name) = <Connate as Config>::SYSTEM_SERVICE else {
+        return;
+    };
+    let Some(index) = svcs
+        .find_by_name::<L>(name.as_bytes())
+        .map(|svc| svc.cfg.index)
+    else {
+        return;
+    };
+    let _ = set_target(svcs, index, now, target);
+}
+
+/// Reap up to `REAP_BATCH_SIZE` dead children, returning how many were reaped
+///
+/// The cap keeps a mass exit, such as every process dying at shutdown, from holding up requests
+/// and other signals until all of them are reaped.
+fn handle_sigchld<const N: usize>(mut svcs: &mut [Service; N]) -> usize {
+    let mut reaped = 0;
+
+    // Loop over all children that died:
+    // - If we recognize the child as a service, tag service as died for state transition logic
+    // - If we don't recognize it, just reap
+    while reaped < REAP_BATCH_SIZE {
+        // Wait for any child (-1) with WNOHANG
+        match waitpid(-1, WaitPidOptions::WNOHANG) {
+            Err(Errno::ECHILD) => break, // No (more) dead children
+            Err(e) => Err(e).or_abort("Unable to waitpid()"),
+            Ok((0, _)) => break, // No (more) dead children
+            Ok((pid, status)) => {
+                reaped += 1;
+
+                let exit_code = if wifexited(status) {
+                    wexitstatus(status)
+                } else if wifsignaled(status) {
+                    128 + wtermsig(status)
+                } else {
+                    // Stopped or continued, *not* killed
+                    // Ignore
+                    continue;
+                };
+
+                // Whose console pipe to end the exited process's output in
+                let mut exited = None;
+
+                if let Some(svc) = svcs.find_by_pid_mut(pid) {
+                    exited = Some(svc.cfg.index);
+                    svc.pid = None;
+                    svc.exit_code = Some(exit_code);
+                    svc.exit_expected = exit_expected(svc, exit_code);
+                    svc.dirty = true;
+                    if let Some((fd_read, fd_write)) = svc.stdin_pipe.take() {
+                        let _ = fd_read.close();
+                        let _ = fd_write.close();
+                    }
+                } else if let Some(svc) = svcs.find_by_supervisor_pid_mut(pid) {
+                    exited = Some(svc.cfg.index);
+                    svc.supervisor_pid = None;
+                    // If supervisor died, we can't reliably track the service's process.
+                    // Don't try to.  Assume it died.
+                    svc.pid = None;
+                    svc.supervisor_pid = None;
+                    svc.exit_code = Some(exit_code);
+                    svc.exit_expected = exit_expected(svc, exit_code);
+                    svc.dirty = true;
+                    if let Some((fd_read, fd_write)) = svc.stdin_pipe.take() {
+                        let _ = fd_read.close();
+                        let _ = fd_write.close();
+                    }
+                }
+                // Other else branch is an unexpected child.  We just reaped it; nothing else to
+                // do.
+
+                if let Some(i) = exited {
+                    let out = console_out(svcs, i);
+                    if let Some(pipe) = svcs.get_mut(i).and_then(|svc| svc.console_pipe.as_mut()) {
+                        pipe.finish(&out);
+                    }
+                }
+            }
+        }
+    }
+
+    reaped
+}
+
+/// Whether a service's exit is the result of connate intentionally stopping it
+///
+/// Stopping sends SIGTERM.  A supervisor receiving it SIGKILLs the main process and exits
+/// accordingly, so either signal is expected.  Dying to SIGKILL from ForceDown is not, as the
+/// service failed to stop when asked.
+fn exit_expected(svc: &Service, exit_code: c_int) -> bool {
+    matches!(svc.state, State::Stopping)
+        && (exit_code == 128 + Signal::SIGTERM as c_int
+            || exit_code == 128 + Signal::SIGKILL as c_int)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Settings;
+    use super::super::next_state::NextState;
+    use super::*;
+    use crate::constants::PANIC_EXIT_CODE;
+
+    const CFG: ServiceConfig = ServiceConfig {
+        name: b"agent",
+        ..ServiceConfig::TEST_DEFAULT
+    };
+
+    fn service(state: State, target: Target) -> Service {
+        Service {
+            target,
+            ..Service::test(&CFG, state)
+        }
+    }
+
+    #[test]
+    fn shutdown_request_brings_session_down() {
+        let mut svcs = [
+            service(State::Up, Target::Up),
+            service(State::Starting, Target::Once),
+            service(State::Down, Target::Down),
+        ];
+
+        // As init, the request is ignored
+        let mut shutting_down = false;
+        request_shutdown(&mut svcs, &mut shutting_down, true);
+        assert!(!shutting_down);
+        assert!(svcs.iter().all(|svc| !svc.dirty));
+        assert!(matches!(svcs[0].target, Target::Up));
+
+        request_shutdown(&mut svcs, &mut shutting_down, false);
+        assert!(shutting_down);
+        for svc in &svcs {
+            assert!(matches!(svc.target, Target::Down));
+            assert!(svc.dirty);
+        }
+    }
+
+    #[test]
+    fn mass_exit_is_reaped_in_bounded_batches() {
+        const CHILDREN: usize = REAP_BATCH_SIZE * 3 + 1;
+
+        // Reaped in a child of its own such that waitpid(-1) cannot take other tests' children
+        let pid = match fork().unwrap() {
+            ForkResult::Child => {
+                let (read_fd, write_fd) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+                let mut pids = [0; CHILDREN];
+                for pid in pids.iter_mut() {
+                    match fork().unwrap() {
+                        // Block until killed
+                        ForkResult::Child => {
+                            let _ = read_fd.read(&mut [0u8; 1]);
+                            exit(0);
+                        }
+                        ForkResult::Parent(child) => *pid = child,
+                    }
+                }
+                for &pid in &pids {
+                    let _ = kill(pid, Signal::SIGKILL);
+                }
+
+                // Re-enter as re-raised SIGCHLDs would until every child is reaped
+                let mut svcs: [Service; 0] = [];
+                let mut reaped = 0;
+                let mut batches_bounded = true;
+                let start = get_time_monotonic().unwrap();
+                while reaped < CHILDREN && get_time_monotonic().unwrap().millis_since(start) < 5000
+                {
+                    let batch = handle_sigchld(&mut svcs);
+                    batches_bounded &= batch <= REAP_BATCH_SIZE;
+                    reaped += batch;
+                }
+                let no_zombies = matches!(waitpid(-1, WaitPidOptions::WNOHANG), Err(Errno::ECHILD));
+
+                let _ = read_fd.close();
+                let _ = write_fd.close();
+                exit(if reaped == CHILDREN && batches_bounded && no_zombies {
+                    0
+                } else {
+                    1
+                });
+            }
+            ForkResult::Parent(pid) => pid,
+        };
+
+        let (_, status) = waitpid(pid, WaitPidOptions::empty()).unwrap();
+        assert_eq!(status, 0);
+    }
+
+    fn deliberately_panics() -> Result<(), Errno> {
+        panic!("deliberate")
+    }
+
+    #[test]
+    fn panicking_run_fn_is_logged_and_retried() {
+        const PANICS: ServiceConfig = ServiceConfig {
+            run: Run::Fn {
+                f: deliberately_panics,
+                log_overwrite: false,
+            },
+            log: Log::Inherit,
+            ..CFG
+        };
+
+        let (read_fd, write_fd) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+
+        // Spawned and reaped in a child of its own such that waitpid(-1) cannot take other tests'
+        // children
+        let pid = match fork().unwrap() {
+            ForkResult::Child => {
+                // The service inherits this as its log
+                let _ = write_fd.dup(STDERR.as_raw(), OpenFlags::empty());
+                // Stands in for the binaries' panic handler, which tests replace with std's
+                std::panic::set_hook(Box::new(|info| {
+                    let message = info.payload().downcast_ref::<&str>().copied();
+                    exit_on_panic(message.unwrap_or(""), info.location())
+                }));
+
+                let mut svcs = [Service::test(&PANICS, State::Down)];
+                svcs[0].dirty = true;
+                // Left over from a previous run, which spawning must not report as this one's
+                svcs[0].exit_code = Some(0);
+                let start = get_time_monotonic().unwrap();
+                let settle = |svcs: &mut [Service; 1]| {
+                    let now = get_time_monotonic().unwrap();
+                    while let Some(i) = svcs.find_dirty_index() {
+                        NextState::new(svcs, i, now, Settings::default()).apply(svcs, i, now);
+                    }
+                };
+                settle(&mut svcs);
+                let spawned = svcs[0].pid.is_some() && svcs[0].exit_code.is_none();
+
+                while svcs[0].pid.is_some()
+                    && get_time_monotonic().unwrap().millis_since(start) < 5000
+                {
+                    handle_sigchld(&mut svcs);
+                }
+                settle(&mut svcs);
+
+                // A panic is a failure like any other rather than fatal, so is retried
+                exit(
+                    if spawned
+                        && svcs[0].exit_code == Some(PANIC_EXIT_CODE)
+                        && matches!(svcs[0].state, State::Retrying)
+                        && svcs[0].attempt_count == 1
+                    {
+                        0
+                    } else {
+                        1
+                    },
+                );
+            }
+            ForkResult::Parent(pid) => pid,
+        };
+        let _ = write_fd.close();
+
+        let (_, status) = waitpid(pid, WaitPidOptions::empty()).unwrap();
+        assert_eq!(status, 0);
+
+        let mut buf = [0u8; 256];
+        let len = read_fd.read(&mut buf).unwrap();
+        let _ = read_fd.close();
+        let output = buf.get(..len).unwrap();
+        assert!(output.starts_with(b"panicked at src/supervisor/handle_signal.rs:"));
+        assert!(output.ends_with(b": deliberate\n"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Lock file guarding against a second connate instance
+//!
+//! Two connates sharing `Config::LOCK_FILE` would otherwise both run, fighting over services and
+//! leaving conctl to talk to whichever it found first.
+
+use crate::err::*;
+use crate::os::*;
+use crate::types::*;
+
+/// Lock `path`, moving its FD to `fd_target`, or abort naming whichever process holds it
+///
+/// Never waits on the lock, such that a second instance fails at once rather than starting once
+/// the first exits.  Locks are per process, so a re-exec'd connate re-locking its own lock file
+/// succeeds.
+pub fn lock_instance(path: &CStr, fd_target: c_int) -> Fd {
+    let flags = OpenFlags::O_RDWR | OpenFlags::O_CREAT;
+    let fd = Fd::open(path, flags, 0o600)
+        .or_fs_abort("open", path)
+        .move_to(fd_target)
+        .or_abort("Unable to dup lock file FD");
+
+    match fd.lock_nonblocking() {
+        Ok(()) => fd,
+        Err(err) if err == Errno::EACCES || err == Errno::EAGAIN => match fd.get_locking_pid() {
+            Ok(Some(pid)) => abort_lock_held_by_pid(path, pid),
+            Ok(None) => abort_acquire_lock(path, None),
+            Err(err) => abort_acquire_lock(path, Some(err)),
+        },
+        Err(err) => abort_acquire_lock(path, Some(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    /// An FD number clear of whatever the test harness has open
+    const FD_TARGET: c_int = 250;
+
+    #[test]
+    fn second_instance_aborts_naming_the_first() {
+        let path = std::env::temp_dir().join(format!("connate-lock-test-{}", getpid()));
+        let path_cstr = CString::new(path.to_str().unwrap()).unwrap();
+        let (ready_read, ready_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let (quit_read, quit_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let (stderr_read, stderr_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+
+        // The first instance holds the lock until told to quit
+        let first = match fork().unwrap() {
+            ForkResult::Child => {
+                let _ = lock_instance(&path_cstr, FD_TARGET);
+                let _ = ready_write.write(b"x");
+                let _ = quit_read.read(&mut [0u8; 1]);
+                exit(0);
+            }
+            ForkResult::Parent(pid) => pid,
+        };
+        let _ = ready_read.read(&mut [0u8; 1]).unwrap();
+
+        let second = match fork().unwrap() {
+            ForkResult::Child => {
+                let _ = stderr_write.move_to(2);
+                let _ = lock_instance(&path_cstr, FD_TARGET);
+                exit(0);
+            }
+            ForkResult::Parent(pid) => pid,
+        };
+        let _ = stderr_write.close();
+        let (_, status) = waitpid(second, WaitPidOptions::empty()).unwrap();
+        let mut buf = [0u8; 512];
+        let n = stderr_read.read(&mut buf).unwrap();
+        let stderr = String::from_utf8_lossy(&buf[..n]);
+
+        let _ = quit_write.write(b"x");
+        let _ = waitpid(first, WaitPidOptions::empty()).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(wexitstatus(status), 1);
+        assert!(stderr.contains(&format!("already held by PID {first}")));
+    }
+}
@@ -0,0 +1,202 @@
+//! Supervision engine
+//!
+//! The state machine, event loop, IPC request handling, and session persistence which together
+//! drive an array of services.  The `connate` binary sets up process properties and file
+//! descriptors then hands off to `run()`.  Other no_std binaries or tests may do the same with
+//! their own services array.
+//!
+//! Connate's fixed FD numbers are still taken from `Config::FIXED_FDS`, as supervisor processes
+//! and conctl reach connate's pipes via `/proc/<pid>/fd/<fd>`.
+
+mod cgroup;
+mod cmdline;
+mod console;
+mod handle_request;
+mod handle_signal;
+mod lock_file;
+mod next_state;
+mod poll;
+mod session;
+mod spawn;
+
+pub use cmdline::*;
+pub use console::*;
+pub use handle_request::*;
+pub use handle_signal::*;
+pub use lock_file::*;
+pub use next_state::*;
+pub use poll::*;
+pub use session::*;
+
+use crate::config::Config;
+use crate::constants::DEFAULT_BLOCKED_TIME_MILLIS;
+use crate::err::*;
+use crate::internal_api::*;
+use crate::ipc::IpcServer;
+use crate::os::*;
+
+/// Config-wide settings which steer supervision
+///
+/// Passed into `run()` rather than read from `Config` so other binaries and tests may supervise
+/// with their own.
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// See `Config::MAX_CONCURRENT_STARTS`
+    pub max_concurrent_starts: Option<usize>,
+    /// See `Config::BLOCKED_TIME`
+    pub blocked_time_millis: i64,
+}
+
+impl Settings {
+    pub const fn from_config<C: Config>() -> Self {
+        Self {
+            max_concurrent_starts: C::MAX_CONCURRENT_STARTS,
+            // build checks this fits in a c_int
+            blocked_time_millis: C::BLOCKED_TIME.as_millis() as i64,
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_concurrent_starts: None,
+            blocked_time_millis: DEFAULT_BLOCKED_TIME_MILLIS,
+        }
+    }
+}
+
+/// Set whether the pipes connate holds for services are closed across exec
+///
+/// They are close-on-exec such that only the processes meant to hold them do.  Before connate
+/// re-execs, they are made inheritable such that the new connate can resume them, and then made
+/// close-on-exec again once it has.
+fn set_pipes_cloexec(svcs: &[Service], cloexec: bool) -> Result<(), Errno> {
+    for svc in svcs {
+        if let Some((read_fd, write_fd)) = &svc.stdin_pipe {
+            read_fd.set_cloexec(cloexec)?;
+            write_fd.set_cloexec(cloexec)?;
+        }
+        if let Some(pipe) = &svc.console_pipe {
+            pipe.read_fd().set_cloexec(cloexec)?;
+            pipe.write_fd().set_cloexec(cloexec)?;
+        }
+    }
+    Ok(())
+}
+
+/// Supervise `svcs` until asked to shut down
+///
+/// `L` maps service names from IPC requests to indices in `svcs`.  The file descriptors are
+/// expected to already be in place, either freshly created or resumed from before a re-exec.
+pub fn run<L: ServiceLookup, const N: usize>(
+    svcs: &mut [Service; N],
+    mut ipc_server: IpcServer,
+    mut signalfd: SignalFd,
+    mut session_fd: SessionFd,
+    settings: Settings,
+) -> ! {
+    let mut shutting_down = false;
+    // Inherited across the exec which resumed this session, if any
+    let _ = set_pipes_cloexec(svcs, true);
+    open_console_out();
+    // Counts of Up and failed services once boot first completes
+    let mut boot_summary = None;
+    let mut poll = Poll::new(&signalfd, &ipc_server);
+    let exec_time = get_time_monotonic().or_abort("Unable to get current time");
+
+    // Main loop
+    loop {
+        let now = get_time_monotonic().or_abort("Unable to get current time");
+
+        // Handle state transitions
+        while let Some(i) = svcs.find_dirty_index() {
+            NextState::new(svcs, i, now, settings).apply(svcs, i, now);
+        }
+
+        // Note the first time every service targeted up has settled
+        if boot_summary.is_none() && svcs.all_targets_settled() {
+            boot_summary = Some(summarize_boot(svcs));
+        }
+
+        // Handle shutting down
+        if shutting_down && svcs.all_down_or_err() {
+            flush_console_pipes(svcs);
+            sync_log_files(svcs);
+            if svcs.any_bad() {
+                print_bad_services(svcs);
+                exit(1);
+            }
+            exit(0);
+        }
+
+        // Sleep until an event occurs, then handle event
+        poll.watch_console(svcs);
+        let (timeout_ms, timeout_svc) = calculate_poll_timeout(svcs, now, settings);
+        match poll.poll(timeout_ms) {
+            PollFdReady::TimeoutExpired => timeout_svc.map_or((), |svc| svc.dirty = true),
+            PollFdReady::Interrupted | PollFdReady::Console => {}
+            PollFdReady::SignalFd => handle_signal::<L, N>(
+                &mut signalfd,
+                svcs,
+                &mut shutting_down,
+                &mut session_fd,
+                now,
+            ),
+            PollFdReady::Request => handle_request::<L, N>(
+                svcs,
+                &mut ipc_server,
+                &mut session_fd,
+                now,
+                exec_time,
+                boot_summary,
+            ),
+        }
+        poll.forward_console(svcs);
+    }
+}
+
+/// Count the services which are Up and which have failed, as reported once boot completes
+fn summarize_boot(svcs: &[Service]) -> (u32, u32) {
+    let count = |f: fn(&State) -> bool| svcs.iter().filter(|svc| f(&svc.state)).count() as u32;
+    (count(|state| matches!(state, State::Up)), count(State::bad))
+}
+
+/// Flush `Log::File` logs to disk before connate exits
+///
+/// Services' writes sit in the page cache, and connate exiting may be followed by a power off
+/// before the kernel flushes them.  Output a logger service writes to its own files is up to the
+/// logger.
+///
+/// Only regular files are synced.  A log path may be a FIFO or terminal, which opening could block
+/// on or take as connate's controlling terminal, and which have nothing to flush.
+fn sync_log_files(svcs: &[Service]) {
+    // Should the path be replaced after the check, still neither block nor take a terminal
+    let flags =
+        OpenFlags::O_RDONLY | OpenFlags::O_NONBLOCK | OpenFlags::O_NOCTTY | OpenFlags::O_CLOEXEC;
+    for svc in svcs {
+        if let Log::File { filepath, .. } = svc.cfg.log
+            && is_regular_file(filepath).unwrap_or(false)
+            && let Ok(fd) = Fd::open(filepath, flags, 0)
+        {
+            let _ = fd.fsync();
+            let _ = fd.close();
+        }
+    }
+}
+
+/// Report the services which make shutting down exit non-zero, so the culprit is apparent from the
+/// final console output
+fn print_bad_services(svcs: &[Service]) {
+    eprintln("ERROR: Shut down with services in a bad state:");
+    for svc in svcs
+        .iter()
+        .filter(|svc| matches!(svc.state, State::Failed | State::CannotStop))
+    {
+        eprint("  ");
+        eprint(svc.cfg.name);
+        eprint(": ");
+        eprint(svc.state);
+        eprint("\n");
+    }
+}
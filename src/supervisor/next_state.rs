@@ -0,0 +1,1501 @@
+//! Service state transition logic
+//!
+//! Supervisor process behavior to keep in mind:
+//! - When connate wants to spawn_* a service that needs multi-process tracking, it first forks off
+//!   a supervisor process.
+//! - The supervisor sends a Request::ServiceStarting with the new service's PID
+//! - There is a brief expected window during Starting where
+//!   `svc.supervisor_pid().is_some() && svc.pid.is_none()`
+//! - This is the only window where the supervisor should exist without the service as its child:
+//!   - If the supervisor fails to start the service, it exits.
+//!   - If the supervisor detects the service died expectedly, it cleans up then exits.
+//!   - If the supervisor detects the child died unexpectedly, it cleans up then exits.
+//! - We can assume if the service dies (e.g. we send it SIGKILL) the supervisor will die as well.
+
+use super::Settings;
+use super::cgroup::{kill_cgroup, signal_cgroup};
+use super::console::{create_console_pipe, new_logger_pipe};
+use super::spawn::*;
+use crate::constants::*;
+use crate::internal_api::*;
+use crate::os::*;
+use crate::types::*;
+use crate::util::random_jitter;
+
+pub enum NextState {
+    // Change svc.state
+    Down,
+    WaitingToStart,
+    SettingUp,
+    Starting,
+    Up,
+    WaitingToStop,
+    Stopping,
+    CleaningUp,
+    ForceDown,
+    FailedOrRetry,
+    Failed,
+    CannotStop,
+    // Retain state
+    None,         // no change
+    UpStable,     // stable; reset retry count
+    AwaitingSlot, // ready to start once a start slot frees
+    StopBlocked,  // stuck waiting to stop; report the blocker but keep running
+}
+
+impl NextState {
+    pub fn new<const N: usize>(
+        svcs: &[Service; N],
+        i: usize,
+        now: timespec,
+        settings: Settings,
+    ) -> Self {
+        let Some(svc) = svcs.get(i) else {
+            return Self::None;
+        };
+
+        match svc.state {
+            State::Down => Self::from_down(svc),
+            State::WaitingToStart => Self::from_waiting_to_start(svc, svcs, now, settings),
+            State::SettingUp => Self::from_setting_up(svc, now),
+            State::Starting => Self::from_starting(svc, now),
+            State::Up => Self::from_up(svc, now),
+            State::WaitingToStop => Self::from_waiting_to_stop(svc, svcs, now, settings),
+            State::Stopping => Self::from_stopping(svc, now),
+            State::CleaningUp => Self::from_cleaning_up(svc, now),
+            State::ForceDown => Self::from_forced_down(svc, now),
+            State::Retrying => Self::from_retrying(svc, now),
+            State::Failed => Self::from_failed(svc),
+            State::CannotStop => Self::from_cannot_stop(svc),
+        }
+    }
+
+    pub fn apply<const N: usize>(self, svcs: &mut [Service; N], i: usize, now: timespec) {
+        if matches!(self, Self::SettingUp | Self::Starting | Self::CleaningUp) {
+            create_logger_pipes(svcs, i);
+            if let Some(svc) = svcs.get_mut(i) {
+                create_console_pipe(svc);
+            }
+        }
+
+        // Immutable read all svcs to get logger_fd, then once we have it get the service we are
+        // interested in as mutable.
+        let Some(svc) = svcs.get(i) else {
+            return;
+        };
+        let logger_fd = svc.logger_fd(svcs);
+        let was_starting = is_starting(svc);
+        let blocker = blockers(svc, svcs).next();
+        let fail_reason = fail_reason(&self, svc, now);
+        let Some(svc) = svcs.get_mut(i) else {
+            return;
+        };
+
+        match self {
+            // Change svc.state
+            Self::Down => apply_down(svc),
+            Self::WaitingToStart => apply_waiting_to_start(svc),
+            Self::SettingUp => apply_setting_up(svc, logger_fd),
+            Self::Starting => apply_starting(svc, logger_fd),
+            Self::Up => apply_up(svc),
+            Self::WaitingToStop => apply_waiting_to_stop(svc),
+            Self::Stopping => apply_stopping(svc),
+            Self::CleaningUp => apply_cleaning_up(svc, logger_fd),
+            Self::FailedOrRetry => apply_failed_or_retry(svc),
+            Self::Failed => apply_fatal(svc),
+            Self::ForceDown => apply_force_down(svc),
+            Self::CannotStop => apply_cannot_stop(svc),
+            // Retain state but do something
+            Self::None => svc.dirty = false,
+            Self::UpStable => {
+                svc.dirty = false;
+                svc.attempt_count = 0;
+            }
+            // Time spent waiting for a slot does not count against max_wait_time, nor should it
+            // leave an expired timeout for poll() to return on immediately and forever.
+            Self::AwaitingSlot => {
+                svc.dirty = false;
+                svc.time = now;
+            }
+            // Killing the service would pull it out from under the dependents it is waiting on,
+            // so leave it running and surface why it is stuck.
+            Self::StopBlocked => {
+                svc.dirty = false;
+                svc.blocked_by = blocker;
+                svc.fail_reason = fail_reason;
+            }
+        }
+
+        // Set common items for when the service state actually changes
+        match self {
+            Self::None | Self::UpStable | Self::AwaitingSlot | Self::StopBlocked => {}
+            _ => {
+                // Update time since state change if the state changed
+                svc.time = now;
+                // Time from beginning to start to being up, for `conctl analyze`
+                if matches!(self, Self::SettingUp) {
+                    svc.start_time = now;
+                } else if was_starting && matches!(svc.state, State::Up) {
+                    svc.start_duration_millis = Some(now.millis_since(svc.start_time));
+                }
+                // If `.ready` was meaningful, it would have been consumed in the apply_* above.
+                svc.ready = false;
+                // Progress describes the process which reported it
+                svc.progress = None;
+                // Retain what a service gave up waiting on for diagnostics
+                svc.blocked_by = match svc.state {
+                    State::Failed => blocker,
+                    _ => None,
+                };
+                // Retain why for `conctl why`
+                if matches!(
+                    svc.state,
+                    State::Failed | State::Retrying | State::CannotStop
+                ) && fail_reason.is_some()
+                {
+                    svc.fail_reason = fail_reason;
+                }
+                // If this service's state changed, there may be another following change available.
+                svc.dirty = true;
+                // If this service state changed, services waiting on this service may no longer be
+                // blocked and need to be re-checked.
+                for &i in svc.cfg.propagate_dirty {
+                    if let Some(svc) = svcs.get_mut(i) {
+                        svc.dirty = true;
+                    }
+                }
+            }
+        }
+
+        // Make room for a service starting on its own rather than waiting on its conflicts, as
+        // explicitly setting its target would have
+        if let Self::WaitingToStart = self {
+            stop_conflicts(svcs, i);
+        }
+
+        // If this service gave up a start slot, services held waiting for one may now proceed
+        if was_starting && svcs.get(i).is_some_and(|svc| !is_starting(svc)) {
+            for svc in svcs.iter_mut() {
+                if matches!(svc.state, State::WaitingToStart) {
+                    svc.dirty = true;
+                }
+            }
+        }
+    }
+
+    fn from_down(svc: &Service) -> Self {
+        match svc.target {
+            _ if svc.has_pid() => Self::ForceDown, // Stop unexpected process
+            Target::Down => Self::None,
+            // apply_down() performs Restart->Up transition. If we're down and target=Restart, the
+            // user set it while we're down.
+            //
+            // Continuing upward doesn't make sense with target=Restart. Instead, apply_down()
+            // again to apply the Restart->Up target transition.
+            Target::Restart => Self::Down,
+            // apply_down() performs Once->Down transition. If we're down and target=Once, the
+            // user set it while we're down.
+            //
+            // If we're down and target=Once, respect the user's request and continue upward.
+            Target::Up | Target::Once => Self::WaitingToStart,
+        }
+    }
+
+    fn from_waiting_to_start<const N: usize>(
+        svc: &Service,
+        svcs: &[Service; N],
+        now: timespec,
+        settings: Settings,
+    ) -> Self {
+        match svc.target {
+            _ if svc.has_pid() => Self::ForceDown, // Stop unexpected process
+            Target::Down | Target::Restart => Self::Down,
+            Target::Up | Target::Once if start_dep_satisfied(svc, svcs) => {
+                if start_slot_available(svcs, settings.max_concurrent_starts) {
+                    Self::SettingUp
+                } else {
+                    // Woken up once a slot frees
+                    Self::AwaitingSlot
+                }
+            }
+            Target::Up | Target::Once if wait_time_elapsed(svc, now) => Self::Failed,
+            Target::Up | Target::Once if deadlocked(svc, svcs, now, settings) => Self::Failed,
+            Target::Up | Target::Once => Self::None,
+        }
+    }
+
+    fn from_setting_up(svc: &Service, now: timespec) -> Self {
+        // Once we're in SettingUp, target doesn't matter until we transition through to Up.
+        // When Up, we can consider continuing downward if target is downward.
+
+        match svc.cfg.setup {
+            Run::None if svc.has_pid() => Self::ForceDown, // Stop unexpected process
+            Run::None => Self::Starting,
+            _ if svc.pid.is_none() && exit_code_success(svc) => Self::Starting,
+            _ if svc.pid.is_none() && exit_code_fatal(svc) => Self::Failed,
+            _ if svc.pid.is_none() => Self::FailedOrRetry,
+            _ if svc.pid.is_some() && setup_time_elapsed(svc, now) => Self::ForceDown,
+            _ => Self::None,
+        }
+    }
+
+    fn from_starting(svc: &Service, now: timespec) -> Self {
+        // Once we're in Staring, target doesn't matter until we transition through to Up.
+        // When Up, we can consider continuing downward if target is downward.
+
+        match svc.cfg.run {
+            Run::None if svc.has_pid() => Self::ForceDown, // Stop unexpected process
+            Run::None => Self::Up,
+            _ if !svc.has_pid() && exit_code_fatal(svc) => Self::Failed,
+            _ if !svc.has_pid() => Self::FailedOrRetry,
+            _ if matches!(svc.cfg.ready, Ready::Immediately) => Self::Up,
+            _ if svc.ready => Self::Up,
+            _ if start_time_elapsed(svc, now) => Self::ForceDown,
+            _ => Self::None,
+        }
+    }
+
+    fn from_up(svc: &Service, now: timespec) -> Self {
+        if matches!(svc.target, Target::Down | Target::Restart) {
+            return Self::WaitingToStop;
+        }
+
+        match svc.cfg.run {
+            Run::None if svc.has_pid() => Self::ForceDown, // Stop unexpected process
+            Run::None => Self::None,
+            // A oneshot finishing successfully is done rather than failed, and goes down through
+            // its cleanup as though it had been stopped
+            _ if !svc.has_pid() && matches!(svc.target, Target::Once) && exit_code_success(svc) => {
+                Self::CleaningUp
+            }
+            _ if !svc.has_pid() && exit_code_fatal(svc) => Self::Failed,
+            _ if !svc.has_pid() => Self::FailedOrRetry,
+            _ if svc.awaiting_up_stable() && up_time_elapsed(svc, now) => Self::UpStable,
+            _ => Self::None,
+        }
+    }
+
+    fn from_waiting_to_stop<const N: usize>(
+        svc: &Service,
+        svcs: &[Service; N],
+        now: timespec,
+        settings: Settings,
+    ) -> Self {
+        match svc.target {
+            Target::Up | Target::Once => Self::Up,
+            Target::Down | Target::Restart if stop_deps_satisfied(svc, svcs) => Self::Stopping,
+            Target::Down | Target::Restart if wait_time_elapsed(svc, now) => Self::StopBlocked,
+            Target::Down | Target::Restart if deadlocked(svc, svcs, now, settings) => {
+                Self::StopBlocked
+            }
+            Target::Down | Target::Restart => Self::None,
+        }
+    }
+
+    fn from_stopping(svc: &Service, now: timespec) -> Self {
+        if !svc.has_pid() {
+            Self::CleaningUp
+        } else if stop_time_elapsed(svc, now) {
+            Self::ForceDown
+        } else {
+            Self::None
+        }
+    }
+
+    fn from_cleaning_up(svc: &Service, now: timespec) -> Self {
+        if !svc.has_pid() {
+            Self::Down
+        } else if clean_up_time_elapsed(svc, now) {
+            Self::ForceDown
+        } else {
+            Self::None
+        }
+    }
+
+    fn from_forced_down(svc: &Service, now: timespec) -> Self {
+        if !svc.has_pid() {
+            match svc.target {
+                Target::Up | Target::Once => Self::FailedOrRetry,
+                Target::Down | Target::Restart => Self::Down,
+            }
+        } else if forced_down_time_elapsed(svc, now) {
+            Self::CannotStop
+        } else {
+            Self::None
+        }
+    }
+
+    fn from_retrying(svc: &Service, now: timespec) -> Self {
+        match svc.target {
+            _ if svc.has_pid() => Self::ForceDown, // Stop unexpected process
+            Target::Down | Target::Restart => Self::Down,
+            // Skip Down and go straight to WaitingToStart to avoid resetting attempt counter
+            Target::Up | Target::Once if retry_period_elapsed(svc, now) => Self::WaitingToStart,
+            Target::Up | Target::Once => Self::None,
+        }
+    }
+
+    fn from_failed(svc: &Service) -> Self {
+        if svc.has_pid() {
+            Self::ForceDown // Stop unexpected process
+        } else {
+            // From the point of view of this module, failed is a terminal state.
+            //
+            // The user can nudge the service out of the failed state by setting a target via
+            // `conctl`, which will update not only the target but also move the state to Down.
+            Self::None
+        }
+    }
+
+    fn from_cannot_stop(svc: &Service) -> Self {
+        if !svc.has_pid() {
+            // We could reach this if:
+            // - The process finally died on its own
+            // - Some external, possibly higher privileged process killed the process
+            //
+            // If we entered CannotStop, something went wrong. We don't automatically transition to
+            // the happy path Down, but instead treat this as a failure even if it eventually
+            // unblocked.
+            Self::FailedOrRetry
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// Why the service is failing, should `next` leave it Failed, Retrying, or CannotStop
+fn fail_reason(next: &NextState, svc: &Service, now: timespec) -> Option<FailReason> {
+    match next {
+        // Only fails if spawning the process fails
+        NextState::SettingUp | NextState::Starting | NextState::CleaningUp => {
+            Some(FailReason::SpawnFailed)
+        }
+        NextState::CannotStop => Some(FailReason::CannotStop),
+        NextState::FailedOrRetry | NextState::Failed | NextState::StopBlocked => match svc.state {
+            State::SettingUp => Some(FailReason::SetupFailed),
+            State::Starting | State::Up => Some(FailReason::RunExited),
+            State::ForceDown => Some(FailReason::TimedOut),
+            State::CannotStop => Some(FailReason::CannotStop),
+            State::WaitingToStart | State::WaitingToStop if wait_time_elapsed(svc, now) => {
+                Some(FailReason::WaitTimedOut)
+            }
+            State::WaitingToStart | State::WaitingToStop => Some(FailReason::Deadlocked),
+            // Cleanup's exit code is ignored, so it cannot fail by exiting.  One which outlives
+            // `max_cleanup_time` is killed and fails from ForceDown as TimedOut.
+            State::CleaningUp => None,
+            // Never left by failing
+            State::Down | State::Stopping | State::Retrying | State::Failed => None,
+        },
+        _ => None,
+    }
+}
+
+fn apply_down(svc: &mut Service) {
+    // Dependency target propagation (e.g. groups) only apply on user-requested target change.
+    // Automatic target changes are handled individually for each service.
+    // Thus, we're not propagating target change here, but only applying it directly on the
+    // service.
+    match svc.target {
+        Target::Down | Target::Up => {}
+        Target::Restart => svc.target = Target::Up,
+        Target::Once => svc.target = Target::Down,
+    }
+
+    // The main process is gone.  Per kill_mode, anything it left behind in its cgroup goes with it.
+    kill_leftover_processes(svc);
+
+    svc.state = State::Down;
+    svc.attempt_count = 0;
+    #[cfg(feature = "settle")]
+    settle_notify(svc);
+}
+
+fn apply_waiting_to_start(svc: &mut Service) {
+    svc.state = State::WaitingToStart;
+    #[cfg(feature = "settle")]
+    settle_clear(svc);
+}
+
+/// Set the targets of a starting service's conflicts and their dependents down, if it is
+/// configured to stop them
+fn stop_conflicts<const N: usize>(svcs: &mut [Service; N], i: usize) {
+    let Some(cfg) = svcs.get(i).map(|svc| svc.cfg) else {
+        return;
+    };
+    if cfg.conflict_action != ConflictAction::Stop {
+        return;
+    }
+
+    for &i in cfg.target_up_propagate_down {
+        if let Some(svc) = svcs.get_mut(i)
+            && !matches!(svc.target, Target::Down)
+        {
+            svc.target = Target::Down;
+            svc.dirty = true;
+        }
+    }
+}
+
+/// Create the pipes between a service about to spawn a process and its logger, if either lacks one
+///
+/// The pipe is close-on-exec such that only the logger's stdin and each producer's own
+/// stdout/stderr refer to it.  Otherwise every process would hold the write end open, and
+/// producers' output would leak into unrelated processes.  If creating it fails, the process
+/// inherits connate's stdin or stdout/stderr instead.
+fn create_logger_pipes<const N: usize>(svcs: &mut [Service; N], i: usize) {
+    let Some(cfg) = svcs.get(i).map(|svc| svc.cfg) else {
+        return;
+    };
+    let own = cfg.is_logger.then_some(i);
+    let logger = match cfg.log {
+        Log::Service(logger) => Some(logger),
+        _ => None,
+    };
+
+    for logger in [own, logger].into_iter().flatten() {
+        if let Some(svc) = svcs.get_mut(logger)
+            && svc.stdin_pipe.is_none()
+        {
+            svc.stdin_pipe = new_logger_pipe().ok();
+        }
+    }
+}
+
+fn apply_setting_up(svc: &mut Service, logger_fd: Option<Fd>) {
+    match svc.spawn_setting_up(logger_fd) {
+        Ok(()) => {
+            svc.state = State::SettingUp;
+            #[cfg(feature = "settle")]
+            settle_clear(svc);
+        }
+        Err(_) => apply_failed_or_retry(svc),
+    }
+}
+
+fn apply_starting(svc: &mut Service, logger_fd: Option<Fd>) {
+    match svc.spawn_run(logger_fd) {
+        Ok(()) => {
+            svc.state = State::Starting;
+            #[cfg(feature = "settle")]
+            settle_clear(svc);
+        }
+        Err(_) => apply_failed_or_retry(svc),
+    }
+}
+
+fn apply_up(svc: &mut Service) {
+    svc.state = State::Up;
+    #[cfg(feature = "settle")]
+    settle_notify(svc);
+}
+
+fn apply_waiting_to_stop(svc: &mut Service) {
+    svc.state = State::WaitingToStop;
+    #[cfg(feature = "settle")]
+    settle_clear(svc);
+}
+
+fn apply_stopping(svc: &mut Service) {
+    // Both SIGTERM success and failure lead to the same behavior:
+    // - If process dies (on its own or because of signal) => Down
+    // - If does not die => ForceDown
+    //
+    // Thus result doesn't matter and should be ignored.
+    match (svc.cfg.kill_mode, svc.cfg.cgroup) {
+        (KillMode::ControlGroup, Some(cgroup)) => {
+            let _ = signal_cgroup(cgroup, Signal::SIGTERM);
+        }
+        _ => {
+            if let Some(pid) = svc.pid {
+                let _ = kill(pid, Signal::SIGTERM);
+            }
+        }
+    }
+    svc.state = State::Stopping;
+    #[cfg(feature = "settle")]
+    settle_clear(svc);
+}
+
+fn apply_cleaning_up(svc: &mut Service, logger_fd: Option<Fd>) {
+    match svc.spawn_cleaning_up(logger_fd) {
+        Ok(()) => {
+            svc.state = State::CleaningUp;
+            #[cfg(feature = "settle")]
+            settle_clear(svc);
+        }
+        Err(_) => apply_failed_or_retry(svc),
+    }
+}
+
+fn apply_force_down(svc: &mut Service) {
+    // The kill() return value doesn't matter.  In every scenario, either:
+    // - The child dies and we continue as though the kill() was successful.
+    // - The child doesn't die, we timeout, and transition to CannotStop.
+    // It doesn't matter if it was because of ESRCH indicating the child died before we sent
+    // SIGKILL, because of EPERM indicating the child didn't die because we lacked permissions,
+    // etc.
+
+    if let Some(pid) = svc.supervisor_pid {
+        // tells supervisor to SIGKILL children until there are none left
+        let _ = kill(pid, Signal::SIGTERM);
+    }
+
+    if let Some(pid) = svc.pid {
+        let _ = kill(pid, Signal::SIGKILL);
+    }
+
+    kill_leftover_processes(svc);
+
+    svc.state = State::ForceDown;
+    #[cfg(feature = "settle")]
+    settle_clear(svc);
+}
+
+/// SIGKILL every process remaining in the service's cgroup if its kill_mode covers the cgroup
+fn kill_leftover_processes(svc: &Service) {
+    if let (KillMode::ControlGroup | KillMode::Mixed, Some(cgroup)) =
+        (svc.cfg.kill_mode, svc.cfg.cgroup)
+    {
+        let _ = kill_cgroup(cgroup);
+    }
+}
+
+fn apply_failed_or_retry(svc: &mut Service) {
+    if svc.cfg.reset_attempts_on == ResetPolicy::CleanExit && exit_code_success(svc) {
+        svc.attempt_count = 0;
+    }
+    svc.attempt_count = svc.attempt_count.saturating_add(1);
+
+    // Once is a single attempt
+    let can_retry = !matches!(svc.target, Target::Once)
+        && svc
+            .cfg
+            .max_attempt_count
+            .is_none_or(|max_attempt_count| svc.attempt_count < max_attempt_count);
+
+    if can_retry {
+        svc.state = State::Retrying;
+        svc.retry_jitter_millis = random_jitter(svc.cfg.retry_jitter_millis as i64);
+        #[cfg(feature = "settle")]
+        settle_clear(svc);
+        return;
+    }
+
+    apply_failed(svc);
+}
+
+/// Fail without considering retry, e.g. due to a configured fatal exit code
+fn apply_fatal(svc: &mut Service) {
+    svc.attempt_count = svc.attempt_count.saturating_add(1);
+    apply_failed(svc);
+}
+
+fn apply_failed(svc: &mut Service) {
+    svc.state = State::Failed;
+    match svc.target {
+        Target::Down | Target::Up => {}
+        Target::Restart => svc.target = Target::Up,
+        Target::Once => svc.target = Target::Down,
+    }
+    #[cfg(feature = "settle")]
+    settle_notify(svc);
+}
+
+fn apply_cannot_stop(svc: &mut Service) {
+    svc.state = State::CannotStop;
+    #[cfg(feature = "settle")]
+    settle_notify(svc);
+}
+
+fn start_dep_satisfied<const N: usize>(svc: &Service, svcs: &[Service; N]) -> bool {
+    needs_satisfied(svc, svcs)
+        && wants_satisfied(svc, svcs)
+        && conflicts_satisfied(svc, svcs)
+        && serializations_satisfied(svc, svcs)
+}
+
+fn needs_satisfied<const N: usize>(svc: &Service, svcs: &[Service; N]) -> bool {
+    deps_satisfied(svc.cfg.needs, svcs, need_satisfied)
+}
+
+fn wants_satisfied<const N: usize>(svc: &Service, svcs: &[Service; N]) -> bool {
+    deps_satisfied(svc.cfg.wants, svcs, want_satisfied)
+}
+
+fn conflicts_satisfied<const N: usize>(svc: &Service, svcs: &[Service; N]) -> bool {
+    deps_satisfied(svc.cfg.conflicts, svcs, conflict_satisfied)
+}
+
+fn serializations_satisfied<const N: usize>(svc: &Service, svcs: &[Service; N]) -> bool {
+    deps_satisfied(svc.cfg.serialize_with, svcs, serialization_satisfied)
+}
+
+fn deps_satisfied<const N: usize>(
+    deps: &[usize],
+    svcs: &[Service; N],
+    satisfied: fn(&Service) -> bool,
+) -> bool {
+    deps.iter()
+        .all(|&i| svcs.get(i).map(satisfied).unwrap_or(true))
+}
+
+fn need_satisfied(dep: &Service) -> bool {
+    matches!(dep.state, State::Up)
+}
+
+// A want waits out a dependency's Retrying, as it may yet come up; entering it re-checks dependents
+// like any other state change, but only settling into Up or Failed lets them proceed.
+fn want_satisfied(dep: &Service) -> bool {
+    matches!(dep.state, State::Up | State::Failed | State::CannotStop)
+}
+
+fn conflict_satisfied(dep: &Service) -> bool {
+    matches!(dep.state, State::Down | State::Failed)
+}
+
+// Only waits out a start already under way; a service which is down, up, or failed has nothing left
+// to serialize with.
+fn serialization_satisfied(dep: &Service) -> bool {
+    !matches!(
+        dep.state,
+        State::WaitingToStart | State::SettingUp | State::Starting
+    )
+}
+
+fn stop_dep_satisfied(dep: &Service) -> bool {
+    matches!(
+        dep.state,
+        State::Down | State::WaitingToStart | State::Failed | State::CannotStop
+    )
+}
+
+// Whether an unsatisfied dependency will never become satisfied without outside intervention
+
+fn need_stuck(dep: &Service) -> bool {
+    matches!(dep.state, State::Failed | State::CannotStop) || matches!(dep.target, Target::Down)
+}
+
+fn want_stuck(_dep: &Service) -> bool {
+    // Wants only wait for the dependency to finish transitioning, which it always eventually does
+    false
+}
+
+fn conflict_stuck(dep: &Service) -> bool {
+    matches!(dep.state, State::CannotStop) || matches!(dep.target, Target::Up | Target::Once)
+}
+
+fn serialization_stuck(_dep: &Service) -> bool {
+    // A dependency waiting to start either eventually starts or gives up waiting
+    false
+}
+
+fn stop_dep_stuck(dep: &Service) -> bool {
+    matches!(dep.target, Target::Up | Target::Once)
+}
+
+/// A dependency list a waiting service waits on, how to tell whether a dependency in it is
+/// satisfied, and how to tell whether an unsatisfied one is stuck
+type WaitingDeps = (&'static [usize], fn(&Service) -> bool, fn(&Service) -> bool);
+
+fn waiting_deps(svc: &Service) -> [WaitingDeps; 4] {
+    let cfg = svc.cfg;
+    match svc.state {
+        State::WaitingToStart => [
+            (cfg.needs, need_satisfied, need_stuck),
+            (cfg.wants, want_satisfied, want_stuck),
+            (cfg.conflicts, conflict_satisfied, conflict_stuck),
+            (
+                cfg.serialize_with,
+                serialization_satisfied,
+                serialization_stuck,
+            ),
+        ],
+        State::WaitingToStop => [
+            (cfg.stop_dependencies, stop_dep_satisfied, stop_dep_stuck),
+            (&[], stop_dep_satisfied, stop_dep_stuck),
+            (&[], stop_dep_satisfied, stop_dep_stuck),
+            (&[], stop_dep_satisfied, stop_dep_stuck),
+        ],
+        _ => [
+            (&[], need_satisfied, need_stuck),
+            (&[], need_satisfied, need_stuck),
+            (&[], need_satisfied, need_stuck),
+            (&[], need_satisfied, need_stuck),
+        ],
+    }
+}
+
+/// Indices of the services keeping a waiting service from starting or stopping
+pub fn blockers<'a, const N: usize>(
+    svc: &Service,
+    svcs: &'a [Service; N],
+) -> impl Iterator<Item = usize> + 'a {
+    waiting_deps(svc)
+        .into_iter()
+        .flat_map(move |(deps, satisfied, _)| {
+            deps.iter()
+                .copied()
+                .filter(move |&i| svcs.get(i).is_some_and(|dep| !satisfied(dep)))
+        })
+}
+
+/// Whether a waiting service has been blocked for a while by services which will never unblock it
+///
+/// For example, a service needing one which failed, or stopping before a dependent which has since
+/// been brought back up.
+fn deadlocked<const N: usize>(
+    svc: &Service,
+    svcs: &[Service; N],
+    now: timespec,
+    settings: Settings,
+) -> bool {
+    if now.millis_since(svc.time) < settings.blocked_time_millis {
+        return false;
+    }
+
+    let mut blocked = false;
+    for (deps, satisfied, stuck) in waiting_deps(svc) {
+        for dep in deps.iter().filter_map(|&i| svcs.get(i)) {
+            if satisfied(dep) {
+                continue;
+            }
+            if !stuck(dep) {
+                return false;
+            }
+            blocked = true;
+        }
+    }
+    blocked
+}
+
+/// Whether the service occupies one of the `max_concurrent_starts` slots
+fn is_starting(svc: &Service) -> bool {
+    matches!(svc.state, State::SettingUp | State::Starting)
+}
+
+fn start_slot_available<const N: usize>(
+    svcs: &[Service; N],
+    max_concurrent_starts: Option<usize>,
+) -> bool {
+    match max_concurrent_starts {
+        Some(max) => svcs.iter().filter(|svc| is_starting(svc)).count() < max,
+        None => true,
+    }
+}
+
+fn stop_deps_satisfied<const N: usize>(svc: &Service, svcs: &[Service; N]) -> bool {
+    deps_satisfied(svc.cfg.stop_dependencies, svcs, stop_dep_satisfied)
+}
+
+fn setup_time_elapsed(svc: &Service, now: timespec) -> bool {
+    svc.cfg
+        .max_setup_time_millis
+        .is_some_and(|max| now.millis_since(svc.time) >= max as i64)
+}
+
+fn start_time_elapsed(svc: &Service, now: timespec) -> bool {
+    svc.cfg
+        .max_ready_time_millis
+        .is_some_and(|max| now.millis_since(svc.time) >= max as i64)
+}
+
+fn up_time_elapsed(svc: &Service, now: timespec) -> bool {
+    now.millis_since(svc.time) >= svc.cfg.up_stable_time_millis as i64
+}
+
+fn stop_time_elapsed(svc: &Service, now: timespec) -> bool {
+    svc.cfg
+        .max_stop_time_millis
+        .is_some_and(|max| now.millis_since(svc.time) >= max as i64)
+}
+
+fn clean_up_time_elapsed(svc: &Service, now: timespec) -> bool {
+    svc.cfg
+        .max_cleanup_time_millis
+        .is_some_and(|max| now.millis_since(svc.time) >= max as i64)
+}
+
+fn wait_time_elapsed(svc: &Service, now: timespec) -> bool {
+    svc.cfg
+        .max_wait_time_millis
+        .is_some_and(|max| now.millis_since(svc.time) >= max as i64)
+}
+
+fn forced_down_time_elapsed(svc: &Service, now: timespec) -> bool {
+    now.millis_since(svc.time) > FORCED_DOWN_TIME_MILLIS
+}
+
+fn exit_code_success(svc: &Service) -> bool {
+    svc.exit_code
+        .is_some_and(|code| code == 0 || svc.cfg.success_exit_codes.contains(&code))
+}
+
+fn exit_code_fatal(svc: &Service) -> bool {
+    svc.exit_code
+        .is_some_and(|code| svc.cfg.fatal_exit_codes.contains(&code))
+}
+
+/// Never true on the same pass as the service entered Retrying, even with no delay.  Otherwise a
+/// service which fails as it spawns would loop through Retrying without ever returning to poll(),
+/// starving signals and requests.  Instead, its timeout of zero has poll() mark it dirty again.
+fn retry_period_elapsed(svc: &Service, now: timespec) -> bool {
+    now != svc.time && now.millis_since(svc.time) >= svc.retry_delay_millis()
+}
+
+/// Write a byte to the settle pipe to notify waiters that service reached a stable state
+///
+/// The pipe is non-blocking and failing to write is fine: a full pipe already holds unread
+/// notifications, and with no waiters nobody needs one.
+#[cfg(feature = "settle")]
+fn settle_notify(svc: &Service) {
+    if let Some((_, ref write_fd)) = svc.settle_pipe {
+        let _ = write_fd.write(&[1u8]);
+    }
+}
+
+/// Drain all bytes from the settle pipe when leaving a stable state
+#[cfg(feature = "settle")]
+fn settle_clear(svc: &Service) {
+    if let Some((ref read_fd, _)) = svc.settle_pipe {
+        let mut buf = [0u8; PIPE_BUF];
+        // This is guaranteed to fully drain the pipe:
+        // - We're blocking signals, and so we can't be interrupted by a signal
+        // - settle_pipe is created as non-blocking
+        // - We *should* only see one byte written into the pipe before it is cleared such that a
+        // single byte read should clear it. To be extra safe, we're reading the entire pipe
+        // buffer. From `man 2 pipe`:
+        //
+        // > If a read(2) specifies a buffer size that is smaller than the next packet, then the
+        // > requested number of bytes are read, and the excess bytes in the packet are discarded.
+        // > Specifying a buffer size of PIPE_BUF will be sufficient to read the largest possible
+        // > packets (see the previous point).
+        let _ = read_fd.read(&mut buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supervisor::poll::calculate_poll_timeout;
+
+    const DEP: usize = 0;
+    const NEEDER: usize = 1;
+    const WANTER: usize = 2;
+
+    const BASE: ServiceConfig = ServiceConfig {
+        name: b"",
+        ..ServiceConfig::TEST_DEFAULT
+    };
+
+    // The dependency's process is never actually spawned; the tests stand in for it by setting its
+    // pid and exit code directly.
+    const DEP_CFG: ServiceConfig = ServiceConfig {
+        name: b"dep",
+        index: DEP,
+        propagate_dirty: &[NEEDER, WANTER],
+        run: Run::Fn {
+            f: || Ok(()),
+            log_overwrite: false,
+        },
+        ..BASE
+    };
+    const NEEDER_CFG: ServiceConfig = ServiceConfig {
+        name: b"needer",
+        index: NEEDER,
+        needs: &[DEP],
+        ..BASE
+    };
+    const WANTER_CFG: ServiceConfig = ServiceConfig {
+        name: b"wanter",
+        index: WANTER,
+        wants: &[DEP],
+        ..BASE
+    };
+
+    const NOW: timespec = timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    /// Run state transitions until nothing is dirty, as the main loop does, counting how many
+    /// times each service begins starting
+    fn settle<const N: usize>(svcs: &mut [Service; N], now: timespec, starts: &mut [u32; N]) {
+        while let Some(i) = svcs.find_dirty_index() {
+            let next = NextState::new(svcs, i, now, Settings::default());
+            if matches!(next, NextState::SettingUp)
+                && let Some(count) = starts.get_mut(i)
+            {
+                *count += 1;
+            }
+            next.apply(svcs, i, now);
+        }
+    }
+
+    fn dep_dies(svcs: &mut [Service; 3]) {
+        svcs[DEP].pid = None;
+        svcs[DEP].exit_code = Some(1);
+        svcs[DEP].dirty = true;
+    }
+
+    fn dep_comes_up(svcs: &mut [Service; 3], now: timespec) {
+        svcs[DEP].pid = Some(i32::MAX);
+        NextState::Up.apply(svcs, DEP, now);
+    }
+
+    #[test]
+    fn dependents_wait_through_dependency_retrying() {
+        let mut svcs = [
+            Service::test(&DEP_CFG, State::Up),
+            Service::test(&NEEDER_CFG, State::WaitingToStart),
+            Service::test(&WANTER_CFG, State::WaitingToStart),
+        ];
+        svcs[DEP].pid = Some(i32::MAX);
+        let mut starts = [0; 3];
+        let mut now = NOW;
+
+        // Flap through Retrying and back up a couple of times before dependents get a look in
+        for _ in 0..2 {
+            dep_dies(&mut svcs);
+            settle(&mut svcs, now, &mut starts);
+            assert!(matches!(svcs[DEP].state, State::Retrying));
+
+            // Both dependents were re-checked and chose to keep waiting
+            for svc in &svcs[NEEDER..] {
+                assert!(matches!(svc.state, State::WaitingToStart));
+                assert!(!svc.dirty);
+            }
+            assert_eq!(starts, [0, 0, 0]);
+
+            now.tv_sec += 1;
+            dep_comes_up(&mut svcs, now);
+            assert!(svcs[NEEDER].dirty && svcs[WANTER].dirty);
+            settle(&mut svcs, now, &mut starts);
+
+            for svc in &svcs[NEEDER..] {
+                assert!(matches!(svc.state, State::Up));
+            }
+            assert_eq!(starts, [0, 1, 1]);
+
+            // Put the dependents back to waiting for the next flap
+            for svc in &mut svcs[NEEDER..] {
+                svc.state = State::WaitingToStart;
+            }
+            starts = [0; 3];
+        }
+    }
+
+    #[test]
+    fn immediate_retry_waits_for_poll() {
+        const IMMEDIATE_CFG: ServiceConfig = ServiceConfig {
+            propagate_dirty: &[],
+            retry_wait_period_millis: 0,
+            max_attempt_count: None,
+            ..DEP_CFG
+        };
+        let mut svcs = [Service::test(&IMMEDIATE_CFG, State::Up)];
+        svcs[0].exit_code = Some(1);
+        svcs[0].dirty = true;
+
+        // Within a single pass, as when a spawn fails synchronously, the service must not be
+        // retried, else the main loop would never get back to poll()
+        for _ in 0..8 {
+            if !svcs[0].dirty {
+                break;
+            }
+            let next = NextState::new(&svcs, 0, NOW, Settings::default());
+            assert!(!matches!(next, NextState::WaitingToStart));
+            next.apply(&mut svcs, 0, NOW);
+        }
+        assert!(matches!(svcs[0].state, State::Retrying));
+        assert!(!svcs[0].dirty);
+
+        // poll() returns at once, marking it dirty to retry on the next pass
+        let (timeout, timeout_svc) = calculate_poll_timeout(&mut svcs, NOW, Settings::default());
+        assert_eq!(timeout, Some(0));
+        timeout_svc.unwrap().dirty = true;
+        let later = timespec {
+            tv_sec: 0,
+            tv_nsec: 1,
+        };
+        assert!(matches!(
+            NextState::new(&svcs, 0, later, Settings::default()),
+            NextState::WaitingToStart
+        ));
+    }
+
+    #[test]
+    fn serialized_service_waits_for_start_to_finish() {
+        const SLOW: usize = 0;
+        const SERIALIZED: usize = 1;
+        const SLOW_CFG: ServiceConfig = ServiceConfig {
+            name: b"slow",
+            index: SLOW,
+            propagate_dirty: &[SERIALIZED],
+            ready: Ready::Notify,
+            ..DEP_CFG
+        };
+        const SERIALIZED_CFG: ServiceConfig = ServiceConfig {
+            name: b"serialized",
+            index: SERIALIZED,
+            serialize_with: &[SLOW],
+            ..BASE
+        };
+
+        let mut svcs = [
+            Service::test(&SLOW_CFG, State::Starting),
+            Service::test(&SERIALIZED_CFG, State::WaitingToStart),
+        ];
+        svcs[SLOW].pid = Some(i32::MAX);
+        svcs[SERIALIZED].dirty = true;
+        let mut starts = [0; 2];
+
+        settle(&mut svcs, NOW, &mut starts);
+        assert!(matches!(svcs[SERIALIZED].state, State::WaitingToStart));
+        assert_eq!(starts, [0, 0]);
+
+        svcs[SLOW].ready = true;
+        svcs[SLOW].dirty = true;
+        settle(&mut svcs, NOW, &mut starts);
+        assert!(matches!(svcs[SLOW].state, State::Up));
+        assert!(matches!(svcs[SERIALIZED].state, State::Up));
+        assert_eq!(starts, [0, 1]);
+    }
+
+    #[test]
+    fn slot_waiter_leaves_expired_timeouts_to_slot_holder() {
+        const HOLDER: usize = 0;
+        const WAITER: usize = 1;
+        const HOLDER_CFG: ServiceConfig = ServiceConfig {
+            name: b"holder",
+            index: HOLDER,
+            propagate_dirty: &[],
+            ready: Ready::Notify,
+            max_ready_time_millis: Some(1_000),
+            ..DEP_CFG
+        };
+        const WAITER_CFG: ServiceConfig = ServiceConfig {
+            name: b"waiter",
+            index: WAITER,
+            max_wait_time_millis: Some(500),
+            ..BASE
+        };
+        const SETTINGS: Settings = Settings {
+            max_concurrent_starts: Some(1),
+            blocked_time_millis: DEFAULT_BLOCKED_TIME_MILLIS,
+        };
+        let late = timespec {
+            tv_sec: 2,
+            tv_nsec: 0,
+        };
+
+        let mut svcs = [
+            Service::test(&HOLDER_CFG, State::Starting),
+            Service::test(&WAITER_CFG, State::WaitingToStart),
+        ];
+        svcs[HOLDER].pid = Some(i32::MAX);
+        svcs[WAITER].dirty = true;
+
+        // Past both services' timeouts, the waiter is held for the slot without failing
+        let next = NextState::new(&svcs, WAITER, late, SETTINGS);
+        assert!(matches!(next, NextState::AwaitingSlot));
+        next.apply(&mut svcs, WAITER, late);
+        assert!(matches!(svcs[WAITER].state, State::WaitingToStart));
+        assert!(!svcs[WAITER].dirty);
+
+        // The expired timeout poll() returns on is the holder's, not the waiter's over and over
+        let (timeout, timeout_svc) = calculate_poll_timeout(&mut svcs, late, SETTINGS);
+        assert_eq!(timeout, Some(0));
+        let timeout_svc = timeout_svc.unwrap();
+        assert_eq!(timeout_svc.cfg.index, HOLDER);
+        timeout_svc.dirty = true;
+
+        // The holder is stopped for never becoming ready, waking the waiter once it frees the slot
+        let next = NextState::new(&svcs, HOLDER, late, SETTINGS);
+        assert!(matches!(next, NextState::ForceDown));
+        next.apply(&mut svcs, HOLDER, late);
+        assert!(svcs[WAITER].dirty);
+        assert!(matches!(
+            NextState::new(&svcs, WAITER, late, SETTINGS),
+            NextState::SettingUp
+        ));
+    }
+
+    #[test]
+    fn stuck_stop_keeps_running_and_reports_blocker() {
+        const STOPPING_CFG: ServiceConfig = ServiceConfig {
+            stop_dependencies: &[NEEDER],
+            propagate_dirty: &[],
+            ..DEP_CFG
+        };
+        const DEPENDENT_CFG: ServiceConfig = ServiceConfig {
+            propagate_dirty: &[DEP],
+            ..NEEDER_CFG
+        };
+        const SETTINGS: Settings = Settings {
+            max_concurrent_starts: None,
+            blocked_time_millis: 500,
+        };
+        let blocked = timespec {
+            tv_sec: 0,
+            tv_nsec: 600_000_000,
+        };
+
+        let mut svcs = [
+            Service::test(&STOPPING_CFG, State::WaitingToStop),
+            Service::test(&DEPENDENT_CFG, State::Up),
+        ];
+        svcs[DEP].target = Target::Down;
+        svcs[DEP].pid = Some(i32::MAX);
+        svcs[NEEDER].pid = Some(i32::MAX);
+
+        // The threshold is configurable
+        assert!(matches!(
+            NextState::new(&svcs, DEP, blocked, Settings::default()),
+            NextState::None
+        ));
+
+        // Blocked by a dependent which is staying up, the service keeps running rather than
+        // failing and being killed out from under it
+        let next = NextState::new(&svcs, DEP, blocked, SETTINGS);
+        assert!(matches!(next, NextState::StopBlocked));
+        next.apply(&mut svcs, DEP, blocked);
+        assert!(matches!(svcs[DEP].state, State::WaitingToStop));
+        assert_eq!(svcs[DEP].pid, Some(i32::MAX));
+        assert_eq!(svcs[DEP].blocked_by, Some(NEEDER));
+        assert_eq!(svcs[DEP].fail_reason, Some(FailReason::Deadlocked));
+        assert!(!svcs[DEP].dirty);
+
+        // Having reported it, there is nothing to time out on
+        let (timeout, _) = calculate_poll_timeout(&mut svcs, blocked, SETTINGS);
+        assert_eq!(timeout, None);
+
+        // Once the dependent stops, the service follows
+        svcs[NEEDER].pid = None;
+        NextState::Down.apply(&mut svcs, NEEDER, blocked);
+        assert!(svcs[DEP].dirty);
+        assert!(matches!(
+            NextState::new(&svcs, DEP, blocked, SETTINGS),
+            NextState::Stopping
+        ));
+    }
+
+    #[test]
+    fn stop_wait_timeout_keeps_running() {
+        const STOPPING_CFG: ServiceConfig = ServiceConfig {
+            stop_dependencies: &[NEEDER],
+            propagate_dirty: &[],
+            max_wait_time_millis: Some(100),
+            ..DEP_CFG
+        };
+        let waited = timespec {
+            tv_sec: 0,
+            tv_nsec: 200_000_000,
+        };
+
+        // The dependent is still stopping, so this is not a deadlock, just a slow stop
+        let mut svcs = [
+            Service::test(&STOPPING_CFG, State::WaitingToStop),
+            Service::test(&NEEDER_CFG, State::Stopping),
+        ];
+        svcs[DEP].target = Target::Down;
+        svcs[DEP].pid = Some(i32::MAX);
+
+        let next = NextState::new(&svcs, DEP, waited, Settings::default());
+        assert!(matches!(next, NextState::StopBlocked));
+        next.apply(&mut svcs, DEP, waited);
+        assert!(matches!(svcs[DEP].state, State::WaitingToStop));
+        assert_eq!(svcs[DEP].pid, Some(i32::MAX));
+        assert_eq!(svcs[DEP].fail_reason, Some(FailReason::WaitTimedOut));
+    }
+
+    #[test]
+    fn wants_proceed_once_dependency_exhausts_retries() {
+        let mut svcs = [
+            Service::test(&DEP_CFG, State::Up),
+            Service::test(&NEEDER_CFG, State::WaitingToStart),
+            Service::test(&WANTER_CFG, State::WaitingToStart),
+        ];
+        svcs[DEP].pid = Some(i32::MAX);
+        let mut starts = [0; 3];
+
+        dep_dies(&mut svcs);
+        settle(&mut svcs, NOW, &mut starts);
+        assert!(matches!(svcs[DEP].state, State::Retrying));
+
+        // Out of attempts, the dependency fails rather than retrying again
+        svcs[DEP].attempt_count = DEP_CFG.max_attempt_count.unwrap_or(u32::MAX);
+        svcs[DEP].state = State::Up;
+        svcs[DEP].pid = Some(i32::MAX);
+        dep_dies(&mut svcs);
+        settle(&mut svcs, NOW, &mut starts);
+        assert!(matches!(svcs[DEP].state, State::Failed));
+
+        // A want is satisfied by the dependency failing, but a need never is
+        assert!(matches!(svcs[NEEDER].state, State::WaitingToStart));
+        assert!(matches!(svcs[WANTER].state, State::Up));
+        assert_eq!(starts, [0, 0, 1]);
+    }
+
+    #[test]
+    fn reset_policy_controls_attempt_count() {
+        const UP_STABLE_CFG: ServiceConfig = ServiceConfig {
+            index: 0,
+            propagate_dirty: &[],
+            ..DEP_CFG
+        };
+        const CLEAN_EXIT_CFG: ServiceConfig = ServiceConfig {
+            reset_attempts_on: ResetPolicy::CleanExit,
+            ..UP_STABLE_CFG
+        };
+        const NEVER_CFG: ServiceConfig = ServiceConfig {
+            reset_attempts_on: ResetPolicy::Never,
+            ..UP_STABLE_CFG
+        };
+        const CLEAN_EXIT_3_CFG: ServiceConfig = ServiceConfig {
+            success_exit_codes: &[3],
+            ..CLEAN_EXIT_CFG
+        };
+        let stable = timespec {
+            tv_sec: 2,
+            tv_nsec: 0,
+        };
+
+        let up = |cfg: &'static ServiceConfig| {
+            let mut svcs = [Service::test(cfg, State::Up)];
+            svcs[0].pid = Some(i32::MAX);
+            svcs[0].attempt_count = 2;
+            svcs[0].dirty = true;
+            settle(&mut svcs, stable, &mut [0]);
+            svcs[0].attempt_count
+        };
+        assert_eq!(up(&UP_STABLE_CFG), 0);
+        assert_eq!(up(&CLEAN_EXIT_CFG), 2);
+        assert_eq!(up(&NEVER_CFG), 2);
+
+        let exits = |cfg: &'static ServiceConfig, code| {
+            let mut svcs = [Service::test(cfg, State::Up)];
+            svcs[0].attempt_count = 2;
+            svcs[0].exit_code = Some(code);
+            svcs[0].dirty = true;
+            settle(&mut svcs, NOW, &mut [0]);
+            (svcs[0].state, svcs[0].attempt_count)
+        };
+        assert!(matches!(exits(&CLEAN_EXIT_CFG, 0), (State::Retrying, 1)));
+        assert!(matches!(exits(&NEVER_CFG, 0), (State::Failed, 3)));
+        // success_exit_codes count as clean, as they do for .setup
+        assert!(matches!(exits(&CLEAN_EXIT_3_CFG, 3), (State::Retrying, 1)));
+        assert!(matches!(exits(&CLEAN_EXIT_CFG, 3), (State::Failed, 3)));
+    }
+
+    #[test]
+    fn conflict_action_stop_brings_conflicts_down() {
+        const STARTER_CFG: ServiceConfig = ServiceConfig {
+            name: b"starter",
+            index: 0,
+            conflicts: &[1],
+            target_up_propagate_down: &[1],
+            conflict_action: ConflictAction::Stop,
+            ..BASE
+        };
+        const WAITER_CFG: ServiceConfig = ServiceConfig {
+            conflict_action: ConflictAction::Wait,
+            ..STARTER_CFG
+        };
+        const CONFLICT_CFG: ServiceConfig = ServiceConfig {
+            name: b"conflict",
+            index: 1,
+            conflicts: &[0],
+            propagate_dirty: &[0],
+            ..BASE
+        };
+
+        // Both up, e.g. at boot or after something needing the conflict brought it up
+        let start = |cfg: &'static ServiceConfig| {
+            let mut svcs = [
+                Service::test(cfg, State::Down),
+                Service::test(&CONFLICT_CFG, State::Up),
+            ];
+            svcs[0].dirty = true;
+            settle(&mut svcs, NOW, &mut [0, 0]);
+            (svcs[0].state, svcs[1].state, svcs[1].target)
+        };
+        assert!(matches!(
+            start(&STARTER_CFG),
+            (State::Up, State::Down, Target::Down)
+        ));
+        assert!(matches!(
+            start(&WAITER_CFG),
+            (State::WaitingToStart, State::Up, Target::Up)
+        ));
+    }
+
+    #[test]
+    fn start_duration_covers_setup_through_up() {
+        let mut svcs = [Service::test(&DEP_CFG, State::Down)];
+        let mut now = NOW;
+        now.tv_sec += 10;
+        NextState::SettingUp.apply(&mut svcs, 0, now);
+        assert_eq!(svcs[0].start_time.tv_sec, 10);
+        assert_eq!(svcs[0].start_duration_millis, None);
+
+        now.tv_sec += 1;
+        now.tv_nsec = 500_000_000;
+        svcs[0].pid = Some(i32::MAX);
+        NextState::Up.apply(&mut svcs, 0, now);
+        assert_eq!(svcs[0].start_duration_millis, Some(1_500));
+
+        // Going down keeps the last measurement around for `conctl analyze`
+        svcs[0].pid = None;
+        now.tv_sec += 60;
+        NextState::Down.apply(&mut svcs, 0, now);
+        assert_eq!(svcs[0].start_duration_millis, Some(1_500));
+    }
+
+    #[test]
+    fn fail_reason_follows_the_state_failed_from() {
+        for (state, reason) in [
+            (State::SettingUp, FailReason::SetupFailed),
+            (State::Up, FailReason::RunExited),
+            (State::ForceDown, FailReason::TimedOut),
+        ] {
+            let mut svcs = [Service::test(&DEP_CFG, state)];
+            NextState::FailedOrRetry.apply(&mut svcs, 0, NOW);
+            assert!(matches!(svcs[0].state, State::Failed | State::Retrying));
+            assert_eq!(svcs[0].fail_reason, Some(reason));
+        }
+
+        let mut svcs = [Service::test(&DEP_CFG, State::Stopping)];
+        NextState::CannotStop.apply(&mut svcs, 0, NOW);
+        assert_eq!(svcs[0].fail_reason, Some(FailReason::CannotStop));
+    }
+
+    #[test]
+    fn progress_is_cleared_on_state_change() {
+        let mut svcs = [Service::test(&DEP_CFG, State::SettingUp)];
+        svcs[0].progress = Some(Progress::new(b"fsck /dev/sda2"));
+        NextState::None.apply(&mut svcs, 0, NOW);
+        assert_eq!(
+            svcs[0].progress.as_ref().map(Progress::as_bytes),
+            Some(&b"fsck /dev/sda2"[..])
+        );
+
+        NextState::FailedOrRetry.apply(&mut svcs, 0, NOW);
+        assert!(svcs[0].progress.is_none());
+    }
+
+    /// Stand in for a oneshot's process exiting, then settle
+    fn exits(svcs: &mut [Service; 1], code: c_int) {
+        svcs[0].pid = None;
+        svcs[0].exit_code = Some(code);
+        svcs[0].dirty = true;
+        settle(svcs, NOW, &mut [0]);
+    }
+
+    #[test]
+    fn once_flips_target_down_when_the_run_finishes() {
+        const ONESHOT_CFG: ServiceConfig = ServiceConfig {
+            index: 0,
+            propagate_dirty: &[],
+            ..DEP_CFG
+        };
+        let up_once = || {
+            let mut svcs = [Service::test(&ONESHOT_CFG, State::Up)];
+            svcs[0].target = Target::Once;
+            svcs[0].pid = Some(i32::MAX);
+            svcs[0].dirty = true;
+            settle(&mut svcs, NOW, &mut [0]);
+            // Still running; nothing to flip yet
+            assert!(matches!(
+                (svcs[0].state, svcs[0].target),
+                (State::Up, Target::Once)
+            ));
+            svcs
+        };
+
+        // Finishing cleanly goes down rather than failing
+        let mut svcs = up_once();
+        exits(&mut svcs, 0);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Down, Target::Down)
+        ));
+        assert_eq!(svcs[0].attempt_count, 0);
+
+        // Failing is final rather than retried, despite `max_attempt_count` allowing more
+        let mut svcs = up_once();
+        exits(&mut svcs, 1);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Failed, Target::Down)
+        ));
+        assert_eq!(svcs[0].attempt_count, 1);
+
+        // Having flipped, nothing further happens
+        svcs[0].dirty = true;
+        settle(&mut svcs, NOW, &mut [0]);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Failed, Target::Down)
+        ));
+    }
+
+    #[test]
+    fn once_without_run_stays_up() {
+        // With nothing to exit, the service never leaves Up on its own and so keeps its target
+        let mut svcs = [Service::test(&BASE, State::Down)];
+        svcs[0].target = Target::Once;
+        svcs[0].dirty = true;
+        let mut starts = [0];
+        settle(&mut svcs, NOW, &mut starts);
+        assert_eq!(starts, [1]);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Up, Target::Once)
+        ));
+    }
+
+    #[test]
+    fn setting_once_from_each_state() {
+        use super::super::handle_request::set_target;
+
+        const ONESHOT_CFG: ServiceConfig = ServiceConfig { index: 0, ..BASE };
+
+        // Down: goes up, keeping the target until it comes back down
+        let mut svcs = [Service::test(&ONESHOT_CFG, State::Down)];
+        svcs[0].target = Target::Down;
+        let _ = set_target(&mut svcs, 0, NOW, Target::Once);
+        settle(&mut svcs, NOW, &mut [0]);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Up, Target::Once)
+        ));
+
+        // Up: stays up without restarting
+        let mut svcs = [Service::test(&ONESHOT_CFG, State::Up)];
+        let mut starts = [0];
+        let _ = set_target(&mut svcs, 0, NOW, Target::Once);
+        settle(&mut svcs, NOW, &mut starts);
+        assert_eq!(starts, [0]);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Up, Target::Once)
+        ));
+
+        // Failed: breaks out and makes a fresh attempt.  Leaving Failed must not consume the new
+        // target, as the failure happened under the old one.
+        let mut svcs = [Service::test(&ONESHOT_CFG, State::Failed)];
+        svcs[0].attempt_count = 3;
+        let _ = set_target(&mut svcs, 0, NOW, Target::Once);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Down, Target::Once)
+        ));
+        let mut starts = [0];
+        settle(&mut svcs, NOW, &mut starts);
+        assert_eq!(starts, [1]);
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Up, Target::Once)
+        ));
+    }
+}
@@ -0,0 +1,292 @@
+use super::Settings;
+use super::console::console_out;
+use crate::err::*;
+use crate::internal_api::*;
+use crate::ipc::*;
+use crate::os::SignalFd;
+use crate::syscall::{PollEvents, PollFd, poll};
+use crate::types::*;
+
+/// Connate-specific poll abstraction
+///
+/// Typically this code base prefers generic os abstractions in src/os.  However, poll() is
+/// difficult abstract both generically and without allocation; thus, we just special-case it to
+/// connate here.
+pub struct Poll<const N: usize> {
+    fds: PollFds<N>,
+    /// How many of `fds.console` are in use, which are the only ones passed to poll()
+    console_len: usize,
+    /// Index of the service owning each in-use entry of `fds.console`
+    console_svcs: [usize; N],
+    /// Whether the last event handled was a signal, to alternate when both fds are ready
+    last_was_signal: bool,
+}
+
+/// The signalfd and request pipe, followed by the console pipes of services which have one
+///
+/// `repr(C)` such that the arrays are laid out back to back and can be passed to poll() as one.
+/// Only services with a console pipe get an entry, as poll() fails with EINVAL when passed more
+/// fds than `RLIMIT_NOFILE` even if they are negative and skipped.
+#[repr(C)]
+struct PollFds<const N: usize> {
+    fixed: [PollFd; 2],
+    console: [PollFd; N],
+}
+
+impl<const N: usize> Poll<N> {
+    pub fn new(signalfd: &SignalFd, ipc_server: &IpcServer) -> Self {
+        let events = PollEvents::POLLIN;
+        let revents = PollEvents::empty();
+
+        let fixed = [
+            PollFd {
+                fd: signalfd.as_raw(),
+                events,
+                revents,
+            },
+            PollFd {
+                fd: ipc_server.fd_req_read().as_raw(),
+                events,
+                revents,
+            },
+        ];
+        let console = [PollFd {
+            fd: -1,
+            events,
+            revents,
+        }; N];
+
+        Self {
+            fds: PollFds { fixed, console },
+            console_len: 0,
+            console_svcs: [0; N],
+            last_was_signal: false,
+        }
+    }
+
+    /// Watch the services' console pipes, which are created as services first start
+    pub fn watch_console(&mut self, svcs: &[Service; N]) {
+        let piped = svcs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, svc)| Some((i, svc.console_pipe.as_ref()?.read_fd().as_raw())));
+        let slots = self
+            .fds
+            .console
+            .iter_mut()
+            .zip(self.console_svcs.iter_mut());
+
+        self.console_len = 0;
+        for ((pollfd, svc_index), (i, fd)) in slots.zip(piped) {
+            pollfd.fd = fd;
+            pollfd.revents = PollEvents::empty();
+            *svc_index = i;
+            self.console_len += 1;
+        }
+    }
+
+    pub fn poll(&mut self, timeout_millis: Option<i32>) -> PollFdReady {
+        let timeout = timeout_millis.unwrap_or(-1);
+
+        // Safety: `PollFds` is `repr(C)` and only holds `PollFd`s, so is equivalent to an array of
+        // `2 + N` of them, of which we pass the first `2 + console_len <= 2 + N`
+        let fds = unsafe {
+            core::slice::from_raw_parts_mut(
+                (&mut self.fds as *mut PollFds<N>).cast::<PollFd>(),
+                2 + self.console_len.min(N),
+            )
+        };
+        match unsafe { poll(fds, timeout) } {
+            Ok(_) => {}
+            // revents are not meaningful if interrupted
+            Err(Errno::EINTR) => return PollFdReady::Interrupted,
+            Err(e) => Err::<(), Errno>(e).or_abort("Unable to call poll()"),
+        }
+
+        // Alternate when both are ready such that a steady stream of one, e.g. SIGCHLD re-raised
+        // while reaping a mass exit in batches, cannot starve the other
+        let [signal_fd, request_fd] = &self.fds.fixed;
+        let signal = signal_fd.revents.contains(PollEvents::POLLIN);
+        let request = request_fd.revents.contains(PollEvents::POLLIN);
+        let ready = match (signal, request) {
+            (true, true) if self.last_was_signal => PollFdReady::Request,
+            (true, _) => PollFdReady::SignalFd,
+            (false, true) => PollFdReady::Request,
+            (false, false) if self.console_ready().next().is_some() => PollFdReady::Console,
+            (false, false) => PollFdReady::TimeoutExpired,
+        };
+        self.last_was_signal = matches!(ready, PollFdReady::SignalFd);
+        ready
+    }
+
+    /// Forward output from the console pipes the last poll() found readable
+    ///
+    /// Done after every poll() regardless of what else was ready, such that console output keeps
+    /// flowing through a steady stream of signals or requests.
+    pub fn forward_console(&self, svcs: &mut [Service; N]) {
+        for i in self.console_ready() {
+            let out = console_out(svcs, i);
+            if let Some(pipe) = svcs.get_mut(i).and_then(|svc| svc.console_pipe.as_mut()) {
+                pipe.forward(&out);
+            }
+        }
+    }
+
+    fn console_ready(&self) -> impl Iterator<Item = usize> + '_ {
+        self.fds
+            .console
+            .iter()
+            .zip(&self.console_svcs)
+            .take(self.console_len)
+            .filter(|(pollfd, _)| pollfd.revents.contains(PollEvents::POLLIN))
+            .map(|(_, &i)| i)
+    }
+}
+
+pub enum PollFdReady {
+    TimeoutExpired,
+    /// A signal interrupted poll() before anything was ready, e.g. connate was stopped and
+    /// continued under a debugger.  The caller should recalculate the timeout and poll again.
+    Interrupted,
+    SignalFd,
+    Request,
+    /// Only services' console pipes are ready, which `Poll::forward_console()` handles
+    Console,
+}
+
+/// Calculate remaining ms until timeout for a single service, or None if no timeout needed
+fn service_timeout(svc: &Service, now: timespec, settings: Settings) -> Option<i64> {
+    let target_ms: i64 = match svc.state {
+        State::SettingUp => svc.cfg.max_setup_time_millis? as i64,
+        State::Starting => svc.cfg.max_ready_time_millis? as i64,
+        State::Up if svc.awaiting_up_stable() => svc.cfg.up_stable_time_millis as i64,
+        State::Stopping => svc.cfg.max_stop_time_millis? as i64,
+        State::CleaningUp => svc.cfg.max_cleanup_time_millis? as i64,
+        // Already reported as stuck; re-checked once a blocker changes state
+        State::WaitingToStop if svc.blocked_by.is_some() => return None,
+        State::WaitingToStart | State::WaitingToStop => {
+            // Check for deadlock once blocked long enough, then fall back to max_wait_time
+            let max_wait = svc.cfg.max_wait_time_millis.map(|max| max as i64);
+            let blocked_time = settings.blocked_time_millis;
+            if now.millis_since(svc.time) < blocked_time {
+                max_wait.map_or(blocked_time, |max| max.min(blocked_time))
+            } else {
+                max_wait?
+            }
+        }
+        State::Retrying if matches!(svc.target, Target::Down | Target::Restart) => return None,
+        State::Retrying => svc.retry_delay_millis(),
+        // Other states don't automatically transition on timeout
+        _ => return None,
+    };
+
+    let elapsed = now.millis_since(svc.time);
+    Some(target_ms.saturating_sub(elapsed))
+}
+
+/// Calculate the poll timeout needed for all services
+///
+/// Returns a mutable reference to the service that will timeout next and an optional timeout value.
+/// If the timeout is `None`, no timeout is needed (infinite wait).
+/// If the timeout is `Some(0)`, a timeout has already expired.
+/// If the timeout is `Some(ms)`, that's the minimum time until next timeout.
+pub fn calculate_poll_timeout<const N: usize>(
+    svcs: &mut [Service; N],
+    now: timespec,
+    settings: Settings,
+) -> (Option<i32>, Option<&mut Service>) {
+    let mut min_time: Option<i32> = None;
+    let mut min_svc: Option<&mut Service> = None;
+
+    for svc in svcs.iter_mut() {
+        let Some(remaining) = service_timeout(svc, now, settings) else {
+            continue;
+        };
+
+        let remaining = remaining.clamp(0, i32::MAX as i64) as i32;
+
+        if min_time.is_none() || min_time.is_some_and(|m| remaining < m) {
+            min_time = Some(remaining);
+            min_svc = Some(svc);
+        }
+    }
+
+    (min_time, min_svc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::os::*;
+    use crate::supervisor::ConsolePipe;
+
+    const SIGUSR1: c_int = 10;
+
+    // Not otherwise needed by connate, which only ever receives signals via signalfd.  The test
+    // binary links libc through std, whose signal() installs a handler such that poll() is
+    // interrupted.
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn ignore_signal(_: c_int) {}
+
+    #[test]
+    fn poll_recovers_from_signal() {
+        // Installed before forking such that the child never sees the default, fatal disposition
+        unsafe { signal(SIGUSR1, ignore_signal) };
+
+        let pid = match fork().unwrap() {
+            ForkResult::Child => {
+                let signalfd = SignalFd::new().unwrap();
+                let (fd_req_read, _fd_req_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+                let (_fd_resp_read, fd_resp_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+                let ipc_server = IpcServer::new_test(fd_req_read, fd_resp_write);
+                let mut poll = Poll::<0>::new(&signalfd, &ipc_server);
+
+                // Interrupted rather than aborting, then usable again
+                let interrupted = matches!(poll.poll(Some(5000)), PollFdReady::Interrupted);
+                let recovered = matches!(poll.poll(Some(0)), PollFdReady::TimeoutExpired);
+                exit(if interrupted && recovered { 0 } else { 1 });
+            }
+            ForkResult::Parent(pid) => pid,
+        };
+
+        // Keep signalling in case the first arrives before the child is in poll()
+        let status = loop {
+            let _ = unsafe { crate::syscall::kill(pid, SIGUSR1) };
+            match waitpid(pid, WaitPidOptions::WNOHANG).unwrap() {
+                (0, _) => std::thread::sleep(std::time::Duration::from_millis(10)),
+                (_, status) => break status,
+            }
+        };
+        assert_eq!(status, 0);
+    }
+
+    #[test]
+    fn only_console_pipes_are_polled() {
+        let signalfd = SignalFd::new().unwrap();
+        let (fd_req_read, _fd_req_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let (_fd_resp_read, fd_resp_write) = Fd::new_pipe(OpenFlags::O_CLOEXEC).unwrap();
+        let ipc_server = IpcServer::new_test(fd_req_read, fd_resp_write);
+        let mut poll = Poll::<3>::new(&signalfd, &ipc_server);
+
+        const CFG: ServiceConfig = ServiceConfig::TEST_DEFAULT;
+        let mut svcs = [
+            Service::test(&CFG, State::Up),
+            Service::test(&CFG, State::Up),
+            Service::test(&CFG, State::Up),
+        ];
+        poll.watch_console(&svcs);
+        assert_eq!(poll.console_len, 0);
+
+        svcs[1].console_pipe = Some(ConsolePipe::new().unwrap());
+        poll.watch_console(&svcs);
+        assert_eq!(poll.console_len, 1);
+
+        let pipe = svcs[1].console_pipe.as_ref().unwrap();
+        pipe.write_fd().write(b"line\n").unwrap();
+        assert!(matches!(poll.poll(Some(0)), PollFdReady::Console));
+        assert!(poll.console_ready().eq([1]));
+    }
+}
@@ -4,17 +4,45 @@
 //! runtime state across exec() calls, enabling seamless re-exec for configuration
 //! updates without losing service state.
 
-use crate::internal::*;
-use connate::constants::*;
-use connate::err::*;
-use connate::internal_api::*;
-use connate::ipc::*;
-use connate::os::*;
-use connate::types::*;
-use connate::util::*;
+use super::console::ConsolePipe;
+use crate::constants::*;
+use crate::err::*;
+use crate::internal_api::*;
+use crate::ipc::*;
+use crate::os::*;
+use crate::types::*;
+use crate::util::*;
 
 pub struct SessionFd(Fd);
 
+/// What a prior connate instance leaves at each fixed FD number across re-exec
+const FIXED_FD_KINDS: [(c_int, &[FdKind]); 7] = [
+    (FD_SESSION_STATE, &[FdKind::Memfd]),
+    (FD_SIGNAL, &[FdKind::Other]),
+    // The lock file may have since been deleted, leaving it nameless
+    (FD_LOCK_FILE, &[FdKind::File, FdKind::Memfd]),
+    (FD_REQ_READ, &[FdKind::Pipe]),
+    (FD_REQ_WRITE, &[FdKind::Pipe]),
+    (FD_RESP_READ, &[FdKind::Pipe]),
+    (FD_RESP_WRITE, &[FdKind::Pipe]),
+];
+
+/// Find a fixed FD which is open but not to what a prior connate instance would have left there
+///
+/// When resuming a session, fixed FDs are expected to be populated.  However, if the session FD
+/// was instead inherited from whatever launched connate, anything at these numbers is foreign and
+/// would be misread or clobbered.
+pub fn unexpected_fixed_fd() -> Option<c_int> {
+    FIXED_FD_KINDS
+        .iter()
+        .find(|&&(fd, kinds)| {
+            Fd::from_raw(fd)
+                .kind()
+                .is_ok_and(|found| !kinds.contains(&found))
+        })
+        .map(|&(fd, _)| fd)
+}
+
 /// Maximum serialized size of a single Service
 ///
 /// Format for each service:
@@ -33,13 +61,20 @@ pub const SESSION_SERVICE_SIZE: usize = 1 // ServiceStart header
     + 1 + size_of::<i32>() // pid: header + value
     + 1 + size_of::<i32>() // supervisor_pid: header + value
     + 1 + size_of::<i32>() * 2 // stdin_pipe: header + 2 fds
+    + 1 + size_of::<i32>() * 2 // console_pipe: header + 2 fds
     + 1 + size_of::<i32>() // exit_code: header + value
+    + 1 + size_of::<u8>() // fail_reason: header + value
     + 1 + size_of::<u32>() // attempt_count: header + value
     + 1 + size_of::<i64>() // time_sec: header + value
     + 1 + size_of::<i64>() // time_nsec: header + value
+    + 1 + size_of::<i64>() // start_time_sec: header + value
+    + 1 + size_of::<i64>() // start_time_nsec: header + value
+    + 1 + size_of::<i64>() // start_duration_millis: header + value
     + 1 + size_of::<i64>() // sigkill_sec: header + value
     + 1 + size_of::<i64>() // sigkill_nsec: header + value
     + 1 // ready: header only
+    + 1 // exit_expected: header only
+    + 1 // has_run: header only
     + 1 + size_of::<i32>() * 2 // settle_pipe: header + 2 fds
     + 1; // ServiceEnd header
 
@@ -106,16 +141,23 @@ session_field_defs! {
     Pid = b'p',
     SupervisorPid = b'P',
     StdinPipe = b'i',
+    ConsolePipe = b'o',
     ReturnValue = b'v',
     SettlePipe = b'q',
+    StartDuration = b'e',
+    FailReason = b'E',
 
     // Integer fields (zipped indicates value is zero)
     AttemptCount = b'a',
     TimeSec = b't',
     TimeNsec = b'n',
+    StartTimeSec = b'b',
+    StartTimeNsec = b'B',
 
     // Boolean flags (presence = true, absence = false)
     Ready = b'y',
+    ExitExpected = b'x',
+    HasRun = b'h',
     // Dirty flag is not meaningful across exec when configured service relations may have changed
     // Cost to re-check a dirty service once is low.
     // Thus, SERVICES.initialize() initializes `dirty = true`.
@@ -183,6 +225,16 @@ macro_rules! read_i64 {
     }};
 }
 
+macro_rules! read_u8 {
+    ( $session:ident, $buf:ident ) => {{
+        let n = $session.0.read($buf.get_mut(..1).ok_or(Errno::EINVAL)?)?;
+        if n != 1 {
+            return Err(Errno::EINVAL);
+        }
+        *$buf.first().ok_or(Errno::EINVAL)?
+    }};
+}
+
 macro_rules! read_pipe {
     ( $session:ident, $buf:ident ) => {{
         let read_fd = read_i32!($session, $buf);
@@ -192,11 +244,15 @@ macro_rules! read_pipe {
 }
 
 impl SessionFd {
-    pub fn resume_or_new(svcs: &mut [Service; SERVICE_COUNT], ipc_server: &mut IpcServer) -> Self {
+    pub fn resume_or_new<L: ServiceLookup, const N: usize>(
+        svcs: &mut [Service; N],
+        ipc_server: &mut IpcServer,
+    ) -> Self {
         let old_fd = Fd::from_raw(FD_SESSION_STATE);
         if old_fd.is_valid() {
             let fd = Self(old_fd);
-            fd.deserialize(svcs).or_abort("Unable to load session");
+            fd.deserialize::<L, N>(svcs)
+                .or_abort("Unable to load session");
             ipc_server.respond(Response::Okay);
             fd
         } else {
@@ -208,7 +264,10 @@ impl SessionFd {
         }
     }
 
-    fn deserialize<const N: usize>(&self, svcs: &mut [Service; N]) -> Result<(), Errno> {
+    fn deserialize<L: ServiceLookup, const N: usize>(
+        &self,
+        svcs: &mut [Service; N],
+    ) -> Result<(), Errno> {
         self.0.lseek(0, SeekWhence::SEEK_SET)?;
         let mut buf = [0u8; SESSION_SERVICE_SIZE];
 
@@ -221,11 +280,18 @@ impl SessionFd {
         let mut pid: Option<pid_t> = None;
         let mut supervisor_pid: Option<pid_t> = None;
         let mut stdin_pipe: Option<(Fd, Fd)> = None;
+        let mut console_pipe: Option<(Fd, Fd)> = None;
         let mut exit_code: Option<c_int> = None;
+        let mut fail_reason: Option<FailReason> = None;
         let mut attempt_count: u32 = 0;
         let mut time_sec: i64 = 0;
         let mut time_nsec: i64 = 0;
+        let mut start_time_sec: i64 = 0;
+        let mut start_time_nsec: i64 = 0;
+        let mut start_duration_millis: Option<i64> = None;
         let mut ready: bool = false;
+        let mut exit_expected: bool = false;
+        let mut has_run: bool = false;
         let mut settle_pipe: Option<(Fd, Fd)> = None;
 
         loop {
@@ -273,7 +339,7 @@ impl SessionFd {
                     //
                     // We want to continue reading out an outdated service's data to consume it to
                     // potentially read a still valid following service.
-                    svc = svcs.find_by_name_mut(name);
+                    svc = svcs.find_by_name_mut::<L>(name);
 
                     // Initialize other fields to default values.
                     state = State::Down;
@@ -281,11 +347,18 @@ impl SessionFd {
                     pid = None;
                     supervisor_pid = None;
                     stdin_pipe = None;
+                    console_pipe = None;
                     exit_code = None;
+                    fail_reason = None;
                     attempt_count = 0;
                     time_sec = 0;
                     time_nsec = 0;
+                    start_time_sec = 0;
+                    start_time_nsec = 0;
+                    start_duration_millis = None;
                     ready = false;
+                    exit_expected = false;
+                    has_run = false;
                     settle_pipe = None;
                 }
 
@@ -302,12 +375,30 @@ impl SessionFd {
                             let _ = read_fd.close();
                             let _ = write_fd.close();
                         }
+                        // Kept even if the new config no longer uses it, as processes from before
+                        // may still be writing to it
+                        if let Some((read_fd, write_fd)) = console_pipe.take() {
+                            svc.console_pipe = Some(ConsolePipe::from_fds(read_fd, write_fd));
+                        }
                         svc.exit_code = exit_code;
+                        svc.fail_reason = fail_reason;
+                        svc.exit_expected = exit_expected;
+                        // Sessions from before has_run was saved can still tell from the other
+                        // fields in most cases
+                        svc.has_run = has_run
+                            || pid.is_some()
+                            || supervisor_pid.is_some()
+                            || exit_code.is_some();
                         svc.attempt_count = attempt_count;
                         svc.time = timespec {
                             tv_sec: time_sec,
                             tv_nsec: time_nsec,
                         };
+                        svc.start_time = timespec {
+                            tv_sec: start_time_sec,
+                            tv_nsec: start_time_nsec,
+                        };
+                        svc.start_duration_millis = start_duration_millis;
                         svc.ready = ready;
 
                         // settle_pipe handling:
@@ -318,7 +409,7 @@ impl SessionFd {
                             svc.settle_pipe = settle_pipe.take();
                         }
                         #[cfg(not(feature = "settle"))]
-                        if let Some((read_fd, write_fd)) = settle_pipe {
+                        if let Some((read_fd, write_fd)) = settle_pipe.take() {
                             let _ = read_fd.close();
                             let _ = write_fd.close();
                         }
@@ -335,6 +426,10 @@ impl SessionFd {
                             let _ = read_fd.close();
                             let _ = write_fd.close();
                         }
+                        if let Some((read_fd, write_fd)) = console_pipe.take() {
+                            let _ = read_fd.close();
+                            let _ = write_fd.close();
+                        }
                         if let Some((read_fd, write_fd)) = settle_pipe.take() {
                             let _ = read_fd.close();
                             let _ = write_fd.close();
@@ -374,8 +469,13 @@ impl SessionFd {
                 }
 
                 SessionField::StdinPipe => stdin_pipe = Some(read_pipe!(self, buf)),
+                SessionField::ConsolePipe => console_pipe = Some(read_pipe!(self, buf)),
 
                 SessionField::ReturnValue => exit_code = Some(read_i32!(self, buf)),
+                // Reasons added by a newer connate are dropped
+                SessionField::FailReason => {
+                    fail_reason = FailReason::from_byte(read_u8!(self, buf)).ok()
+                }
 
                 SessionField::SettlePipe => settle_pipe = Some(read_pipe!(self, buf)),
 
@@ -388,8 +488,18 @@ impl SessionFd {
                         time_nsec = 0;
                     }
                 }
+                SessionField::StartTimeSec => start_time_sec = read_i64!(self, buf),
+                SessionField::StartTimeNsec => {
+                    start_time_nsec = read_i64!(self, buf);
+                    if !(0..=999_999_999).contains(&start_time_nsec) {
+                        start_time_nsec = 0;
+                    }
+                }
+                SessionField::StartDuration => start_duration_millis = Some(read_i64!(self, buf)),
 
                 SessionField::Ready => ready = true,
+                SessionField::ExitExpected => exit_expected = true,
+                SessionField::HasRun => has_run = true,
             }
         }
 
@@ -451,11 +561,22 @@ impl SessionFd {
                 writer.push(&write_fd.as_raw().to_le_bytes())?;
             }
 
+            if let Some(pipe) = &svc.console_pipe {
+                writer.push(&[SessionField::ConsolePipe.as_byte()])?;
+                writer.push(&pipe.read_fd().as_raw().to_le_bytes())?;
+                writer.push(&pipe.write_fd().as_raw().to_le_bytes())?;
+            }
+
             if let Some(exit_code) = svc.exit_code {
                 writer.push(&[SessionField::ReturnValue.as_byte()])?;
                 writer.push(&exit_code.to_le_bytes())?;
             }
 
+            if let Some(reason) = svc.fail_reason {
+                writer.push(&[SessionField::FailReason.as_byte()])?;
+                writer.push(&[reason.as_byte()])?;
+            }
+
             #[cfg(feature = "settle")]
             if let Some((read_fd, write_fd)) = &svc.settle_pipe {
                 writer.push(&[SessionField::SettlePipe.as_byte()])?;
@@ -479,20 +600,206 @@ impl SessionFd {
                 writer.push(&svc.time.tv_nsec.to_le_bytes())?;
             }
 
+            if svc.start_time.tv_sec != 0 {
+                writer.push(&[SessionField::StartTimeSec.as_byte()])?;
+                writer.push(&svc.start_time.tv_sec.to_le_bytes())?;
+            }
+
+            if svc.start_time.tv_nsec != 0 {
+                writer.push(&[SessionField::StartTimeNsec.as_byte()])?;
+                writer.push(&svc.start_time.tv_nsec.to_le_bytes())?;
+            }
+
+            if let Some(millis) = svc.start_duration_millis {
+                writer.push(&[SessionField::StartDuration.as_byte()])?;
+                writer.push(&millis.to_le_bytes())?;
+            }
+
             // Boolean flags (header only if true)
             if svc.ready {
                 writer.push(&[SessionField::Ready.as_byte()])?;
             }
 
+            if svc.exit_expected {
+                writer.push(&[SessionField::ExitExpected.as_byte()])?;
+            }
+
+            if svc.has_run {
+                writer.push(&[SessionField::HasRun.as_byte()])?;
+            }
+
             // ServiceEnd
             writer.push(&[SessionField::ServiceEnd.as_byte()])?;
 
-            let n = self.0.write(writer.as_slice())?;
-            if n != writer.pos() {
-                return Err(Errno::EINVAL);
-            }
+            self.0.write_all(writer.as_slice())?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::Settings;
+    use super::super::next_state::NextState;
+    use super::*;
+
+    const CFG: ServiceConfig = ServiceConfig {
+        name: b"oneshot",
+        init_target: Target::Once,
+        run: Run::Fn {
+            f: || Ok(()),
+            log_overwrite: false,
+        },
+        max_attempt_count: Some(1),
+        ..ServiceConfig::TEST_DEFAULT
+    };
+
+    struct Lookup;
+
+    impl ServiceLookup for Lookup {
+        fn index_of(name: &[u8]) -> Option<usize> {
+            (name == CFG.name).then_some(0)
+        }
+
+        fn boot_target(_name: &[u8]) -> Option<&'static [usize]> {
+            None
+        }
+
+        fn emergency_target() -> &'static [usize] {
+            &[]
+        }
+    }
+
+    const NOW: timespec = timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    fn service(state: State, target: Target) -> Service {
+        Service {
+            target,
+            ..Service::test(&CFG, state)
+        }
+    }
+
+    /// Run `f` in a child process, as it rearranges the process's FDs, returning its exit code
+    fn in_child(f: fn() -> bool) -> c_int {
+        match fork().unwrap() {
+            ForkResult::Child => exit(if f() { 0 } else { 1 }),
+            ForkResult::Parent(pid) => waitpid(pid, WaitPidOptions::empty()).unwrap().1,
+        }
+    }
+
+    /// Populate the fixed FDs as a prior connate instance would have
+    fn populate_fixed_fds() {
+        let _ = Fd::new_memfd(c"connate", MemfdFlags::empty())
+            .unwrap()
+            .move_to(FD_SESSION_STATE)
+            .unwrap();
+        let _ = SignalFd::new().unwrap().move_to(FD_SIGNAL).unwrap();
+        for (read_fd, write_fd) in [(FD_REQ_READ, FD_REQ_WRITE), (FD_RESP_READ, FD_RESP_WRITE)] {
+            let (read, write) = Fd::new_pipe(OpenFlags::empty()).unwrap();
+            let _ = read.move_to(read_fd).unwrap();
+            let _ = write.move_to(write_fd).unwrap();
+        }
+    }
+
+    #[test]
+    fn resumed_fixed_fds_are_expected() {
+        assert_eq!(
+            in_child(|| {
+                populate_fixed_fds();
+                unexpected_fixed_fd().is_none()
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn occupied_fixed_fd_is_detected() {
+        assert_eq!(
+            in_child(|| {
+                populate_fixed_fds();
+                // Something foreign where a pipe is expected
+                let _ = Fd::open(c"/dev/null", OpenFlags::O_RDONLY, 0)
+                    .unwrap()
+                    .move_to(FD_RESP_READ)
+                    .unwrap();
+                unexpected_fixed_fd() == Some(FD_RESP_READ)
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn once_survives_reexec_mid_lifecycle() {
+        let mut running = service(State::Up, Target::Once);
+        running.pid = Some(i32::MAX);
+        running.has_run = true;
+
+        let mut session_fd =
+            SessionFd(Fd::new_memfd(c"connate-test", MemfdFlags::empty()).unwrap());
+        session_fd.save(&[running]).unwrap();
+
+        // The new instance starts from its config's defaults
+        let mut svcs = [service(State::Down, Target::Down)];
+        session_fd.deserialize::<Lookup, 1>(&mut svcs).unwrap();
+        let _ = session_fd.0.close();
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target, svcs[0].pid),
+            (State::Up, Target::Once, Some(i32::MAX))
+        ));
+
+        // The run ending after the re-exec still flips the target
+        svcs[0].pid = None;
+        svcs[0].exit_code = Some(1);
+        svcs[0].dirty = true;
+        let svcs = &mut svcs;
+        while let Some(i) = svcs.find_dirty_index() {
+            NextState::new(svcs, i, NOW, Settings::default()).apply(svcs, i, NOW);
+        }
+        assert!(matches!(
+            (svcs[0].state, svcs[0].target),
+            (State::Failed, Target::Down)
+        ));
+    }
+
+    #[test]
+    fn start_timing_survives_reexec() {
+        let mut up = service(State::Up, Target::Up);
+        up.start_time = timespec {
+            tv_sec: 12,
+            tv_nsec: 345_000_000,
+        };
+        up.start_duration_millis = Some(2_500);
+
+        let mut session_fd =
+            SessionFd(Fd::new_memfd(c"connate-test", MemfdFlags::empty()).unwrap());
+        session_fd.save(&[up]).unwrap();
+
+        let mut svcs = [service(State::Down, Target::Down)];
+        session_fd.deserialize::<Lookup, 1>(&mut svcs).unwrap();
+        let _ = session_fd.0.close();
+        assert_eq!(
+            (svcs[0].start_time.tv_sec, svcs[0].start_time.tv_nsec),
+            (12, 345_000_000)
+        );
+        assert_eq!(svcs[0].start_duration_millis, Some(2_500));
+    }
+
+    #[test]
+    fn fail_reason_survives_reexec() {
+        let mut failed = service(State::Failed, Target::Up);
+        failed.fail_reason = Some(FailReason::Deadlocked);
+
+        let mut session_fd =
+            SessionFd(Fd::new_memfd(c"connate-test", MemfdFlags::empty()).unwrap());
+        session_fd.save(&[failed]).unwrap();
+
+        let mut svcs = [service(State::Down, Target::Down)];
+        session_fd.deserialize::<Lookup, 1>(&mut svcs).unwrap();
+        let _ = session_fd.0.close();
+        assert_eq!(svcs[0].fail_reason, Some(FailReason::Deadlocked));
+    }
+}
@@ -1,13 +1,19 @@
-use connate::constants::*;
-use connate::err::*;
-use connate::internal_api::*;
-use connate::ipc::*;
-use connate::os::*;
-use connate::syscall::{PollEvents, PollFd, poll};
-use connate::types::*;
-use connate::util::BufWriter;
+use super::cgroup::join_cgroup;
+use super::console::uses_console_pipe;
+use crate::config::{Config, Connate};
+use crate::constants::*;
+use crate::err::*;
+use crate::internal_api::*;
+use crate::ipc::*;
+use crate::os::*;
+use crate::syscall::{PollEvents, PollFd, poll};
+use crate::types::*;
+use crate::util::BufWriter;
 use itoa::Integer;
 
+/// Space for reading a supervisor's child PIDs to signal them all
+const CHILD_PIDS_BUF_SIZE: usize = 4096;
+
 pub trait Spawn {
     fn spawn_setting_up(&mut self, logger_write_fd: Option<Fd>) -> Result<(), Errno>;
     fn spawn_run(&mut self, logger_write_fd: Option<Fd>) -> Result<(), Errno>;
@@ -57,6 +63,7 @@ fn spawn_direct(svc: &mut Service, run: &Run, logger_write_fd: Option<Fd>) -> Re
         Run::None => false,
     };
 
+    clear_exit(svc);
     let pid = match fork()? {
         ForkResult::Parent(pid) => pid,
         ForkResult::Child => {
@@ -64,12 +71,13 @@ fn spawn_direct(svc: &mut Service, run: &Run, logger_write_fd: Option<Fd>) -> Re
             if setup_process(svc, logger_write_fd, log_overwrite).is_err() {
                 exit(1);
             }
-            execute_run(run);
+            execute_run(run, svc.cfg);
             // execute_run never returns on success (exec or exit)
         }
     };
 
     svc.pid = Some(pid);
+    svc.has_run = true;
     Ok(())
 }
 
@@ -80,6 +88,7 @@ fn spawn_supervised(
     logger_write_fd: Option<Fd>,
     notify_daemonize: bool,
 ) -> Result<(), Errno> {
+    clear_exit(svc);
     let pid = match fork()? {
         ForkResult::Parent(pid) => pid,
         ForkResult::Child => {
@@ -90,9 +99,19 @@ fn spawn_supervised(
     };
 
     svc.supervisor_pid = Some(pid);
+    svc.has_run = true;
     Ok(())
 }
 
+/// Forget the previous process's exit, which no longer describes the service once another spawns
+///
+/// Otherwise a stale exit code would be reported while the new process runs, and would be judged
+/// in its place should spawning it fail.
+fn clear_exit(svc: &mut Service) {
+    svc.exit_code = None;
+    svc.exit_expected = false;
+}
+
 /// Run the supervisor process
 ///
 /// This function never returns - it either exits or aborts.
@@ -138,7 +157,7 @@ fn run_supervisor(
             if setup_process(svc, logger_write_fd, log_overwrite).is_err() {
                 exit(1);
             }
-            execute_run(run);
+            execute_run(run, svc.cfg);
             // execute_run never returns on success
         }
         Err(_) => exit(1),
@@ -161,9 +180,12 @@ fn run_supervisor(
 
     loop {
         // Poll for signals
-        let poll_result = unsafe { poll(core::slice::from_mut(&mut pollfd), -1) };
-        if poll_result.is_err() {
-            continue; // Interrupted, retry
+        match unsafe { poll(core::slice::from_mut(&mut pollfd), -1) } {
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            // Nothing else is expected with a single valid fd.  Retrying would likely spin, and
+            // exiting would leave the service unsupervised.
+            Err(_) => kill_and_exit(main_pid, stop_all_children),
         }
 
         if !pollfd.revents.contains(PollEvents::POLLIN) {
@@ -173,7 +195,8 @@ fn run_supervisor(
         // Read signal
         let signal = match signalfd.read_signal() {
             Ok(sig) => sig,
-            Err(_) => continue,
+            Err(Errno::EINTR | Errno::EAGAIN) => continue,
+            Err(_) => kill_and_exit(main_pid, stop_all_children),
         };
 
         match signal {
@@ -181,27 +204,30 @@ fn run_supervisor(
                 reap_children(
                     &mut main_pid,
                     notify_daemonize,
-                    stop_all_children,
-                    svc.cfg.name,
+                    svc.cfg,
+                    &mut signalfd,
                     &mut ipc_client,
                 );
             }
-            Signal::SIGTERM => {
-                let _ = kill(main_pid, Signal::SIGKILL);
-                let exit_code = match waitpid(main_pid, WaitPidOptions::empty()) {
-                    Ok((_, status)) => exit_code_from_status(status),
-                    Err(_) => 1,
-                };
-                if stop_all_children {
-                    kill_all_children();
-                }
-                exit(exit_code);
-            }
+            Signal::SIGTERM => kill_and_exit(main_pid, stop_all_children),
             _ => {}
         }
     }
 }
 
+/// Kill the supervised process and exit with its exit code
+fn kill_and_exit(main_pid: pid_t, stop_all_children: bool) -> ! {
+    let _ = kill(main_pid, Signal::SIGKILL);
+    let exit_code = match waitpid(main_pid, WaitPidOptions::empty()) {
+        Ok((_, status)) => exit_code_from_status(status),
+        Err(_) => 1,
+    };
+    if stop_all_children {
+        kill_all_children();
+    }
+    exit(exit_code);
+}
+
 /// Set up child process before exec
 fn setup_process(
     svc: &Service,
@@ -211,18 +237,33 @@ fn setup_process(
     // Close connate's internal FDs that we inherited
     close_inherited_fds();
 
-    // Create new session (detach from controlling terminal)
-    let _ = setsid();
+    // Create new session (detach from controlling terminal) unless configured to share connate's
+    if svc.cfg.new_session {
+        let _ = setsid();
+    }
 
-    // Setup logging
-    setup_logging(svc, logger_write_fd, log_overwrite)?;
+    // Join the service's cgroup while still privileged
+    if let Some(cgroup) = svc.cfg.cgroup {
+        join_cgroup(cgroup)?;
+    }
+
+    // Setup stdin and logging, which a controlling terminal replaces
+    if let Some(path) = svc.cfg.controlling_tty {
+        setup_controlling_tty(path)?;
+    } else {
+        setup_stdin(svc)?;
+        setup_logging(svc, logger_write_fd, log_overwrite)?;
+    }
 
     // Change directory if configured
     if let Some(path) = svc.cfg.chdir {
         chdir(path)?;
     }
 
-    // Drop privileges (setgid must come before setuid)
+    // Drop privileges (setgroups and setgid must come before setuid)
+    if let Some(gids) = svc.cfg.supplementary_gids {
+        setgroups(gids)?;
+    }
     if let Some(gid) = svc.cfg.gid {
         setgid(gid)?;
     }
@@ -241,6 +282,45 @@ fn setup_process(
     Ok(())
 }
 
+/// Make the terminal at `path` the controlling terminal and use it for stdio
+///
+/// Must follow setsid(), as only a session leader can take a controlling terminal.
+fn setup_controlling_tty(path: &CStr) -> Result<(), Errno> {
+    // O_NOCTTY such that the explicit ioctl below is the only way the terminal is taken
+    let tty = Fd::open(path, OpenFlags::O_RDWR | OpenFlags::O_NOCTTY, 0)?;
+    tty.set_controlling_tty()?;
+    tty.dup(STDIN.as_raw(), OpenFlags::empty())?;
+    tty.dup(STDOUT.as_raw(), OpenFlags::empty())?;
+    tty.dup(STDERR.as_raw(), OpenFlags::empty())?;
+    tty.close()
+}
+
+/// Set up stdin for child process
+fn setup_stdin(svc: &Service) -> Result<(), Errno> {
+    // Loggers read what other services write to their pipe
+    if let Some((read_fd, _)) = &svc.stdin_pipe {
+        read_fd.dup(STDIN.as_raw(), OpenFlags::empty())?;
+        return Ok(());
+    }
+
+    match svc.cfg.stdin {
+        StdinSource::Null => {
+            let dev_null = Fd::open(c"/dev/null", OpenFlags::O_RDONLY, 0)?;
+            dev_null.dup(STDIN.as_raw(), OpenFlags::empty())?;
+            dev_null.close()?;
+        }
+        StdinSource::Inherit => {
+            // Do nothing, inherit parent's stdin
+        }
+        StdinSource::File(path) => {
+            let fd = Fd::open(path, OpenFlags::O_RDONLY, 0)?;
+            fd.dup(STDIN.as_raw(), OpenFlags::empty())?;
+            fd.close()?;
+        }
+    }
+    Ok(())
+}
+
 /// Set up logging for child process
 fn setup_logging(
     svc: &Service,
@@ -256,7 +336,14 @@ fn setup_logging(
             dev_null.close()?;
         }
         Log::Inherit => {
-            // Do nothing, inherit parent's stdout/stderr
+            // Go through connate a line at a time if configured to, otherwise inherit parent's
+            // stdout/stderr
+            if uses_console_pipe(svc.cfg)
+                && let Some(pipe) = &svc.console_pipe
+            {
+                pipe.write_fd().dup(STDOUT.as_raw(), OpenFlags::empty())?;
+                pipe.write_fd().dup(STDERR.as_raw(), OpenFlags::empty())?;
+            }
         }
         Log::File { filepath, mode } => {
             let flags = OpenFlags::O_WRONLY | OpenFlags::O_CREAT;
@@ -271,8 +358,24 @@ fn setup_logging(
             log_fd.close()?;
         }
         Log::Service(_) => {
-            // Use the provided logger pipe
-            if let Some(fd) = logger_write_fd {
+            // Go through connate a line at a time if configured to, otherwise use the provided
+            // logger pipe
+            if uses_console_pipe(svc.cfg)
+                && let Some(pipe) = &svc.console_pipe
+            {
+                pipe.write_fd().dup(STDOUT.as_raw(), OpenFlags::empty())?;
+                pipe.write_fd().dup(STDERR.as_raw(), OpenFlags::empty())?;
+            } else if let Some(fd) = logger_write_fd {
+                // Connate's write end is non-blocking with LINE_BUFFER_CONSOLE; see
+                // new_logger_pipe().  Block on a full pipe as though sharing it as usual.
+                let fd = if <Connate as Config>::LINE_BUFFER_CONSOLE
+                    && let Ok(own) = fd.reopen(OpenFlags::O_WRONLY)
+                {
+                    fd.close()?;
+                    own
+                } else {
+                    fd
+                };
                 fd.dup(STDOUT.as_raw(), OpenFlags::empty())?;
                 fd.dup(STDERR.as_raw(), OpenFlags::empty())?;
                 fd.close()?;
@@ -285,7 +388,7 @@ fn setup_logging(
 /// Execute a Run variant
 ///
 /// This function never returns on success (exec replaces the process or exit is called).
-fn execute_run(run: &Run) -> ! {
+fn execute_run(run: &Run, cfg: &ServiceConfig) -> ! {
     match run {
         Run::None => exit(0),
         Run::Exec {
@@ -294,8 +397,17 @@ fn execute_run(run: &Run) -> ! {
             envp,
             ..
         } => {
+            let mut merged = [core::ptr::null(); MAX_MERGED_ENV_COUNT + 1];
+            // Safety: both are generated or ABI-provided null-terminated arrays
+            let envp = match (cfg.clear_env, cfg.inherit_env) {
+                (true, []) => *envp,
+                (true, only) => unsafe {
+                    merge_envp(*envp, startup_envp(), Some(only), &mut merged)
+                },
+                (false, _) => unsafe { merge_envp(*envp, startup_envp(), None, &mut merged) },
+            };
             // execve never returns on success
-            let _ = unsafe { connate::syscall::execve(pathname, *argv, *envp) };
+            let _ = unsafe { crate::syscall::execve(pathname, *argv, envp) };
             exit(1);
         }
         Run::Fn { f, .. } => match f() {
@@ -307,6 +419,53 @@ fn execute_run(run: &Run) -> ! {
 
 /// Read first child PID from /proc/self/task/{pid}/children
 fn read_first_child_pid() -> Option<pid_t> {
+    let mut buf = [0u8; pid_t::MAX_STR_LEN + 1];
+    let data = read_child_pids(&mut buf)?;
+
+    // Find end of first PID (space or newline)
+    let end = data
+        .iter()
+        .position(|&b| b == b' ' || b == b'\n')
+        .unwrap_or(data.len());
+
+    parse_pid(data.get(..end)?)
+}
+
+/// Send `signal` to every child
+///
+/// Children beyond what fits in the buffer are missed.  Callers follow up with
+/// `kill_all_children()`, which does not miss any.
+fn signal_children(signal: Signal) {
+    let mut buf = [0u8; CHILD_PIDS_BUF_SIZE];
+    let Some(data) = read_child_pids(&mut buf) else {
+        return;
+    };
+
+    // Skip the trailing PID if the buffer cut it off
+    let complete = match data.len() {
+        CHILD_PIDS_BUF_SIZE => data.rsplitn(2, |&b| b == b' ').nth(1).unwrap_or(&[]),
+        _ => data,
+    };
+
+    for pid in complete
+        .split(|&b| b == b' ' || b == b'\n')
+        .filter_map(parse_pid)
+    {
+        signal_child(pid, signal);
+    }
+}
+
+/// Send `signal` to a child's process group, or just the child if it does not lead one
+///
+/// Orphans reparented to the supervisor are typically still in the main process's group.
+fn signal_child(pid: pid_t, signal: Signal) {
+    if kill(-pid, signal).is_err() {
+        let _ = kill(pid, signal);
+    }
+}
+
+/// Read the space-separated PIDs in /proc/self/task/{pid}/children into `buf`
+fn read_child_pids(buf: &mut [u8]) -> Option<&[u8]> {
     let pid = getpid();
 
     // Build path: /proc/self/task/{pid}/children
@@ -326,20 +485,15 @@ fn read_first_child_pid() -> Option<pid_t> {
     let path = unsafe { CStr::from_bytes_with_nul_unchecked(writer.as_slice()) };
 
     let fd = Fd::open(path, OpenFlags::O_RDONLY, 0).ok()?;
-    let mut buf = [0u8; pid_t::MAX_STR_LEN + 1];
-    let bytes_read = fd.read(&mut buf).ok()?;
+    let bytes_read = loop {
+        match fd.read(buf) {
+            Err(Errno::EINTR) => continue,
+            result => break result.ok()?,
+        }
+    };
     fd.close().ok()?;
 
-    // Parse first space-separated PID
-    let data = buf.get(..bytes_read)?;
-
-    // Find end of first PID (space or newline)
-    let end = data
-        .iter()
-        .position(|&b| b == b' ' || b == b'\n')
-        .unwrap_or(data.len());
-
-    parse_pid(data.get(..end)?)
+    buf.get(..bytes_read)
 }
 
 /// Parse a PID from ASCII bytes
@@ -363,21 +517,17 @@ fn parse_pid(bytes: &[u8]) -> Option<pid_t> {
 ///
 /// These FDs are specific to connate's operation and should not be leaked to service processes.
 fn close_inherited_fds() {
-    let _ = Fd::from_raw(FD_SESSION_STATE).close();
-    let _ = Fd::from_raw(FD_SIGNAL).close();
-    let _ = Fd::from_raw(FD_LOCK_FILE).close();
-    let _ = Fd::from_raw(FD_REQ_READ).close();
-    let _ = Fd::from_raw(FD_REQ_WRITE).close();
-    let _ = Fd::from_raw(FD_RESP_READ).close();
-    let _ = Fd::from_raw(FD_RESP_WRITE).close();
+    for fd in FIXED_FDS {
+        let _ = Fd::from_raw(fd).close();
+    }
 }
 
 /// Reap children and handle main process exit
 fn reap_children(
     main_pid: &mut pid_t,
     notify_daemonize: bool,
-    stop_all_children: bool,
-    svc_name: &'static [u8],
+    cfg: &'static ServiceConfig,
+    signalfd: &mut SignalFd,
     ipc_client: &mut IpcClient,
 ) {
     loop {
@@ -395,12 +545,16 @@ fn reap_children(
 
         if notify_daemonize && let Some(new_pid) = read_first_child_pid() {
             ipc_client.lock_quiet();
-            let _ = ipc_client.send_and_receive(Request::DaemonReady(new_pid, svc_name));
+            let _ = ipc_client.send_and_receive(Request::DaemonReady(new_pid, cfg.name));
             ipc_client.unlock();
             *main_pid = new_pid;
         } else {
-            if stop_all_children {
-                kill_all_children();
+            if cfg.stop_all_children {
+                stop_children(
+                    signalfd,
+                    cfg.children_stop_signal,
+                    cfg.children_stop_time_millis,
+                );
             }
             exit(exit_code);
         }
@@ -418,20 +572,74 @@ fn exit_code_from_status(status: c_int) -> c_int {
     }
 }
 
+/// Stop the children left once the main process is gone
+///
+/// Unless `signal` is SIGKILL, sends it to every child and gives them `time_millis` to exit
+/// before resorting to SIGKILL.  A SIGTERM from connate, which is forcing the service down, cuts
+/// the wait short.
+fn stop_children(signalfd: &mut SignalFd, signal: Signal, time_millis: c_int) {
+    if signal != Signal::SIGKILL {
+        signal_children(signal);
+        wait_for_children(signalfd, time_millis);
+    }
+    kill_all_children();
+}
+
+/// Wait up to `time_millis` for every child to exit
+fn wait_for_children(signalfd: &mut SignalFd, time_millis: c_int) {
+    let Ok(start) = get_time_monotonic() else {
+        return;
+    };
+    let mut pollfd = PollFd {
+        fd: signalfd.as_raw(),
+        events: PollEvents::POLLIN,
+        revents: PollEvents::empty(),
+    };
+
+    loop {
+        reap_zombies();
+        if read_first_child_pid().is_none() {
+            return;
+        }
+
+        let Ok(now) = get_time_monotonic() else {
+            return;
+        };
+        let remaining = (time_millis as i64).saturating_sub(now.millis_since(start));
+        if remaining <= 0 {
+            return;
+        }
+
+        match unsafe { poll(core::slice::from_mut(&mut pollfd), remaining as i32) } {
+            Ok(0) => return,
+            Ok(_) => {}
+            Err(Errno::EINTR) => continue,
+            Err(_) => return,
+        }
+
+        // SIGCHLD is handled by reaping at the top of the loop
+        if let Ok(Signal::SIGTERM) = signalfd.read_signal() {
+            return;
+        }
+    }
+}
+
 /// Kill all children and wait for them to die
 fn kill_all_children() {
     while let Some(pid) = read_first_child_pid() {
-        // Kill the process group (negative pid)
-        let _ = kill(-pid, Signal::SIGKILL);
-
-        // Reap any zombies
-        loop {
-            match waitpid(-1, WaitPidOptions::WNOHANG) {
-                Ok((0, _)) => break,
-                Ok((_, _)) => continue,
-                Err(Errno::ECHILD) => break,
-                Err(_) => break,
-            }
+        signal_child(pid, Signal::SIGKILL);
+        reap_zombies();
+    }
+}
+
+/// Reap any children which have already exited
+fn reap_zombies() {
+    loop {
+        match waitpid(-1, WaitPidOptions::WNOHANG) {
+            Ok((0, _)) => break,
+            Ok((_, _)) => continue,
+            Err(Errno::ECHILD) => break,
+            Err(_) => break,
         }
     }
 }
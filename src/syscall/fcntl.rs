@@ -8,9 +8,14 @@ use syscalls::{Sysno, syscall};
 // Assert the type we are using is the same size as c_short.
 const _: () = assert!(core::mem::size_of::<c_short>() == core::mem::size_of::<u16>());
 
+/// File descriptor flag for F_GETFD/F_SETFD
+pub const FD_CLOEXEC: c_int = 1;
+
 #[allow(non_camel_case_types)]
 #[repr(C)]
 pub enum FcntlCmd {
+    F_GETFD = 1,
+    F_SETFD = 2,
     F_GETFL = 3,
     F_SETFL = 4,
     F_GETLK = 5,
@@ -62,7 +67,7 @@ pub unsafe fn fcntl(fd: c_int, cmd: FcntlCmd, flock: &mut Flock) -> Result<c_int
     syscall!(Sysno::fcntl, fd, cmd, flock as *mut Flock).map(|ret| ret as c_int)
 }
 
-// fcntl for F_GETFL/F_SETFL which take/return integer flags
+// fcntl for F_GETFD/F_SETFD/F_GETFL/F_SETFL which take/return integer flags
 pub unsafe fn fcntl_flags(fd: c_int, cmd: FcntlCmd, flags: c_int) -> Result<c_int, Errno> {
     syscall!(Sysno::fcntl, fd, cmd, flags).map(|ret| ret as c_int)
 }
@@ -64,6 +64,9 @@ pub struct Stat {
 #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
 const _: () = assert!(core::mem::size_of::<Stat>() == 128);
 
+/// Operate on `dirfd` itself when `pathname` is empty
+pub const AT_EMPTY_PATH: c_int = 0x1000;
+
 // `man 2 fstatat`:
 //
 // SYNOPSIS
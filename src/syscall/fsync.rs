@@ -0,0 +1,21 @@
+use crate::err::*;
+use crate::types::c_int;
+use syscalls::{Sysno, syscall};
+
+// `man 2 fsync`:
+//
+// SYNOPSIS
+//        int fsync(int fd);
+//
+// DESCRIPTION
+//        fsync() transfers ("flushes") all modified in-core data of (i.e., modified buffer cache
+//        pages for) the file referred to by the file descriptor fd to the disk device (or other
+//        permanent storage device) so that all changed information can be retrieved even if the
+//        system crashes or is rebooted.
+//
+// RETURN VALUE
+//        On success, these system calls return zero. On error, -1 is returned, and errno is set
+//        to indicate the error.
+pub unsafe fn fsync(fd: c_int) -> Result<(), Errno> {
+    syscall!(Sysno::fsync, fd).map(|_| ())
+}
@@ -20,6 +20,10 @@ pub enum IoctlRequest {
     TCGETS = 0x5401,
     #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
     TCGETS = 0x5401,
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    TIOCSCTTY = 0x540E,
+    #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    TIOCSCTTY = 0x540E,
 }
 
 // NAME
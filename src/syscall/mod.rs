@@ -16,6 +16,7 @@ mod exit;
 mod fcntl;
 mod fork;
 mod fstatat;
+mod fsync;
 mod ftruncate;
 mod getdents;
 mod getpid;
@@ -55,6 +56,7 @@ pub use exit::*;
 pub use fcntl::*;
 pub use fork::*;
 pub use fstatat::*;
+pub use fsync::*;
 pub use ftruncate::*;
 pub use getdents::*;
 pub use getpid::*;
@@ -12,6 +12,7 @@ impl OpenFlags {
     pub const O_WRONLY: Self = Self(0o0000001);
     pub const O_RDWR: Self = Self(0o0000002);
     pub const O_CREAT: Self = Self(0o0000100);
+    pub const O_NOCTTY: Self = Self(0o0000400);
     pub const O_TRUNC: Self = Self(0o0001000);
     pub const O_APPEND: Self = Self(0o0002000);
     pub const O_NONBLOCK: Self = Self(0o0004000);
@@ -9,6 +9,7 @@ pub struct PollEvents(u16);
 
 impl PollEvents {
     pub const POLLIN: Self = Self(0x0001);
+    pub const POLLOUT: Self = Self(0x0004);
 
     pub const fn empty() -> Self {
         Self(0)
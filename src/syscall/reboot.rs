@@ -8,6 +8,8 @@ pub const LINUX_REBOOT_MAGIC2: i32 = 0x28121969;
 pub const LINUX_REBOOT_CMD_POWER_OFF: i32 = 0x4321FEDC_u32 as i32;
 pub const LINUX_REBOOT_CMD_RESTART: i32 = 0x01234567;
 pub const LINUX_REBOOT_CMD_HALT: i32 = 0xCDEF0123_u32 as i32;
+pub const LINUX_REBOOT_CMD_CAD_ON: i32 = 0x89ABCDEF_u32 as i32;
+pub const LINUX_REBOOT_CMD_CAD_OFF: i32 = 0x00000000;
 
 // `man 2 reboot`:
 //
@@ -12,6 +12,11 @@ pub unsafe fn setgid(gid: gid_t) -> Result<(), Errno> {
     syscall!(Sysno::setgid, gid).map(|_| ())
 }
 
+/// Set the supplementary group IDs of the calling process
+pub unsafe fn setgroups(gids: &[gid_t]) -> Result<(), Errno> {
+    syscall!(Sysno::setgroups, gids.len(), gids.as_ptr()).map(|_| ())
+}
+
 /// Set real, effective, and saved user IDs
 pub unsafe fn setresuid(ruid: uid_t, euid: uid_t, suid: uid_t) -> Result<(), Errno> {
     syscall!(Sysno::setresuid, ruid, euid, suid).map(|_| ())
@@ -43,11 +43,13 @@ impl SigInfo {
         const SIGINT: u32 = Signal::SIGINT as u32;
         const SIGTERM: u32 = Signal::SIGTERM as u32;
         const SIGCHLD: u32 = Signal::SIGCHLD as u32;
+        const SIGPWR: u32 = Signal::SIGPWR as u32;
         match self.si_signo {
             SIGHUP => Signal::SIGHUP,
             SIGINT => Signal::SIGINT,
             SIGTERM => Signal::SIGTERM,
             SIGCHLD => Signal::SIGCHLD,
+            SIGPWR => Signal::SIGPWR,
             _ => Signal::UNRECOGNIZED,
         }
     }
@@ -32,7 +32,7 @@ pub type CStr = core::ffi::CStr;
 
 #[allow(non_camel_case_types)]
 #[repr(C)]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct timespec {
     pub tv_sec: i64,
     pub tv_nsec: i64,
@@ -40,11 +40,23 @@ pub struct timespec {
 const _: () = assert!(core::mem::size_of::<timespec>() == 16);
 
 impl timespec {
+    /// Milliseconds elapsed from `earlier` to `self`
+    ///
+    /// Timestamps are expected to come from a clock which never goes backward, such as
+    /// CLOCK_BOOTTIME.  Should one nonetheless appear to (e.g. a kernel bug, or a timestamp
+    /// restored from a session written by another boot), this returns 0 rather than a negative or
+    /// wrapped value which would fire timeouts incorrectly.
     pub fn millis_since(self, earlier: timespec) -> i64 {
         self.tv_sec
-            .wrapping_sub(earlier.tv_sec)
+            .saturating_sub(earlier.tv_sec)
             .saturating_mul(1000)
-            .saturating_add(self.tv_nsec.wrapping_sub(earlier.tv_nsec) / 1_000_000)
+            .saturating_add(self.tv_nsec.saturating_sub(earlier.tv_nsec) / 1_000_000)
+            .max(0)
+    }
+
+    /// Whole seconds elapsed from `earlier` to `self`, clamped to 0 like [`Self::millis_since`]
+    pub fn secs_since(self, earlier: timespec) -> i64 {
+        self.tv_sec.saturating_sub(earlier.tv_sec).max(0)
     }
 }
 
@@ -70,6 +82,8 @@ pub enum Signal {
     SIGTERM = 15,
     // SIGSTKFLT = 16,
     SIGCHLD = 17,
+    // ...
+    SIGPWR = 30,
     UNRECOGNIZED = u32::MAX,
 }
 
@@ -79,6 +93,19 @@ impl Signal {
 
         1 << (self as usize - 1)
     }
+
+    /// Symbolic name, e.g. `SIGTERM`
+    pub fn name(self) -> &'static str {
+        match self {
+            Signal::SIGHUP => "SIGHUP",
+            Signal::SIGINT => "SIGINT",
+            Signal::SIGKILL => "SIGKILL",
+            Signal::SIGTERM => "SIGTERM",
+            Signal::SIGCHLD => "SIGCHLD",
+            Signal::SIGPWR => "SIGPWR",
+            Signal::UNRECOGNIZED => "UNRECOGNIZED",
+        }
+    }
 }
 
 /// Documentation on this type has inconsistent descriptions of its size and format.
@@ -212,3 +239,33 @@ impl AtomicBool {
         self.0.swap(new_val, core::sync::atomic::Ordering::AcqRel)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ts(tv_sec: i64, tv_nsec: i64) -> timespec {
+        timespec { tv_sec, tv_nsec }
+    }
+
+    #[test]
+    fn millis_since_forward() {
+        assert_eq!(ts(10, 500_000_000).millis_since(ts(10, 0)), 500);
+        assert_eq!(ts(12, 0).millis_since(ts(10, 250_000_000)), 1750);
+        assert_eq!(ts(5, 0).secs_since(ts(2, 999_999_999)), 3);
+    }
+
+    #[test]
+    fn millis_since_backward_clamps_to_zero() {
+        assert_eq!(ts(10, 0).millis_since(ts(11, 0)), 0);
+        assert_eq!(ts(10, 0).millis_since(ts(10, 1_000_000)), 0);
+        assert_eq!(ts(0, 0).millis_since(ts(i64::MAX, 0)), 0);
+        assert_eq!(ts(10, 0).secs_since(ts(11, 0)), 0);
+        assert_eq!(ts(i64::MIN, 0).secs_since(ts(i64::MAX, 0)), 0);
+    }
+
+    #[test]
+    fn millis_since_saturates() {
+        assert_eq!(ts(i64::MAX, 0).millis_since(ts(i64::MIN, 0)), i64::MAX);
+    }
+}
@@ -0,0 +1,304 @@
+//! Human-readable formatting helpers
+//!
+//! These write into a `BufWriter` rather than printing directly so that callers may measure,
+//! colorize, or transmit the output as they see fit.  `parse_duration` reads durations back in.
+
+use crate::err::Errno;
+use crate::util::BufWriter;
+
+/// Seconds per displayed duration unit and the unit's suffix, largest first
+pub const DURATION_UNITS: [(i64, &[u8]); 4] = [(86400, b"d"), (3600, b"h"), (60, b"m"), (1, b"s")];
+
+/// Split a duration in seconds into days, hours, minutes, and seconds
+pub fn duration_units(seconds: i64) -> [i64; 4] {
+    [
+        seconds / 86400,
+        (seconds % 86400) / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60,
+    ]
+}
+
+/// Index into `DURATION_UNITS` of the first unit to display
+///
+/// Leading zero units are skipped.  Seconds are always displayed.
+pub fn duration_first_unit(units: &[i64; 4]) -> usize {
+    units
+        .iter()
+        .position(|&value| value > 0)
+        .unwrap_or(units.len() - 1)
+}
+
+/// Write a single duration unit, e.g. `02h`
+///
+/// If `pad`, zero-pad to two digits.  This should be used for all units following the first
+/// displayed unit.
+pub fn fmt_duration_unit(
+    writer: &mut BufWriter,
+    value: i64,
+    suffix: &[u8],
+    pad: bool,
+) -> Result<(), Errno> {
+    if pad && (0..10).contains(&value) {
+        writer.push(b"0")?;
+    }
+    let mut buf = itoa::Buffer::new();
+    writer.push(buf.format(value).as_bytes())?;
+    writer.push(suffix)
+}
+
+/// Write a duration in seconds, e.g. `9s`, `5m07s`, or `1d02h03m04s`
+pub fn fmt_duration(writer: &mut BufWriter, seconds: i64) -> Result<(), Errno> {
+    let units = duration_units(seconds);
+    let first = duration_first_unit(&units);
+
+    for (i, (value, (_, suffix))) in units.iter().zip(DURATION_UNITS.iter()).enumerate() {
+        if i >= first {
+            fmt_duration_unit(writer, *value, suffix, i > first)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse a duration in seconds, as either a bare number of seconds or in `fmt_duration`'s units
+///
+/// For example `90`, `90s`, `5m`, or `1d02h03m04s`.  Units must be given largest first, and each
+/// at most once.  A bare number may not follow units, as `1h30` could mean minutes or seconds.
+pub fn parse_duration(bytes: &[u8]) -> Result<i64, Errno> {
+    if bytes.is_empty() {
+        return Err(Errno::EINVAL);
+    }
+
+    let mut total: i64 = 0;
+    let mut value: Option<i64> = None;
+    // Units still allowed, as each must be smaller than the last
+    let mut units = DURATION_UNITS.iter();
+    for &b in bytes {
+        if b.is_ascii_digit() {
+            let digit = (b - b'0') as i64;
+            value = Some(
+                value
+                    .unwrap_or(0)
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add(digit))
+                    .ok_or(Errno::EINVAL)?,
+            );
+            continue;
+        }
+
+        let (scale, _) = units
+            .by_ref()
+            .find(|(_, suffix)| suffix == &[b])
+            .ok_or(Errno::EINVAL)?;
+        total = value
+            .take()
+            .ok_or(Errno::EINVAL)?
+            .checked_mul(*scale)
+            .and_then(|v| v.checked_add(total))
+            .ok_or(Errno::EINVAL)?;
+    }
+
+    match value {
+        Some(secs) if units.len() == DURATION_UNITS.len() => Ok(secs),
+        Some(_) => Err(Errno::EINVAL),
+        None => Ok(total),
+    }
+}
+
+/// Write a duration in milliseconds as seconds with three decimal places, e.g. `1.250s`
+pub fn fmt_millis(writer: &mut BufWriter, millis: i64) -> Result<(), Errno> {
+    if millis < 0 {
+        writer.push(b"-")?;
+    }
+    // Unsigned such that the fraction stays positive and i64::MIN does not overflow
+    let millis = millis.unsigned_abs();
+    let mut buf = itoa::Buffer::new();
+    writer.push(buf.format(millis / 1000).as_bytes())?;
+    writer.push(b".")?;
+    let fraction = millis % 1000;
+    if fraction < 100 {
+        writer.push(if fraction < 10 { b"00" } else { b"0" })?;
+    }
+    writer.push(buf.format(fraction).as_bytes())?;
+    writer.push(b"s")
+}
+
+/// Write seconds since the Unix epoch as a UTC date and time, e.g. `2024-03-01 14:05:09 UTC`
+pub fn fmt_timestamp(writer: &mut BufWriter, unix_seconds: i64) -> Result<(), Errno> {
+    let days = unix_seconds.div_euclid(86400);
+    let [_, hour, minute, second] = duration_units(unix_seconds.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+
+    let mut buf = itoa::Buffer::new();
+    writer.push(buf.format(year).as_bytes())?;
+    for (sep, value) in [
+        (b"-", month),
+        (b"-", day),
+        (b" ", hour),
+        (b":", minute),
+        (b":", second),
+    ] {
+        writer.push(sep)?;
+        fmt_duration_unit(writer, value, b"", true)?;
+    }
+    writer.push(b" UTC")
+}
+
+/// Convert days since the Unix epoch to a proleptic Gregorian (year, month, day)
+///
+/// Howard Hinnant's `civil_from_days`, which counts in 400-year eras starting March 1st such that
+/// the leap day falls at the end of each year.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Write a byte count with binary (1024-based) units, e.g. `512B`, `1.5K`, or `20M`
+///
+/// Values under 10 of a unit show one truncated decimal place; larger values are truncated to a
+/// whole number.
+pub fn fmt_bytes(writer: &mut BufWriter, bytes: u64) -> Result<(), Errno> {
+    const SUFFIXES: [&[u8]; 7] = [b"B", b"K", b"M", b"G", b"T", b"P", b"E"];
+
+    let mut buf = itoa::Buffer::new();
+
+    let mut unit: usize = 0;
+    let mut scale: u64 = 1;
+    while bytes / scale >= 1024 && unit + 1 < SUFFIXES.len() {
+        unit += 1;
+        scale = scale.saturating_mul(1024);
+    }
+    let suffix = SUFFIXES.get(unit).ok_or(Errno::EINVAL)?;
+
+    let whole = bytes / scale;
+    if unit == 0 || whole >= 10 {
+        writer.push(buf.format(whole).as_bytes())?;
+    } else {
+        // One decimal place; computed in u128 to avoid overflow with large `bytes`
+        let tenths = ((bytes as u128 * 10) / scale as u128) % 10;
+        writer.push(buf.format(whole).as_bytes())?;
+        writer.push(b".")?;
+        writer.push(buf.format(tenths as u8).as_bytes())?;
+    }
+    writer.push(suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duration(seconds: i64) -> String {
+        let mut buf = [0u8; 64];
+        let mut writer = BufWriter::new(&mut buf);
+        fmt_duration(&mut writer, seconds).unwrap();
+        String::from_utf8(writer.as_slice().to_vec()).unwrap()
+    }
+
+    fn bytes(n: u64) -> String {
+        let mut buf = [0u8; 64];
+        let mut writer = BufWriter::new(&mut buf);
+        fmt_bytes(&mut writer, n).unwrap();
+        String::from_utf8(writer.as_slice().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn test_fmt_duration_seconds() {
+        assert_eq!(duration(0), "0s");
+        assert_eq!(duration(9), "9s");
+        assert_eq!(duration(59), "59s");
+    }
+
+    #[test]
+    fn test_fmt_duration_boundaries() {
+        assert_eq!(duration(60), "1m00s");
+        assert_eq!(duration(3599), "59m59s");
+        assert_eq!(duration(3600), "1h00m00s");
+        assert_eq!(duration(86399), "23h59m59s");
+        assert_eq!(duration(86400), "1d00h00m00s");
+        assert_eq!(duration(93784), "1d02h03m04s");
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration(b"0"), Ok(0));
+        assert_eq!(parse_duration(b"90"), Ok(90));
+        assert_eq!(parse_duration(b"90s"), Ok(90));
+        assert_eq!(parse_duration(b"5m"), Ok(300));
+        assert_eq!(parse_duration(b"1h30m"), Ok(5400));
+        assert_eq!(parse_duration(b"1d02h03m04s"), Ok(93784));
+        assert_eq!(parse_duration(b"3000000000"), Ok(3_000_000_000));
+
+        for invalid in [
+            &b""[..],
+            b"s",
+            b"-5",
+            b"1.5",
+            b"5x",
+            b"1m2h",
+            b"1m1m",
+            b"1s1",
+            b"1h30",
+            b"99999999999999999999",
+            b"999999999999999999d",
+        ] {
+            assert_eq!(parse_duration(invalid), Err(Errno::EINVAL));
+        }
+    }
+
+    #[test]
+    fn test_fmt_millis() {
+        let millis = |n| {
+            let mut buf = [0u8; 64];
+            let mut writer = BufWriter::new(&mut buf);
+            fmt_millis(&mut writer, n).unwrap();
+            String::from_utf8(writer.as_slice().to_vec()).unwrap()
+        };
+        assert_eq!(millis(0), "0.000s");
+        assert_eq!(millis(7), "0.007s");
+        assert_eq!(millis(42), "0.042s");
+        assert_eq!(millis(1250), "1.250s");
+        assert_eq!(millis(75_001), "75.001s");
+        assert_eq!(millis(-7), "-0.007s");
+        assert_eq!(millis(-1250), "-1.250s");
+        assert_eq!(millis(i64::MIN), "-9223372036854775.808s");
+    }
+
+    #[test]
+    fn test_fmt_timestamp() {
+        let timestamp = |n| {
+            let mut buf = [0u8; 64];
+            let mut writer = BufWriter::new(&mut buf);
+            fmt_timestamp(&mut writer, n).unwrap();
+            String::from_utf8(writer.as_slice().to_vec()).unwrap()
+        };
+        assert_eq!(timestamp(0), "1970-01-01 00:00:00 UTC");
+        assert_eq!(timestamp(-1), "1969-12-31 23:59:59 UTC");
+        assert_eq!(timestamp(951_782_400), "2000-02-29 00:00:00 UTC");
+        assert_eq!(timestamp(1_709_301_909), "2024-03-01 14:05:09 UTC");
+    }
+
+    #[test]
+    fn test_fmt_bytes() {
+        assert_eq!(bytes(0), "0B");
+        assert_eq!(bytes(1023), "1023B");
+        assert_eq!(bytes(1024), "1.0K");
+        assert_eq!(bytes(1536), "1.5K");
+        assert_eq!(bytes(10 * 1024), "10K");
+        assert_eq!(bytes(20 * 1024 * 1024 + 1), "20M");
+        assert_eq!(bytes(u64::MAX), "15E");
+    }
+}
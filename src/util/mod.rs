@@ -1,6 +1,10 @@
 //! Miscellaneous utility code
 
 mod buf_writer;
+mod fmt;
 mod mem;
+mod rand;
 pub use buf_writer::*;
+pub use fmt::*;
 pub use mem::*;
+pub use rand::*;
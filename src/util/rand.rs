@@ -0,0 +1,65 @@
+//! Small, non-cryptographic pseudo-random number generator
+//!
+//! Only suitable where the quality of randomness does not matter, such as spreading out retry
+//! delays.  State is process-global and expected to be seeded once at startup.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Used both as the pre-seed state and to replace a zero seed, which xorshift cannot escape.
+const DEFAULT_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+static STATE: AtomicU64 = AtomicU64::new(DEFAULT_SEED);
+
+/// Seed the process-global generator
+pub fn seed_random(seed: u64) {
+    let seed = if seed == 0 { DEFAULT_SEED } else { seed };
+    STATE.store(seed, Ordering::Relaxed);
+}
+
+/// Get the next pseudo-random value (xorshift64*)
+pub fn random_u64() -> u64 {
+    let mut x = STATE.load(Ordering::Relaxed);
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    STATE.store(x, Ordering::Relaxed);
+    x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+}
+
+/// Get a pseudo-random value within `-max..=max`
+///
+/// Returns 0 if `max` is not positive.
+pub fn random_jitter(max: i64) -> i64 {
+    if max <= 0 {
+        return 0;
+    }
+    let span = (max as u64).saturating_mul(2).saturating_add(1);
+    let offset = random_u64().checked_rem(span).unwrap_or(0);
+    (offset as i64).saturating_sub(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_jitter_non_positive() {
+        assert_eq!(random_jitter(0), 0);
+        assert_eq!(random_jitter(-5), 0);
+    }
+
+    #[test]
+    fn test_random_jitter_bounds() {
+        seed_random(12345);
+        for _ in 0..1000 {
+            let j = random_jitter(10);
+            assert!((-10..=10).contains(&j));
+        }
+    }
+
+    #[test]
+    fn test_seed_zero() {
+        seed_random(0);
+        assert_ne!(random_u64(), 0);
+    }
+}